@@ -264,5 +264,447 @@ pub(crate) fn define() -> SettingGroup {
         true,
     );
 
+    // Spectre mitigation options.
+
+    settings.add_enum(
+        "blade",
+        r#"
+            Blade Spectre v1 mitigation strategy.
+
+            Blade computes a minimal cut of the dataflow graph from transiently-loaded values to
+            the operands that may leak them, and protects every cut point.
+
+            - none: Do not insert any Spectre mitigations.
+            - lfence: Insert a speculation barrier (see `blade_barrier`) at every cut point.
+            - lfence_per_block: Like "lfence", but place each fence at the beginning of the basic
+              block containing the cut point.
+            - slh: Protect the loads feeding every cut point with speculative load hardening,
+              masking their addresses, or their values as `blade_slh_mask` says, against their
+              bounds. Accesses to dynamic heaps and tables are bounded by their current size,
+              and accesses to static heaps by their static bound.
+            - slh_with_fence_fallback: Like "slh", but protect the loads which have no bounds
+              with a speculation barrier right after them instead of failing, so every load gets
+              the cheapest sound protection available. `blade_slh_fake_bounds` has no effect.
+            - analyze: Compute the cut and report the fences "lfence" would insert, without
+              modifying the function.
+
+            The legacy x86 and the AArch64 backends support every strategy. AArch64 uses
+            `dsb sy ; isb` as its speculation barrier, and follows the loads protected by "slh"
+            with a `csdb` barrier as Arm recommends. Compiling with any strategy but "none" and
+            "analyze" fails on other targets, rather than leaving the code unprotected;
+            `TargetIsa::blade_capabilities` tells which strategies a target supports.
+        "#,
+        vec![
+            "none",
+            "lfence",
+            "lfence_per_block",
+            "slh",
+            "slh_with_fence_fallback",
+            "analyze",
+        ],
+    );
+
+    settings.add_enum(
+        "blade_placement",
+        r#"
+            Where Blade places the speculation barrier cutting the flow of a transient value
+            from one computation into another.
+
+            - late: Right before the computation the transient value flows into, closest to the
+              sink.
+            - early: Right after the computation producing the transient value, closest to the
+              load it comes from.
+
+            Barriers cutting the flow out of a load or into a select always follow the
+            computation of the transient value, and barriers cutting the flow into a sink always
+            precede it. The two policies interact differently with
+            instruction scheduling and register pressure.
+        "#,
+        vec!["late", "early"],
+    );
+
+    settings.add_enum(
+        "blade_cut_bias",
+        r#"
+            Which minimal cut Blade protects when several have the same cost.
+
+            - sources: The cut closest to the loads, which protects transient values as soon as
+              they are produced.
+            - sinks: The cut closest to the sinks, which protects transient values as late as
+              possible, so more of the computation on them runs before each barrier.
+
+            With `blade=slh`, every load flowing into a cut point is hardened, so a cut closer
+            to the sinks may harden more loads than its cost suggests.
+        "#,
+        vec!["sources", "sinks"],
+    );
+
+    settings.add_enum(
+        "blade_engine",
+        r#"
+            How Blade decides where to protect the dataflow.
+
+            - min_cut: Compute a minimal cut of the Blade graph, as in the Blade paper.
+            - dataflow: Type every value as stable or transient in program order, as the type
+              system of the Blade paper does, and place a speculation barrier right before
+              every sink which would otherwise receive a transient value. This needs no graph,
+              but usually places more barriers than the minimal cut. Only `blade=lfence` and
+              `blade=analyze` support it; other strategies, including a custom `BladeStrategy`,
+              use the minimal cut.
+            - check: Compute the minimal cut, then type the protected function, and fail the
+              compilation with a verifier error if any sink still receives a transient value.
+              This is an independent oracle for the construction of the Blade graph, meant for
+              testing. `blade=lfence_per_block`, and functions which exceed
+              `blade_max_edges_log2`, stop speculation at branches instead, and aren't checked.
+        "#,
+        vec!["min_cut", "dataflow", "check"],
+    );
+
+    settings.add_enum(
+        "blade_slh_mask",
+        r#"
+            What `blade=slh` masks to harden a load.
+
+            - address: The address of the load, which becomes zero when it is out of bounds.
+              The load can't even be issued until its bounds are checked.
+            - value: The value produced by the load, which becomes zero when its address is out
+              of bounds. The load is issued right away, and only the users of its value wait
+              for the bounds check.
+
+            Loads of floating-point or vector values, and of integers wider than a pointer, are
+            always hardened through their addresses.
+        "#,
+        vec!["address", "value"],
+    );
+
+    settings.add_enum(
+        "blade_barrier",
+        r#"
+            The speculation barrier instruction placed by Blade on x86. Other targets always
+            use their own architectural barrier.
+
+            - lfence: The `lfence` instruction, which AMD and Intel both recommend as a
+              speculation barrier.
+            - mfence: The `mfence` instruction, which also orders memory accesses. Use it on
+              AMD processors where `lfence` is not dispatch serializing.
+            - serialize: The `serialize` instruction, which fully serializes execution. It is
+              only available on recent Intel processors.
+        "#,
+        vec!["lfence", "mfence", "serialize"],
+    );
+
+    settings.add_enum(
+        "blade_transient_params",
+        r#"
+            Which block parameters Blade treats as sources, whatever flows into them.
+
+            - none: Only the parameters declared transient by the producer. The values passed
+              to every other parameter flow into it precisely.
+            - blocks: The parameters of every block but the entry block. This over-approximates
+              the dataflow through block parameters, which helps validate it: every value the
+              precise mode leaves unprotected here must be unable to reach a sink.
+            - all: The parameters of every block, including the function parameters.
+
+            This is only meant for validating the analysis, and costs many more mitigations.
+        "#,
+        vec!["none", "blocks", "all"],
+    );
+
+    settings.add_num(
+        "blade_max_edges_log2",
+        r#"
+            The log2 of the maximum number of edges in the Blade graph of a function.
+
+            Computing the minimal cut of larger graphs could dominate compilation time, so
+            functions whose graph exceeds this size get a speculation barrier at the beginning
+            of every basic block instead, whatever the `blade` strategy.
+
+            The default is 20, which translates to about a million edges.
+            "#,
+        20,
+    );
+
+    settings.add_bool(
+        "blade_coarsen",
+        r#"
+            Merge chains of values which can only leak through their last value into a single
+            node of the Blade graph.
+
+            This makes the graph, and the time spent computing its cut, much smaller for large
+            functions, at the cost of placing some barriers later than necessary, which may
+            make them more expensive.
+            "#,
+        false,
+    );
+
+    settings.add_bool(
+        "blade_split_edges",
+        r#"
+            Place the speculation barriers cutting the flow of a value into a block parameter
+            on the control flow edges which pass it, rather than at the beginning of the block.
+
+            A barrier at the beginning of the block stalls every path into it, including those
+            which pass trusted values. With this, the barrier goes right before the jump
+            carrying the transient value instead, and conditional branches are redirected
+            through a new block holding that jump, so the other paths don't stall. This only
+            applies to `blade=lfence` and `blade=lfence_per_block` with late placement, and not
+            with `blade_coarsen`, whose merged nodes don't map to single edges.
+            "#,
+        false,
+    );
+
+    settings.add_bool(
+        "blade_prioritize_nested_regions",
+        r#"
+            Prefer cutting the Blade graph inside deeply nested speculative regions.
+
+            The cost of every cut point is its weight scaled by four minus the number of
+            conditional branches and other speculation points which dominate it, and by one at
+            a depth of three or more. Among cuts of similar cost, Blade then picks the one
+            nested the deepest, away from the straight-line code which every execution runs.
+            "#,
+        false,
+    );
+
+    settings.add_num(
+        "blade_weight_fence_load",
+        r#"
+            The cost Blade assigns to a speculation barrier right after a load.
+
+            Blade computes a cut of minimal total cost, so raising the weight of a class of cut
+            points relative to the others steers barriers away from it. Weights of zero are
+            treated as one.
+            "#,
+        1,
+    );
+
+    settings.add_num(
+        "blade_weight_fence_value",
+        r#"
+            The cost Blade assigns to a speculation barrier between the computation of two
+            values, neither of which is a load, or between a value and a single one of the
+            operands a sink leaks.
+            "#,
+        1,
+    );
+
+    settings.add_num(
+        "blade_weight_fence_sink",
+        r#"
+            The cost Blade assigns to a speculation barrier right before a sink other than a
+            branch, such as a memory access, protecting all of the operands it leaks.
+            "#,
+        1,
+    );
+
+    settings.add_num(
+        "blade_weight_fence_branch",
+        r#"
+            The cost Blade assigns to a speculation barrier right before a branch.
+            "#,
+        1,
+    );
+
+    settings.add_num(
+        "blade_weight_slh",
+        r#"
+            The cost Blade assigns to protecting a load with speculative load hardening.
+
+            With `blade=slh`, this replaces `blade_weight_fence_load`.
+            "#,
+        1,
+    );
+
+    settings.add_num(
+        "blade_slh_size_budget",
+        r#"
+            The most instructions speculative load hardening may add to a function, as a
+            percentage of the instructions the function had before Blade ran.
+
+            Each hardened load costs several instructions to compute and apply its mask, which
+            matters for code sensitive to the size of the instruction cache. Once `blade=slh`
+            has added this many instructions to a function, the remaining loads are protected
+            with a speculation barrier right after them instead, as
+            `blade=slh_with_fence_fallback` does for the loads without bounds. The load which
+            exhausts the budget is still hardened, so a function may exceed it by the cost of
+            one load. The instructions computing bounds from the bounds checks guarding the
+            loads count towards the budget too.
+
+            The default is 0, which sets no budget.
+            "#,
+        0,
+    );
+
+    settings.add_num(
+        "blade_sink_classes",
+        r#"
+            The set of operands which Blade treats as sinks, as a sum of:
+
+            - 1: the addresses of loads.
+            - 2: the addresses of stores.
+            - 4: the values being stored.
+            - 8: the conditions of branches, and the indexes of jump tables.
+            - 16: call arguments. This guarantees that functions are never called with
+              transient arguments, which is what allows Blade to treat function parameters as
+              stable. The callee of indirect calls is an indirect target instead.
+            - 32: the targets of indirect calls and jumps.
+            - 64: the operands deciding whether an instruction traps, such as the conditions of
+              conditional traps and the divisors of divisions.
+
+            Blade only protects the operands of the classes in this set, so leakage models which
+            don't consider some of these channels can avoid paying for them. The default is 63,
+            which includes every class but the trap conditions: most traps guard bounds checks
+            of indexes which are rarely transient, and protecting them all is costly.
+            "#,
+        63,
+    );
+
+    settings.add_enum(
+        "blade_leakage_model",
+        r#"
+            Which channels Blade assumes transient values leak through.
+
+            - cache: The cache, through the addresses of memory accesses, and control flow, as
+              selected by `blade_sink_classes`.
+            - memory_contents: The contents of memory only, i.e. the values being stored. This
+              is much cheaper than the cache model, as bounds checks and address computations
+              need no protection, but only stops transient data from being written out.
+              `blade_sink_classes` and the timing channels of `blade_division_is_sink` and
+              `blade_float_is_sink` are ignored.
+
+              Unlike the values being stored, call arguments are not written to memory by the
+              call itself, but they are sinks as well: callees treat their parameters as stable
+              unless `blade_transient_params` says otherwise, so they would store transient
+              arguments unprotected.
+        "#,
+        vec!["cache", "memory_contents"],
+    );
+
+    settings.add_bool(
+        "blade_division_is_sink",
+        r#"
+            Treat the operands of integer divisions and remainders as Blade sinks.
+
+            The latency of a division depends on its operands on many processors, so it can
+            leak them through timing even without accessing memory. This is off by default,
+            matching Blade's leakage model, which only considers the cache.
+            "#,
+        false,
+    );
+
+    settings.add_bool(
+        "blade_float_is_sink",
+        r#"
+            Treat the operands of floating-point operations with data-dependent latency as
+            Blade sinks.
+
+            Multiplications, divisions, square roots and fused multiply-adds are much slower
+            with subnormal operands on many processors, so they can leak them through timing.
+            This is meant for constant-time code, and is off by default.
+            "#,
+        false,
+    );
+
+    settings.add_bool(
+        "blade_exclude_trusted_loads",
+        r#"
+            Don't treat loads of trusted runtime data as Blade sources.
+
+            Such loads read the bases and bounds of heaps and tables, the addresses of globals,
+            and other fields of the VM context, at addresses which don't depend on the guest.
+            Producers mark them with the `trusted` Blade class, and legalization marks the loads
+            of global values computed from the VM context. Their values can't be steered by
+            the guest even speculatively, so excluding them shrinks the cut around every memory
+            access. They remain sinks like any other load.
+            "#,
+        false,
+    );
+
+    settings.add_bool(
+        "blade_exclude_non_speculative_loads",
+        r#"
+            Don't treat loads which can't execute speculatively as Blade sources.
+
+            Execution only goes down a mispredicted path past a conditional branch or trap, an
+            indirect jump, or a call, and stops at a speculation barrier. Loads which none of
+            these can reach without crossing a barrier, such as those in the straight-line code
+            at the beginning of a function or after a call fenced by `blade_fence_after_calls`,
+            always read the memory they are meant to, provided the function itself is never
+            entered speculatively. Only enable
+            this when every caller guarantees that, e.g. with a speculation barrier before the
+            call. They remain sinks like any other load.
+            "#,
+        false,
+    );
+
+    settings.add_bool(
+        "blade_exclude_global_accesses",
+        r#"
+            Don't treat the accesses to Wasm globals as Blade sources or sinks.
+
+            Globals live at fixed offsets from the VM context, so the guest can't steer these
+            accesses even speculatively. Producers mark them with the `global` Blade class.
+            The values stored into globals remain sinks, so a transient value never reaches a
+            global, and the loads from globals never produce one.
+            "#,
+        false,
+    );
+
+    settings.add_bool(
+        "blade_exclude_stack_accesses",
+        r#"
+            Don't treat the accesses to fixed stack slots as Blade sources or sinks.
+
+            This covers `stack_load` and `stack_store`, along with the loads and stores of the
+            address computed by a `stack_addr`, as the legalizer expands them. Their slot and
+            offset are immediates, so the guest can't steer them even speculatively. However,
+            a transient value stored into a slot whose address escapes is no longer tracked
+            to the loads which read it back, which is why this is off by default.
+            "#,
+        false,
+    );
+
+    settings.add_enum(
+        "blade_stack_pointer_accesses",
+        r#"
+            How Blade treats the memory accesses relative to the stack pointer which aren't
+            operands of their instructions, such as `x86_pop`, and the stack pointer itself, as
+            read by `ifcmp_sp`.
+
+            - strict: Pops are sources like any other load, and the stack pointer is an
+              implicit operand which Blade doesn't track, so its uses are diagnosed.
+            - sandbox: The guest can neither address the stack nor steer the stack pointer, so
+              pops aren't sources and the stack pointer is stable. A transient value pushed on
+              the stack is still a sink as a stored value, but isn't tracked to the pop which
+              reads it back.
+        "#,
+        vec!["strict", "sandbox"],
+    );
+
+    settings.add_bool(
+        "blade_slh_fake_bounds",
+        r#"
+            Use fake bounds for loads that `blade=slh` must protect but that have no bounds
+            attached.
+
+            The fake bounds cover the whole address space, so the resulting code has the cost of
+            SLH without any of its protection. This is only useful for measuring overhead; when
+            disabled, such loads are a compilation error. Use `blade=slh_with_fence_fallback` to
+            fence them instead.
+            "#,
+        false,
+    );
+
+    settings.add_bool(
+        "blade_fence_after_calls",
+        r#"
+            Place a speculation barrier immediately after every call returns.
+
+            This is independent of the `blade` setting and of the cut it computes, and provides
+            defense in depth against mispredicted returns and speculative execution past calls.
+            "#,
+        false,
+    );
+
     settings.build()
 }
@@ -173,6 +173,22 @@ pub trait CodeSink {
     }
 }
 
+/// Size in bytes of the speculation barrier requested by `Function::pre_lfence` and
+/// `Function::post_lfence`. Every barrier the `blade_barrier` setting can select has this size.
+pub const LFENCE_SIZE: CodeOffset = 3;
+
+/// Get the total size in bytes of the speculation barriers placed around `inst`.
+pub fn lfence_size(func: &Function, inst: Inst) -> CodeOffset {
+    let mut size = 0;
+    if func.pre_lfence[inst] {
+        size += LFENCE_SIZE;
+    }
+    if func.post_lfence[inst] {
+        size += LFENCE_SIZE;
+    }
+    size
+}
+
 /// Report a bad encoding error.
 #[cold]
 pub fn bad_encoding(func: &Function, inst: Inst) -> ! {
@@ -27,7 +27,7 @@
 //! block23:
 //! ```
 
-use crate::binemit::{CodeInfo, CodeOffset};
+use crate::binemit::{lfence_size, CodeInfo, CodeOffset, LFENCE_SIZE};
 use crate::cursor::{Cursor, FuncCursor};
 use crate::dominator_tree::DominatorTree;
 use crate::flowgraph::ControlFlowGraph;
@@ -76,6 +76,7 @@ pub fn relax_branches(
                 divert.apply(&cur.func.dfg[inst]);
                 let enc = cur.func.encodings[inst];
                 offset += encinfo.byte_size(enc, inst, &divert, &cur.func);
+                offset += lfence_size(&cur.func, inst);
             }
         }
     }
@@ -102,6 +103,16 @@ pub fn relax_branches(
 
                 let enc = cur.func.encodings[inst];
 
+                // A speculation barrier placed before the instruction moves the instruction itself.
+                if cur.func.pre_lfence[inst] {
+                    offset += LFENCE_SIZE;
+                }
+                let post_lfence_size = if cur.func.post_lfence[inst] {
+                    LFENCE_SIZE
+                } else {
+                    0
+                };
+
                 // See if this is a branch has a range and a destination, and if the target is in
                 // range.
                 if let Some(range) = encinfo.branch_range(enc) {
@@ -110,12 +121,14 @@ pub fn relax_branches(
                         if !range.contains(offset, dest_offset) {
                             offset +=
                                 relax_branch(&mut cur, &divert, offset, dest_offset, &encinfo, isa);
+                            offset += post_lfence_size;
                             continue;
                         }
                     }
                 }
 
                 offset += encinfo.byte_size(enc, inst, &divert, &cur.func);
+                offset += post_lfence_size;
             }
         }
     }
@@ -178,6 +191,11 @@ fn try_fold_redundant_jump(
         return false;
     }
 
+    // Bypassing a jump that carries a speculation barrier would drop the barrier.
+    if func.pre_lfence[second_inst] || func.post_lfence[second_inst] {
+        return false;
+    }
+
     // Now we need to fix up first_inst's block parameters to match second_inst's,
     // without changing the branch-specific arguments.
     //
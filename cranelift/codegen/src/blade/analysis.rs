@@ -0,0 +1,166 @@
+//! Blade as a pure analysis.
+//!
+//! `analyze` builds the Blade graph of a function and computes its minimal cut without touching
+//! the function, for tools which want to know what Blade makes of some code rather than harden
+//! it. Unlike `blade=analyze`, which runs the whole pass on a copy of the function, nothing is
+//! cloned, no ISA is needed, and the result describes the cut itself rather than the mitigations
+//! placed for it.
+
+use super::graph::{build_blade_graph_for_func, BladeNode};
+use super::strategy::{BladeStrategy, FenceStrategy};
+use super::{after_def, BladeDiagnostic, BladePolicy, DefaultBladePolicy, FencePoint};
+use crate::ir::{Function, Inst, Value};
+use crate::settings::Flags;
+use crate::HashSet;
+use alloc::vec::Vec;
+
+/// An edge of the minimal cut of the Blade graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CutEdge {
+    /// The edge from the source node into a transient value, protected where the value is
+    /// defined.
+    Source(Value),
+    /// The flow of the value `from` into `to`, either a value computed from it or an instruction
+    /// leaking it.
+    Flow {
+        /// The value flowing out.
+        from: Value,
+        /// Where it flows into.
+        to: BladeNode,
+    },
+    /// The edge from an instruction leaking some of its operands into the sink node, protected
+    /// right before the instruction.
+    Sink(Inst),
+}
+
+/// What Blade makes of a function.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BladeAnalysis {
+    /// The transient values, produced by loads or declared transient, in value order.
+    pub sources: Vec<Value>,
+    /// The instructions which leak some of their operands, in instruction order.
+    pub sinks: Vec<Inst>,
+    /// The edges of the minimal cut separating the sources from the sinks.
+    pub cut: Vec<CutEdge>,
+    /// The total weight of `cut`.
+    pub max_flow: u64,
+    /// Where speculation barriers protect `cut`, in the order of its edges and without
+    /// duplicates.
+    pub suggested_fences: Vec<FencePoint>,
+    /// Every instruction the Blade graph doesn't model exactly.
+    pub diagnostics: Vec<BladeDiagnostic>,
+}
+
+/// Analyze `func` as configured by `flags`, classifying its instructions with `policy`, or with
+/// the `DefaultBladePolicy` of the flags if there is none.
+///
+/// The graph is weighted and its cut protected as with `blade=lfence` or
+/// `blade=lfence_per_block`, whichever `flags` select, except that the control flow edges which
+/// `blade_split_edges` would split are never split: the barriers cutting them are suggested at
+/// the beginning of their destination instead. The graph is analyzed whatever its size, without
+/// falling back to fencing every block as `blade_max_edges_log2` asks the pass to.
+pub fn analyze(func: &Function, flags: &Flags, policy: Option<&dyn BladePolicy>) -> BladeAnalysis {
+    let default_policy;
+    let policy = match policy {
+        Some(policy) => policy,
+        None => {
+            default_policy = DefaultBladePolicy::from_flags(func, flags);
+            &default_policy
+        }
+    };
+    let strategy = FenceStrategy::from_flags(flags);
+    let blade_graph = build_blade_graph_for_func(
+        func,
+        policy,
+        strategy.edge_weights(flags),
+        flags.blade_coarsen(),
+    );
+
+    let mut sources: Vec<Value> = blade_graph.sources().collect();
+    sources.sort_unstable();
+    sources.dedup();
+    let mut sinks: Vec<Inst> = blade_graph.sinks().collect();
+    sinks.sort_unstable();
+    sinks.dedup();
+
+    let (cut_edges, max_flow) = blade_graph.min_cut_with_flow(flags.blade_cut_bias());
+    let cut: Vec<CutEdge> = cut_edges
+        .into_iter()
+        .map(|(from, to)| {
+            if from == blade_graph.source_node {
+                match blade_graph.bladenode(to) {
+                    Some(BladeNode::ValueDef(value)) => CutEdge::Source(value),
+                    other => panic!("unexpected successor of the source node: {:?}", other),
+                }
+            } else if to == blade_graph.sink_node {
+                match blade_graph.bladenode(from) {
+                    Some(BladeNode::Sink(inst)) => CutEdge::Sink(inst),
+                    other => panic!("unexpected predecessor of the sink node: {:?}", other),
+                }
+            } else {
+                match (blade_graph.bladenode(from), blade_graph.bladenode(to)) {
+                    (Some(BladeNode::ValueDef(from)), Some(to)) => CutEdge::Flow { from, to },
+                    other => panic!("unexpected cut edge {:?}", other),
+                }
+            }
+        })
+        .collect();
+
+    let mut seen = HashSet::new();
+    let suggested_fences = cut
+        .iter()
+        .map(|&edge| {
+            let point = match edge {
+                CutEdge::Source(value) => after_def(func, value),
+                CutEdge::Flow { from, to } => strategy.edge_point(func, from, to),
+                CutEdge::Sink(inst) => FencePoint::Before(inst),
+            };
+            strategy.place(func, point)
+        })
+        .filter(|&point| seen.insert(point))
+        .collect();
+
+    BladeAnalysis {
+        sources,
+        sinks,
+        cut,
+        max_flow,
+        suggested_fences,
+        diagnostics: blade_graph.diagnostics().to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::{types, InstBuilder, MemFlags};
+    use crate::settings::{self, Configurable};
+
+    #[test]
+    fn analyze_load_then_store() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let value = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let load = cur.func.dfg.value_def(value).unwrap_inst();
+        let store = cur.ins().store(MemFlags::new(), value, ptr, 8);
+        cur.ins().return_(&[]);
+
+        let analysis = analyze(&func, &settings::Flags::new(settings::builder()), None);
+        assert_eq!(analysis.sources, [value]);
+        assert_eq!(analysis.sinks, [load, store]);
+        assert_eq!(analysis.cut, [CutEdge::Source(value)]);
+        assert_eq!(analysis.max_flow, 1);
+        assert_eq!(analysis.suggested_fences, [FencePoint::After(load)]);
+        assert!(analysis.diagnostics.is_empty());
+
+        // Cutting the flow into the sink is as cheap, and nearer to it.
+        let mut flags = settings::builder();
+        flags.set("blade_cut_bias", "sinks").unwrap();
+        let analysis = analyze(&func, &settings::Flags::new(flags), None);
+        assert_eq!(analysis.suggested_fences, [FencePoint::Before(store)]);
+    }
+}
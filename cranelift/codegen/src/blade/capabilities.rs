@@ -0,0 +1,168 @@
+//! What a target can compile of Blade.
+//!
+//! Not every backend emits the speculation barriers and branchless selects the Blade mitigations
+//! are made of. `BladeCapabilities` describes what a `TargetIsa` emits, so that a combination of
+//! settings the target can't compile is rejected when the ISA is configured rather than when the
+//! first function is, and so that embedders can offer only the strategies which work.
+
+use crate::isa::TargetIsa;
+use crate::result::{CodegenError, CodegenResult};
+use crate::settings::Blade;
+use alloc::vec::Vec;
+
+/// Every `blade` strategy, in declaration order.
+const STRATEGIES: [Blade; 6] = [
+    Blade::None,
+    Blade::Lfence,
+    Blade::LfencePerBlock,
+    Blade::Slh,
+    Blade::SlhWithFenceFallback,
+    Blade::Analyze,
+];
+
+/// The Blade mitigations a target can emit, see `TargetIsa::blade_capabilities`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BladeCapabilities {
+    /// Whether the target emits the speculation barriers requested by `Function::pre_lfence` and
+    /// `Function::post_lfence`.
+    pub barriers: bool,
+    /// Whether the target lowers the conditional selects computing the masks of speculative load
+    /// hardening without branches.
+    pub slh: bool,
+}
+
+impl BladeCapabilities {
+    /// The capabilities of a target which emits every Blade mitigation.
+    pub const ALL: Self = Self {
+        barriers: true,
+        slh: true,
+    };
+
+    /// Can the target compile the `blade` strategy?
+    ///
+    /// Every strategy which modifies the function fences every basic block of the functions
+    /// exceeding `blade_max_edges_log2`, so it needs barriers, even speculative load hardening.
+    pub fn supports(&self, blade: Blade) -> bool {
+        match blade {
+            Blade::None | Blade::Analyze => true,
+            Blade::Lfence | Blade::LfencePerBlock => self.barriers,
+            Blade::Slh | Blade::SlhWithFenceFallback => self.barriers && self.slh,
+        }
+    }
+
+    /// Get every `blade` strategy the target can compile, in declaration order.
+    pub fn strategies(&self) -> Vec<Blade> {
+        STRATEGIES
+            .iter()
+            .copied()
+            .filter(|&blade| self.supports(blade))
+            .collect()
+    }
+}
+
+/// Check that `isa` can compile the Blade settings of its flags.
+///
+/// Compiling a function with settings which fail this check is an error, rather than leaving the
+/// function unprotected, so this lets the settings be rejected as soon as they are parsed.
+pub fn check_blade_settings(isa: &dyn TargetIsa) -> CodegenResult<()> {
+    let capabilities = isa.blade_capabilities();
+    let flags = isa.flags();
+    let blade = flags.blade();
+    if !capabilities.supports(blade) {
+        return Err(CodegenError::Unsupported(format!(
+            "blade={} is not supported on {}",
+            blade,
+            isa.name()
+        )));
+    }
+    // Analysis only fences calls on targets with barriers, see `do_blade`.
+    if flags.blade_fence_after_calls() && !capabilities.barriers && blade != Blade::Analyze {
+        return Err(CodegenError::Unsupported(format!(
+            "blade_fence_after_calls is not supported on {}, which has no speculation barriers",
+            isa.name()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strategies() {
+        assert_eq!(BladeCapabilities::ALL.strategies(), STRATEGIES);
+        assert_eq!(
+            BladeCapabilities::default().strategies(),
+            [Blade::None, Blade::Analyze]
+        );
+        let barriers = BladeCapabilities {
+            barriers: true,
+            slh: false,
+        };
+        assert_eq!(
+            barriers.strategies(),
+            [
+                Blade::None,
+                Blade::Lfence,
+                Blade::LfencePerBlock,
+                Blade::Analyze
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "riscv")]
+    fn check_unsupported_target() {
+        use crate::isa;
+        use crate::settings::{self, Configurable};
+        use core::str::FromStr;
+        use target_lexicon::Triple;
+
+        let check = |settings: &[(&str, &str)]| {
+            let mut flags = settings::builder();
+            for &(name, value) in settings {
+                flags.set(name, value).unwrap();
+            }
+            let isa = isa::lookup(Triple::from_str("riscv64").unwrap())
+                .unwrap()
+                .finish(settings::Flags::new(flags));
+            check_blade_settings(&*isa)
+        };
+        assert!(check(&[]).is_ok());
+        assert!(check(&[("blade", "analyze")]).is_ok());
+        assert!(check(&[("blade", "lfence")]).is_err());
+        assert!(check(&[("blade", "slh")]).is_err());
+        assert!(check(&[("blade_fence_after_calls", "true")]).is_err());
+        assert!(check(&[("blade", "analyze"), ("blade_fence_after_calls", "true")]).is_ok());
+    }
+
+    #[test]
+    #[cfg(all(feature = "x86", feature = "x64"))]
+    fn check_barriers_only_target() {
+        use crate::isa;
+        use crate::settings::{self, Configurable};
+        use core::str::FromStr;
+        use target_lexicon::Triple;
+
+        let check = |blade: &str| {
+            let mut flags = settings::builder();
+            flags.set("blade", blade).unwrap();
+            let mut isa = isa::lookup(Triple::from_str("x86_64").unwrap()).unwrap();
+            isa.enable("use_new_backend").unwrap();
+            let isa = isa.finish(settings::Flags::new(flags));
+            assert_eq!(
+                isa.blade_capabilities(),
+                BladeCapabilities {
+                    barriers: true,
+                    slh: false,
+                }
+            );
+            check_blade_settings(&*isa)
+        };
+        assert!(check("lfence").is_ok());
+        assert!(check("lfence_per_block").is_ok());
+        assert!(check("slh").is_err());
+        assert!(check("slh_with_fence_fallback").is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Reusing the minimal cuts of isomorphic Blade graphs.
+//!
+//! Wasm modules often contain many near-identical small functions, such as thunks and accessors,
+//! whose Blade graphs are the same up to the numbering of their nodes and the order of their
+//! edges. A `BladeCutCache` remembers the cut of every graph it has seen, keyed on a canonical
+//! form of the graph, so that the maximum flow of each is only computed once.
+//!
+//! The canonical form numbers the nodes by the classes of a color refinement, so that isomorphic
+//! graphs usually get the same one. Two graphs with the same form are always isomorphic, since it
+//! lists every edge. The cuts picked by the solver are the ones closest to the source or sink
+//! node, which are unique, so a reused cut is exactly the one it would have computed.
+
+use super::maxflow::{FlowGraph, Node};
+use crate::settings::BladeCutBias;
+use crate::{HashMap, HashSet};
+use alloc::vec::Vec;
+
+/// The structure of a Blade graph which its cut depends on, with canonical node numbers.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct CutKey {
+    num_nodes: usize,
+    /// The edges and their capacities, sorted.
+    edges: Vec<(Node, Node, u32)>,
+    source: Node,
+    sink: Node,
+    bias: BladeCutBias,
+}
+
+/// A cut and the value of the maximum flow through it.
+type Cut = (Vec<(Node, Node)>, u64);
+
+/// The most rounds of color refinement run to number the nodes of a graph. Refinement usually
+/// settles in a few rounds; past this, the remaining ties are broken by the node numbers.
+const MAX_REFINEMENT_ROUNDS: usize = 8;
+
+/// A cache of the minimal cuts of the Blade graphs compiled so far.
+///
+/// Reuse the same cache for all the functions of a module, by keeping it in the `Context`
+/// compiling them.
+#[derive(Clone, Debug, Default)]
+pub struct BladeCutCache {
+    /// The cut of every graph, with canonical node numbers.
+    cuts: HashMap<CutKey, Cut>,
+    /// The total number of edges of the graphs in `cuts`.
+    edges: usize,
+    hits: usize,
+}
+
+impl BladeCutCache {
+    /// The most edges of all the cached graphs together. Graphs are no longer added once this is
+    /// reached, to bound the memory held by the cache.
+    pub const MAX_EDGES: usize = 1 << 20;
+
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of graphs whose cut is cached.
+    pub fn len(&self) -> usize {
+        self.cuts.len()
+    }
+
+    /// Is the cache empty?
+    pub fn is_empty(&self) -> bool {
+        self.cuts.is_empty()
+    }
+
+    /// Get the number of cuts reused from the cache so far.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Get the cut of `graph` between `source` and `sink` with `bias`, computing it with `solve`
+    /// unless an isomorphic graph was seen before. Returns whether the cut was reused.
+    pub(super) fn get_or_solve(
+        &mut self,
+        graph: &FlowGraph,
+        source: Node,
+        sink: Node,
+        bias: BladeCutBias,
+        solve: impl FnOnce() -> Cut,
+    ) -> (Cut, bool) {
+        let order = canonical_order(graph, source, sink);
+        let mut canonical = vec![0; order.len()];
+        for (index, &node) in order.iter().enumerate() {
+            canonical[node] = index;
+        }
+        let mut edges: Vec<_> = graph
+            .edges()
+            .map(|(from, to, capacity)| (canonical[from], canonical[to], capacity))
+            .collect();
+        edges.sort_unstable();
+        let key = CutKey {
+            num_nodes: order.len(),
+            edges,
+            source: canonical[source],
+            sink: canonical[sink],
+            bias,
+        };
+        if let Some((cut, flow)) = self.cuts.get(&key) {
+            self.hits += 1;
+            // List the cut edges in the order the solver would, which follows the order in which
+            // they were added to `graph`.
+            let cut: HashSet<_> = cut
+                .iter()
+                .map(|&(from, to)| (order[from], order[to]))
+                .collect();
+            let mut cut: Vec<_> = graph
+                .edges()
+                .map(|(from, to, _)| (from, to))
+                .filter(|edge| cut.contains(edge))
+                .collect();
+            cut.sort_by_key(|&(from, _)| from);
+            return ((cut, *flow), true);
+        }
+        let (cut, flow) = solve();
+        if self.edges + key.edges.len() <= Self::MAX_EDGES {
+            self.edges += key.edges.len();
+            let canonical_cut = cut
+                .iter()
+                .map(|&(from, to)| (canonical[from], canonical[to]))
+                .collect();
+            self.cuts.insert(key, (canonical_cut, flow));
+        }
+        ((cut, flow), false)
+    }
+}
+
+/// Number the nodes of `graph` so that isomorphic graphs usually get the same numbers, returning
+/// the nodes in their new order.
+///
+/// The nodes are colored by iterated refinement: every round splits the nodes of a color by the
+/// capacities and colors of their edges. The source and sink nodes start with colors of their
+/// own. The nodes are then ordered by color, and by their original numbers within a color.
+fn canonical_order(graph: &FlowGraph, source: Node, sink: Node) -> Vec<Node> {
+    let num_nodes = graph.num_nodes();
+    let mut colors: Vec<usize> = (0..num_nodes)
+        .map(|node| usize::from(node == source) + 2 * usize::from(node == sink))
+        .collect();
+    let mut num_colors = 0;
+    for _ in 0..MAX_REFINEMENT_ROUNDS {
+        // The color of every node, then the capacities and colors of its successors and
+        // predecessors.
+        let mut signatures: Vec<(usize, Vec<(u32, usize)>, Vec<(u32, usize)>)> = colors
+            .iter()
+            .map(|&color| (color, Vec::new(), Vec::new()))
+            .collect();
+        for (from, to, capacity) in graph.edges() {
+            signatures[from].1.push((capacity, colors[to]));
+            signatures[to].2.push((capacity, colors[from]));
+        }
+        for signature in &mut signatures {
+            signature.1.sort_unstable();
+            signature.2.sort_unstable();
+        }
+        let mut distinct: Vec<_> = signatures.iter().collect();
+        distinct.sort_unstable();
+        distinct.dedup();
+        // A signature starts with the previous color, so this never merges two colors.
+        for (color, signature) in colors.iter_mut().zip(&signatures) {
+            *color = distinct.binary_search(&signature).unwrap();
+        }
+        if distinct.len() == num_colors {
+            break;
+        }
+        num_colors = distinct.len();
+    }
+    let mut order: Vec<Node> = (0..num_nodes).collect();
+    order.sort_by_key(|&node| (colors[node], node));
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a diamond from `s` to `t` through `a` and `b`, adding the nodes and edges in the
+    /// given orders. Its cut is the edges from `a` to `t` and from `s` to `b`.
+    fn diamond(nodes: [usize; 4], edges: [usize; 4]) -> (FlowGraph, [Node; 4]) {
+        let mut g = FlowGraph::new();
+        let mut ids = [0; 4];
+        for &node in &nodes {
+            ids[node] = g.add_node();
+        }
+        let [s, a, b, t] = ids;
+        let all = [(s, a, 3), (a, t, 1), (s, b, 2), (b, t, 2)];
+        for &edge in &edges {
+            let (from, to, capacity) = all[edge];
+            g.add_edge(from, to, capacity);
+        }
+        (g, ids)
+    }
+
+    fn solve(g: &FlowGraph, s: Node, t: Node) -> impl FnOnce() -> Cut {
+        let mut g = g.clone();
+        move || {
+            let flow = g.clone().max_flow(s, t);
+            (g.min_cut(s, t), flow)
+        }
+    }
+
+    #[test]
+    fn isomorphic_graphs() {
+        let mut cache = BladeCutCache::new();
+        let bias = BladeCutBias::Sources;
+
+        let (g, [s, a, b, t]) = diamond([0, 1, 2, 3], [0, 1, 2, 3]);
+        let ((cut, flow), reused) = cache.get_or_solve(&g, s, t, bias, solve(&g, s, t));
+        assert!(!reused);
+        assert_eq!(cut, vec![(s, b), (a, t)]);
+        assert_eq!(flow, 3);
+
+        // The same graph with its nodes numbered and its edges added in another order.
+        let (g, [s, a, b, t]) = diamond([3, 2, 0, 1], [3, 1, 2, 0]);
+        let ((cut, flow), reused) = cache.get_or_solve(&g, s, t, bias, || unreachable!());
+        assert!(reused);
+        assert_eq!(cut, solve(&g, s, t)().0);
+        assert!(cut.contains(&(a, t)) && cut.contains(&(s, b)));
+        assert_eq!(flow, 3);
+
+        // Other capacities make another graph.
+        let mut g = FlowGraph::new();
+        let s = g.add_node();
+        let a = g.add_node();
+        let t = g.add_node();
+        g.add_edge(s, a, 1);
+        g.add_edge(a, t, 2);
+        let (_, reused) = cache.get_or_solve(&g, s, t, bias, solve(&g, s, t));
+        assert!(!reused);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.hits(), 1);
+    }
+}
@@ -0,0 +1,156 @@
+//! A def-use graph for the values of a function.
+
+use crate::entity::SecondaryMap;
+use crate::ir::instructions::BranchInfo;
+use crate::ir::{Function, Inst, Value};
+use alloc::vec::Vec;
+
+/// A use of a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueUse {
+    /// The value is used as an operand of this instruction.
+    Inst(Inst),
+    /// The value is passed as a block argument and becomes this block parameter.
+    Value(Value),
+}
+
+/// The uses of every value in a function.
+///
+/// Block arguments on branches are connected directly to the block parameters they flow into, so
+/// the graph follows dataflow across control flow edges.
+pub struct DefUseGraph {
+    map: SecondaryMap<Value, Vec<ValueUse>>,
+}
+
+impl DefUseGraph {
+    /// Build the def-use graph for `func`.
+    ///
+    /// Block parameters are connected by walking the branches of the function rather than the
+    /// predecessors recorded in a `ControlFlowGraph`, so every kind of branch contributes its
+    /// edges, and the result doesn't depend on the CFG being up to date.
+    pub fn for_function(func: &Function) -> Self {
+        let mut map: SecondaryMap<Value, Vec<ValueUse>> =
+            SecondaryMap::with_capacity(func.dfg.num_values());
+
+        for block in func.layout.blocks() {
+            for inst in func.layout.block_insts(block) {
+                // Instruction operands.
+                for &arg in func.dfg.inst_args(inst) {
+                    let arg = func.dfg.resolve_aliases(arg);
+                    map[arg].push(ValueUse::Inst(inst));
+                }
+
+                // Block arguments passed to the parameters of the destination.
+                match func.dfg.analyze_branch(inst) {
+                    BranchInfo::SingleDest(dest, args) => {
+                        let params = func.dfg.block_params(dest);
+                        debug_assert_eq!(args.len(), params.len());
+                        for (&arg, &param) in args.iter().zip(params) {
+                            let arg = func.dfg.resolve_aliases(arg);
+                            map[arg].push(ValueUse::Value(param));
+                        }
+                    }
+                    // Neither the jump table entries nor the default destination of a
+                    // `br_table` can take block arguments (the verifier rejects table targets
+                    // with parameters), so there is no dataflow into block parameters here.
+                    BranchInfo::Table(..) | BranchInfo::NotABranch => {}
+                }
+            }
+        }
+
+        Self { map }
+    }
+
+    /// Get the uses of `value`, which must not be an alias.
+    pub fn uses(&self, value: Value) -> &[ValueUse] {
+        &self.map[value]
+    }
+
+    /// Iterate over all values in the graph along with their uses.
+    pub fn iter(&self) -> impl Iterator<Item = (Value, &[ValueUse])> {
+        self.map
+            .iter()
+            .map(|(value, uses)| (value, uses.as_slice()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::{types, InstBuilder, JumpTableData};
+
+    #[test]
+    fn br_table() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let index = func.dfg.append_block_param(block0, types::I32);
+        let block1 = func.dfg.make_block();
+        let block2 = func.dfg.make_block();
+        let param = func.dfg.append_block_param(block2, types::I32);
+
+        let mut jt_data = JumpTableData::new();
+        jt_data.push_entry(block1);
+        jt_data.push_entry(block2);
+        let jt = func.create_jump_table(jt_data);
+
+        let br_table;
+        let jump;
+        let ret;
+        let imm;
+        {
+            let mut cur = FuncCursor::new(&mut func);
+
+            cur.insert_block(block0);
+            br_table = cur.ins().br_table(index, block1, jt);
+
+            cur.insert_block(block1);
+            imm = cur.ins().iconst(types::I32, 0);
+            jump = cur.ins().jump(block2, &[imm]);
+
+            cur.insert_block(block2);
+            ret = cur.ins().return_(&[param]);
+        }
+
+        // `block2` has parameters and is a jump table target, which the verifier rejects, but
+        // building the def-use graph must still succeed and follow the `jump`.
+        let def_use = DefUseGraph::for_function(&func);
+
+        let uses_of = |value| {
+            def_use
+                .iter()
+                .find(|&(v, _)| v == value)
+                .map_or(&[][..], |(_, uses)| uses)
+        };
+        assert_eq!(uses_of(index), &[ValueUse::Inst(br_table)]);
+        assert_eq!(
+            uses_of(imm),
+            &[ValueUse::Inst(jump), ValueUse::Value(param)]
+        );
+        assert_eq!(uses_of(param), &[ValueUse::Inst(ret)]);
+    }
+
+    #[test]
+    fn fallthrough() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let arg = func.dfg.append_block_param(block0, types::I32);
+        let block1 = func.dfg.make_block();
+        let param = func.dfg.append_block_param(block1, types::I32);
+
+        let fallthrough;
+        {
+            let mut cur = FuncCursor::new(&mut func);
+
+            cur.insert_block(block0);
+            fallthrough = cur.ins().fallthrough(block1, &[arg]);
+
+            cur.insert_block(block1);
+            cur.ins().return_(&[param]);
+        }
+
+        let def_use = DefUseGraph::for_function(&func);
+        let (_, uses) = def_use.iter().find(|&(v, _)| v == arg).unwrap();
+        assert_eq!(uses, &[ValueUse::Inst(fallthrough), ValueUse::Value(param)]);
+    }
+}
@@ -0,0 +1,1795 @@
+//! The Blade graph.
+//!
+//! The Blade graph has a node for every value that may carry transient data, a node for every
+//! instruction that may leak a value it consumes, and two distinguished nodes: a source node with
+//! an edge to every value produced by a source instruction, and a sink node with an edge from
+//! every leaking instruction. Which instructions are sources and sinks is up to a `BladePolicy`,
+//! by default loads and the memory accesses and branches which leak their operands. A minimal cut
+//! between the source and the sink tells us the cheapest set of places where protecting the
+//! dataflow prevents every transient value from reaching a sink.
+//!
+//! The capacity of every edge is the weight of its class, scaled by the count of the block where
+//! cutting it places a barrier in `Function::blade_block_counts`. With a profile, the cut then
+//! minimizes the number of barriers executed rather than the number of barriers in the code.
+//! With `EdgeWeights::prioritize_nested`, it is also scaled down with the number of speculation
+//! points dominating that block, so that the cut prefers deeply nested speculative regions.
+//!
+//! The CPU flags computed by comparisons are values like any other, each with a node of its own:
+//! they depend on the compared values, a `brif` testing them leaks them, and a `trueif` or
+//! `selectif` turning them back into a value depends on them. A transient condition can't reach
+//! a branch through the flags, or through a boolean materialized from them, without crossing the
+//! cut.
+//!
+//! The arms of a `select`, `selectif` or `bitselect` each keep an edge of their own into its
+//! result, and an edge from an arm is cut right after the arm is computed rather than before the
+//! select. In branchless code, where both arms are always computed, the cut can then protect only
+//! the arm carrying transient data instead of everything upstream of the select.
+//!
+//! Loads and stores of stack slots whose address doesn't escape are neither sources nor sinks;
+//! the values stored into such a slot flow directly into the values loaded from it.
+//!
+//! The taints declared in `Function::blade_taint` override all of this: a value declared a source
+//! gets an edge from the source node whatever defines it, and a stable value gets no edge into it
+//! at all.
+
+use super::cut_cache::BladeCutCache;
+use super::def_use::{DefUseGraph, ValueUse};
+use super::local_slots::LocalSlots;
+use super::maxflow::{FlowGraph, Node};
+use super::policy::{BladePolicy, InstClassification};
+use super::speculation::nesting_depths;
+use super::{BladeDiagnostic, BladeDiagnosticKind};
+use crate::entity::{EntitySet, SecondaryMap};
+use crate::ir::{BladeTaint, Block, Function, Inst, Opcode, Value, ValueDef};
+use crate::packed_option::PackedOption;
+use crate::settings::BladeCutBias;
+use crate::HashSet;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+/// A node of the Blade graph, other than the source and sink nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BladeNode {
+    /// The definition of a value, either as an instruction result or as a block parameter.
+    ValueDef(Value),
+    /// An instruction which leaks some of its operands.
+    Sink(Inst),
+}
+
+/// The Blade graph of a function.
+pub struct BladeGraph {
+    /// The underlying flow graph.
+    graph: FlowGraph,
+    /// The node which all transient values flow from.
+    pub source_node: Node,
+    /// The node which all leaking instructions flow into.
+    pub sink_node: Node,
+    /// The Blade node represented by every flow graph node, or `None` for the source and sink
+    /// nodes.
+    bladenodes: Vec<Option<BladeNode>>,
+    /// Instructions which the graph skips or only approximates, in layout order.
+    diagnostics: Vec<BladeDiagnostic>,
+}
+
+impl BladeGraph {
+    /// Get the Blade node represented by `node`, or `None` for the source and sink nodes.
+    pub fn bladenode(&self, node: Node) -> Option<BladeNode> {
+        self.bladenodes[node]
+    }
+
+    /// Get the instructions which the graph skips or only approximates.
+    pub fn diagnostics(&self) -> &[BladeDiagnostic] {
+        &self.diagnostics
+    }
+
+    /// Get the number of nodes in the graph, besides the source and sink nodes.
+    pub fn num_nodes(&self) -> usize {
+        self.graph.num_nodes() - 2
+    }
+
+    /// Get the number of edges in the graph.
+    pub fn num_edges(&self) -> usize {
+        self.graph.num_edges()
+    }
+
+    /// Get the number of transient values, i.e. the edges from the source node.
+    pub fn num_sources(&self) -> usize {
+        self.graph.successors(self.source_node).count()
+    }
+
+    /// Get the number of instructions which leak some of their operands, i.e. the edges into
+    /// the sink node.
+    pub fn num_sinks(&self) -> usize {
+        self.graph.predecessors(self.sink_node).count()
+    }
+
+    /// Get the transient values, i.e. the successors of the source node.
+    pub fn sources(&self) -> impl Iterator<Item = Value> + '_ {
+        self.graph
+            .successors(self.source_node)
+            .filter_map(move |node| match self.bladenode(node) {
+                Some(BladeNode::ValueDef(value)) => Some(value),
+                _ => None,
+            })
+    }
+
+    /// Get the instructions which leak some of their operands, i.e. the predecessors of the sink
+    /// node.
+    pub fn sinks(&self) -> impl Iterator<Item = Inst> + '_ {
+        self.graph
+            .predecessors(self.sink_node)
+            .filter_map(move |node| match self.bladenode(node) {
+                Some(BladeNode::Sink(inst)) => Some(inst),
+                _ => None,
+            })
+    }
+
+    /// Compute a minimal set of edges whose removal disconnects the sink node from the source
+    /// node, picking the one closest to the side `bias` asks for among those of equal cost.
+    #[cfg(any(test, feature = "testing_hooks"))]
+    pub fn min_cut(&self, bias: BladeCutBias) -> Vec<(Node, Node)> {
+        self.min_cut_with_flow(bias).0
+    }
+
+    /// Compute a minimal cut like `min_cut`, along with the value of the maximum flow, which is
+    /// the total capacity of the cut edges.
+    pub fn min_cut_with_flow(&self, bias: BladeCutBias) -> (Vec<(Node, Node)>, u64) {
+        // The solver consumes the capacities, so run it on a copy to keep the graph usable.
+        let mut graph = self.graph.clone();
+        let flow = graph.max_flow(self.source_node, self.sink_node);
+        // The flow is already maximal, so this only computes the cut of the residual graph.
+        let cut = match bias {
+            BladeCutBias::Sources => graph.min_cut(self.source_node, self.sink_node),
+            BladeCutBias::Sinks => graph.min_cut_near_sink(self.source_node, self.sink_node),
+        };
+        (cut, flow)
+    }
+
+    /// Compute a minimal cut like `min_cut_with_flow`, reusing the cut of an isomorphic graph from
+    /// `cache` if it has one. Also returns whether the cut was reused.
+    pub fn min_cut_cached(
+        &self,
+        bias: BladeCutBias,
+        cache: &mut BladeCutCache,
+    ) -> (Vec<(Node, Node)>, u64, bool) {
+        let ((cut, flow), reused) =
+            cache.get_or_solve(&self.graph, self.source_node, self.sink_node, bias, || {
+                self.min_cut_with_flow(bias)
+            });
+        (cut, flow, reused)
+    }
+
+    /// Get all the transient sources from which any of `nodes` is reachable.
+    ///
+    /// This is a single backward traversal shared by all of `nodes`, so every node of the graph
+    /// is visited at most once and every source is returned at most once.
+    pub fn ancestors_of_all(&self, nodes: impl IntoIterator<Item = Node>) -> Vec<Value> {
+        let mut ancestors = Vec::new();
+        let mut visited = vec![false; self.graph.num_nodes()];
+        let mut stack: Vec<Node> = nodes.into_iter().collect();
+        while let Some(node) = stack.pop() {
+            if core::mem::replace(&mut visited[node], true) {
+                continue;
+            }
+            for pred in self.graph.predecessors(node) {
+                if pred == self.source_node {
+                    if let Some(BladeNode::ValueDef(value)) = self.bladenode(node) {
+                        ancestors.push(value);
+                    }
+                } else if !visited[pred] {
+                    stack.push(pred);
+                }
+            }
+        }
+        ancestors
+    }
+}
+
+/// The capacities of the classes of edges of the Blade graph, i.e. the costs of protecting the
+/// corresponding cut points.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EdgeWeights {
+    /// Edges from the source node to the values produced by loads.
+    pub source: u32,
+    /// Edges between two values, and from a value into an instruction leaking it.
+    pub dependency: u32,
+    /// Edges from instructions which leak their operands, other than branches, into the sink
+    /// node.
+    pub sink: u32,
+    /// Edges from branches which leak their operands into the sink node.
+    pub branch: u32,
+    /// Scale the capacity of every edge down with the depth of the speculative regions nested
+    /// around the cut point, as `blade_prioritize_nested_regions` asks.
+    pub prioritize_nested: bool,
+}
+
+impl Default for EdgeWeights {
+    fn default() -> Self {
+        Self {
+            source: 1,
+            dependency: 1,
+            sink: 1,
+            branch: 1,
+            prioritize_nested: false,
+        }
+    }
+}
+
+/// Incrementally builds a `BladeGraph`.
+struct BladeGraphBuilder {
+    graph: FlowGraph,
+    weights: EdgeWeights,
+    source_node: Node,
+    sink_node: Node,
+    /// The Blade node represented by every flow graph node created so far.
+    bladenodes: Vec<Option<BladeNode>>,
+    /// The node of every value definition which has one.
+    value_nodes: SecondaryMap<Value, Option<Node>>,
+    /// The node of every instruction which leaks some of its operands.
+    sink_nodes: SecondaryMap<Inst, Option<Node>>,
+    /// Edges added so far, so that we never add the same edge twice.
+    edges: HashSet<(Node, Node)>,
+    diagnostics: Vec<BladeDiagnostic>,
+    /// Values which share the node of another value, see `coarsen`.
+    merged: SecondaryMap<Value, PackedOption<Value>>,
+}
+
+impl BladeGraphBuilder {
+    fn new(
+        func: &Function,
+        weights: EdgeWeights,
+        merged: SecondaryMap<Value, PackedOption<Value>>,
+    ) -> Self {
+        let mut graph = FlowGraph::new();
+        let source_node = graph.add_node();
+        let sink_node = graph.add_node();
+        Self {
+            graph,
+            weights,
+            source_node,
+            sink_node,
+            bladenodes: vec![None, None],
+            value_nodes: SecondaryMap::with_capacity(func.dfg.num_values()),
+            sink_nodes: SecondaryMap::with_capacity(func.dfg.num_insts()),
+            edges: HashSet::new(),
+            diagnostics: Vec::new(),
+            merged,
+        }
+    }
+
+    /// Create a node for `bladenode`.
+    fn add_node(&mut self, bladenode: BladeNode) -> Node {
+        let node = self.graph.add_node();
+        debug_assert_eq!(node, self.bladenodes.len());
+        self.bladenodes.push(Some(bladenode));
+        node
+    }
+
+    /// Get the node for the definition of `value`.
+    fn value_node(&mut self, value: Value) -> Node {
+        let value = self.merged[value].expand().unwrap_or(value);
+        match self.value_nodes[value] {
+            Some(node) => node,
+            None => {
+                let node = self.add_node(BladeNode::ValueDef(value));
+                self.value_nodes[value] = Some(node);
+                node
+            }
+        }
+    }
+
+    /// Get the node for the leaking instruction `inst`.
+    fn sink_inst_node(&mut self, inst: Inst) -> Node {
+        match self.sink_nodes[inst] {
+            Some(node) => node,
+            None => {
+                let node = self.add_node(BladeNode::Sink(inst));
+                self.sink_nodes[inst] = Some(node);
+                node
+            }
+        }
+    }
+
+    /// Add an edge with the given capacity, unless it already exists or is a self-loop.
+    fn add_edge(&mut self, from: Node, to: Node, capacity: u32) {
+        if from != to && self.edges.insert((from, to)) {
+            self.graph.add_edge(from, to, capacity);
+        }
+    }
+
+    /// Mark `value` as a transient source, defined in a block executed `count` times.
+    fn add_source(&mut self, value: Value, count: u64) {
+        let node = self.value_node(value);
+        self.add_edge(self.source_node, node, scale(self.weights.source, count));
+    }
+
+    /// Mark `value` as leaked by `inst`, which is a branch if `is_branch`, in a block executed
+    /// `count` times.
+    ///
+    /// Cutting the edge from `value` into `inst` only protects that operand, like the flow of a
+    /// value into another, while cutting the edge from `inst` into the sink node protects all of
+    /// its operands at once.
+    fn add_sink(&mut self, value: Value, inst: Inst, is_branch: bool, count: u64) {
+        let weight = if is_branch {
+            self.weights.branch
+        } else {
+            self.weights.sink
+        };
+        let value_node = self.value_node(value);
+        let inst_node = self.sink_inst_node(inst);
+        self.add_edge(value_node, inst_node, scale(self.weights.dependency, count));
+        self.add_edge(inst_node, self.sink_node, scale(weight, count));
+    }
+
+    /// Record that `to` is computed from `from`, with `to` defined in a block executed `count`
+    /// times.
+    ///
+    /// The edge is weighted by the block where the default late placement cuts it, that of `to`
+    /// unless `from` is an arm of the select defining `to`.
+    fn add_dependency(&mut self, from: Value, to: Value, count: u64) {
+        let from_node = self.value_node(from);
+        let to_node = self.value_node(to);
+        self.add_edge(from_node, to_node, scale(self.weights.dependency, count));
+    }
+
+    fn finish(self) -> BladeGraph {
+        BladeGraph {
+            graph: self.graph,
+            source_node: self.source_node,
+            sink_node: self.sink_node,
+            bladenodes: self.bladenodes,
+            diagnostics: self.diagnostics,
+        }
+    }
+}
+
+/// Scale the capacity `weight` of an edge by the `count` of the block where it is cut.
+///
+/// Blocks without a count execute once as far as the cut is concerned.
+fn scale(weight: u32, count: u64) -> u32 {
+    u32::try_from(u64::from(weight).saturating_mul(count.max(1))).unwrap_or(u32::max_value())
+}
+
+/// Is `value` transient by itself, given whether it is defined by a source instruction?
+pub fn is_source_value(func: &Function, value: Value, defined_by_source: bool) -> bool {
+    match func.blade_taint[value] {
+        BladeTaint::Default => defined_by_source,
+        BladeTaint::Source => true,
+        BladeTaint::Stable => false,
+    }
+}
+
+/// Can `value` be computed from transient values?
+pub fn is_stable(func: &Function, value: Value) -> bool {
+    func.blade_taint[value] == BladeTaint::Stable
+}
+
+/// Classify every instruction of `func` with `policy`.
+///
+/// The accesses to stack slots that don't escape are neither sources nor sinks, and the sink
+/// operands have their aliases resolved.
+pub fn classify(
+    func: &Function,
+    policy: &dyn BladePolicy,
+    local_slots: &LocalSlots,
+) -> SecondaryMap<Inst, InstClassification> {
+    let mut classes = SecondaryMap::with_capacity(func.dfg.num_insts());
+    for block in func.layout.blocks() {
+        for inst in func.layout.block_insts(block) {
+            let mut class = policy.classify_inst(func, inst);
+            if local_slots.slot(inst).is_some() {
+                class.source = false;
+                class.sinks.clear();
+            }
+            for sink in &mut class.sinks {
+                *sink = func.dfg.resolve_aliases(*sink);
+            }
+            classes[inst] = class;
+        }
+    }
+    classes
+}
+
+/// Get the values which result number `index` of `inst` is computed from.
+///
+/// The results of instructions whose dependencies aren't known are assumed to depend on every
+/// operand, see `known_result_dependencies`.
+pub fn result_dependencies(
+    func: &Function,
+    inst: Inst,
+    index: usize,
+    classes: &SecondaryMap<Inst, InstClassification>,
+) -> Vec<Value> {
+    known_result_dependencies(func, inst, index, classes)
+        .unwrap_or_else(|| all_operands(func, inst))
+}
+
+/// Get the values which result number `index` of `inst` is computed from, unless `inst` has
+/// several results and we don't know which operands each of them depends on.
+///
+/// Legalization splits wide values which were just concatenated, and each half of such a split
+/// is one of the concatenated values, unless the concatenation is itself a source. The results
+/// of the other multi-result instructions we know of each depend on every operand.
+fn known_result_dependencies(
+    func: &Function,
+    inst: Inst,
+    index: usize,
+    classes: &SecondaryMap<Inst, InstClassification>,
+) -> Option<Vec<Value>> {
+    let args = func.dfg.inst_args(inst);
+    match func.dfg[inst].opcode() {
+        Opcode::Isplit | Opcode::Vsplit => {
+            if let ValueDef::Result(concat, _) = func.dfg.value_def(args[0]) {
+                match func.dfg[concat].opcode() {
+                    Opcode::Iconcat | Opcode::Vconcat if !classes[concat].source => {
+                        let half = func.dfg.inst_args(concat)[index];
+                        return Some(vec![func.dfg.resolve_aliases(half)]);
+                    }
+                    _ => {}
+                }
+            }
+            Some(all_operands(func, inst))
+        }
+        // The sum or difference, and the carry or borrow out, are computed from both operands and
+        // any carry or borrow in.
+        Opcode::IaddCout
+        | Opcode::IaddIfcout
+        | Opcode::IaddCarry
+        | Opcode::IaddIfcarry
+        | Opcode::IsubBout
+        | Opcode::IsubIfbout
+        | Opcode::IsubBorrow
+        | Opcode::IsubIfborrow => Some(all_operands(func, inst)),
+        // Both halves of a double-width product, and the quotient and the remainder of a
+        // double-width division, are computed from every operand.
+        #[cfg(feature = "x86")]
+        Opcode::X86Umulx
+        | Opcode::X86Smulx
+        | Opcode::X86Udivmodx
+        | Opcode::X86Sdivmodx
+        // The index of the bit found, and the flags telling whether there was one, are both
+        // computed from the single operand.
+        | Opcode::X86Bsr
+        | Opcode::X86Bsf => Some(all_operands(func, inst)),
+        _ if args.is_empty() || func.dfg.inst_results(inst).len() <= 1 => {
+            Some(all_operands(func, inst))
+        }
+        _ => None,
+    }
+}
+
+/// Get every operand of `inst`, with their aliases resolved.
+fn all_operands(func: &Function, inst: Inst) -> Vec<Value> {
+    func.dfg
+        .inst_args(inst)
+        .iter()
+        .map(|&arg| func.dfg.resolve_aliases(arg))
+        .collect()
+}
+
+/// Get the arms of `inst` if it is a constant-time select, that is the values it chooses between.
+pub(super) fn select_arms(func: &Function, inst: Inst) -> &[Value] {
+    match func.dfg[inst].opcode() {
+        Opcode::Select | Opcode::Selectif | Opcode::Bitselect => &func.dfg.inst_args(inst)[1..],
+        _ => &[],
+    }
+}
+
+/// Is `from` an arm of the select defining `to`?
+pub(super) fn is_select_arm(func: &Function, from: Value, to: Value) -> bool {
+    match func.dfg.value_def(to) {
+        ValueDef::Result(inst, _) => select_arms(func, inst)
+            .iter()
+            .any(|&arm| func.dfg.resolve_aliases(arm) == from),
+        ValueDef::Param(..) => false,
+    }
+}
+
+/// Get the block where `value` is defined.
+fn def_block(func: &Function, value: Value) -> Option<Block> {
+    match func.dfg.value_def(value) {
+        ValueDef::Result(inst, _) => func.layout.inst_block(inst),
+        ValueDef::Param(block, _) => Some(block),
+    }
+}
+
+/// Find the chains of values which can share a single node of the Blade graph.
+///
+/// CPU flags always keep a node of their own, so that the cut sees every condition computed
+/// from a transient value, as well as every instruction testing it. So do the arms of selects,
+/// so that the cut can protect one arm without the other.
+///
+/// A value which is neither transient by itself nor a sink operand, and whose only use computes
+/// a single other value which isn't declared stable, can only leak through that value. Giving
+/// both the node of the latter keeps every cut valid: an edge into the shared node is cut by a
+/// barrier before the definition of the last value of the chain, which every path through the
+/// chain reaches. The barrier may be further from the cut point than necessary, but the graph
+/// gets much smaller.
+///
+/// Returns a map from every merged value to the value whose node it shares.
+fn coarsen(
+    func: &Function,
+    def_use: &DefUseGraph,
+    policy: &dyn BladePolicy,
+    classes: &SecondaryMap<Inst, InstClassification>,
+) -> SecondaryMap<Value, PackedOption<Value>> {
+    let mut unmergeable = EntitySet::with_capacity(func.dfg.num_values());
+    for block in func.layout.blocks() {
+        for &param in func.dfg.block_params(block) {
+            if is_source_value(func, param, policy.is_source_param(func, param)) {
+                unmergeable.insert(param);
+            }
+        }
+        for inst in func.layout.block_insts(block) {
+            for &result in func.dfg.inst_results(inst) {
+                if is_source_value(func, result, classes[inst].source)
+                    || func.dfg.value_type(result).is_flags()
+                {
+                    unmergeable.insert(result);
+                }
+            }
+            for &value in &classes[inst].sinks {
+                unmergeable.insert(value);
+            }
+            for &arm in select_arms(func, inst) {
+                unmergeable.insert(func.dfg.resolve_aliases(arm));
+            }
+        }
+    }
+
+    // The single value each mergeable value flows into.
+    let mut mergeable = Vec::new();
+    let mut next = SecondaryMap::<Value, PackedOption<Value>>::with_capacity(func.dfg.num_values());
+    for (value, uses) in def_use.iter() {
+        if unmergeable.contains(value) {
+            continue;
+        }
+        let successor = match *uses {
+            [ValueUse::Inst(inst)] => match *func.dfg.inst_results(inst) {
+                [result] => result,
+                _ => continue,
+            },
+            [ValueUse::Value(param)] => param,
+            _ => continue,
+        };
+        if is_stable(func, successor) {
+            continue;
+        }
+        mergeable.push(value);
+        next[value] = successor.into();
+    }
+
+    // Follow every chain to its end, compressing the paths as we go. A chain may loop back on
+    // itself through block parameters, in which case it ends where it closes the loop. Each walk
+    // marks the values it visits with its own number, starting from one, to detect this.
+    let mut merged =
+        SecondaryMap::<Value, PackedOption<Value>>::with_capacity(func.dfg.num_values());
+    let mut visited_by = SecondaryMap::<Value, usize>::with_capacity(func.dfg.num_values());
+    let mut path = Vec::new();
+    for (walk, &start) in mergeable.iter().enumerate() {
+        if merged[start].is_some() {
+            continue;
+        }
+        let mut value = start;
+        let end = loop {
+            if let Some(end) = merged[value].expand() {
+                break end;
+            }
+            match next[value].expand() {
+                Some(successor) if visited_by[value] != walk + 1 => {
+                    visited_by[value] = walk + 1;
+                    path.push(value);
+                    value = successor;
+                }
+                _ => break value,
+            }
+        };
+        for value in path.drain(..) {
+            if value != end {
+                merged[value] = end.into();
+            }
+        }
+    }
+    merged
+}
+
+/// The number of depths of nested speculative regions which `EdgeWeights::prioritize_nested`
+/// tells apart. Deeper regions cost as much as the deepest one.
+const NESTING_LEVELS: u64 = 4;
+
+/// Build the Blade graph for `func`, with edge capacities given by `weights`.
+///
+/// `policy` decides which instructions introduce and leak transient values. The taints declared
+/// in `func.blade_taint` take precedence over its decisions.
+///
+/// With `coarsen`, chains of values which can only leak through their last value share a single
+/// node, which makes the graph much smaller at the cost of slightly worse barrier placement.
+pub fn build_blade_graph_for_func(
+    func: &Function,
+    policy: &dyn BladePolicy,
+    weights: EdgeWeights,
+    coarsen_chains: bool,
+) -> BladeGraph {
+    let def_use = DefUseGraph::for_function(func);
+    let local_slots = LocalSlots::for_function(func, &def_use);
+    let classes = classify(func, policy, &local_slots);
+    let merged = if coarsen_chains {
+        coarsen(func, &def_use, policy, &classes)
+    } else {
+        SecondaryMap::new()
+    };
+    let mut builder = BladeGraphBuilder::new(func, weights, merged);
+
+    let depths = if weights.prioritize_nested {
+        nesting_depths(func)
+    } else {
+        SecondaryMap::new()
+    };
+    let count = |block: Block| {
+        if weights.prioritize_nested {
+            let depth = u64::from(depths[block]).min(NESTING_LEVELS - 1);
+            func.blade_block_counts[block]
+                .max(1)
+                .saturating_mul(NESTING_LEVELS - depth)
+        } else {
+            func.blade_block_counts[block]
+        }
+    };
+    for block in func.layout.blocks() {
+        for &param in func.dfg.block_params(block) {
+            if is_source_value(func, param, policy.is_source_param(func, param)) {
+                builder.add_source(param, count(block));
+            }
+        }
+
+        for inst in func.layout.block_insts(block) {
+            let class = &classes[inst];
+            for &result in func.dfg.inst_results(inst) {
+                if is_source_value(func, result, class.source) {
+                    builder.add_source(result, count(block));
+                }
+            }
+
+            if let Some(slot) = local_slots.slot(inst) {
+                // The accesses to a slot that doesn't escape are neither sources nor sinks, but a
+                // load from it produces whatever was stored into it.
+                for &result in func.dfg.inst_results(inst) {
+                    if is_stable(func, result) {
+                        continue;
+                    }
+                    for &value in local_slots.stored_into(slot) {
+                        builder.add_dependency(value, result, count(block));
+                    }
+                }
+            } else {
+                let is_branch = func.dfg[inst].opcode().is_branch();
+                for &value in &class.sinks {
+                    builder.add_sink(value, inst, is_branch, count(block));
+                }
+            }
+
+            for (index, &result) in func.dfg.inst_results(inst).iter().enumerate() {
+                if is_stable(func, result) {
+                    continue;
+                }
+                for value in result_dependencies(func, inst, index, &classes) {
+                    // The edge from an arm of a select is cut where the arm is defined.
+                    let block = if is_select_arm(func, value, result) {
+                        def_block(func, value).unwrap_or(block)
+                    } else {
+                        block
+                    };
+                    builder.add_dependency(value, result, count(block));
+                }
+            }
+
+            let mut kinds = class.diagnostics.clone();
+            if known_result_dependencies(func, inst, 0, &classes).is_none() {
+                kinds.push(BladeDiagnosticKind::MultipleResults);
+            }
+            for kind in kinds {
+                builder.diagnostics.push(BladeDiagnostic {
+                    inst,
+                    kind,
+                    srcloc: func.srclocs[inst],
+                });
+            }
+        }
+    }
+
+    for (value, uses) in def_use.iter() {
+        for value_use in uses {
+            match *value_use {
+                ValueUse::Value(param) if !is_stable(func, param) => {
+                    let block = match func.dfg.value_def(param) {
+                        ValueDef::Param(block, _) => block,
+                        ValueDef::Result(..) => unreachable!("{} is not a block parameter", param),
+                    };
+                    builder.add_dependency(value, param, count(block));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::policy::{DefaultBladePolicy, SinkClasses, SinkOptions, SourceOptions};
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::condcodes::IntCC;
+    use crate::ir::{
+        types, AbiParam, BladeClass, ExtFuncData, ExternalName, InstBuilder, MemFlags, Signature,
+        StackSlotData, StackSlotKind, TrapCode,
+    };
+    use crate::isa::CallConv;
+    use crate::settings::{self, Configurable};
+
+    /// Build a function which loads a value and stores it back, returning the function and its
+    /// load and store.
+    fn load_then_store() -> (Function, Inst, Inst) {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let value = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let load = cur.func.dfg.value_def(value).unwrap_inst();
+        let store = cur.ins().store(MemFlags::new(), value, ptr, 8);
+        cur.ins().return_(&[]);
+
+        (func, load, store)
+    }
+
+    #[test]
+    fn default_class() {
+        let (func, _, _) = load_then_store();
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            false,
+        );
+        assert_eq!(blade_graph.min_cut(BladeCutBias::Sources).len(), 1);
+    }
+
+    #[test]
+    fn explicit_class() {
+        // A load which isn't a source leaves nothing to protect.
+        let (mut func, load, _) = load_then_store();
+        func.blade_class[load] = BladeClass::Neither;
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            false,
+        );
+        assert!(blade_graph.min_cut(BladeCutBias::Sources).is_empty());
+
+        // Neither does a store which doesn't leak its operands.
+        let (mut func, _, store) = load_then_store();
+        func.blade_class[store] = BladeClass::Neither;
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            false,
+        );
+        assert!(blade_graph.min_cut(BladeCutBias::Sources).is_empty());
+
+        // A source feeding the return, which is explicitly a sink, must be protected.
+        let (mut func, _, store) = load_then_store();
+        func.blade_class[store] = BladeClass::Neither;
+        let ret = func
+            .layout
+            .last_inst(func.layout.entry_block().unwrap())
+            .unwrap();
+        let value = func.dfg.inst_args(store)[0];
+        func.dfg.append_inst_arg(ret, value);
+        func.blade_class[ret] = BladeClass::Sink;
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            false,
+        );
+        assert_eq!(blade_graph.min_cut(BladeCutBias::Sources).len(), 1);
+    }
+
+    #[test]
+    fn custom_policy() {
+        // A policy under which returns leak their operands, and nothing else does.
+        struct LeakyReturns;
+        impl BladePolicy for LeakyReturns {
+            fn classify_inst(&self, func: &Function, inst: Inst) -> InstClassification {
+                let opcode = func.dfg[inst].opcode();
+                InstClassification {
+                    source: opcode.can_load(),
+                    sinks: if opcode.is_return() {
+                        func.dfg.inst_args(inst).to_vec()
+                    } else {
+                        Vec::new()
+                    },
+                    diagnostics: Vec::new(),
+                }
+            }
+        }
+
+        let (mut func, _, store) = load_then_store();
+        let blade_graph =
+            build_blade_graph_for_func(&func, &LeakyReturns, EdgeWeights::default(), false);
+        assert!(blade_graph.min_cut(BladeCutBias::Sources).is_empty());
+
+        let ret = func
+            .layout
+            .last_inst(func.layout.entry_block().unwrap())
+            .unwrap();
+        let value = func.dfg.inst_args(store)[0];
+        func.dfg.append_inst_arg(ret, value);
+        let blade_graph =
+            build_blade_graph_for_func(&func, &LeakyReturns, EdgeWeights::default(), false);
+        assert_eq!(blade_graph.min_cut(BladeCutBias::Sources).len(), 1);
+    }
+
+    #[test]
+    fn local_stack_slots() {
+        // Store a value into a stack slot, load it back, and use it as an address.
+        let build = |transient: bool| {
+            let mut func = Function::new();
+            let slot = func.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 8));
+            let block0 = func.dfg.make_block();
+            let ptr = func.dfg.append_block_param(block0, types::I64);
+
+            let mut cur = FuncCursor::new(&mut func);
+            cur.insert_block(block0);
+            let value = if transient {
+                cur.ins().load(types::I64, MemFlags::new(), ptr, 0)
+            } else {
+                ptr
+            };
+            let addr = cur.ins().stack_addr(types::I64, slot, 0);
+            cur.ins().store(MemFlags::trusted(), value, addr, 0);
+            let loaded = cur.ins().load(types::I64, MemFlags::trusted(), addr, 0);
+            cur.ins().store(MemFlags::new(), ptr, loaded, 0);
+            cur.ins().return_(&[]);
+            build_blade_graph_for_func(
+                &func,
+                &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+                EdgeWeights::default(),
+                false,
+            )
+        };
+
+        // The slot only ever holds the parameter, so there is nothing to protect.
+        assert!(build(false).min_cut(BladeCutBias::Sources).is_empty());
+        // A transient value still flows through the slot.
+        assert_eq!(build(true).min_cut(BladeCutBias::Sources).len(), 1);
+    }
+
+    #[test]
+    fn stack_accesses() {
+        // Load an address from a slot whose address escapes, and store a transient value into
+        // the slot as the legalizer expands `stack_store`.
+        let mut func = Function::new();
+        let slot = func.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 16));
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let escaped = cur.ins().stack_addr(types::I64, slot, 0);
+        cur.ins().store(MemFlags::new(), escaped, ptr, 0);
+        let addr = cur.ins().stack_load(types::I64, slot, 0);
+        cur.ins().load(types::I64, MemFlags::new(), addr, 0);
+        let transient = cur.ins().load(types::I64, MemFlags::new(), ptr, 8);
+        let slot_addr = cur.ins().stack_addr(types::I64, slot, 8);
+        cur.ins()
+            .store(MemFlags::trusted(), transient, slot_addr, 0);
+        cur.ins().return_(&[]);
+
+        let cut = |exclude: bool| {
+            let sources = SourceOptions {
+                stack_loads: !exclude,
+                ..SourceOptions::default()
+            };
+            let sinks = SinkOptions {
+                stack_stores: !exclude,
+                ..SinkOptions::default()
+            };
+            let policy = DefaultBladePolicy::new(&func, sources, sinks);
+            build_blade_graph_for_func(&func, &policy, EdgeWeights::default(), false)
+                .min_cut(BladeCutBias::Sources)
+                .len()
+        };
+        assert_eq!(cut(false), 2);
+        assert_eq!(cut(true), 0);
+    }
+
+    #[test]
+    fn trusted_loads() {
+        // A trusted load is a source unless excluded.
+        let (mut func, load, _) = load_then_store();
+        func.blade_class[load] = BladeClass::Trusted;
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            false,
+        );
+        assert_eq!(blade_graph.min_cut(BladeCutBias::Sources).len(), 1);
+
+        let sources = SourceOptions {
+            trusted_loads: false,
+            ..SourceOptions::default()
+        };
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, sources, SinkOptions::default()),
+            EdgeWeights::default(),
+            false,
+        );
+        assert!(blade_graph.min_cut(BladeCutBias::Sources).is_empty());
+        assert!(blade_graph.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn global_accesses() {
+        // Load the address of an imported global from the VM context, then load the global and
+        // store it to memory.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let vmctx = func.dfg.append_block_param(block0, types::I64);
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let addr = cur.ins().load(types::I64, MemFlags::trusted(), vmctx, 0);
+        let vmctx_load = cur.func.dfg.value_def(addr).unwrap_inst();
+        let value = cur.ins().load(types::I64, MemFlags::trusted(), addr, 8);
+        let global_load = cur.func.dfg.value_def(value).unwrap_inst();
+        cur.ins().store(MemFlags::new(), value, ptr, 0);
+        cur.ins().return_(&[]);
+        func.blade_class[vmctx_load] = BladeClass::Trusted;
+        func.blade_class[global_load] = BladeClass::Global;
+
+        let cut = |func: &Function, exclude: bool| {
+            let sources = SourceOptions {
+                trusted_loads: false,
+                global_loads: !exclude,
+                ..SourceOptions::default()
+            };
+            let sinks = SinkOptions {
+                global_addresses: !exclude,
+                ..SinkOptions::default()
+            };
+            let policy = DefaultBladePolicy::new(func, sources, sinks);
+            build_blade_graph_for_func(func, &policy, EdgeWeights::default(), false)
+                .min_cut(BladeCutBias::Sinks)
+                .len()
+        };
+        // The value of the global reaches a sink.
+        assert_eq!(cut(&func, false), 1);
+        assert_eq!(cut(&func, true), 0);
+
+        // A transient value stored into the global must still be protected.
+        let mut cur = FuncCursor::new(&mut func).at_inst(global_load);
+        let transient = cur.ins().load(types::I64, MemFlags::new(), ptr, 8);
+        let global_store = cur.ins().store(MemFlags::trusted(), transient, addr, 8);
+        cur.func.blade_class[global_store] = BladeClass::Global;
+        assert_eq!(cut(&func, true), 1);
+    }
+
+    #[test]
+    fn non_speculative_loads() {
+        // Nothing precedes the load, so it can't execute speculatively.
+        let (mut func, load, _) = load_then_store();
+        let sources = SourceOptions {
+            non_speculative_loads: false,
+            ..SourceOptions::default()
+        };
+        let build = |func: &Function| {
+            build_blade_graph_for_func(
+                func,
+                &DefaultBladePolicy::new(func, sources, SinkOptions::default()),
+                EdgeWeights::default(),
+                false,
+            )
+        };
+        assert!(build(&func).min_cut(BladeCutBias::Sources).is_empty());
+
+        // A call before it may return speculatively.
+        let sig = func.import_signature(Signature::new(CallConv::SystemV));
+        let callee = func.import_function(ExtFuncData {
+            name: ExternalName::testcase("callee"),
+            signature: sig,
+            colocated: false,
+        });
+        let mut cur = FuncCursor::new(&mut func).at_inst(load);
+        let call = cur.ins().call(callee, &[]);
+        assert_eq!(build(&func).num_sources(), 1);
+        assert_eq!(build(&func).min_cut(BladeCutBias::Sources).len(), 1);
+
+        // Unless a barrier after the call dominates the load, which prunes its source edge.
+        func.post_lfence[call] = true;
+        assert_eq!(build(&func).num_sources(), 0);
+        assert!(build(&func).min_cut(BladeCutBias::Sources).is_empty());
+    }
+
+    #[test]
+    fn declared_taints() {
+        // A parameter used as an address is only protected once declared transient.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        cur.ins().store(MemFlags::new(), ptr, ptr, 0);
+        cur.ins().return_(&[]);
+        let build = |func: &Function| {
+            build_blade_graph_for_func(
+                func,
+                &DefaultBladePolicy::new(func, SourceOptions::default(), SinkOptions::default()),
+                EdgeWeights::default(),
+                true,
+            )
+        };
+        assert!(build(&func).min_cut(BladeCutBias::Sources).is_empty());
+        func.blade_taint[ptr] = BladeTaint::Source;
+        assert_eq!(build(&func).min_cut(BladeCutBias::Sources).len(), 1);
+
+        // A stable value is neither transient by itself nor computed from transient values.
+        let (mut func, load, _) = load_then_store();
+        let value = func.dfg.first_result(load);
+        func.blade_taint[value] = BladeTaint::Stable;
+        assert!(build(&func).min_cut(BladeCutBias::Sources).is_empty());
+
+        // Nor is a value computed from a load.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let value = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let addr = cur.ins().iadd(ptr, value);
+        cur.ins().store(MemFlags::new(), ptr, addr, 0);
+        cur.ins().return_(&[]);
+        assert_eq!(build(&func).min_cut(BladeCutBias::Sources).len(), 1);
+        func.blade_taint[addr] = BladeTaint::Stable;
+        assert!(build(&func).min_cut(BladeCutBias::Sources).is_empty());
+    }
+
+    #[test]
+    fn transient_params() {
+        // The function parameter is used as an address directly, and through a block parameter.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+        let param = func.dfg.append_block_param(block1, types::I64);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        cur.ins().jump(block1, &[ptr]);
+        cur.insert_block(block1);
+        cur.ins().load(types::I64, MemFlags::new(), param, 0);
+        cur.ins().return_(&[]);
+
+        let cut = |block_params, function_params| {
+            let sources = SourceOptions {
+                block_params,
+                function_params,
+                ..SourceOptions::default()
+            };
+            let policy = DefaultBladePolicy::new(&func, sources, SinkOptions::default());
+            build_blade_graph_for_func(&func, &policy, EdgeWeights::default(), false)
+                .min_cut(BladeCutBias::Sinks)
+                .len()
+        };
+        assert_eq!(cut(false, false), 0);
+        assert_eq!(cut(true, false), 1);
+        assert_eq!(cut(true, true), 2);
+    }
+
+    #[test]
+    fn division_sinks() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let value = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        cur.ins().udiv(ptr, value);
+        cur.ins().return_(&[]);
+
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            false,
+        );
+        assert!(blade_graph.min_cut(BladeCutBias::Sources).is_empty());
+
+        let sinks = SinkOptions {
+            division: true,
+            ..SinkOptions::default()
+        };
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), sinks),
+            EdgeWeights::default(),
+            false,
+        );
+        assert_eq!(blade_graph.min_cut(BladeCutBias::Sources).len(), 1);
+        assert!(blade_graph.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn indirect_callee_sink() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let sig = func.import_signature(Signature::new(CallConv::SystemV));
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let callee = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        cur.ins().call_indirect(sig, callee, &[]);
+        cur.ins().return_(&[]);
+
+        // The callee is a sink even when call arguments are not.
+        let sinks = SinkOptions {
+            classes: SinkClasses::default().without(SinkClasses::CALL_ARGUMENTS),
+            ..SinkOptions::default()
+        };
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), sinks),
+            EdgeWeights::default(),
+            false,
+        );
+        assert_eq!(blade_graph.min_cut(BladeCutBias::Sources).len(), 1);
+    }
+
+    #[test]
+    fn trap_conditions() {
+        // A trap on a transient condition and a division by a transient divisor.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let cond = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let divisor = cur.ins().load(types::I64, MemFlags::new(), ptr, 8);
+        let dividend = cur.ins().load(types::I64, MemFlags::new(), ptr, 16);
+        let trap = cur.ins().trapnz(cond, TrapCode::User(0));
+        let quotient = cur.ins().udiv(dividend, divisor);
+        let div = cur.func.dfg.value_def(quotient).unwrap_inst();
+        cur.ins().return_(&[]);
+
+        let build = |classes: SinkClasses| {
+            let sinks = SinkOptions {
+                classes,
+                ..SinkOptions::default()
+            };
+            let policy = DefaultBladePolicy::new(&func, SourceOptions::default(), sinks);
+            build_blade_graph_for_func(&func, &policy, EdgeWeights::default(), false)
+        };
+        // Not sinks by default, but reported as leaking through their side effects.
+        let blade_graph = build(SinkClasses::default());
+        assert!(blade_graph.min_cut(BladeCutBias::Sinks).is_empty());
+        assert_eq!(blade_graph.diagnostics().len(), 2);
+        assert!(blade_graph
+            .diagnostics()
+            .iter()
+            .all(|d| d.kind == BladeDiagnosticKind::UnhandledSideEffect));
+
+        let blade_graph = build(SinkClasses::default() | SinkClasses::TRAP_CONDITIONS);
+        assert_eq!(blade_graph.min_cut(BladeCutBias::Sinks).len(), 2);
+        assert!(blade_graph.diagnostics().is_empty());
+
+        // Only the operands deciding whether they trap are sinks, not the dividend.
+        let sinks = SinkOptions {
+            classes: SinkClasses::default() | SinkClasses::TRAP_CONDITIONS,
+            ..SinkOptions::default()
+        };
+        let policy = DefaultBladePolicy::new(&func, SourceOptions::default(), sinks);
+        assert_eq!(policy.classify_inst(&func, trap).sinks, [cond]);
+        assert_eq!(policy.classify_inst(&func, div).sinks, [divisor]);
+    }
+
+    #[test]
+    fn sink_classes() {
+        // Leak one transient value through each of a stored value, a load address and a branch
+        // condition.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let stored = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let addr = cur.ins().load(types::I64, MemFlags::new(), ptr, 8);
+        let cond = cur.ins().load(types::I64, MemFlags::new(), ptr, 16);
+        cur.ins().store(MemFlags::new(), stored, ptr, 24);
+        cur.ins().load(types::I64, MemFlags::new(), addr, 0);
+        cur.ins().brz(cond, block1, &[]);
+        cur.ins().jump(block1, &[]);
+        cur.insert_block(block1);
+        cur.ins().return_(&[]);
+
+        let cut = |classes: SinkClasses| {
+            let sinks = SinkOptions {
+                classes,
+                ..SinkOptions::default()
+            };
+            let policy = DefaultBladePolicy::new(&func, SourceOptions::default(), sinks);
+            build_blade_graph_for_func(&func, &policy, EdgeWeights::default(), false)
+                .min_cut(BladeCutBias::Sinks)
+                .len()
+        };
+        assert_eq!(cut(SinkClasses::all()), 3);
+        assert_eq!(cut(SinkClasses::LOAD_ADDRESSES), 1);
+        assert_eq!(cut(SinkClasses::STORE_ADDRESSES), 0);
+        assert_eq!(
+            cut(SinkClasses::STORE_VALUES | SinkClasses::BRANCH_CONDITIONS),
+            2
+        );
+        assert_eq!(cut(SinkClasses::empty()), 0);
+    }
+
+    #[test]
+    fn memory_contents_model() {
+        // Leak one transient value through each of a stored value, a load address, a branch
+        // condition and a division.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let stored = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let addr = cur.ins().load(types::I64, MemFlags::new(), ptr, 8);
+        let cond = cur.ins().load(types::I64, MemFlags::new(), ptr, 16);
+        let divisor = cur.ins().load(types::I64, MemFlags::new(), ptr, 24);
+        let store = cur.ins().store(MemFlags::new(), stored, ptr, 32);
+        cur.ins().load(types::I64, MemFlags::new(), addr, 0);
+        cur.ins().udiv(ptr, divisor);
+        cur.ins().brz(cond, block1, &[]);
+        cur.ins().jump(block1, &[]);
+        cur.insert_block(block1);
+        cur.ins().return_(&[]);
+
+        // Only the stored value is a sink, whatever the other sink settings say.
+        let mut flags = settings::builder();
+        flags.set("blade_leakage_model", "memory_contents").unwrap();
+        flags.enable("blade_division_is_sink").unwrap();
+        let policy = DefaultBladePolicy::from_flags(&func, &settings::Flags::new(flags));
+        let blade_graph = build_blade_graph_for_func(&func, &policy, EdgeWeights::default(), false);
+        assert_eq!(blade_graph.sinks().collect::<Vec<_>>(), [store]);
+        assert_eq!(blade_graph.min_cut(BladeCutBias::Sinks).len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn stack_pointer_accesses() {
+        // Leak a popped value through a load address, and compare the stack pointer.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let limit = func.dfg.append_block_param(block0, types::I64);
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let popped = cur.ins().x86_pop(types::I64);
+        cur.ins().load(types::I64, MemFlags::new(), popped, 0);
+        let flags = cur.ins().ifcmp_sp(limit);
+        let cmp = cur.func.dfg.value_def(flags).unwrap_inst();
+        cur.ins().return_(&[]);
+
+        let build = |model| {
+            let mut flags = settings::builder();
+            flags.set("blade_stack_pointer_accesses", model).unwrap();
+            let policy = DefaultBladePolicy::from_flags(&func, &settings::Flags::new(flags));
+            build_blade_graph_for_func(&func, &policy, EdgeWeights::default(), false)
+        };
+        let blade_graph = build("strict");
+        assert!(blade_graph.sources().any(|value| value == popped));
+        assert_eq!(blade_graph.min_cut(BladeCutBias::Sinks).len(), 1);
+        assert_eq!(blade_graph.diagnostics().len(), 1);
+        assert_eq!(blade_graph.diagnostics()[0].inst, cmp);
+        assert_eq!(
+            blade_graph.diagnostics()[0].kind,
+            BladeDiagnosticKind::ImplicitOperand
+        );
+
+        // The guest can't steer the stack pointer nor what it points to.
+        let blade_graph = build("sandbox");
+        assert!(!blade_graph.sources().any(|value| value == popped));
+        assert!(blade_graph.min_cut(BladeCutBias::Sinks).is_empty());
+        assert!(blade_graph.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn float_sinks() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let x = cur.ins().load(types::F64, MemFlags::new(), ptr, 0);
+        let y = cur.ins().fadd(x, x);
+        cur.ins().sqrt(y);
+        cur.ins().return_(&[]);
+
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            false,
+        );
+        assert!(blade_graph.min_cut(BladeCutBias::Sources).is_empty());
+
+        let sinks = SinkOptions {
+            float: true,
+            ..SinkOptions::default()
+        };
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), sinks),
+            EdgeWeights::default(),
+            false,
+        );
+        assert_eq!(blade_graph.min_cut(BladeCutBias::Sources).len(), 1);
+    }
+
+    #[test]
+    fn block_counts() {
+        // A load in a hot block whose result only leaks in a cold block.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+        let block2 = func.dfg.make_block();
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let value = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        cur.ins().brz(ptr, block2, &[]);
+        cur.ins().jump(block1, &[]);
+        cur.insert_block(block1);
+        cur.ins().load(types::I64, MemFlags::new(), value, 0);
+        let leak = cur.func.layout.last_inst(block1).unwrap();
+        cur.ins().jump(block2, &[]);
+        cur.insert_block(block2);
+        cur.ins().return_(&[]);
+
+        let cut = |func: &Function| {
+            let policy =
+                DefaultBladePolicy::new(func, SourceOptions::default(), SinkOptions::default());
+            let blade_graph =
+                build_blade_graph_for_func(func, &policy, EdgeWeights::default(), false);
+            let cut = blade_graph.min_cut(BladeCutBias::Sources);
+            assert_eq!(cut.len(), 1);
+            (
+                blade_graph.bladenode(cut[0].0),
+                blade_graph.bladenode(cut[0].1),
+            )
+        };
+        // Statically, fencing the load is as cheap as fencing the leak.
+        assert_eq!(cut(&func), (None, Some(BladeNode::ValueDef(value))));
+
+        // Dynamically, the leak executes much less often.
+        func.blade_block_counts[block0] = 1000;
+        func.blade_block_counts[block1] = 10;
+        assert_eq!(cut(&func).1, Some(BladeNode::Sink(leak)));
+    }
+
+    #[test]
+    fn prioritize_nested_regions() {
+        // A load whose result only leaks under a conditional branch.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+        let block2 = func.dfg.make_block();
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let value = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        cur.ins().brz(ptr, block2, &[]);
+        cur.ins().jump(block1, &[]);
+        cur.insert_block(block1);
+        cur.ins().load(types::I64, MemFlags::new(), value, 0);
+        let leak = cur.func.layout.last_inst(block1).unwrap();
+        cur.ins().jump(block2, &[]);
+        cur.insert_block(block2);
+        cur.ins().return_(&[]);
+
+        let cut = |prioritize_nested: bool| {
+            let policy =
+                DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default());
+            let weights = EdgeWeights {
+                prioritize_nested,
+                ..EdgeWeights::default()
+            };
+            let blade_graph = build_blade_graph_for_func(&func, &policy, weights, false);
+            let cut = blade_graph.min_cut(BladeCutBias::Sources);
+            assert_eq!(cut.len(), 1);
+            blade_graph.bladenode(cut[0].1)
+        };
+        // Both cut points cost the same.
+        assert_eq!(cut(false), Some(BladeNode::ValueDef(value)));
+
+        // The leak is nested in the region of the branch, so it is cheaper to protect.
+        assert_eq!(cut(true), Some(BladeNode::Sink(leak)));
+    }
+
+    #[test]
+    fn weights() {
+        let (func, _, _) = load_then_store();
+
+        // Fencing the store is too expensive, so fence the load.
+        let weights = EdgeWeights {
+            sink: 2,
+            ..EdgeWeights::default()
+        };
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            weights,
+            false,
+        );
+        let cut = blade_graph.min_cut(BladeCutBias::Sources);
+        assert_eq!(cut.len(), 1);
+        assert_eq!(cut[0].0, blade_graph.source_node);
+
+        // And the other way around.
+        let weights = EdgeWeights {
+            source: 2,
+            ..EdgeWeights::default()
+        };
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            weights,
+            false,
+        );
+        let cut = blade_graph.min_cut(BladeCutBias::Sources);
+        assert_eq!(cut.len(), 1);
+        assert_ne!(cut[0].0, blade_graph.source_node);
+    }
+
+    #[test]
+    fn sink_weights() {
+        // Store a loaded value at a loaded address, leaking both.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let addr = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let value = cur.ins().load(types::I64, MemFlags::new(), ptr, 8);
+        cur.ins().store(MemFlags::new(), value, addr, 0);
+        cur.ins().return_(&[]);
+
+        let cut = |dependency, sink| {
+            let weights = EdgeWeights {
+                source: 10,
+                dependency,
+                sink,
+                ..EdgeWeights::default()
+            };
+            let blade_graph = build_blade_graph_for_func(
+                &func,
+                &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+                weights,
+                false,
+            );
+            let sink_node = blade_graph.sink_node;
+            blade_graph
+                .min_cut_with_flow(BladeCutBias::Sinks)
+                .0
+                .iter()
+                .map(|&(_, to)| to == sink_node)
+                .collect::<Vec<_>>()
+        };
+        // A single barrier before the store is cheaper than protecting each operand...
+        assert_eq!(cut(2, 3), [true]);
+        // ...unless each operand is cheap enough to protect on its own.
+        assert_eq!(cut(1, 3), [false, false]);
+    }
+
+    #[test]
+    fn coarsen_chains() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+
+        // A load whose result goes through a chain of additions before being stored.
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let mut value = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        for _ in 0..10 {
+            value = cur.ins().iadd_imm(value, 1);
+        }
+        let store = cur.ins().store(MemFlags::new(), value, ptr, 8);
+        cur.ins().return_(&[]);
+
+        let fine = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            false,
+        );
+        let coarse = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            true,
+        );
+        assert!(coarse.num_edges() < fine.num_edges());
+
+        // The chain still has to be cut somewhere, and the whole chain is represented by its
+        // last value.
+        assert_eq!(coarse.min_cut(BladeCutBias::Sources).len(), 1);
+        let chain: Vec<_> = (0..coarse.graph.num_nodes())
+            .filter_map(|node| match coarse.bladenode(node) {
+                Some(BladeNode::ValueDef(v)) if v != ptr => Some(v),
+                _ => None,
+            })
+            .collect();
+        let loaded = func
+            .dfg
+            .inst_results(func.layout.first_inst(block0).unwrap())[0];
+        assert_eq!(chain.len(), 2);
+        assert!(chain.contains(&loaded));
+        assert!(chain.contains(&value));
+        assert!(func.dfg.inst_args(store).contains(&value));
+    }
+
+    #[test]
+    fn flags_nodes() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+
+        // A loaded value compared into CPU flags, which are both branched on and turned back
+        // into an index.
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let loaded = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let flags = cur.ins().ifcmp_imm(loaded, 0);
+        let cond = cur.ins().trueif(IntCC::Equal, flags);
+        let index = cur.ins().bint(types::I64, cond);
+        let addr = cur.ins().iadd(ptr, index);
+        let store = cur.ins().store(MemFlags::new(), ptr, addr, 0);
+        let brif = cur.ins().brif(IntCC::Equal, flags, block1, &[]);
+        cur.ins().jump(block1, &[]);
+        cur.insert_block(block1);
+        cur.ins().return_(&[]);
+
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            true,
+        );
+        let has_node = |bladenode| blade_graph.bladenodes.contains(&Some(bladenode));
+        assert!(has_node(BladeNode::ValueDef(flags)));
+        let sinks: Vec<_> = blade_graph.sinks().collect();
+        assert!(sinks.contains(&store));
+        assert!(sinks.contains(&brif));
+        let sink_nodes = (0..blade_graph.graph.num_nodes()).filter(|&node| {
+            blade_graph.bladenode(node) == Some(BladeNode::Sink(brif))
+                || blade_graph.bladenode(node) == Some(BladeNode::Sink(store))
+        });
+        assert_eq!(blade_graph.ancestors_of_all(sink_nodes), [loaded]);
+        assert_eq!(blade_graph.min_cut(BladeCutBias::Sources).len(), 1);
+    }
+
+    #[test]
+    fn select_arms_are_split() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let n = func.dfg.append_block_param(block0, types::I64);
+
+        // A select between two values computed from loads, each used only by the select.
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let a = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let b = cur.ins().load(types::I64, MemFlags::new(), ptr, 8);
+        let x = cur.ins().iadd_imm(a, 1);
+        let y = cur.ins().iadd_imm(b, 1);
+        let chosen = cur.ins().select(n, x, y);
+        let select = cur.func.dfg.value_def(chosen).unwrap_inst();
+        cur.ins().store(MemFlags::new(), chosen, ptr, 16);
+        cur.ins().return_(&[]);
+
+        assert_eq!(select_arms(&func, select), [x, y]);
+        assert!(is_select_arm(&func, x, chosen));
+        assert!(!is_select_arm(&func, n, chosen));
+
+        // Coarsening keeps a node for each arm rather than merging them into the select.
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            true,
+        );
+        let has_node = |bladenode| blade_graph.bladenodes.contains(&Some(bladenode));
+        assert!(has_node(BladeNode::ValueDef(x)));
+        assert!(has_node(BladeNode::ValueDef(y)));
+        assert!(has_node(BladeNode::ValueDef(chosen)));
+    }
+
+    #[test]
+    fn split_halves() {
+        // Store either half of a split of a loaded value concatenated with a constant.
+        let split_then_store = |half: usize| {
+            let mut func = Function::new();
+            let block0 = func.dfg.make_block();
+            let ptr = func.dfg.append_block_param(block0, types::I64);
+
+            let mut cur = FuncCursor::new(&mut func);
+            cur.insert_block(block0);
+            let loaded = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+            let constant = cur.ins().iconst(types::I64, 0);
+            let wide = cur.ins().iconcat(loaded, constant);
+            let (lo, hi) = cur.ins().isplit(wide);
+            cur.ins().store(MemFlags::new(), [lo, hi][half], ptr, 8);
+            cur.ins().return_(&[]);
+            build_blade_graph_for_func(
+                &func,
+                &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+                EdgeWeights::default(),
+                false,
+            )
+        };
+
+        // Only the low half is transient.
+        let blade_graph = split_then_store(0);
+        assert_eq!(blade_graph.min_cut(BladeCutBias::Sources).len(), 1);
+        let blade_graph = split_then_store(1);
+        assert!(blade_graph.min_cut(BladeCutBias::Sources).is_empty());
+        assert!(blade_graph.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn carry_flags() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let n = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+
+        // Store the sum of the parameters, and branch on the carry out of adding a loaded value.
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let loaded = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let (_, carry) = cur.ins().iadd_ifcout(loaded, n);
+        let (sum, _) = cur.ins().iadd_ifcout(ptr, n);
+        let store = cur.ins().store(MemFlags::new(), sum, ptr, 8);
+        let brif = cur.ins().brif(IntCC::UnsignedLessThan, carry, block1, &[]);
+        cur.ins().jump(block1, &[]);
+        cur.insert_block(block1);
+        cur.ins().return_(&[]);
+
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            false,
+        );
+        let graph = &blade_graph;
+        let sink_node = |inst| {
+            (0..graph.graph.num_nodes())
+                .filter(move |&node| graph.bladenode(node) == Some(BladeNode::Sink(inst)))
+        };
+        // Both results depend on both operands, and only those.
+        assert_eq!(blade_graph.ancestors_of_all(sink_node(brif)), [loaded]);
+        assert!(blade_graph.ancestors_of_all(sink_node(store)).is_empty());
+        assert!(blade_graph.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn shared_ancestors() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let n = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+
+        // Two loaded values, each reaching several sinks, some of them through the same values.
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let a = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let b = cur.ins().load(types::I64, MemFlags::new(), ptr, 8);
+        let sum = cur.ins().iadd(a, b);
+        let store0 = cur.ins().store(MemFlags::new(), n, sum, 0);
+        let store1 = cur.ins().store(MemFlags::new(), n, sum, 8);
+        let store2 = cur.ins().store(MemFlags::new(), n, ptr, 16);
+        let brz = cur.ins().brz(a, block1, &[]);
+        cur.ins().jump(block1, &[]);
+        cur.insert_block(block1);
+        cur.ins().return_(&[]);
+
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            false,
+        );
+        let sink_nodes = |insts: &[Inst]| {
+            (0..blade_graph.graph.num_nodes())
+                .filter(|&node| match blade_graph.bladenode(node) {
+                    Some(BladeNode::Sink(inst)) => insts.contains(&inst),
+                    _ => false,
+                })
+                .collect::<Vec<_>>()
+        };
+        // Every source is found once, however many of the sinks it reaches.
+        let mut ancestors =
+            blade_graph.ancestors_of_all(sink_nodes(&[store0, store1, store2, brz]));
+        ancestors.sort();
+        assert_eq!(ancestors, [a, b]);
+        assert_eq!(blade_graph.ancestors_of_all(sink_nodes(&[brz])), [a]);
+        assert!(blade_graph
+            .ancestors_of_all(sink_nodes(&[store2]))
+            .is_empty());
+        assert!(blade_graph.ancestors_of_all(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let x = func.dfg.append_block_param(block0, types::I64);
+        let y = func.dfg.append_block_param(block0, types::I64);
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(types::I64));
+        sig.returns.push(AbiParam::new(types::I64));
+        sig.returns.push(AbiParam::new(types::I64));
+        let sig = func.import_signature(sig);
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let quotient = cur.ins().udiv(x, y);
+        let udiv = cur.func.dfg.value_def(quotient).unwrap_inst();
+        let (sum, _) = cur.ins().iadd_ifcout(x, y);
+        let call_indirect = cur.ins().call_indirect(sig, x, &[y]);
+        let pinned = cur.ins().get_pinned_reg(types::I64);
+        let get_pinned_reg = cur.func.dfg.value_def(pinned).unwrap_inst();
+        let store = cur.ins().store(MemFlags::new(), sum, pinned, 0);
+        let ret = cur.ins().return_(&[quotient]);
+
+        let blade_graph = build_blade_graph_for_func(
+            &func,
+            &DefaultBladePolicy::new(&func, SourceOptions::default(), SinkOptions::default()),
+            EdgeWeights::default(),
+            false,
+        );
+        let diagnostics: Vec<_> = blade_graph
+            .diagnostics()
+            .iter()
+            .map(|d| (d.inst, d.kind))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            [
+                (udiv, BladeDiagnosticKind::UnhandledSideEffect),
+                (call_indirect, BladeDiagnosticKind::MultipleResults),
+                (get_pinned_reg, BladeDiagnosticKind::ImplicitOperand),
+                (ret, BladeDiagnosticKind::UnhandledSideEffect),
+            ]
+        );
+        assert!(diagnostics.iter().all(|&(inst, _)| inst != store));
+    }
+}
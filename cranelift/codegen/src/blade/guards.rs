@@ -0,0 +1,509 @@
+//! Bounds established by explicit bounds checks.
+//!
+//! Code with explicit bounds checks compares an index against a limit and traps when the index is
+//! past it, then accesses memory at a base plus the index. cranelift-wasm guards the accesses to
+//! Wasm heaps and tables this way, and other frontends check their arrays the same way, usually
+//! scaling the index by the size of the elements, and often branching to a block which reports
+//! the error before trapping. The legalization of conditional traps turns them into branches over
+//! a block which only traps too, so that the address computed from the index looks like any
+//! other pointer to the Blade pass. Yet only a mispredicted check lets the access go past the
+//! limit, which is exactly what speculative load hardening masks against.
+//!
+//! This module recognizes these guards, whatever their trap code, and turns them into bounds for
+//! the addresses which add the checked index, possibly scaled by a power of two, to a base, so
+//! that SLH reuses the checks instead of falling back to fake bounds or speculation barriers for
+//! the loads from them.
+
+use super::{access_size, flags_live_before, iconst_value};
+use crate::cursor::{Cursor, EncCursor};
+use crate::entity::EntitySet;
+use crate::flowgraph::ControlFlowGraph;
+use crate::ir::condcodes::{CondCode, IntCC};
+use crate::ir::{
+    Block, Bounds, Function, Inst, InstBuilder, InstructionData, Opcode, Value, ValueDef,
+};
+use crate::isa::TargetIsa;
+use crate::HashMap;
+use alloc::vec::Vec;
+
+/// Attach bounds to the addresses of `loads` which are computed from an index checked by a
+/// guard, and have no bounds yet.
+///
+/// The bounds cover every access of `loads` through the address whenever the guard passes.
+/// Returns the number of addresses which got bounds.
+pub(super) fn bound_guarded_loads(
+    func: &mut Function,
+    isa: &dyn TargetIsa,
+    cfg: &ControlFlowGraph,
+    loads: &[Inst],
+) -> usize {
+    // The end of the furthest access through each address, in order of first access.
+    let mut addrs = Vec::new();
+    let mut ends = HashMap::<Value, i64>::new();
+    for &load in loads {
+        let (addr, offset): (_, i64) = match func.dfg[load] {
+            InstructionData::Load { arg, offset, .. } => (arg, offset.into()),
+            _ => continue,
+        };
+        let addr = func.dfg.resolve_aliases(addr);
+        if func.dfg.bounds[addr].is_some() {
+            continue;
+        }
+        // The static offset of a Wasm access is either folded into the load or added last.
+        let (addr, offset) = match offset_address(func, addr) {
+            Some((base, imm)) => match offset.checked_add(imm) {
+                Some(offset) => (base, offset),
+                None => continue,
+            },
+            None => (addr, offset),
+        };
+        let end = match offset.checked_add(i64::from(access_size(func, load))) {
+            Some(end) if end > 0 => end,
+            _ => continue,
+        };
+        if func.dfg.bounds[addr].is_some() {
+            continue;
+        }
+        let furthest = ends.entry(addr).or_insert_with(|| {
+            addrs.push(addr);
+            end
+        });
+        *furthest = (*furthest).max(end);
+    }
+
+    let mut bounded = 0;
+    for addr in addrs {
+        let (iadd, base, limit, shift) = match guarded_address(func, cfg, addr) {
+            Some(guarded) => guarded,
+            None => continue,
+        };
+        let end = ends[&addr] as u64;
+        let bounds = match limit {
+            // The index is at most `max`, so the accesses end before
+            // `base + (max << shift) + end`, unless scaling it wraps around.
+            Limit::Static(max) => {
+                let bits = func.dfg.value_type(addr).bits();
+                match max
+                    .checked_mul(1 << shift)
+                    .filter(|&offset| bits >= 64 || offset >> bits == 0)
+                    .and_then(|offset| offset.checked_add(end))
+                {
+                    Some(size) => Bounds::Static { base, size },
+                    None => continue,
+                }
+            }
+            // The bounds are computed right after the address, where they mustn't clobber a
+            // condition tested later.
+            Limit::Dynamic { .. } if flags_live_before_next(func, iadd) => continue,
+            Limit::Dynamic { limit, inclusive } => {
+                let mut pos = EncCursor::new(func, isa).after_inst(iadd);
+                pos.use_srcloc(iadd);
+                let addr_ty = pos.func.dfg.value_type(base);
+                let limit = if pos.func.dfg.value_type(limit) != addr_ty {
+                    pos.ins().uextend(addr_ty, limit)
+                } else {
+                    limit
+                };
+                // Any array fits in the address space, so the scaled limit doesn't overflow.
+                let limit = if shift > 0 {
+                    pos.ins().ishl_imm(limit, i64::from(shift))
+                } else {
+                    limit
+                };
+                let sum = pos.ins().iadd(base, limit);
+                // Below an exclusive limit, the index is at most one element before it.
+                let upper = if inclusive {
+                    end as i64
+                } else {
+                    end as i64 - (1 << shift)
+                };
+                let upper = pos.ins().iadd_imm(sum, upper);
+                Bounds::Dynamic { lower: base, upper }
+            }
+        };
+        func.dfg.bounds[addr] = Some(bounds);
+        bounded += 1;
+    }
+    bounded
+}
+
+/// Is a CPU flags value live right after `inst`?
+fn flags_live_before_next(func: &Function, inst: Inst) -> bool {
+    func.layout
+        .next_inst(inst)
+        .map_or(false, |next| flags_live_before(func, next))
+}
+
+/// Split `addr` into a base and a non-negative offset, if it is an `iadd_imm`.
+fn offset_address(func: &Function, addr: Value) -> Option<(Value, i64)> {
+    let inst = match func.dfg.value_def(addr) {
+        ValueDef::Result(inst, _) => inst,
+        ValueDef::Param(..) => return None,
+    };
+    match func.dfg[inst] {
+        InstructionData::BinaryImm64 {
+            opcode: Opcode::IaddImm,
+            arg,
+            imm,
+        } if imm.bits() >= 0 => Some((func.dfg.resolve_aliases(arg), imm.bits())),
+        _ => None,
+    }
+}
+
+/// The largest index a guard lets through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Limit {
+    /// The index is at most this constant.
+    Static(u64),
+    /// The index is at most `limit` if `inclusive`, and below it otherwise.
+    Dynamic { limit: Value, inclusive: bool },
+}
+
+/// A condition on which a guard traps: `lhs cc rhs`.
+#[derive(Clone, Copy, Debug)]
+struct Comparison {
+    cc: IntCC,
+    lhs: Value,
+    rhs: Operand,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Value(Value),
+    /// An immediate, truncated to the type of the compared values.
+    Imm(u64),
+}
+
+impl Comparison {
+    /// Get the negation of this comparison.
+    fn inverse(self) -> Self {
+        Self {
+            cc: self.cc.inverse(),
+            ..self
+        }
+    }
+
+    /// Get the limit this comparison puts on `index`, if it traps whenever `index` is too large.
+    fn limit(self, index: Value) -> Option<Limit> {
+        let Self { cc, lhs, rhs } = match (self.cc, self.rhs) {
+            (IntCC::UnsignedLessThan, Operand::Value(rhs))
+            | (IntCC::UnsignedLessThanOrEqual, Operand::Value(rhs)) => Self {
+                cc: self.cc.reverse(),
+                lhs: rhs,
+                rhs: Operand::Value(self.lhs),
+            },
+            _ => self,
+        };
+        if lhs != index {
+            return None;
+        }
+        match (cc, rhs) {
+            (IntCC::UnsignedGreaterThan, Operand::Imm(max)) => Some(Limit::Static(max)),
+            (IntCC::UnsignedGreaterThanOrEqual, Operand::Imm(limit)) if limit > 0 => {
+                Some(Limit::Static(limit - 1))
+            }
+            (IntCC::UnsignedGreaterThan, Operand::Value(limit)) => Some(Limit::Dynamic {
+                limit,
+                inclusive: true,
+            }),
+            (IntCC::UnsignedGreaterThanOrEqual, Operand::Value(limit)) => Some(Limit::Dynamic {
+                limit,
+                inclusive: false,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Split `offset` into an index and the amount it is shifted left by, if it scales the index by a
+/// power of two, before or after legalization turned its immediate into a constant. Otherwise,
+/// `offset` is the index itself.
+fn scaled_index(func: &Function, offset: Value) -> (Value, u32) {
+    let inst = match func.dfg.value_def(offset) {
+        ValueDef::Result(inst, _) => inst,
+        ValueDef::Param(..) => return (offset, 0),
+    };
+    let factor_shift = |factor: i64| {
+        if factor > 0 && (factor as u64).is_power_of_two() {
+            Some(i64::from(factor.trailing_zeros()))
+        } else {
+            None
+        }
+    };
+    let scaled = match func.dfg[inst] {
+        InstructionData::BinaryImm64 {
+            opcode: Opcode::IshlImm,
+            arg,
+            imm,
+        } => Some((arg, imm.bits())),
+        InstructionData::BinaryImm64 {
+            opcode: Opcode::ImulImm,
+            arg,
+            imm,
+        } => factor_shift(imm.bits()).map(|shift| (arg, shift)),
+        InstructionData::Binary {
+            opcode: Opcode::Ishl,
+            args,
+        } => iconst_value(func, args[1]).map(|shift| (args[0], shift)),
+        InstructionData::Binary {
+            opcode: Opcode::Imul,
+            args,
+        } => match (iconst_value(func, args[0]), iconst_value(func, args[1])) {
+            (None, Some(factor)) => factor_shift(factor).map(|shift| (args[0], shift)),
+            (Some(factor), None) => factor_shift(factor).map(|shift| (args[1], shift)),
+            _ => None,
+        },
+        _ => None,
+    };
+    match scaled {
+        // Elements are much smaller than this, and larger shifts may discard bits of the index.
+        Some((arg, shift)) if shift >= 0 && shift < 32 => {
+            (func.dfg.resolve_aliases(arg), shift as u32)
+        }
+        _ => (offset, 0),
+    }
+}
+
+/// Find the guard of `addr`, if it is the sum of a base and of an index, possibly scaled by a
+/// power of two, which a guard checks.
+///
+/// Returns the `iadd` computing `addr`, the base, the limit the guard puts on the index, and the
+/// amount the index is shifted left by.
+fn guarded_address(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    addr: Value,
+) -> Option<(Inst, Value, Limit, u32)> {
+    let iadd = match func.dfg.value_def(addr) {
+        ValueDef::Result(inst, _) => inst,
+        ValueDef::Param(..) => return None,
+    };
+    let args = match func.dfg[iadd] {
+        InstructionData::Binary {
+            opcode: Opcode::Iadd,
+            args,
+        } => args,
+        _ => return None,
+    };
+    let guards = guards_before(func, cfg, iadd);
+    for &(base, offset) in &[(args[0], args[1]), (args[1], args[0])] {
+        let (index, shift) = scaled_index(func, func.dfg.resolve_aliases(offset));
+        // Narrow indexes are zero-extended to the pointer type, which preserves their limit.
+        let mut indexes = vec![index];
+        if let ValueDef::Result(inst, _) = func.dfg.value_def(index) {
+            if let InstructionData::Unary {
+                opcode: Opcode::Uextend,
+                arg,
+            } = func.dfg[inst]
+            {
+                indexes.push(func.dfg.resolve_aliases(arg));
+            }
+        }
+        for guard in &guards {
+            for &index in &indexes {
+                if let Some(limit) = guard.limit(index) {
+                    return Some((iadd, func.dfg.resolve_aliases(base), limit, shift));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Get the conditions of the guards which every execution of `inst` passed, closest first: the
+/// conditional traps before it in its block, then the branch over a trap block into its block
+/// and the guards before that branch, and so on up the blocks which have a single predecessor.
+fn guards_before(func: &Function, cfg: &ControlFlowGraph, inst: Inst) -> Vec<Comparison> {
+    let mut guards = Vec::new();
+    let mut visited = EntitySet::new();
+    let mut inst = inst;
+    loop {
+        let block = func
+            .layout
+            .inst_block(inst)
+            .expect("instruction not in the layout");
+        // A chain of single predecessors may loop back, past the instructions it started from.
+        if !visited.insert(block) {
+            break;
+        }
+        let mut prev = func.layout.prev_inst(inst);
+        while let Some(inst) = prev {
+            guards.extend(trap_condition(func, inst));
+            prev = func.layout.prev_inst(inst);
+        }
+        let mut preds = cfg.pred_iter(block);
+        let pred = match (preds.next(), preds.next()) {
+            (Some(pred), None) => pred,
+            _ => break,
+        };
+        guards.extend(branch_condition(func, pred.inst, block));
+        inst = pred.inst;
+    }
+    guards
+}
+
+/// Get the condition on which `inst` traps, if it is a conditional trap which can't be resumed.
+fn trap_condition(func: &Function, inst: Inst) -> Option<Comparison> {
+    match func.dfg[inst] {
+        InstructionData::CondTrap {
+            opcode: opcode @ Opcode::Trapz,
+            arg,
+            ..
+        }
+        | InstructionData::CondTrap {
+            opcode: opcode @ Opcode::Trapnz,
+            arg,
+            ..
+        } => bool_comparison(func, arg, opcode == Opcode::Trapnz),
+        InstructionData::IntCondTrap {
+            opcode: Opcode::Trapif,
+            cond,
+            arg,
+            ..
+        } => flags_comparison(func, cond, arg),
+        _ => None,
+    }
+}
+
+/// Get the condition on which `branch` leads to a trap block instead of `dest`, if it is a
+/// conditional branch followed by a jump, one of them going to `dest` and the other one to a
+/// block which always traps.
+fn branch_condition(func: &Function, branch: Inst, dest: Block) -> Option<Comparison> {
+    // The legalized form of a conditional trap, `brz c, dest; jump trap`, with the trap
+    // condition on either of the two instructions.
+    let (cond_branch, jump) = match func.dfg[branch].opcode() {
+        Opcode::Jump => (func.layout.prev_inst(branch)?, branch),
+        _ => (branch, func.layout.next_inst(branch)?),
+    };
+    if func.dfg[jump].opcode() != Opcode::Jump {
+        return None;
+    }
+    let taken = func.dfg[cond_branch].branch_destination()?;
+    let fallthrough = func.dfg[jump].branch_destination()?;
+    let (trap_block, to_trap) = if fallthrough == dest && taken != dest {
+        (taken, true)
+    } else if taken == dest && fallthrough != dest {
+        (fallthrough, false)
+    } else {
+        return None;
+    };
+    if !always_traps(func, trap_block) {
+        return None;
+    }
+    let comparison = taken_condition(func, cond_branch)?;
+    Some(if to_trap {
+        comparison
+    } else {
+        comparison.inverse()
+    })
+}
+
+/// Get the condition on which the conditional branch `branch` is taken.
+fn taken_condition(func: &Function, branch: Inst) -> Option<Comparison> {
+    let pool = &func.dfg.value_lists;
+    match func.dfg[branch] {
+        InstructionData::Branch {
+            opcode, ref args, ..
+        } => bool_comparison(func, args.first(pool)?, opcode == Opcode::Brnz),
+        InstructionData::BranchInt {
+            opcode: Opcode::Brif,
+            cond,
+            ref args,
+            ..
+        } => flags_comparison(func, cond, args.first(pool)?),
+        InstructionData::BranchIcmp {
+            opcode: Opcode::BrIcmp,
+            cond,
+            ref args,
+            ..
+        } => {
+            let args = args.as_slice(pool);
+            Some(Comparison {
+                cc: cond,
+                lhs: func.dfg.resolve_aliases(args[0]),
+                rhs: Operand::Value(func.dfg.resolve_aliases(args[1])),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Does every execution of `block` end in a trap which can't be resumed?
+///
+/// The block may report the error, with calls for instance, but mustn't branch anywhere else.
+fn always_traps(func: &Function, block: Block) -> bool {
+    let last = match func.layout.last_inst(block) {
+        Some(last) => last,
+        None => return false,
+    };
+    func.dfg[last].opcode() == Opcode::Trap
+        && func
+            .layout
+            .block_insts(block)
+            .all(|inst| !func.dfg[inst].opcode().is_branch())
+}
+
+/// Get the comparison which the boolean `value` is the result of, negated unless `when_true`.
+fn bool_comparison(func: &Function, value: Value, when_true: bool) -> Option<Comparison> {
+    let inst = match func.dfg.value_def(func.dfg.resolve_aliases(value)) {
+        ValueDef::Result(inst, _) => inst,
+        ValueDef::Param(..) => return None,
+    };
+    let comparison = match func.dfg[inst] {
+        InstructionData::IntCompare { cond, args, .. } => Comparison {
+            cc: cond,
+            lhs: func.dfg.resolve_aliases(args[0]),
+            rhs: Operand::Value(func.dfg.resolve_aliases(args[1])),
+        },
+        InstructionData::IntCompareImm { cond, arg, imm, .. } => Comparison {
+            cc: cond,
+            lhs: func.dfg.resolve_aliases(arg),
+            rhs: imm_operand(func, arg, imm.into()),
+        },
+        _ => return None,
+    };
+    Some(if when_true {
+        comparison
+    } else {
+        comparison.inverse()
+    })
+}
+
+/// Get the comparison which `cc` tests on the CPU flags `flags`.
+fn flags_comparison(func: &Function, cc: IntCC, flags: Value) -> Option<Comparison> {
+    let inst = match func.dfg.value_def(func.dfg.resolve_aliases(flags)) {
+        ValueDef::Result(inst, _) => inst,
+        ValueDef::Param(..) => return None,
+    };
+    match func.dfg[inst] {
+        InstructionData::Binary {
+            opcode: Opcode::Ifcmp,
+            args,
+        } => Some(Comparison {
+            cc,
+            lhs: func.dfg.resolve_aliases(args[0]),
+            rhs: Operand::Value(func.dfg.resolve_aliases(args[1])),
+        }),
+        InstructionData::BinaryImm64 {
+            opcode: Opcode::IfcmpImm,
+            arg,
+            imm,
+        } => Some(Comparison {
+            cc,
+            lhs: func.dfg.resolve_aliases(arg),
+            rhs: imm_operand(func, arg, imm.into()),
+        }),
+        _ => None,
+    }
+}
+
+/// Get the immediate `imm` compared with `arg`, truncated to the type of `arg`.
+fn imm_operand(func: &Function, arg: Value, imm: i64) -> Operand {
+    let bits = func.dfg.value_type(arg).bits();
+    let imm = imm as u64;
+    Operand::Imm(if bits < 64 {
+        imm & ((1 << bits) - 1)
+    } else {
+        imm
+    })
+}
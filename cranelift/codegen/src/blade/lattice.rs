@@ -0,0 +1,218 @@
+//! The type system of Blade, as a dataflow analysis.
+//!
+//! The Blade paper types every value as either *stable* or *transient*. The results of sources
+//! are transient, the results of every other instruction are transient when they depend on a
+//! transient value, and a speculation barrier makes every value computed before it stable. A
+//! function is well-typed when no sink ever receives a transient value.
+//!
+//! This computes the transient values at every point of the function directly, without the Blade
+//! graph. It can place barriers by itself, right before every sink which would otherwise receive
+//! a transient value, and it can check the protections derived from the minimal cut, which makes
+//! it an independent oracle for the construction of the graph.
+
+use super::def_use::DefUseGraph;
+use super::graph::{classify, is_source_value, is_stable, result_dependencies};
+use super::local_slots::LocalSlots;
+use super::policy::{BladePolicy, InstClassification};
+use crate::entity::{EntityRef, EntitySet, SecondaryMap};
+use crate::ir::instructions::BranchInfo;
+use crate::ir::{Block, Function, Inst, Value};
+use alloc::vec::Vec;
+
+/// A sink which receives a transient value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// The sink.
+    pub inst: Inst,
+    /// The transient operand.
+    pub value: Value,
+}
+
+/// Check that no sink of `func` receives a transient value, given the speculation barriers
+/// recorded in `func` and the loads which speculative load hardening protected.
+///
+/// Returns every sink operand which is transient in spite of these protections.
+pub fn check(func: &Function, policy: &dyn BladePolicy, hardened: &[Inst]) -> Vec<Violation> {
+    let typer = Typer::new(func, policy, hardened);
+    let states = typer.entry_states();
+    let mut violations = Vec::new();
+    for block in func.layout.blocks() {
+        let mut state = states[block.index()].clone();
+        typer.type_block(block, &mut state, &mut Vec::new(), &mut |inst, value| {
+            violations.push(Violation { inst, value });
+            false
+        });
+    }
+    violations
+}
+
+/// Get the sinks of `func` before which a speculation barrier makes the function well-typed,
+/// given the barriers already recorded in it.
+///
+/// Each barrier makes every value stable, so the sinks following it in its block only get one
+/// when a new transient value reaches them.
+pub fn fenced_sinks(func: &Function, policy: &dyn BladePolicy) -> Vec<Inst> {
+    let typer = Typer::new(func, policy, &[]);
+    let states = typer.entry_states();
+    let mut sinks = Vec::new();
+    for block in func.layout.blocks() {
+        let mut state = states[block.index()].clone();
+        typer.type_block(block, &mut state, &mut Vec::new(), &mut |inst, _| {
+            sinks.push(inst);
+            true
+        });
+    }
+    sinks
+}
+
+/// Types the values of a function.
+struct Typer<'a> {
+    func: &'a Function,
+    policy: &'a dyn BladePolicy,
+    classes: SecondaryMap<Inst, InstClassification>,
+    local_slots: LocalSlots,
+    /// Loads which produce stable values, since their addresses or values are masked.
+    hardened: EntitySet<Inst>,
+}
+
+impl<'a> Typer<'a> {
+    fn new(func: &'a Function, policy: &'a dyn BladePolicy, hardened: &[Inst]) -> Self {
+        let def_use = DefUseGraph::for_function(func);
+        let local_slots = LocalSlots::for_function(func, &def_use);
+        let classes = classify(func, policy, &local_slots);
+        let mut hardened_set = EntitySet::with_capacity(func.dfg.num_insts());
+        for &load in hardened {
+            hardened_set.insert(load);
+        }
+        Self {
+            func,
+            policy,
+            classes,
+            local_slots,
+            hardened: hardened_set,
+        }
+    }
+
+    /// Compute the transient values at the beginning of every block, indexed by block.
+    ///
+    /// The state at the beginning of a block is the union of the states at the branches into it,
+    /// so this iterates over the blocks until none of these states grows any more.
+    fn entry_states(&self) -> Vec<EntitySet<Value>> {
+        let func = self.func;
+        let mut states =
+            vec![EntitySet::with_capacity(func.dfg.num_values()); func.dfg.num_blocks()];
+        for block in func.layout.blocks() {
+            for &param in func.dfg.block_params(block) {
+                if is_source_value(func, param, self.policy.is_source_param(func, param)) {
+                    states[block.index()].insert(param);
+                }
+            }
+        }
+
+        let mut worklist: Vec<Block> = func.layout.blocks().collect();
+        worklist.reverse();
+        let mut queued = vec![true; func.dfg.num_blocks()];
+        let mut edges = Vec::new();
+        while let Some(block) = worklist.pop() {
+            queued[block.index()] = false;
+            let mut state = states[block.index()].clone();
+            self.type_block(block, &mut state, &mut edges, &mut |_, _| false);
+            for (dest, incoming) in edges.drain(..) {
+                let dest_state = &mut states[dest.index()];
+                let mut changed = false;
+                for value in incoming.keys() {
+                    if incoming.contains(value) {
+                        changed |= dest_state.insert(value);
+                    }
+                }
+                if changed && !queued[dest.index()] {
+                    queued[dest.index()] = true;
+                    worklist.push(dest);
+                }
+            }
+        }
+        states
+    }
+
+    /// Type the instructions of `block` in layout order, starting from the transient values in
+    /// `state`.
+    ///
+    /// The transient values flowing along every outgoing edge are pushed to `edges`.
+    /// `transient_sink` is called with every sink operand which is transient where it is used,
+    /// and returns whether to place a speculation barrier right before the sink.
+    fn type_block(
+        &self,
+        block: Block,
+        state: &mut EntitySet<Value>,
+        edges: &mut Vec<(Block, EntitySet<Value>)>,
+        transient_sink: &mut dyn FnMut(Inst, Value) -> bool,
+    ) {
+        let func = self.func;
+        for inst in func.layout.block_insts(block) {
+            if func.pre_lfence[inst] {
+                state.clear();
+            }
+
+            let class = &self.classes[inst];
+            let mut fenced = false;
+            for &value in &class.sinks {
+                if !fenced && state.contains(value) && transient_sink(inst, value) {
+                    fenced = true;
+                }
+            }
+            if fenced {
+                state.clear();
+            }
+
+            match func.dfg.analyze_branch(inst) {
+                BranchInfo::SingleDest(dest, args) => {
+                    let mut incoming = state.clone();
+                    for (&arg, &param) in args.iter().zip(func.dfg.block_params(dest)) {
+                        let arg = func.dfg.resolve_aliases(arg);
+                        if state.contains(arg) && !is_stable(func, param) {
+                            incoming.insert(param);
+                        }
+                    }
+                    edges.push((dest, incoming));
+                }
+                BranchInfo::Table(table, default) => {
+                    let dests = func.jump_tables[table].iter().copied();
+                    for dest in dests.chain(default) {
+                        edges.push((dest, state.clone()));
+                    }
+                }
+                BranchInfo::NotABranch => {}
+            }
+
+            let hardened = self.hardened.contains(inst);
+            let mut transient = Vec::new();
+            for (index, &result) in func.dfg.inst_results(inst).iter().enumerate() {
+                if hardened {
+                    continue;
+                }
+                if is_source_value(func, result, class.source) {
+                    transient.push(result);
+                    continue;
+                }
+                if is_stable(func, result) {
+                    continue;
+                }
+                let mut deps = result_dependencies(func, inst, index, &self.classes);
+                if let Some(slot) = self.local_slots.slot(inst) {
+                    // A load from a slot that doesn't escape produces whatever was stored into it.
+                    deps.extend_from_slice(self.local_slots.stored_into(slot));
+                }
+                if deps.iter().any(|&value| state.contains(value)) {
+                    transient.push(result);
+                }
+            }
+            for value in transient {
+                state.insert(value);
+            }
+
+            if func.post_lfence[inst] {
+                state.clear();
+            }
+        }
+    }
+}
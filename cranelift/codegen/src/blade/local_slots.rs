@@ -0,0 +1,253 @@
+//! Escape analysis of the stack slots of a function.
+//!
+//! An explicit stack slot whose address is only ever used as the address of loads and stores, at
+//! constant offsets within the slot, can only be accessed by those loads and stores. Their
+//! addresses can't be steered elsewhere even speculatively, so the loads don't read transient
+//! values by themselves, and the stores leak nothing through their operands. The values stored
+//! into such a slot still flow into the values loaded from it, though.
+
+use super::def_use::{DefUseGraph, ValueUse};
+use crate::entity::{EntitySet, SecondaryMap};
+use crate::ir::immediates::Offset32;
+use crate::ir::{Function, Inst, InstructionData, Opcode, StackSlot, StackSlotKind, Value};
+use crate::packed_option::PackedOption;
+use alloc::vec::Vec;
+
+/// The loads and stores of a function which access stack slots that don't escape.
+pub struct LocalSlots {
+    /// The slot accessed by every such load or store.
+    accesses: SecondaryMap<Inst, PackedOption<StackSlot>>,
+    /// The values stored into every slot that doesn't escape.
+    stored: SecondaryMap<StackSlot, Vec<Value>>,
+}
+
+/// An access to a stack slot at a constant offset.
+struct Access {
+    inst: Inst,
+    slot: StackSlot,
+    offset: i64,
+    /// An upper bound of the number of bytes accessed.
+    size: u32,
+    /// The value stored by a store.
+    stored: Option<Value>,
+}
+
+impl LocalSlots {
+    /// Find the accesses to the stack slots of `func` which don't escape.
+    ///
+    /// Addresses are only followed through `iadd_imm`. Any other use of the address of a slot,
+    /// including passing it as a block argument or storing it, makes the slot escape. If any
+    /// access may fall outside of its slot, it may touch another one, so no slot is local.
+    pub fn for_function(func: &Function, def_use: &DefUseGraph) -> Self {
+        let mut accesses = Vec::new();
+        let mut escaped = EntitySet::new();
+        // Addresses of slots, along with the slot and the offset they point to.
+        let mut addresses = Vec::new();
+
+        for block in func.layout.blocks() {
+            for inst in func.layout.block_insts(block) {
+                match func.dfg[inst] {
+                    InstructionData::StackLoad {
+                        opcode,
+                        stack_slot,
+                        offset,
+                    } => {
+                        let offset = offset_value(offset);
+                        let result = func.dfg.first_result(inst);
+                        if opcode == Opcode::StackAddr {
+                            addresses.push((result, stack_slot, offset));
+                        } else {
+                            accesses.push(Access {
+                                inst,
+                                slot: stack_slot,
+                                offset,
+                                size: func.dfg.value_type(result).bytes(),
+                                stored: None,
+                            });
+                        }
+                    }
+                    InstructionData::StackStore {
+                        arg,
+                        stack_slot,
+                        offset,
+                        ..
+                    } => {
+                        accesses.push(Access {
+                            inst,
+                            slot: stack_slot,
+                            offset: offset_value(offset),
+                            size: func.dfg.value_type(arg).bytes(),
+                            stored: Some(func.dfg.resolve_aliases(arg)),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        while let Some((addr, slot, offset)) = addresses.pop() {
+            for &value_use in def_use.uses(addr) {
+                let inst = match value_use {
+                    ValueUse::Inst(inst) => inst,
+                    ValueUse::Value(_) => {
+                        escaped.insert(slot);
+                        continue;
+                    }
+                };
+                match func.dfg[inst] {
+                    InstructionData::BinaryImm64 {
+                        opcode: Opcode::IaddImm,
+                        imm,
+                        ..
+                    } => match offset.checked_add(imm.into()) {
+                        Some(offset) => addresses.push((func.dfg.first_result(inst), slot, offset)),
+                        None => {
+                            escaped.insert(slot);
+                        }
+                    },
+                    InstructionData::Load {
+                        offset: access_offset,
+                        ..
+                    } => accesses.push(Access {
+                        inst,
+                        slot,
+                        offset: offset + offset_value(access_offset),
+                        size: func.dfg.value_type(func.dfg.first_result(inst)).bytes(),
+                        stored: None,
+                    }),
+                    InstructionData::Store {
+                        args,
+                        offset: access_offset,
+                        ..
+                    } if func.dfg.resolve_aliases(args[0]) != addr => {
+                        let value = func.dfg.resolve_aliases(args[0]);
+                        accesses.push(Access {
+                            inst,
+                            slot,
+                            offset: offset + offset_value(access_offset),
+                            size: func.dfg.value_type(value).bytes(),
+                            stored: Some(value),
+                        });
+                    }
+                    _ => {
+                        escaped.insert(slot);
+                    }
+                }
+            }
+        }
+
+        let mut local_slots = Self {
+            accesses: SecondaryMap::new(),
+            stored: SecondaryMap::new(),
+        };
+        let in_bounds = |access: &Access| {
+            let data = &func.stack_slots[access.slot];
+            access.offset >= 0 && access.offset + i64::from(access.size) <= i64::from(data.size)
+        };
+        if !accesses.iter().all(in_bounds) {
+            return local_slots;
+        }
+        for access in accesses {
+            if escaped.contains(access.slot)
+                || func.stack_slots[access.slot].kind != StackSlotKind::ExplicitSlot
+            {
+                continue;
+            }
+            local_slots.accesses[access.inst] = access.slot.into();
+            if let Some(value) = access.stored {
+                local_slots.stored[access.slot].push(value);
+            }
+        }
+        local_slots
+    }
+
+    /// Get the slot accessed by `inst`, if it is a load or store of a slot that doesn't escape.
+    pub fn slot(&self, inst: Inst) -> Option<StackSlot> {
+        self.accesses[inst].expand()
+    }
+
+    /// Get the values stored into `slot`, which must not escape.
+    pub fn stored_into(&self, slot: StackSlot) -> &[Value] {
+        &self.stored[slot]
+    }
+}
+
+/// Get the value of the immediate `offset`.
+fn offset_value(offset: Offset32) -> i64 {
+    let offset: i32 = offset.into();
+    offset.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::{types, InstBuilder, MemFlags, StackSlotData};
+
+    /// Build a function which stores its parameter into an explicit slot through `stack_addr`
+    /// and loads it back at `load_offset`, after `escape` optionally uses the address of the
+    /// slot. Returns the function, its store and its load.
+    fn store_then_load(
+        load_offset: i32,
+        escape: impl FnOnce(&mut FuncCursor, Value),
+    ) -> (Function, Inst, Inst) {
+        let mut func = Function::new();
+        let slot = func.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 16));
+        let block0 = func.dfg.make_block();
+        let value = func.dfg.append_block_param(block0, types::I64);
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let addr = cur.ins().stack_addr(types::I64, slot, 0);
+        let store = cur.ins().store(MemFlags::trusted(), value, addr, 0);
+        let addr = cur.ins().iadd_imm(addr, 8);
+        escape(&mut cur, addr);
+        let loaded = cur
+            .ins()
+            .load(types::I64, MemFlags::trusted(), addr, load_offset - 8);
+        let load = cur.func.dfg.value_def(loaded).unwrap_inst();
+        cur.ins().return_(&[loaded]);
+
+        (func, store, load)
+    }
+
+    #[test]
+    fn local_slot() {
+        let (func, store, load) = store_then_load(0, |_, _| {});
+        let local_slots = LocalSlots::for_function(&func, &DefUseGraph::for_function(&func));
+        let slot = local_slots.slot(store).unwrap();
+        assert_eq!(local_slots.slot(load), Some(slot));
+        assert_eq!(
+            local_slots.stored_into(slot),
+            func.dfg.inst_args(store)[..1].as_ref()
+        );
+    }
+
+    #[test]
+    fn escaping_slot() {
+        // The address of the slot is stored to memory.
+        let (func, store, load) = store_then_load(0, |cur, addr| {
+            cur.ins().store(MemFlags::trusted(), addr, addr, 0);
+        });
+        let local_slots = LocalSlots::for_function(&func, &DefUseGraph::for_function(&func));
+        assert_eq!(local_slots.slot(store), None);
+        assert_eq!(local_slots.slot(load), None);
+
+        // The address of the slot is offset by a dynamic value.
+        let (func, store, load) = store_then_load(0, |cur, addr| {
+            cur.ins().iadd(addr, addr);
+        });
+        let local_slots = LocalSlots::for_function(&func, &DefUseGraph::for_function(&func));
+        assert_eq!(local_slots.slot(store), None);
+        assert_eq!(local_slots.slot(load), None);
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        // The load reads past the end of the slot.
+        let (func, store, load) = store_then_load(12, |_, _| {});
+        let local_slots = LocalSlots::for_function(&func, &DefUseGraph::for_function(&func));
+        assert_eq!(local_slots.slot(store), None);
+        assert_eq!(local_slots.slot(load), None);
+    }
+}
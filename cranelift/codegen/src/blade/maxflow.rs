@@ -0,0 +1,333 @@
+//! A small max-flow / min-cut solver.
+//!
+//! This implements the Edmonds-Karp algorithm over a residual graph stored in flat vectors. Blade
+//! graphs have unit capacities almost everywhere, so each augmenting path found by the breadth-first
+//! search typically increases the flow by one.
+
+use crate::HashSet;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// Index of a node in a `FlowGraph`.
+pub type Node = usize;
+
+/// An edge of the residual graph.
+///
+/// Edges are always added in pairs: the edge at an even index `e` is an edge of the original
+/// graph, and the edge at `e ^ 1` is its reverse residual edge, with an initial capacity of zero.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct FlowEdge {
+    /// Destination node.
+    to: Node,
+    /// Remaining capacity of this edge in the residual graph.
+    capacity: u32,
+}
+
+/// A directed graph with edge capacities.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FlowGraph {
+    /// For each node, the indices into `edges` of its outgoing residual edges.
+    adjacency: Vec<Vec<usize>>,
+    /// All residual edges, in pairs.
+    edges: Vec<FlowEdge>,
+}
+
+impl FlowGraph {
+    /// Create a new empty graph.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a node to the graph and return it.
+    pub fn add_node(&mut self) -> Node {
+        self.adjacency.push(Vec::new());
+        self.adjacency.len() - 1
+    }
+
+    /// Get the number of nodes in the graph.
+    pub fn num_nodes(&self) -> usize {
+        self.adjacency.len()
+    }
+
+    /// Get the number of edges in the graph.
+    pub fn num_edges(&self) -> usize {
+        // Every edge has a residual twin.
+        self.edges.len() / 2
+    }
+
+    /// Add an edge from `from` to `to` with the given capacity.
+    pub fn add_edge(&mut self, from: Node, to: Node, capacity: u32) {
+        let e = self.edges.len();
+        self.edges.push(FlowEdge { to, capacity });
+        self.edges.push(FlowEdge {
+            to: from,
+            capacity: 0,
+        });
+        self.adjacency[from].push(e);
+        self.adjacency[to].push(e + 1);
+    }
+
+    /// Iterate over the edges of the original graph, with their capacities, in the order they
+    /// were added.
+    ///
+    /// The capacities are the remaining ones once a flow was computed.
+    pub fn edges(&self) -> impl Iterator<Item = (Node, Node, u32)> + '_ {
+        self.edges
+            .chunks(2)
+            .map(|pair| (pair[1].to, pair[0].to, pair[0].capacity))
+    }
+
+    /// Iterate over the successors of `node` in the original graph.
+    pub fn successors(&self, node: Node) -> impl Iterator<Item = Node> + '_ {
+        self.adjacency[node]
+            .iter()
+            .filter(|&&e| e % 2 == 0)
+            .map(move |&e| self.edges[e].to)
+    }
+
+    /// Iterate over the predecessors of `node` in the original graph.
+    pub fn predecessors(&self, node: Node) -> impl Iterator<Item = Node> + '_ {
+        self.adjacency[node]
+            .iter()
+            .filter(|&&e| e % 2 == 1)
+            .map(move |&e| self.edges[e].to)
+    }
+
+    /// Compute a maximum flow from `source` to `sink`, returning its value.
+    ///
+    /// This consumes the capacities of the graph, which is left in its residual state.
+    pub fn max_flow(&mut self, source: Node, sink: Node) -> u64 {
+        let mut flow = 0;
+        while let Some(parent_edges) = self.augmenting_path(source, sink) {
+            // Find the bottleneck capacity along the path.
+            let mut bottleneck = u32::max_value();
+            let mut node = sink;
+            while node != source {
+                let e = parent_edges[node];
+                bottleneck = bottleneck.min(self.edges[e].capacity);
+                node = self.edges[e ^ 1].to;
+            }
+
+            // Push flow along the path.
+            let mut node = sink;
+            while node != source {
+                let e = parent_edges[node];
+                self.edges[e].capacity -= bottleneck;
+                self.edges[e ^ 1].capacity = self.edges[e ^ 1].capacity.saturating_add(bottleneck);
+                node = self.edges[e ^ 1].to;
+            }
+
+            flow += u64::from(bottleneck);
+        }
+        flow
+    }
+
+    /// Compute a minimum cut between `source` and `sink`.
+    ///
+    /// This runs `max_flow` and returns the edges of the original graph that go from the nodes
+    /// still reachable from `source` in the residual graph to the nodes that aren't. Of all the
+    /// minimum cuts, this is the one closest to `source`.
+    pub fn min_cut(&mut self, source: Node, sink: Node) -> Vec<(Node, Node)> {
+        self.max_flow(source, sink);
+        let source_side = self.residual_reachable(source);
+        self.checked_cut(source, sink, &source_side)
+    }
+
+    /// Compute the minimum cut between `source` and `sink` closest to `sink`.
+    ///
+    /// This is like `min_cut`, except that the cut edges go from the nodes which can't reach
+    /// `sink` in the residual graph to the nodes which can.
+    pub fn min_cut_near_sink(&mut self, source: Node, sink: Node) -> Vec<(Node, Node)> {
+        self.max_flow(source, sink);
+        let mut source_side = self.residual_coreachable(sink);
+        for side in &mut source_side {
+            *side = !*side;
+        }
+        self.checked_cut(source, sink, &source_side)
+    }
+
+    /// Get the edges of the original graph from the nodes of `source_side` to the other nodes,
+    /// checking in debug builds that they form a cut.
+    fn checked_cut(&self, source: Node, sink: Node, source_side: &[bool]) -> Vec<(Node, Node)> {
+        let mut cut = Vec::new();
+        for (from, edges) in self.adjacency.iter().enumerate() {
+            if !source_side[from] {
+                continue;
+            }
+            for &e in edges.iter().filter(|&&e| e % 2 == 0) {
+                let to = self.edges[e].to;
+                if !source_side[to] {
+                    cut.push((from, to));
+                }
+            }
+        }
+        debug_assert!(
+            self.is_cut(source, sink, &cut),
+            "min cut leaves a path from {} to {}",
+            source,
+            sink
+        );
+        cut
+    }
+
+    /// Check that removing the `cut` edges leaves no path from `source` to `sink` in the original
+    /// graph.
+    ///
+    /// This ignores capacities, so it works on a residual graph as well.
+    pub fn is_cut(&self, source: Node, sink: Node, cut: &[(Node, Node)]) -> bool {
+        let cut: HashSet<(Node, Node)> = cut.iter().cloned().collect();
+        let mut visited = vec![false; self.num_nodes()];
+        let mut stack = vec![source];
+        visited[source] = true;
+        while let Some(node) = stack.pop() {
+            for &e in self.adjacency[node].iter().filter(|&&e| e % 2 == 0) {
+                let to = self.edges[e].to;
+                if !visited[to] && !cut.contains(&(node, to)) {
+                    visited[to] = true;
+                    stack.push(to);
+                }
+            }
+        }
+        !visited[sink]
+    }
+
+    /// Find a shortest path from `source` to `sink` with remaining capacity.
+    ///
+    /// Returns, for every node on the path, the edge used to reach it.
+    fn augmenting_path(&self, source: Node, sink: Node) -> Option<Vec<usize>> {
+        let mut parent_edges = vec![usize::max_value(); self.num_nodes()];
+        let mut visited = vec![false; self.num_nodes()];
+        let mut queue = VecDeque::new();
+        visited[source] = true;
+        queue.push_back(source);
+        while let Some(node) = queue.pop_front() {
+            for &e in &self.adjacency[node] {
+                let edge = &self.edges[e];
+                if edge.capacity == 0 || visited[edge.to] {
+                    continue;
+                }
+                visited[edge.to] = true;
+                parent_edges[edge.to] = e;
+                if edge.to == sink {
+                    return Some(parent_edges);
+                }
+                queue.push_back(edge.to);
+            }
+        }
+        None
+    }
+
+    /// Compute the set of nodes reachable from `source` through edges with remaining capacity.
+    fn residual_reachable(&self, source: Node) -> Vec<bool> {
+        let mut reachable = vec![false; self.num_nodes()];
+        let mut stack = vec![source];
+        reachable[source] = true;
+        while let Some(node) = stack.pop() {
+            for &e in &self.adjacency[node] {
+                let edge = &self.edges[e];
+                if edge.capacity > 0 && !reachable[edge.to] {
+                    reachable[edge.to] = true;
+                    stack.push(edge.to);
+                }
+            }
+        }
+        reachable
+    }
+
+    /// Compute the set of nodes from which `sink` is reachable through edges with remaining
+    /// capacity.
+    fn residual_coreachable(&self, sink: Node) -> Vec<bool> {
+        let mut coreachable = vec![false; self.num_nodes()];
+        let mut stack = vec![sink];
+        coreachable[sink] = true;
+        while let Some(node) = stack.pop() {
+            // Every residual edge into `node` is the twin of one of its outgoing edges.
+            for &e in &self.adjacency[node] {
+                let from = self.edges[e].to;
+                if self.edges[e ^ 1].capacity > 0 && !coreachable[from] {
+                    coreachable[from] = true;
+                    stack.push(from);
+                }
+            }
+        }
+        coreachable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_path() {
+        let mut g = FlowGraph::new();
+        let s = g.add_node();
+        let a = g.add_node();
+        let t = g.add_node();
+        g.add_edge(s, a, 1);
+        g.add_edge(a, t, 1);
+        assert_eq!(g.clone().max_flow(s, t), 1);
+        assert_eq!(g.min_cut(s, t), vec![(s, a)]);
+    }
+
+    #[test]
+    fn bottleneck() {
+        // Two sources feed a single node which fans out to two sinks: the cheapest cut is the
+        // single edge in the middle.
+        let mut g = FlowGraph::new();
+        let s = g.add_node();
+        let a = g.add_node();
+        let b = g.add_node();
+        let m = g.add_node();
+        let n = g.add_node();
+        let c = g.add_node();
+        let d = g.add_node();
+        let t = g.add_node();
+        g.add_edge(s, a, 1);
+        g.add_edge(s, b, 1);
+        g.add_edge(a, m, 1);
+        g.add_edge(b, m, 1);
+        g.add_edge(m, n, 1);
+        g.add_edge(n, c, 1);
+        g.add_edge(n, d, 1);
+        g.add_edge(c, t, 1);
+        g.add_edge(d, t, 1);
+        assert_eq!(g.clone().max_flow(s, t), 1);
+        assert_eq!(g.clone().min_cut(s, t), vec![(m, n)]);
+
+        assert!(g.is_cut(s, t, &[(m, n)]));
+        assert!(g.is_cut(s, t, &[(s, a), (s, b)]));
+        assert!(!g.is_cut(s, t, &[(s, a)]));
+        assert!(!g.is_cut(s, t, &[]));
+    }
+
+    #[test]
+    fn near_sink() {
+        // A chain with a single bottleneck capacity at each end: the two minimum cuts are its
+        // first and last edges.
+        let mut g = FlowGraph::new();
+        let s = g.add_node();
+        let a = g.add_node();
+        let b = g.add_node();
+        let t = g.add_node();
+        g.add_edge(s, a, 1);
+        g.add_edge(a, b, 2);
+        g.add_edge(b, t, 1);
+        assert_eq!(g.clone().min_cut(s, t), vec![(s, a)]);
+        assert_eq!(g.clone().min_cut_near_sink(s, t), vec![(b, t)]);
+
+        // Both sides agree when the minimum cut is unique.
+        g.add_edge(s, b, 1);
+        assert_eq!(g.clone().min_cut(s, t), vec![(b, t)]);
+        assert_eq!(g.min_cut_near_sink(s, t), vec![(b, t)]);
+    }
+
+    #[test]
+    fn disconnected() {
+        let mut g = FlowGraph::new();
+        let s = g.add_node();
+        let t = g.add_node();
+        assert_eq!(g.clone().max_flow(s, t), 0);
+        assert!(g.min_cut(s, t).is_empty());
+    }
+}
@@ -0,0 +1,2884 @@
+//! Blade: automatic Spectre v1 mitigation.
+//!
+//! Blade treats every value produced by a load as *transient*, since under speculative execution
+//! a load may observe out-of-bounds memory, and every operand which may leak through a cache side
+//! channel (addresses of memory accesses, stored values, branch conditions, and optionally call
+//! arguments) as a *sink*. It then computes a minimal cut of the dataflow graph separating the
+//! transient sources from the sinks, and protects each cut point, either with a speculation
+//! barrier or with speculative load hardening (SLH) of the loads feeding it. The `analysis`
+//! module computes the cut on its own, without touching the function, and a `BladeCutCache`
+//! shares the cuts of isomorphic graphs between the functions of a module.
+//!
+//! See "Automatically Eliminating Speculative Leaks from Cryptographic Code with Blade"
+//! (Vassena et al., POPL 2021).
+
+pub mod analysis;
+mod capabilities;
+mod cut_cache;
+mod def_use;
+mod graph;
+mod guards;
+mod lattice;
+mod local_slots;
+mod maxflow;
+#[cfg(feature = "testing_hooks")]
+pub mod phases;
+mod policy;
+mod speculation;
+mod strategy;
+
+pub use self::capabilities::{check_blade_settings, BladeCapabilities};
+pub use self::cut_cache::BladeCutCache;
+pub use self::graph::{BladeNode, EdgeWeights};
+pub use self::policy::{
+    BladePolicy, DefaultBladePolicy, InstClassification, SinkClasses, SinkOptions, SourceOptions,
+};
+pub use self::strategy::{BladeCut, BladeStrategy, FenceStrategy, SlhStrategy};
+
+use self::graph::{build_blade_graph_for_func, BladeGraph};
+use self::maxflow::Node;
+use self::strategy::builtin_strategy;
+use crate::binemit::CodeOffset;
+use crate::cursor::{Cursor, EncCursor};
+use crate::dominator_tree::DominatorTree;
+use crate::entity::EntitySet;
+use crate::flowgraph::ControlFlowGraph;
+use crate::ir::condcodes::IntCC;
+use crate::ir::instructions::BranchInfo;
+use crate::ir::{
+    Bounds, Function, Inst, InstBuilder, InstructionData, Opcode, SourceLoc, Value, ValueDef,
+    ValueList,
+};
+use crate::isa::TargetIsa;
+use crate::loop_analysis::LoopAnalysis;
+use crate::result::{CodegenError, CodegenResult};
+use crate::settings::{Blade, BladeEngine, BladeSlhMask, BladeTransientParams, Flags};
+use crate::timing;
+use crate::verifier::VerifierErrors;
+use crate::HashMap;
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+use core::iter::Sum;
+use core::ops::Add;
+use core::time::Duration;
+use log::debug;
+use target_lexicon::Architecture;
+
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
+
+/// Statistics about the mitigations Blade inserted into a function.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct BladeStats {
+    /// Number of values defined in the function before Blade ran.
+    pub values: usize,
+    /// Number of nodes in the Blade graph, besides the source and sink nodes.
+    pub graph_nodes: usize,
+    /// Number of edges in the Blade graph.
+    pub graph_edges: usize,
+    /// Number of transient values in the Blade graph.
+    pub sources: usize,
+    /// Number of instructions which leak some of their operands in the Blade graph.
+    pub sinks: usize,
+    /// Value of the maximum flow through the Blade graph, i.e. the total weight of the cut.
+    pub max_flow: u64,
+    /// Number of edges in the minimal cut.
+    pub cut_size: usize,
+    /// Every speculation barrier that was placed, in placement order.
+    pub fence_points: Vec<FencePoint>,
+    /// Every load protected with speculative load hardening, in protection order.
+    pub slh_points: Vec<Inst>,
+    /// Number of distinct transient sources, loads or block parameters, which the edges of the
+    /// cut trace back to with speculative load hardening. Each is protected once, however many
+    /// edges it flows through.
+    pub slh_sources: usize,
+    /// Every instruction inserted by speculative load hardening to compute the masks and mask the
+    /// addresses of the loads, in layout order.
+    pub slh_insts: Vec<Inst>,
+    /// Number of loads protected with a speculation barrier rather than hardened, because the
+    /// instructions added by speculative load hardening exhausted `blade_slh_size_budget`.
+    pub slh_budget_fallbacks: usize,
+    /// Number of control flow edges split to hold a speculation barrier, see
+    /// `blade_split_edges`.
+    pub split_edges: usize,
+    /// Time spent in the Blade pass. This is only measured with the `std` feature.
+    pub time: Duration,
+    /// Time spent computing the minimal cut, which is part of `time`. This is only measured
+    /// with the `std` feature.
+    pub solver_time: Duration,
+    /// Whether the Blade graph exceeded `blade_max_edges_log2`, so that every basic block was
+    /// fenced instead of the cut points.
+    pub fell_back: bool,
+    /// Whether the minimal cut was reused from an isomorphic graph in the `BladeCutCache`, rather
+    /// than computed.
+    pub cut_reused: bool,
+    /// Every instruction the Blade graph doesn't model exactly, or which Blade couldn't protect
+    /// as requested, so that its coverage can be audited.
+    pub diagnostics: Vec<BladeDiagnostic>,
+    /// Every mitigation that was placed: the speculation barriers, then the loads protected with
+    /// speculative load hardening.
+    pub mitigations: Vec<BladeMitigation>,
+    /// Every load producing a transient value in the Blade graph, in instruction order.
+    ///
+    /// This is empty if no graph was built, and coarsening only keeps one load of every merged
+    /// node.
+    pub source_sites: Vec<BladeSite>,
+    /// Every instruction which leaks some of its operands in the Blade graph, in instruction
+    /// order, with the same caveats as `source_sites`.
+    pub sink_sites: Vec<BladeSite>,
+    /// The offset of every speculation barrier in the emitted code, in code order, so that they
+    /// can be patched out on hardware which doesn't need them. Every barrier is `LFENCE_SIZE`
+    /// bytes long.
+    ///
+    /// The new backends don't keep track of instruction offsets, so this is empty there.
+    pub barrier_offsets: Vec<CodeOffset>,
+    /// The number of bytes the speculation barriers and the instructions inserted by speculative
+    /// load hardening take in the emitted code, i.e. the growth of the code of the function
+    /// attributable to Blade, besides the jumps of split edges and the register moves and spills
+    /// its new values may need.
+    ///
+    /// Like `barrier_offsets`, this is only known with the legacy backends, and `None` with the
+    /// new ones.
+    pub code_size_growth: Option<CodeOffset>,
+    /// A static estimate of the run-time cost of the mitigations.
+    pub estimate: BladeCostEstimate,
+    /// What the function relies on from its callers and guarantees to its callees.
+    pub contract: BladeContract,
+}
+
+impl BladeStats {
+    /// Get the number of speculation barriers that were placed.
+    pub fn fences(&self) -> usize {
+        self.fence_points.len()
+    }
+
+    /// Get the number of loads protected with speculative load hardening.
+    pub fn slh_loads(&self) -> usize {
+        self.slh_points.len()
+    }
+
+    /// The header of the rows written by `csv_row`.
+    pub const CSV_HEADER: &'static str = "function,values,graph_edges,cut_size,fences,slh_loads,\
+        fell_back,time_us,graph_nodes,sources,sinks,max_flow,solver_time_us,slh_insts,\
+        code_size_growth";
+
+    /// Format these statistics as a CSV row for the function named `function`, with the columns
+    /// of `CSV_HEADER` and without a line terminator. The code size growth is empty when it is
+    /// unknown.
+    pub fn csv_row(&self, function: &str) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            csv_field(function),
+            self.values,
+            self.graph_edges,
+            self.cut_size,
+            self.fences(),
+            self.slh_loads(),
+            self.fell_back,
+            self.time.as_micros(),
+            self.graph_nodes,
+            self.sources,
+            self.sinks,
+            self.max_flow,
+            self.solver_time.as_micros(),
+            self.slh_insts.len(),
+            self.code_size_growth
+                .map_or(String::new(), |bytes| bytes.to_string())
+        )
+    }
+}
+
+/// A static estimate of the run-time cost of the Blade mitigations of some code.
+///
+/// Every instruction and mitigation is weighted by the execution count of its block, as
+/// recorded in `Function::blade_block_counts`, or failing a profile by `LOOP_WEIGHT` to the
+/// power of its loop depth. Estimates of several functions add up to one for all of them, so
+/// that the overhead of a whole module can be told before running it. The estimate is rough: it
+/// ignores the instructions which a barrier actually stalls, and the work hidden by
+/// out-of-order execution.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct BladeCostEstimate {
+    /// The weighted number of instructions, not counting those added by Blade.
+    pub insts: u64,
+    /// The weighted number of speculation barriers.
+    pub fences: u64,
+    /// The weighted number of loads protected with speculative load hardening.
+    pub slh_loads: u64,
+}
+
+impl BladeCostEstimate {
+    /// The weight of a block in one more loop than another, without a profile.
+    pub const LOOP_WEIGHT: u64 = 8;
+    /// The deepest loop nesting told apart from shallower ones, without a profile.
+    pub const MAX_LOOP_DEPTH: u32 = 4;
+    /// The estimated cost of a speculation barrier, in instructions.
+    pub const FENCE_COST: u64 = 30;
+    /// The estimated cost of hardening a load, in instructions: computing and applying its mask.
+    pub const SLH_COST: u64 = 3;
+
+    /// Estimate the ratio of the run time of the protected code to that of the unprotected code.
+    ///
+    /// This is 1 for code without mitigations.
+    pub fn slowdown(&self) -> f64 {
+        if self.insts == 0 {
+            return 1.0;
+        }
+        let overhead = self
+            .fences
+            .saturating_mul(Self::FENCE_COST)
+            .saturating_add(self.slh_loads.saturating_mul(Self::SLH_COST));
+        1.0 + overhead as f64 / self.insts as f64
+    }
+}
+
+impl Add for BladeCostEstimate {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            insts: self.insts.saturating_add(other.insts),
+            fences: self.fences.saturating_add(other.fences),
+            slh_loads: self.slh_loads.saturating_add(other.slh_loads),
+        }
+    }
+}
+
+impl Sum for BladeCostEstimate {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), Add::add)
+    }
+}
+
+/// What a function protected by Blade relies on from its callers, and guarantees to its callees.
+///
+/// Blade treats function parameters as stable by default, which is only sound if every caller
+/// treats the arguments it passes as sinks. Functions compiled with different settings, or only
+/// some of them hardened, can break this: a hardened function called by one that isn't may leak
+/// the transient arguments it is passed, and a hardened function passing transient arguments to
+/// one that isn't leaks them through its callee. `may_call` tells which calls are safe.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct BladeContract {
+    /// Whether Blade placed mitigations in the function, rather than only analyzing it.
+    pub hardened: bool,
+    /// Whether the function protects itself from transient arguments, as with
+    /// `blade_transient_params=all` or when every basic block was fenced.
+    pub transient_params: bool,
+    /// Whether the function only passes stable arguments to its callees, as when call arguments
+    /// are in `blade_sink_classes`.
+    pub stable_call_args: bool,
+}
+
+impl BladeContract {
+    /// The contract of a function hardened with `flags` and the default policy, which
+    /// `fell_back` to fencing every basic block or not.
+    fn from_flags(flags: &Flags, fell_back: bool) -> Self {
+        let stable_call_args = SinkClasses::from_flags(flags).contains(SinkClasses::CALL_ARGUMENTS);
+        Self {
+            hardened: true,
+            transient_params: fell_back
+                || flags.blade_transient_params() == BladeTransientParams::All,
+            stable_call_args: stable_call_args && !fell_back,
+        }
+    }
+
+    /// Can a function with this contract call one with the `callee` contract without either
+    /// leaking transient values which the other protects?
+    ///
+    /// Calls between functions which are both unhardened promise nothing, so they are always
+    /// fine.
+    pub fn may_call(&self, callee: &Self) -> bool {
+        !(self.hardened || callee.hardened) || self.stable_call_args || callee.transient_params
+    }
+}
+
+/// Estimate the cost of the mitigations recorded in `stats` for `func`, which they were placed
+/// in.
+fn estimate_cost(func: &Function, stats: &BladeStats) -> BladeCostEstimate {
+    let profiled = func.blade_block_counts.values().any(|&count| count != 0);
+    let mut loops = LoopAnalysis::new();
+    if !profiled {
+        let cfg = ControlFlowGraph::with_function(func);
+        let domtree = DominatorTree::with_function(func, &cfg);
+        loops.compute(func, &cfg, &domtree);
+    }
+    let weight = |inst: Inst| match func.layout.inst_block(inst) {
+        Some(block) if profiled => func.blade_block_counts[block],
+        Some(block) => {
+            let depth = u32::try_from(loops.loop_depth(block)).unwrap_or(u32::max_value());
+            BladeCostEstimate::LOOP_WEIGHT.pow(depth.min(BladeCostEstimate::MAX_LOOP_DEPTH))
+        }
+        None => 0,
+    };
+
+    let mut added = EntitySet::with_capacity(func.dfg.num_insts());
+    for &inst in &stats.slh_insts {
+        added.insert(inst);
+    }
+    let insts = func
+        .layout
+        .blocks()
+        .flat_map(|block| func.layout.block_insts(block))
+        .filter(|&inst| !added.contains(inst))
+        .map(weight)
+        .fold(0, u64::saturating_add);
+    let fences = stats
+        .fence_points
+        .iter()
+        .map(|&point| match point {
+            FencePoint::Before(inst) | FencePoint::After(inst) => weight(inst),
+        })
+        .fold(0, u64::saturating_add);
+    let slh_loads = stats
+        .slh_points
+        .iter()
+        .map(|&inst| weight(inst))
+        .fold(0, u64::saturating_add);
+    BladeCostEstimate {
+        insts,
+        fences,
+        slh_loads,
+    }
+}
+
+/// Quote `field` for a CSV row if it contains a separator, a quote, or a line break.
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// A Spectre mitigation placed by Blade.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct BladeMitigation {
+    /// The instruction the mitigation protects.
+    pub inst: Inst,
+    /// How the instruction is protected.
+    pub kind: MitigationKind,
+    /// The source location of `inst`, which tells what part of the source program needed the
+    /// mitigation.
+    pub srcloc: SourceLoc,
+    /// The offset of the code of `inst` from the beginning of the function, if known.
+    ///
+    /// This is filled in by `Context::compile` with the legacy backends. A barrier placed before
+    /// the instruction starts at this offset, and one placed after it ends the instruction's
+    /// code.
+    pub offset: Option<CodeOffset>,
+}
+
+/// An instruction of the Blade graph, so that external tools can check the protections of the
+/// emitted code against it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct BladeSite {
+    /// The instruction.
+    pub inst: Inst,
+    /// The source location of `inst`.
+    pub srcloc: SourceLoc,
+    /// The offset of the code of `inst` from the beginning of the function, if known.
+    ///
+    /// This is filled in by `Context::compile` with the legacy backends, like the offsets of the
+    /// mitigations.
+    pub offset: Option<CodeOffset>,
+}
+
+/// The kinds of Spectre mitigations placed by Blade.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub enum MitigationKind {
+    /// A speculation barrier immediately before the instruction.
+    FenceBefore,
+    /// A speculation barrier immediately after the instruction.
+    FenceAfter,
+    /// Speculative load hardening of the address or the value of the load, as set by
+    /// `blade_slh_mask`.
+    Slh,
+}
+
+impl fmt::Display for MitigationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::FenceBefore => "fence before",
+            Self::FenceAfter => "fence after",
+            Self::Slh => "slh",
+        })
+    }
+}
+
+/// Run the Blade pass on `func`, inserting Spectre mitigations as configured by the ISA flags.
+///
+/// The sources and sinks of transient values are classified by `policy`, or by the
+/// `DefaultBladePolicy` of the flags if there is none. The minimal cut is protected by
+/// `strategy`, or by the `FenceStrategy` or `SlhStrategy` of the `blade` setting if there is none.
+/// The cut is looked up in `cut_cache`, and added to it, if there is one.
+///
+/// With `blade=analyze`, `func` is left untouched and the returned statistics describe the
+/// mitigations that `blade=lfence` would insert, except for the barriers after calls requested by
+/// `blade_fence_after_calls`, which are placed in every mode on targets with speculation barriers.
+pub(crate) fn do_blade(
+    func: &mut Function,
+    isa: &dyn TargetIsa,
+    policy: Option<&dyn BladePolicy>,
+    strategy: Option<&mut dyn BladeStrategy>,
+    cut_cache: Option<&mut BladeCutCache>,
+) -> CodegenResult<BladeStats> {
+    let _tt = timing::blade();
+    #[cfg(feature = "std")]
+    let start = std::time::Instant::now();
+
+    // Refuse to harden code for a target which would silently drop the mitigations. Analysis
+    // leaves `func` untouched, so it is fine on any target.
+    check_blade_settings(isa)?;
+    let blade = isa.flags().blade();
+
+    let values = num_defined_values(func);
+    let (stats, mitigations, estimate) = match blade {
+        Blade::Analyze => {
+            let mut call_fences = BladeStats::default();
+            // A target without barriers couldn't emit them, so they are only reported there.
+            if isa.flags().blade_fence_after_calls() && isa.blade_capabilities().barriers {
+                fence_after_calls(func, &mut call_fences);
+            }
+            // Place the fences on a copy, so the report matches exactly what enforcement does.
+            // The copy may also have new blocks holding some of them.
+            let mut copy = func.clone();
+            let mut stats = run_blade(&mut copy, isa, Blade::Lfence, policy, strategy, cut_cache)?;
+            // The barriers after calls are already in the copy, so `run_blade` didn't list them.
+            call_fences.fence_points.append(&mut stats.fence_points);
+            stats.fence_points = call_fences.fence_points;
+            let mitigations = list_mitigations(&copy, &stats);
+            let estimate = estimate_cost(&copy, &stats);
+            (stats, mitigations, estimate)
+        }
+        blade => {
+            let stats = run_blade(func, isa, blade, policy, strategy, cut_cache)?;
+            let mitigations = list_mitigations(func, &stats);
+            let estimate = estimate_cost(func, &stats);
+            (stats, mitigations, estimate)
+        }
+    };
+    // A custom policy may classify the parameters and call arguments either way, so assume it
+    // upholds neither side of the contract.
+    let contract = match blade {
+        Blade::None | Blade::Analyze => BladeContract::default(),
+        _ if policy.is_some() => BladeContract {
+            hardened: true,
+            ..BladeContract::default()
+        },
+        _ => BladeContract::from_flags(isa.flags(), stats.fell_back),
+    };
+    let stats = BladeStats {
+        values,
+        mitigations,
+        estimate,
+        contract,
+        ..stats
+    };
+
+    #[cfg(feature = "std")]
+    let stats = BladeStats {
+        time: start.elapsed(),
+        ..stats
+    };
+    Ok(stats)
+}
+
+/// Count the values defined by the instructions and block parameters of `func`.
+fn num_defined_values(func: &Function) -> usize {
+    func.layout
+        .blocks()
+        .map(|block| {
+            let results: usize = func
+                .layout
+                .block_insts(block)
+                .map(|inst| func.dfg.inst_results(inst).len())
+                .sum();
+            func.dfg.num_block_params(block) + results
+        })
+        .sum()
+}
+
+/// List the mitigations recorded in `stats`, with the source locations of their instructions.
+fn list_mitigations(func: &Function, stats: &BladeStats) -> Vec<BladeMitigation> {
+    let fences = stats.fence_points.iter().map(|&point| match point {
+        FencePoint::Before(inst) => (inst, MitigationKind::FenceBefore),
+        FencePoint::After(inst) => (inst, MitigationKind::FenceAfter),
+    });
+    let slh = stats
+        .slh_points
+        .iter()
+        .map(|&inst| (inst, MitigationKind::Slh));
+    fences
+        .chain(slh)
+        .map(|(inst, kind)| BladeMitigation {
+            inst,
+            kind,
+            srcloc: func.srclocs[inst],
+            offset: None,
+        })
+        .collect()
+}
+
+/// Insert the mitigations of the `blade` mode into `func`, protecting the minimal cut with
+/// `strategy` if there is one, and reusing the cuts of `cut_cache` if there is one.
+fn run_blade(
+    func: &mut Function,
+    isa: &dyn TargetIsa,
+    blade: Blade,
+    policy: Option<&dyn BladePolicy>,
+    strategy: Option<&mut dyn BladeStrategy>,
+    cut_cache: Option<&mut BladeCutCache>,
+) -> CodegenResult<BladeStats> {
+    let flags = isa.flags();
+    let mut stats = BladeStats::default();
+
+    if flags.blade_fence_after_calls() {
+        fence_after_calls(func, &mut stats);
+    }
+
+    if blade == Blade::None {
+        return Ok(stats);
+    }
+
+    let default_policy;
+    let policy = match policy {
+        Some(policy) => policy,
+        None => {
+            default_policy = DefaultBladePolicy::from_flags(func, flags);
+            &default_policy
+        }
+    };
+
+    let custom_strategy = strategy.is_some();
+    let mut builtin;
+    let strategy: &mut dyn BladeStrategy = match strategy {
+        Some(strategy) => strategy,
+        None => {
+            builtin = builtin_strategy(flags, blade);
+            builtin.as_mut()
+        }
+    };
+
+    // The typing only knows where barriers go, so it can't stand in for a custom strategy.
+    let engine = flags.blade_engine();
+    if engine == BladeEngine::Dataflow && blade == Blade::Lfence && !custom_strategy {
+        let sinks = {
+            let _tt = timing::blade_typing();
+            lattice::fenced_sinks(func, policy)
+        };
+        debug!(
+            "Blade: {} sinks fenced by typing {}",
+            sinks.len(),
+            func.name
+        );
+        for sink in sinks {
+            let point = FencePoint::Before(sink);
+            if insert_fence(func, point) {
+                stats.fence_points.push(point);
+            }
+        }
+        return Ok(stats);
+    }
+
+    let blade_graph = build_graph(func, flags, strategy, Some(policy));
+    for diagnostic in blade_graph.diagnostics() {
+        debug!(
+            "Blade: {} in {}: {}",
+            func.dfg.display_inst(diagnostic.inst, None),
+            func.name,
+            diagnostic.kind
+        );
+    }
+    stats.graph_nodes = blade_graph.num_nodes();
+    stats.graph_edges = blade_graph.num_edges();
+    stats.sources = blade_graph.num_sources();
+    stats.sinks = blade_graph.num_sinks();
+    stats.diagnostics = blade_graph.diagnostics().to_vec();
+    let loads = blade_graph
+        .sources()
+        .filter_map(|value| match func.dfg.value_def(value) {
+            ValueDef::Result(inst, _) => Some(inst),
+            ValueDef::Param(..) => None,
+        });
+    stats.source_sites = graph_sites(func, loads);
+    stats.sink_sites = graph_sites(func, blade_graph.sinks());
+    debug!(
+        "Blade: {} nodes, {} edges, {} sources and {} sinks in {}",
+        stats.graph_nodes, stats.graph_edges, stats.sources, stats.sinks, func.name
+    );
+    let max_edges = 1usize
+        .checked_shl(flags.blade_max_edges_log2().into())
+        .unwrap_or(usize::max_value());
+    if blade_graph.num_edges() > max_edges {
+        debug!(
+            "Blade: {} edges in {}, fencing every basic block",
+            blade_graph.num_edges(),
+            func.name
+        );
+        stats.fell_back = true;
+        fence_every_block(func, &mut stats);
+        return Ok(stats);
+    }
+
+    let cut_edges = {
+        let _tt = timing::blade_min_cut();
+        #[cfg(feature = "std")]
+        let start = std::time::Instant::now();
+        let bias = flags.blade_cut_bias();
+        let (cut_edges, max_flow) = match cut_cache {
+            Some(cache) => {
+                let (cut_edges, max_flow, reused) = blade_graph.min_cut_cached(bias, cache);
+                stats.cut_reused = reused;
+                (cut_edges, max_flow)
+            }
+            None => blade_graph.min_cut_with_flow(bias),
+        };
+        #[cfg(feature = "std")]
+        {
+            stats.solver_time = start.elapsed();
+        }
+        stats.max_flow = max_flow;
+        cut_edges
+    };
+    stats.cut_size = cut_edges.len();
+    debug!(
+        "Blade: {} cut edges with a max flow of {} in {} ({:?}), solved in {:?}",
+        cut_edges.len(),
+        stats.max_flow,
+        func.name,
+        blade,
+        stats.solver_time
+    );
+
+    insert_mitigations(func, isa, strategy, &blade_graph, &cut_edges, &mut stats)?;
+    if engine == BladeEngine::Check && blade != Blade::LfencePerBlock {
+        check_typing(func, policy, &stats)?;
+    }
+    Ok(stats)
+}
+
+/// Locate the instructions `insts` of the Blade graph of `func`, sorted and without duplicates.
+fn graph_sites(func: &Function, insts: impl Iterator<Item = Inst>) -> Vec<BladeSite> {
+    let mut insts: Vec<Inst> = insts.collect();
+    insts.sort_unstable();
+    insts.dedup();
+    insts
+        .into_iter()
+        .map(|inst| BladeSite {
+            inst,
+            srcloc: func.srclocs[inst],
+            offset: None,
+        })
+        .collect()
+}
+
+/// Check that no sink of `func` receives a transient value once it is protected as recorded in
+/// `stats`, reporting every sink which does as a verifier error.
+fn check_typing(
+    func: &Function,
+    policy: &dyn BladePolicy,
+    stats: &BladeStats,
+) -> CodegenResult<()> {
+    let _tt = timing::blade_typing();
+    let violations = lattice::check(func, policy, &stats.slh_points);
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let mut errors = VerifierErrors::default();
+    for violation in violations {
+        errors.report((
+            violation.inst,
+            format!("{}", func.dfg.display_inst(violation.inst, None)),
+            format!(
+                "transient {} reaches this sink in spite of the Blade protections",
+                violation.value
+            ),
+        ));
+    }
+    Err(errors.into())
+}
+
+/// Build the Blade graph of `func` as configured by `flags`, classifying its instructions with
+/// `policy` if there is one, and weighting its edges as `strategy` asks.
+fn build_graph(
+    func: &Function,
+    flags: &Flags,
+    strategy: &dyn BladeStrategy,
+    policy: Option<&dyn BladePolicy>,
+) -> BladeGraph {
+    let _tt = timing::blade_graph();
+    let default_policy;
+    let policy = match policy {
+        Some(policy) => policy,
+        None => {
+            default_policy = DefaultBladePolicy::from_flags(func, flags);
+            &default_policy
+        }
+    };
+    build_blade_graph_for_func(
+        func,
+        policy,
+        strategy.edge_weights(flags),
+        flags.blade_coarsen(),
+    )
+}
+
+/// Protect every edge of `cut_edges` in `func` with `strategy`.
+fn insert_mitigations(
+    func: &mut Function,
+    isa: &dyn TargetIsa,
+    strategy: &mut dyn BladeStrategy,
+    blade_graph: &BladeGraph,
+    cut_edges: &[(Node, Node)],
+    stats: &mut BladeStats,
+) -> CodegenResult<()> {
+    let _tt = timing::blade_insert();
+    let mut cut = BladeCut::new(func, isa, blade_graph, stats);
+    for &(from, to) in cut_edges {
+        if from == blade_graph.source_node {
+            match blade_graph.bladenode(to) {
+                Some(BladeNode::ValueDef(value)) => strategy.protect_source(&mut cut, value)?,
+                other => panic!("unexpected successor of the source node: {:?}", other),
+            }
+        } else if to == blade_graph.sink_node {
+            match blade_graph.bladenode(from) {
+                Some(BladeNode::Sink(inst)) => strategy.protect_sink(&mut cut, inst)?,
+                other => panic!("unexpected predecessor of the sink node: {:?}", other),
+            }
+        } else {
+            match (blade_graph.bladenode(from), blade_graph.bladenode(to)) {
+                (Some(BladeNode::ValueDef(value)), Some(to)) => {
+                    strategy.protect_edge(&mut cut, value, to)?
+                }
+                other => panic!("unexpected cut edge {:?}", other),
+            }
+        }
+    }
+    strategy.finish(&mut cut)
+}
+
+/// Mark every call in `func` to be followed by a speculation barrier.
+fn fence_after_calls(func: &mut Function, stats: &mut BladeStats) {
+    for block in func.layout.blocks() {
+        for inst in func.layout.block_insts(block) {
+            if func.dfg[inst].opcode().is_call() && !func.post_lfence[inst] {
+                func.post_lfence[inst] = true;
+                stats.fence_points.push(FencePoint::After(inst));
+            }
+        }
+    }
+}
+
+/// Place a speculation barrier at the beginning of every basic block of `func`.
+///
+/// This stops all speculative execution past branches, without needing the Blade graph.
+fn fence_every_block(func: &mut Function, stats: &mut BladeStats) {
+    let mut points = Vec::new();
+    for block in func.layout.blocks() {
+        if let Some(first) = func.layout.first_inst(block) {
+            points.push(FencePoint::Before(first));
+        }
+        for inst in func.layout.block_insts(block) {
+            if func.dfg[inst].opcode().is_branch() && func.layout.next_inst(inst).is_some() {
+                points.push(FencePoint::After(inst));
+            }
+        }
+    }
+    for point in points {
+        if insert_fence(func, point) {
+            stats.fence_points.push(point);
+        }
+    }
+}
+
+/// Get the branches passing `value` to the block parameter `param`.
+///
+/// Every node of the Blade graph stands for a single value without coarsening, so these branches
+/// carry all of the flow from `value` into `param`.
+fn branches_into_param(func: &Function, value: Value, param: Value) -> Vec<Inst> {
+    let mut branches = Vec::new();
+    let (dest, num) = match func.dfg.value_def(param) {
+        ValueDef::Param(block, num) => (block, num),
+        ValueDef::Result(..) => return branches,
+    };
+    for block in func.layout.blocks() {
+        for inst in func.layout.block_insts(block) {
+            match func.dfg.analyze_branch(inst) {
+                BranchInfo::SingleDest(target, args)
+                    if target == dest && func.dfg.resolve_aliases(args[num]) == value =>
+                {
+                    branches.push(inst);
+                }
+                _ => {}
+            }
+        }
+    }
+    branches
+}
+
+/// Get the unconditional jump through which `branch` passes its block arguments, where a
+/// speculation barrier only stalls the paths taking `branch`.
+///
+/// A conditional branch is redirected to a new block at the end of the function, which jumps
+/// to its destination with its arguments.
+fn fence_edge(
+    func: &mut Function,
+    isa: &dyn TargetIsa,
+    branch: Inst,
+    stats: &mut BladeStats,
+) -> Inst {
+    if func.dfg[branch].opcode().is_terminator() {
+        return branch;
+    }
+    let dest = func.dfg[branch]
+        .branch_destination()
+        .expect("conditional branch without a destination");
+    let fixed_args = func.dfg.inst_fixed_args(branch).to_vec();
+    let block_args = func.dfg.inst_variable_args(branch).to_vec();
+    let edge_block = func.dfg.make_block();
+    func.layout.append_block(edge_block);
+    {
+        let dfg = &mut func.dfg;
+        let fixed_args = ValueList::from_slice(&fixed_args, &mut dfg.value_lists);
+        let data = &mut dfg[branch];
+        *data.branch_destination_mut().expect("branch") = edge_block;
+        data.take_value_list(); // Drop the block arguments.
+        data.put_value_list(fixed_args);
+    }
+    if func.update_encoding(branch, isa).is_err() {
+        panic!("a branch without block arguments must be encodable");
+    }
+
+    let mut pos = EncCursor::new(func, isa);
+    pos.use_srcloc(branch);
+    pos.goto_first_insertion_point(edge_block);
+    let jump = pos.ins().jump(dest, &block_args);
+    stats.split_edges += 1;
+    jump
+}
+
+/// Get the point right before the definition of `value`.
+fn before_def(func: &Function, value: Value) -> FencePoint {
+    match func.dfg.value_def(value) {
+        ValueDef::Result(inst, _) => FencePoint::Before(inst),
+        ValueDef::Param(block, _) => FencePoint::Before(
+            func.layout
+                .first_inst(block)
+                .expect("block parameter defined in an empty block"),
+        ),
+    }
+}
+
+/// Get the first point where `value` is defined, i.e. right after the instruction defining it, or
+/// right before the first instruction of the block of a parameter.
+fn after_def(func: &Function, value: Value) -> FencePoint {
+    match func.dfg.value_def(value) {
+        ValueDef::Result(inst, _) => FencePoint::After(inst),
+        ValueDef::Param(..) => before_def(func, value),
+    }
+}
+
+/// A location where Blade places a speculation barrier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub enum FencePoint {
+    /// Immediately before the instruction.
+    Before(Inst),
+    /// Immediately after the instruction.
+    After(Inst),
+}
+
+/// An instruction which Blade skips or only approximates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct BladeDiagnostic {
+    /// The instruction.
+    pub inst: Inst,
+    /// How Blade falls short of modeling or protecting it.
+    pub kind: BladeDiagnosticKind,
+    /// The source location of `inst`.
+    pub srcloc: SourceLoc,
+}
+
+/// The ways in which Blade may fall short of modeling or protecting an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub enum BladeDiagnosticKind {
+    /// The instruction has side effects which may depend on its operands, such as a conditional
+    /// trap or a trapping division when trap conditions aren't in `blade_sink_classes`, a
+    /// return, or a call when call arguments aren't in `blade_sink_classes`, but its operands
+    /// are not treated as sinks. Transient values may leak through it.
+    UnhandledSideEffect,
+    /// The instruction reads state which is not one of its operands, such as the pinned
+    /// register, so the dependencies of its results are unknown.
+    ImplicitOperand,
+    /// The instruction has several results whose dependencies Blade doesn't know, such as a
+    /// call, so each is assumed to depend on every operand. This is conservative, but may cost
+    /// extra mitigations.
+    MultipleResults,
+    /// The load was hardened against the fake bounds of `blade_slh_fake_bounds`, which cover
+    /// the whole address space, so it isn't protected at all.
+    FakeBounds,
+    /// Speculative load hardening couldn't protect the load, or the block parameter declared
+    /// transient, or exhausted `blade_slh_size_budget` before reaching it, so it got a
+    /// speculation barrier instead. This is as safe, but slower.
+    SlhFallback,
+}
+
+impl BladeDiagnosticKind {
+    /// Can transient values leak through the instruction despite Blade?
+    pub fn is_unprotected(self) -> bool {
+        match self {
+            Self::UnhandledSideEffect | Self::ImplicitOperand | Self::FakeBounds => true,
+            Self::MultipleResults | Self::SlhFallback => false,
+        }
+    }
+}
+
+impl fmt::Display for BladeDiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::UnhandledSideEffect => "operands of side effect not treated as sinks",
+            Self::ImplicitOperand => "implicit operand not tracked",
+            Self::MultipleResults => "results assumed to depend on every operand",
+            Self::FakeBounds => "load hardened against fake bounds",
+            Self::SlhFallback => "fenced instead of hardened",
+        })
+    }
+}
+
+/// Place a speculation barrier at `point`.
+///
+/// Returns `true` if a new barrier was placed, and `false` if there already was one.
+fn insert_fence(func: &mut Function, point: FencePoint) -> bool {
+    let flag = match point {
+        FencePoint::Before(inst) => &mut func.pre_lfence[inst],
+        FencePoint::After(inst) => &mut func.post_lfence[inst],
+    };
+    !core::mem::replace(flag, true)
+}
+
+/// Get the point at the beginning of the basic block containing `point`.
+///
+/// Cranelift blocks may end with several branches, so the basic block containing `point` starts
+/// either at the beginning of its block, or right after the closest branch preceding it. In the
+/// latter case, `BladeCut::insert_fence` also fences the edge taken by the branch.
+fn beginning_of_block(func: &Function, point: FencePoint) -> FencePoint {
+    let mut inst = match point {
+        FencePoint::Before(inst) => inst,
+        FencePoint::After(inst) if func.dfg[inst].opcode().is_branch() => return point,
+        FencePoint::After(inst) => inst,
+    };
+    while let Some(prev) = func.layout.prev_inst(inst) {
+        if func.dfg[prev].opcode().is_branch() {
+            return FencePoint::After(prev);
+        }
+        inst = prev;
+    }
+    FencePoint::Before(inst)
+}
+
+/// State of the speculative load hardening of a function.
+struct SLHContext {
+    /// Loads which have already been protected.
+    protected_loads: EntitySet<Inst>,
+    /// Protected loads whose address or value was masked, in protection order.
+    hardened_loads: Vec<Inst>,
+    /// Protected loads which couldn't be masked and got a speculation barrier instead.
+    fenced_loads: Vec<Inst>,
+    /// Whether to use fake bounds for loads without bounds.
+    fake_bounds: bool,
+    /// Whether to protect loads without bounds with a speculation barrier, which takes precedence
+    /// over `fake_bounds`.
+    fence_unbounded: bool,
+    /// What to mask when possible.
+    mask: BladeSlhMask,
+    /// The masks computed so far for every pointer, offset and access size.
+    masks: HashMap<(Value, i64, i64), Vec<Value>>,
+    /// The masks computed from fake bounds, which protect nothing.
+    fake_masks: EntitySet<Value>,
+    /// Transient values which couldn't be protected as requested.
+    diagnostics: Vec<BladeDiagnostic>,
+    /// The number of instructions the hardening may add before the remaining loads are fenced
+    /// instead, if any.
+    budget: Option<usize>,
+    /// The number of instructions the hardening added so far, including the ones computing the
+    /// bounds of guarded loads.
+    added_insts: usize,
+    /// The number of loads fenced because the budget was exhausted.
+    budget_fallbacks: usize,
+}
+
+impl SLHContext {
+    fn new(
+        func: &Function,
+        fake_bounds: bool,
+        fence_unbounded: bool,
+        mask: BladeSlhMask,
+        budget: Option<usize>,
+    ) -> Self {
+        Self {
+            protected_loads: EntitySet::with_capacity(func.dfg.num_insts()),
+            hardened_loads: Vec::new(),
+            fenced_loads: Vec::new(),
+            fake_bounds,
+            fence_unbounded,
+            mask,
+            masks: HashMap::new(),
+            fake_masks: EntitySet::new(),
+            diagnostics: Vec::new(),
+            budget,
+            added_insts: 0,
+            budget_fallbacks: 0,
+        }
+    }
+
+    /// Protect `load` with speculative load hardening, unless it is already protected.
+    ///
+    /// Only loads whose address is a single pointer can be masked. Complex loads, whose address
+    /// is the sum of several values, are first split into the `iadd`s computing that sum and a
+    /// load from it, when exactly one of the values has bounds, which the sum gets. Other loads,
+    /// such as those of stack slots and the remaining complex loads, are protected with a
+    /// speculation barrier right after them instead. So are the loads without bounds with
+    /// `blade=slh_with_fence_fallback`. A pointer which adds a constant to a pointer with
+    /// bounds, as for Wasm offsets too wide for a load, is checked against those bounds, with the
+    /// constant added to the offset of the load.
+    ///
+    /// This masks the address of `load` so that it becomes zero whenever the accessed bytes are
+    /// out of the bounds attached to it, using only conditional moves so that the masking itself
+    /// can't be mispredicted. With dynamic bounds, this is:
+    ///
+    /// ```clif
+    ///     zero = iconst 0
+    ///     ones = iconst -1
+    ///     lower_adj = iadd_imm lower, -offset
+    ///     upper_adj = iadd_imm upper, -(offset + size)
+    ///     f1 = ifcmp ptr, lower_adj
+    ///     m1 = selectif ult f1, zero, ones
+    ///     f2 = ifcmp ptr, upper_adj
+    ///     mask = selectif ugt f2, zero, m1
+    ///     masked = band ptr, mask
+    ///     v = load masked+offset
+    /// ```
+    ///
+    /// On AArch64, the load is also preceded by a `csdb` barrier, as Arm recommends. A load
+    /// between the definition of CPU flags and one of their uses is protected with a speculation
+    /// barrier instead, since masking it would clobber the flags.
+    ///
+    /// When `ptr` is `lower` plus a zero-extended index, as for Wasm heap accesses, and `offset`
+    /// isn't negative, the lower bound can't be violated, so only the upper bound is checked:
+    ///
+    /// ```clif
+    ///     zero = iconst 0
+    ///     ones = iconst -1
+    ///     upper_adj = iadd_imm upper, -(offset + size)
+    ///     f = ifcmp ptr, upper_adj
+    ///     mask = selectif ugt f, zero, ones
+    ///     masked = band ptr, mask
+    ///     v = load masked+offset
+    /// ```
+    ///
+    /// With static bounds, a single comparison against an immediate covers both bounds:
+    ///
+    /// ```clif
+    ///     zero = iconst 0
+    ///     ones = iconst -1
+    ///     rel = isub ptr, base
+    ///     f = ifcmp_imm rel, bound_size - (offset + size)
+    ///     mask = selectif ugt f, zero, ones
+    ///     masked = band ptr, mask
+    ///     v = load masked+offset
+    /// ```
+    ///
+    /// With `blade_slh_mask=value`, integer loads no wider than a pointer keep their address, and
+    /// the mask is applied to their value instead:
+    ///
+    /// ```clif
+    ///     loaded = load ptr+offset
+    ///     v = band loaded, mask
+    /// ```
+    ///
+    /// The mask is first reduced to the type of the value if it is narrower than a pointer.
+    ///
+    /// Once the instructions added so far exhaust the budget, `load` is protected with a
+    /// speculation barrier right after it instead.
+    ///
+    /// Instead of computing a new mask, this reuses any mask of the same access through the same
+    /// pointer that dominates `load`. When the pointer is a block parameter, and every predecessor
+    /// of its block has such a mask for the argument it passes, the masks are passed along as a
+    /// new block parameter. This requires the loads to be protected in reverse post-order.
+    fn do_slh_on(
+        &mut self,
+        func: &mut Function,
+        isa: &dyn TargetIsa,
+        cfg: &ControlFlowGraph,
+        domtree: &DominatorTree,
+        load: Inst,
+    ) -> CodegenResult<()> {
+        if !self.protected_loads.insert(load) {
+            return Ok(());
+        }
+        if self
+            .budget
+            .map_or(false, |budget| self.added_insts >= budget)
+        {
+            self.budget_fallbacks += 1;
+            self.fence(func, load);
+            return Ok(());
+        }
+        // A condition computed before the load and tested after it would be clobbered by the
+        // masking.
+        if flags_live_before(func, load) {
+            self.fence(func, load);
+            return Ok(());
+        }
+        let first_new_inst = func.dfg.num_insts();
+
+        let (addr, offset): (_, i64) = match func.dfg[load] {
+            InstructionData::Load { arg, offset, .. } => (arg, offset.into()),
+            InstructionData::LoadComplex { offset, .. }
+                if complex_load_bounds(func, load).is_some()
+                    || (self.fake_bounds && !self.fence_unbounded) =>
+            {
+                (split_complex_load(func, isa, load)?, offset.into())
+            }
+            _ => {
+                self.fence(func, load);
+                return Ok(());
+            }
+        };
+        let addr = func.dfg.resolve_aliases(addr);
+        let (ptr, offset) = bounded_base(func, addr, offset);
+        let ptr_ty = func.dfg.value_type(ptr);
+        let value_ty = func.dfg.value_type(func.dfg.first_result(load));
+        let access_size = i64::from(access_size(func, load));
+        let mask_value = self.mask == BladeSlhMask::Value
+            && value_ty.is_int()
+            && value_ty.bits() <= ptr_ty.bits();
+        let key = (ptr, offset, access_size);
+        let mask = match self.available_mask(func, cfg, domtree, key, load) {
+            Some(mask) => mask,
+            None => {
+                let bounds = func.dfg.bounds[ptr];
+                if bounds.is_none() && self.fence_unbounded {
+                    self.fence(func, load);
+                    return Ok(());
+                }
+                if bounds.is_none() && !self.fake_bounds {
+                    return Err(CodegenError::Unsupported(format!(
+                        "Blade SLH on {} without bounds for {}",
+                        func.dfg.display_inst(load, isa),
+                        ptr
+                    )));
+                }
+                // Attribute the masking to the load, so that profiles account for its cost there.
+                let mut pos = EncCursor::new(func, isa).at_inst(load);
+                pos.use_srcloc(load);
+                let mask = compute_mask(&mut pos, ptr, offset, access_size, bounds);
+                if bounds.is_none() {
+                    self.fake_masks.insert(mask);
+                }
+                self.masks.entry(key).or_default().push(mask);
+                mask
+            }
+        };
+
+        if self.fake_masks.contains(mask) {
+            self.diagnostics.push(BladeDiagnostic {
+                inst: load,
+                kind: BladeDiagnosticKind::FakeBounds,
+                srcloc: func.srclocs[load],
+            });
+        }
+
+        let mut pos = EncCursor::new(func, isa).at_inst(load);
+        pos.use_srcloc(load);
+        if mask_value {
+            let value = pos.func.dfg.first_result(load);
+            let mask = if value_ty == ptr_ty {
+                mask
+            } else {
+                pos.ins().ireduce(value_ty, mask)
+            };
+            // Give the load a new result, and define its old one by masking the new one.
+            let loaded = pos.func.dfg.replace_result(value, value_ty);
+            pos.goto_after_inst(load);
+            pos.ins().with_result(value).band(loaded, mask);
+        } else {
+            let masked = pos.ins().band(addr, mask);
+            pos.func.dfg.inst_args_mut(load)[0] = masked;
+        }
+        self.hardened_loads.push(load);
+        if let Architecture::Aarch64(_) = isa.triple().architecture {
+            // Arm processors may speculate the results of conditional selects, unless they are
+            // followed by a `csdb`. The masks are computed before the load either way.
+            pos.func.pre_csdb[load] = true;
+        }
+        self.added_insts += func.dfg.num_insts() - first_new_inst;
+        Ok(())
+    }
+
+    /// Protect `load` with a speculation barrier right after it, since it can't be hardened.
+    fn fence(&mut self, func: &mut Function, load: Inst) {
+        func.post_lfence[load] = true;
+        self.fenced_loads.push(load);
+        self.diagnostics.push(BladeDiagnostic {
+            inst: load,
+            kind: BladeDiagnosticKind::SlhFallback,
+            srcloc: func.srclocs[load],
+        });
+    }
+
+    /// Find a mask for `key` which is available at `load`, either because it dominates it or
+    /// because it can be passed as a block argument from every predecessor of the block
+    /// parameter which is the pointer of `key`.
+    fn available_mask(
+        &mut self,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        domtree: &DominatorTree,
+        key: (Value, i64, i64),
+        load: Inst,
+    ) -> Option<Value> {
+        if let Some(masks) = self.masks.get(&key) {
+            let dominating = masks
+                .iter()
+                .find(|&&mask| domtree.dominates(func.dfg.value_def(mask), load, &func.layout));
+            if let Some(&mask) = dominating {
+                return Some(mask);
+            }
+        }
+
+        let (ptr, offset, access_size) = key;
+        let (block, num) = match func.dfg.value_def(ptr) {
+            ValueDef::Param(block, num) => (block, num),
+            ValueDef::Result(..) => return None,
+        };
+        if func.layout.entry_block() == Some(block) || cfg.pred_iter(block).next().is_none() {
+            return None;
+        }
+        let mut incoming = Vec::new();
+        for pred in cfg.pred_iter(block) {
+            let arg = match func.dfg.analyze_branch(pred.inst) {
+                BranchInfo::SingleDest(dest, args) if dest == block => args[num],
+                _ => return None,
+            };
+            let arg = func.dfg.resolve_aliases(arg);
+            let mask = self
+                .masks
+                .get(&(arg, offset, access_size))?
+                .iter()
+                .copied()
+                .find(|&mask| {
+                    domtree.dominates(func.dfg.value_def(mask), pred.inst, &func.layout)
+                })?;
+            incoming.push((pred.inst, mask));
+        }
+
+        let ptr_ty = func.dfg.value_type(ptr);
+        let mask = func.dfg.append_block_param(block, ptr_ty);
+        for (branch, arg) in incoming {
+            if self.fake_masks.contains(arg) {
+                self.fake_masks.insert(mask);
+            }
+            func.dfg.append_inst_arg(branch, arg);
+        }
+        self.masks.entry(key).or_default().push(mask);
+        Some(mask)
+    }
+}
+
+/// Get the pointer with bounds which `addr` adds a constant to, along with `offset` plus that
+/// constant, or `addr` and `offset` themselves if there is none.
+///
+/// cranelift-wasm adds the part of a Wasm offset which doesn't fit in the offset of a load to the
+/// address of the heap access, which legalization may turn into an `iadd` of an `iconst` when
+/// the constant is too wide for an immediate. The access is then bounded by the heap access.
+fn bounded_base(func: &Function, addr: Value, offset: i64) -> (Value, i64) {
+    if func.dfg.bounds[addr].is_some() {
+        return (addr, offset);
+    }
+    let inst = match func.dfg.value_def(addr) {
+        ValueDef::Result(inst, _) => inst,
+        ValueDef::Param(..) => return (addr, offset),
+    };
+    let (base, imm) = match func.dfg[inst] {
+        InstructionData::BinaryImm64 {
+            opcode: Opcode::IaddImm,
+            arg,
+            imm,
+        } => (arg, imm.into()),
+        InstructionData::Binary {
+            opcode: Opcode::Iadd,
+            args,
+        } => match (iconst_value(func, args[0]), iconst_value(func, args[1])) {
+            (_, Some(imm)) => (args[0], imm),
+            (Some(imm), None) => (args[1], imm),
+            (None, None) => return (addr, offset),
+        },
+        _ => return (addr, offset),
+    };
+    let base = func.dfg.resolve_aliases(base);
+    match offset.checked_add(imm) {
+        Some(offset) if func.dfg.bounds[base].is_some() => (base, offset),
+        _ => (addr, offset),
+    }
+}
+
+/// Get the constant `value` is defined as by an `iconst`, if it is.
+fn iconst_value(func: &Function, value: Value) -> Option<i64> {
+    match func.dfg.value_def(func.dfg.resolve_aliases(value)) {
+        ValueDef::Result(inst, _) => match func.dfg[inst] {
+            InstructionData::UnaryImm {
+                opcode: Opcode::Iconst,
+                imm,
+            } => Some(imm.into()),
+            _ => None,
+        },
+        ValueDef::Param(..) => None,
+    }
+}
+
+/// Get the bounds of the only address component of the complex load `load` which has bounds, if
+/// there is exactly one.
+fn complex_load_bounds(func: &Function, load: Inst) -> Option<Bounds> {
+    let mut bounds = func
+        .dfg
+        .inst_args(load)
+        .iter()
+        .filter_map(|&arg| func.dfg.bounds[func.dfg.resolve_aliases(arg)]);
+    match (bounds.next(), bounds.next()) {
+        (Some(bounds), None) => Some(bounds),
+        _ => None,
+    }
+}
+
+/// Replace the complex load `load` with the `iadd`s of its address components and the equivalent
+/// load from their sum, returning the sum. The sum gets the bounds of its components, if exactly
+/// one has bounds.
+fn split_complex_load(
+    func: &mut Function,
+    isa: &dyn TargetIsa,
+    load: Inst,
+) -> CodegenResult<Value> {
+    let unsupported = |func: &Function| {
+        CodegenError::Unsupported(format!(
+            "Blade SLH on the complex load {}",
+            func.dfg.display_inst(load, isa)
+        ))
+    };
+    let (opcode, flags, offset) = match func.dfg[load] {
+        InstructionData::LoadComplex {
+            opcode,
+            flags,
+            offset,
+            ..
+        } => (opcode, flags, offset),
+        _ => return Err(unsupported(func)),
+    };
+    let opcode = match opcode {
+        Opcode::LoadComplex => Opcode::Load,
+        Opcode::Uload8Complex => Opcode::Uload8,
+        Opcode::Sload8Complex => Opcode::Sload8,
+        Opcode::Uload16Complex => Opcode::Uload16,
+        Opcode::Sload16Complex => Opcode::Sload16,
+        Opcode::Uload32Complex => Opcode::Uload32,
+        Opcode::Sload32Complex => Opcode::Sload32,
+        Opcode::Uload8x8Complex => Opcode::Uload8x8,
+        Opcode::Sload8x8Complex => Opcode::Sload8x8,
+        Opcode::Uload16x4Complex => Opcode::Uload16x4,
+        Opcode::Sload16x4Complex => Opcode::Sload16x4,
+        Opcode::Uload32x2Complex => Opcode::Uload32x2,
+        Opcode::Sload32x2Complex => Opcode::Sload32x2,
+        _ => return Err(unsupported(func)),
+    };
+    let args = func.dfg.inst_args(load).to_vec();
+    let bounds = complex_load_bounds(func, load);
+
+    let mut pos = EncCursor::new(func, isa).at_inst(load);
+    pos.use_srcloc(load);
+    let addr = args[1..]
+        .iter()
+        .fold(args[0], |sum, &arg| pos.ins().iadd(sum, arg));
+    pos.func.dfg[load] = InstructionData::Load {
+        opcode,
+        arg: addr,
+        flags,
+        offset,
+    };
+    pos.func.dfg.bounds[addr] = bounds;
+    pos.func
+        .update_encoding(load, isa)
+        .map_err(|_| unsupported(pos.func))?;
+    Ok(addr)
+}
+
+/// Is a CPU flags value live right before `inst`, i.e. defined before it and used at or after
+/// it in its block?
+///
+/// Flags can't be spilled or copied, so no code which may clobber them, such as the comparisons
+/// and arithmetic computing SLH masks, can be inserted there.
+fn flags_live_before(func: &Function, inst: Inst) -> bool {
+    let mut defined = Vec::new();
+    let mut next = Some(inst);
+    while let Some(inst) = next {
+        let live_in = func.dfg.inst_args(inst).iter().any(|&arg| {
+            let arg = func.dfg.resolve_aliases(arg);
+            func.dfg.value_type(arg).is_flags() && !defined.contains(&arg)
+        });
+        if live_in {
+            return true;
+        }
+        defined.extend(
+            func.dfg
+                .inst_results(inst)
+                .iter()
+                .filter(|&&result| func.dfg.value_type(result).is_flags()),
+        );
+        next = func.layout.next_inst(inst);
+    }
+    false
+}
+
+/// Get the number of bytes accessed by `load`, which extending loads make narrower than their
+/// result.
+fn access_size(func: &Function, load: Inst) -> u32 {
+    match func.dfg[load].opcode() {
+        Opcode::Uload8 | Opcode::Sload8 => 1,
+        Opcode::Uload16 | Opcode::Sload16 => 2,
+        Opcode::Uload32 | Opcode::Sload32 => 4,
+        Opcode::Uload8x8
+        | Opcode::Sload8x8
+        | Opcode::Uload16x4
+        | Opcode::Sload16x4
+        | Opcode::Uload32x2
+        | Opcode::Sload32x2 => 8,
+        _ => func.dfg.value_type(func.dfg.first_result(load)).bytes(),
+    }
+}
+
+/// Emit code computing a mask which is all ones when the `size` bytes at `ptr + offset` are within
+/// `bounds`, and zero otherwise. Without bounds, the whole address space is within bounds.
+fn compute_mask(
+    pos: &mut EncCursor,
+    ptr: Value,
+    offset: i64,
+    access_size: i64,
+    bounds: Option<Bounds>,
+) -> Value {
+    let ptr_ty = pos.func.dfg.value_type(ptr);
+    let zero = pos.ins().iconst(ptr_ty, 0);
+    let ones = pos.ins().iconst(ptr_ty, -1);
+    match bounds {
+        Some(Bounds::Dynamic { lower, upper }) => {
+            let lower = pos.func.dfg.resolve_aliases(lower);
+            let upper = pos.func.dfg.resolve_aliases(upper);
+            if offset >= 0 && is_above(pos.func, ptr, lower) {
+                // The lower bound always holds, so only check the upper one.
+                let upper_adj = offset_bound(pos, upper, offset + access_size);
+                let flags = pos.ins().ifcmp(ptr, upper_adj);
+                select_mask(pos, IntCC::UnsignedGreaterThan, flags, zero, ones)
+            } else {
+                let lower_adj = offset_bound(pos, lower, offset);
+                let upper_adj = offset_bound(pos, upper, offset + access_size);
+                range_mask(pos, ptr, lower_adj, upper_adj, zero, ones)
+            }
+        }
+        Some(Bounds::Static { base, size }) => {
+            let base = pos.func.dfg.resolve_aliases(base);
+            let mut rel = pos.ins().isub(ptr, base);
+            if offset < 0 {
+                rel = add_imm(pos, rel, offset);
+            }
+            let limit = i128::from(size) - i128::from(access_size) - i128::from(offset.max(0));
+            let max_rel = i128::from(u64::max_value() >> (64 - ptr_ty.bits()));
+            if limit < 0 {
+                // Every access is out of bounds.
+                zero
+            } else if limit >= max_rel {
+                // Every access is in bounds, as with a static bound covering the address space.
+                ones
+            } else {
+                let flags = match i32::try_from(limit) {
+                    // The new backends expect immediate forms to be legalized away.
+                    Ok(limit) if pos.isa.get_mach_backend().is_none() => {
+                        pos.ins().ifcmp_imm(rel, i64::from(limit))
+                    }
+                    _ => {
+                        let limit = pos.ins().iconst(ptr_ty, limit as i64);
+                        pos.ins().ifcmp(rel, limit)
+                    }
+                };
+                select_mask(pos, IntCC::UnsignedGreaterThan, flags, zero, ones)
+            }
+        }
+        None => {
+            let lower = pos.ins().iconst(ptr_ty, 0);
+            let upper = pos.ins().iconst(ptr_ty, -1);
+            let upper_adj = offset_bound(pos, upper, offset + access_size);
+            range_mask(pos, ptr, lower, upper_adj, zero, ones)
+        }
+    }
+}
+
+/// Emit code subtracting `distance` from `bound`, turning a bound of the bytes a load accesses
+/// into a bound of its pointer.
+///
+/// A load of `size` bytes at `ptr+offset` accesses the bytes from `ptr + offset` to
+/// `ptr + offset + size`, so the distance is `offset` for the lower bound, and `offset + size`
+/// for the upper one. Comparing the raw pointer instead would mask too little for a positive
+/// offset, and too much for a negative one.
+fn offset_bound(pos: &mut EncCursor, bound: Value, distance: i64) -> Value {
+    if distance == 0 {
+        bound
+    } else {
+        add_imm(pos, bound, -distance)
+    }
+}
+
+/// Emit code adding the constant `imm` to `x`.
+fn add_imm(pos: &mut EncCursor, x: Value, imm: i64) -> Value {
+    if pos.isa.get_mach_backend().is_some() || i32::try_from(imm).is_err() {
+        // The new backends expect immediate forms to be legalized away, and fold constants
+        // themselves. The legacy backends can't encode immediates wider than 32 bits, which
+        // the offsets of 64-bit memories may need.
+        let ty = pos.func.dfg.value_type(x);
+        let imm = pos.ins().iconst(ty, imm);
+        pos.ins().iadd(x, imm)
+    } else {
+        pos.ins().iadd_imm(x, imm)
+    }
+}
+
+/// Is `ptr` computed by adding a zero-extended value to `lower`, so that it is never below it?
+///
+/// Legalization computes the addresses of dynamic heap and table accesses this way from their
+/// base, which is their lower bound. The sum can't wrap around, since the zero-extended value is
+/// much smaller than the address space.
+fn is_above(func: &Function, ptr: Value, lower: Value) -> bool {
+    let inst = match func.dfg.value_def(ptr) {
+        ValueDef::Result(inst, _) if func.dfg[inst].opcode() == Opcode::Iadd => inst,
+        _ => return false,
+    };
+    let args = func.dfg.inst_args(inst);
+    let (a, b) = (
+        func.dfg.resolve_aliases(args[0]),
+        func.dfg.resolve_aliases(args[1]),
+    );
+    let index = if a == lower {
+        b
+    } else if b == lower {
+        a
+    } else {
+        return false;
+    };
+    match func.dfg.value_def(index) {
+        ValueDef::Result(def, _) => func.dfg[def].opcode() == Opcode::Uextend,
+        ValueDef::Param(..) => false,
+    }
+}
+
+/// Emit code computing a mask which is all ones when `lower <= ptr <= upper`, and zero otherwise.
+fn range_mask(
+    pos: &mut EncCursor,
+    ptr: Value,
+    lower: Value,
+    upper: Value,
+    zero: Value,
+    ones: Value,
+) -> Value {
+    let lower_flags = pos.ins().ifcmp(ptr, lower);
+    let mask = select_mask(pos, IntCC::UnsignedLessThan, lower_flags, zero, ones);
+    let upper_flags = pos.ins().ifcmp(ptr, upper);
+    select_mask(pos, IntCC::UnsignedGreaterThan, upper_flags, zero, mask)
+}
+
+/// Emit a `selectif` of `x` when `flags` satisfy `cc`, and of `y` otherwise, which must be lowered
+/// without branches.
+fn select_mask(pos: &mut EncCursor, cc: IntCC, flags: Value, x: Value, y: Value) -> Value {
+    let ty = pos.func.dfg.value_type(y);
+    let mask = pos.ins().selectif(ty, cc, flags, x, y);
+    let select = pos.built_inst();
+    pos.func.branchless[select] = true;
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_cost, BladeContract, BladeCostEstimate, BladeStats, FencePoint};
+    #[cfg(feature = "x86")]
+    use super::{BladeCutCache, BladeDiagnosticKind, MitigationKind};
+    #[cfg(feature = "x86")]
+    use crate::binemit::LFENCE_SIZE;
+    use crate::binemit::{NullRelocSink, NullStackmapSink, NullTrapSink};
+    use crate::cursor::{Cursor, FuncCursor};
+    #[cfg(feature = "x86")]
+    use crate::ir::condcodes::IntCC;
+    use crate::ir::{types, AbiParam, Function, Inst, InstBuilder, MemFlags, Value};
+    #[cfg(feature = "x86")]
+    use crate::ir::{Bounds, ExtFuncData, ExternalName, Opcode, Signature, TrapCode, ValueDef};
+    use crate::isa;
+    #[cfg(feature = "x86")]
+    use crate::isa::CallConv;
+    use crate::result::CodegenResult;
+    use crate::settings::{self, Configurable};
+    use crate::Context;
+    use alloc::vec::Vec;
+    use core::str::FromStr;
+    use target_lexicon::Triple;
+
+    /// Build a function with several loads flowing into addresses, stores and branches, across
+    /// block parameters, so that the cut has many points of equal cost to pick from.
+    fn loads_and_branches() -> Function {
+        let mut func = Function::new();
+        for _ in 0..2 {
+            func.signature.params.push(AbiParam::new(types::I64));
+        }
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let n = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+        let x = func.dfg.append_block_param(block1, types::I64);
+        let block2 = func.dfg.make_block();
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let a = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let b = cur.ins().load(types::I64, MemFlags::new(), a, 8);
+        let c = cur.ins().load(types::I64, MemFlags::new(), ptr, 16);
+        let sum = cur.ins().iadd(b, c);
+        cur.ins().brz(sum, block1, &[a]);
+        cur.ins().jump(block2, &[]);
+
+        cur.insert_block(block1);
+        let d = cur.ins().load(types::I64, MemFlags::new(), x, 0);
+        cur.ins().store(MemFlags::new(), n, d, 0);
+        cur.ins().return_(&[]);
+
+        cur.insert_block(block2);
+        let e = cur.ins().iadd(c, n);
+        let f = cur.ins().load(types::I64, MemFlags::new(), e, 0);
+        cur.ins().store(MemFlags::new(), f, ptr, 24);
+        cur.ins().return_(&[]);
+
+        func
+    }
+
+    /// Compile `func` for `isa`, returning the mitigations Blade placed and the emitted code.
+    fn compile(
+        func: &Function,
+        isa: &dyn isa::TargetIsa,
+    ) -> (Vec<super::BladeMitigation>, Vec<u8>) {
+        let mut context = Context::for_function(func.clone());
+        let mut mem = Vec::new();
+        context
+            .compile_and_emit(
+                isa,
+                &mut mem,
+                &mut NullRelocSink {},
+                &mut NullTrapSink {},
+                &mut NullStackmapSink {},
+            )
+            .unwrap();
+        (context.blade_stats.mitigations, mem)
+    }
+
+    #[test]
+    fn cost_estimate() {
+        // A loop loading a value on every iteration, with a barrier after the load and another
+        // after the loop.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+        let block2 = func.dfg.make_block();
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        cur.ins().jump(block1, &[]);
+        cur.insert_block(block1);
+        let value = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let load = cur.func.dfg.value_def(value).unwrap_inst();
+        cur.ins().brnz(value, block1, &[]);
+        cur.ins().jump(block2, &[]);
+        cur.insert_block(block2);
+        let ret = cur.ins().return_(&[]);
+
+        let stats = BladeStats {
+            fence_points: vec![FencePoint::After(load), FencePoint::Before(ret)],
+            ..BladeStats::default()
+        };
+        let weight = BladeCostEstimate::LOOP_WEIGHT;
+        let estimate = estimate_cost(&func, &stats);
+        assert_eq!(
+            estimate,
+            BladeCostEstimate {
+                insts: 2 + 3 * weight,
+                fences: 1 + weight,
+                slh_loads: 0,
+            }
+        );
+        assert!(estimate.slowdown() > 1.0);
+        assert_eq!(BladeCostEstimate::default().slowdown(), 1.0);
+
+        // A profile overrides the loop depths.
+        func.blade_block_counts[block0] = 1;
+        func.blade_block_counts[block1] = 100;
+        func.blade_block_counts[block2] = 1;
+        let estimate = estimate_cost(&func, &stats);
+        assert_eq!(estimate.insts, 2 + 300);
+        assert_eq!(estimate.fences, 1 + 100);
+        assert_eq!(
+            [estimate, estimate]
+                .iter()
+                .copied()
+                .sum::<BladeCostEstimate>(),
+            estimate + estimate
+        );
+    }
+
+    #[test]
+    fn contract() {
+        let contract = |settings: &[(&str, &str)], fell_back| {
+            let mut flags = settings::builder();
+            for &(name, value) in settings {
+                flags.set(name, value).unwrap();
+            }
+            BladeContract::from_flags(&settings::Flags::new(flags), fell_back)
+        };
+        let stable_params = contract(&[], false);
+        assert!(stable_params.hardened);
+        assert!(stable_params.stable_call_args);
+        assert!(!stable_params.transient_params);
+        let transient_params = contract(
+            &[
+                ("blade_transient_params", "all"),
+                ("blade_sink_classes", "47"),
+            ],
+            false,
+        );
+        assert!(transient_params.transient_params);
+        assert!(!transient_params.stable_call_args);
+        assert!(!contract(&[("blade_sink_classes", "15")], false).stable_call_args);
+        let fell_back = contract(&[], true);
+        assert!(fell_back.transient_params);
+        assert!(!fell_back.stable_call_args);
+
+        // Unhardened functions neither pass stable arguments nor protect themselves from
+        // transient ones.
+        let unhardened = BladeContract::default();
+        assert!(unhardened.may_call(&unhardened));
+        assert!(!unhardened.may_call(&stable_params));
+        assert!(unhardened.may_call(&transient_params));
+        assert!(stable_params.may_call(&unhardened));
+        assert!(!transient_params.may_call(&unhardened));
+        assert!(!transient_params.may_call(&stable_params));
+        assert!(stable_params.may_call(&transient_params));
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn analyze_fences_calls() {
+        use super::do_blade;
+
+        let mut flags = settings::builder();
+        flags.set("blade", "analyze").unwrap();
+        flags.enable("blade_fence_after_calls").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+
+        let mut func = loads_and_branches();
+        let sig = func.import_signature(Signature::new(CallConv::SystemV));
+        let callee = func.import_function(ExtFuncData {
+            name: ExternalName::testcase("callee"),
+            signature: sig,
+            colocated: false,
+        });
+        let entry = func.layout.entry_block().unwrap();
+        let first = func.layout.first_inst(entry).unwrap();
+        let call = FuncCursor::new(&mut func)
+            .at_inst(first)
+            .ins()
+            .call(callee, &[]);
+        let original = func.clone();
+
+        let stats = do_blade(&mut func, &*isa, None, None, None).unwrap();
+        // The call is fenced, and reported first as `blade=lfence` would.
+        assert!(func.post_lfence[call]);
+        assert_eq!(stats.fence_points[0], FencePoint::After(call));
+        assert!(stats.fence_points.len() > 1);
+        // Nothing else is.
+        for block in func.layout.blocks() {
+            for inst in func.layout.block_insts(block) {
+                assert!(!func.pre_lfence[inst]);
+                assert_eq!(func.post_lfence[inst], inst == call);
+            }
+        }
+        assert_eq!(func.dfg.num_insts(), original.dfg.num_insts());
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn cut_cache() {
+        let isa = |bias: &str| {
+            let mut flags = settings::builder();
+            flags.set("blade", "lfence").unwrap();
+            flags.set("blade_cut_bias", bias).unwrap();
+            isa::lookup(Triple::from_str("x86_64").unwrap())
+                .unwrap()
+                .finish(settings::Flags::new(flags))
+        };
+        let uncached = {
+            let mut context = Context::for_function(loads_and_branches());
+            context.compile(&*isa("sources")).unwrap();
+            context.blade_stats
+        };
+
+        let mut context = Context::new();
+        context.set_blade_cut_cache(BladeCutCache::new());
+        let mut compile = |bias| {
+            context.clear();
+            context.func = loads_and_branches();
+            context.compile(&*isa(bias)).unwrap();
+            context.blade_stats.clone()
+        };
+        let first = compile("sources");
+        assert!(!first.cut_reused);
+        let second = compile("sources");
+        assert!(second.cut_reused);
+        // A reused cut places exactly the mitigations a computed one would.
+        assert_eq!(second.fence_points, uncached.fence_points);
+        assert_eq!(second.max_flow, uncached.max_flow);
+        // The cut depends on the bias, so it is part of the key.
+        assert!(!compile("sinks").cut_reused);
+
+        let cache = context.blade_cut_cache.as_ref().unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.hits(), 1);
+    }
+
+    /// Compiling the same function twice must place the same mitigations and emit the same bytes,
+    /// so that the mitigations of a build can be audited and reproduced.
+    fn check_deterministic(triple: &str) {
+        let func = loads_and_branches();
+        for &blade in &[
+            "lfence",
+            "lfence_per_block",
+            "slh",
+            "slh_with_fence_fallback",
+            "analyze",
+        ] {
+            for &bias in &["sources", "sinks"] {
+                let mut flags = settings::builder();
+                flags.set("blade", blade).unwrap();
+                flags.set("blade_cut_bias", bias).unwrap();
+                flags.enable("blade_slh_fake_bounds").unwrap();
+                let isa = isa::lookup(Triple::from_str(triple).unwrap())
+                    .unwrap()
+                    .finish(settings::Flags::new(flags));
+
+                let (mitigations, code) = compile(&func, &*isa);
+                assert!(
+                    !mitigations.is_empty(),
+                    "no mitigations with blade={}",
+                    blade
+                );
+                for _ in 0..4 {
+                    let again = compile(&func, &*isa);
+                    assert_eq!(again.0, mitigations, "blade={} on {}", blade, triple);
+                    assert_eq!(again.1, code, "blade={} on {}", blade, triple);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn barrier_offsets() {
+        // Every barrier is emitted as the instruction `blade_barrier` selects.
+        for &(barrier, bytes) in &[
+            ("lfence", [0x0f, 0xae, 0xe8]),
+            ("mfence", [0x0f, 0xae, 0xf0]),
+            ("serialize", [0x0f, 0x01, 0xe8]),
+        ] {
+            let mut flags = settings::builder();
+            flags.set("blade", "lfence").unwrap();
+            flags.set("blade_barrier", barrier).unwrap();
+            let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+                .unwrap()
+                .finish(settings::Flags::new(flags));
+
+            let mut context = Context::for_function(loads_and_branches());
+            let mut mem = Vec::new();
+            context
+                .compile_and_emit(
+                    &*isa,
+                    &mut mem,
+                    &mut NullRelocSink {},
+                    &mut NullTrapSink {},
+                    &mut NullStackmapSink {},
+                )
+                .unwrap();
+            let func = &context.func;
+            let barriers = func
+                .layout
+                .blocks()
+                .flat_map(|block| func.layout.block_insts(block))
+                .map(|inst| {
+                    usize::from(func.pre_lfence[inst]) + usize::from(func.post_lfence[inst])
+                })
+                .sum::<usize>();
+            let offsets = &context.blade_stats.barrier_offsets;
+            assert!(barriers > 0);
+            assert_eq!(offsets.len(), barriers);
+            for &offset in offsets {
+                let offset = offset as usize;
+                assert_eq!(mem[offset..offset + 3], bytes, "blade_barrier={}", barrier);
+            }
+        }
+    }
+
+    /// A strategy counting the edges it is asked to protect, and fencing them as `blade=lfence`
+    /// does.
+    struct CountingStrategy {
+        inner: super::FenceStrategy,
+        edges: usize,
+        finished: usize,
+    }
+
+    impl super::BladeStrategy for CountingStrategy {
+        fn protect_source(&mut self, cut: &mut super::BladeCut, value: Value) -> CodegenResult<()> {
+            self.edges += 1;
+            self.inner.protect_source(cut, value)
+        }
+
+        fn protect_edge(
+            &mut self,
+            cut: &mut super::BladeCut,
+            from: Value,
+            to: super::BladeNode,
+        ) -> CodegenResult<()> {
+            self.edges += 1;
+            self.inner.protect_edge(cut, from, to)
+        }
+
+        fn protect_sink(&mut self, cut: &mut super::BladeCut, inst: Inst) -> CodegenResult<()> {
+            self.edges += 1;
+            self.inner.protect_sink(cut, inst)
+        }
+
+        fn finish(&mut self, cut: &mut super::BladeCut) -> CodegenResult<()> {
+            self.finished += 1;
+            self.inner.finish(cut)
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn custom_strategy() {
+        use super::{do_blade, BladeStrategy, FenceStrategy};
+
+        let mut flags = settings::builder();
+        flags.set("blade", "lfence").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+        let builtin = do_blade(&mut loads_and_branches(), &*isa, None, None, None).unwrap();
+
+        let mut func = loads_and_branches();
+        let mut strategy = CountingStrategy {
+            inner: FenceStrategy::from_flags(isa.flags()),
+            edges: 0,
+            finished: 0,
+        };
+        let stats = do_blade(&mut func, &*isa, None, Some(&mut strategy), None).unwrap();
+        assert!(stats.cut_size > 0);
+        assert_eq!(strategy.edges, stats.cut_size);
+        assert_eq!(strategy.finished, 1);
+        assert_eq!(
+            strategy.edge_weights(isa.flags()),
+            FenceStrategy::from_flags(isa.flags()).edge_weights(isa.flags())
+        );
+        // Delegating every edge places the mitigations of the built-in strategy.
+        assert_eq!(stats.mitigations, builtin.mitigations);
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn graph_metrics() {
+        let mut flags = settings::builder();
+        flags.set("blade", "lfence").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+        let mut context = Context::for_function(loads_and_branches());
+        context.compile(&*isa).unwrap();
+        let stats = &context.blade_stats;
+
+        // Every load is a source, and leaks its address along with the branch and the stores.
+        assert_eq!(stats.sources, 5);
+        assert_eq!(stats.sinks, 8);
+        assert!(stats.graph_nodes > stats.sinks);
+        assert!(stats.graph_edges >= stats.sources + 2 * stats.sinks);
+        // With unit weights, the flow is the size of the cut.
+        assert_eq!(stats.max_flow, stats.cut_size as u64);
+        assert!(stats.solver_time <= stats.time);
+
+        let row = stats.csv_row("f");
+        assert_eq!(
+            row.split(',').count(),
+            super::BladeStats::CSV_HEADER.split(',').count()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn graph_budget() {
+        use super::do_blade;
+
+        let isa = |max_edges_log2: &str| {
+            let mut flags = settings::builder();
+            flags.set("blade", "slh").unwrap();
+            flags.set("blade_max_edges_log2", max_edges_log2).unwrap();
+            flags.enable("blade_slh_fake_bounds").unwrap();
+            isa::lookup(Triple::from_str("x86_64").unwrap())
+                .unwrap()
+                .finish(settings::Flags::new(flags))
+        };
+        let within = do_blade(&mut loads_and_branches(), &*isa("20"), None, None, None).unwrap();
+        assert!(!within.fell_back);
+
+        // Whatever the strategy, a graph over the budget gets a barrier at the beginning of every
+        // block, and after every branch in the middle of one, without computing a cut.
+        let mut func = loads_and_branches();
+        let original = func.clone();
+        let stats = do_blade(&mut func, &*isa("2"), None, None, None).unwrap();
+        assert!(stats.graph_edges > 4);
+        assert!(stats.fell_back);
+        assert_eq!(stats.cut_size, 0);
+        assert_eq!(stats.slh_loads(), 0);
+        let mut barriers = 0;
+        for block in func.layout.blocks() {
+            for inst in func.layout.block_insts(block) {
+                let first = func.layout.first_inst(block) == Some(inst);
+                let mid_branch =
+                    func.dfg[inst].opcode().is_branch() && func.layout.next_inst(inst).is_some();
+                assert_eq!(func.pre_lfence[inst], first);
+                assert_eq!(func.post_lfence[inst], mid_branch);
+                barriers += usize::from(first) + usize::from(mid_branch);
+            }
+        }
+        assert_eq!(stats.fence_points.len(), barriers);
+        assert_eq!(func.dfg.num_insts(), original.dfg.num_insts());
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn slh_fence_fallback() {
+        let isa_for = |blade: &str| {
+            let mut flags = settings::builder();
+            flags.set("blade", blade).unwrap();
+            isa::lookup(Triple::from_str("x86_64").unwrap())
+                .unwrap()
+                .finish(settings::Flags::new(flags))
+        };
+        // Only the loads directly from the first parameter have bounds.
+        let mut func = loads_and_branches();
+        let entry = func.layout.entry_block().unwrap();
+        let (ptr, n) = match *func.dfg.block_params(entry) {
+            [ptr, n] => (ptr, n),
+            _ => unreachable!(),
+        };
+        func.dfg.bounds[ptr] = Some(Bounds::Dynamic {
+            lower: ptr,
+            upper: n,
+        });
+
+        let mut context = Context::for_function(func.clone());
+        assert!(context.compile(&*isa_for("slh")).is_err());
+
+        let (mitigations, _) = compile(&func, &*isa_for("slh_with_fence_fallback"));
+        let bounded = |inst| func.dfg.inst_args(inst)[0] == ptr;
+        assert!(mitigations.iter().any(|m| bounded(m.inst)));
+        for mitigation in &mitigations {
+            let expected = if bounded(mitigation.inst) {
+                MitigationKind::Slh
+            } else {
+                MitigationKind::FenceAfter
+            };
+            assert_eq!(mitigation.kind, expected);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn slh_diagnostics() {
+        let stats_for = |blade: &str| {
+            let mut flags = settings::builder();
+            flags.set("blade", blade).unwrap();
+            flags.enable("blade_slh_fake_bounds").unwrap();
+            let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+                .unwrap()
+                .finish(settings::Flags::new(flags));
+            let mut context = Context::for_function(loads_and_branches());
+            context.compile(&*isa).unwrap();
+            context.blade_stats
+        };
+
+        // Every load hardened against fake bounds is reported as unprotected.
+        let stats = stats_for("slh");
+        assert!(!stats.slh_points.is_empty());
+        let fake: Vec<_> = stats
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == BladeDiagnosticKind::FakeBounds)
+            .map(|d| d.inst)
+            .collect();
+        assert_eq!(fake, stats.slh_points);
+        assert!(BladeDiagnosticKind::FakeBounds.is_unprotected());
+
+        // Every load fenced instead is reported, but protected.
+        let stats = stats_for("slh_with_fence_fallback");
+        assert!(stats.slh_points.is_empty());
+        let fenced: Vec<_> = stats
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == BladeDiagnosticKind::SlhFallback)
+            .map(|d| FencePoint::After(d.inst))
+            .collect();
+        assert_eq!(fenced, stats.fence_points);
+        assert!(!BladeDiagnosticKind::SlhFallback.is_unprotected());
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn slh_size_budget() {
+        let stats_for = |budget: &str| {
+            let mut flags = settings::builder();
+            flags.set("blade", "slh").unwrap();
+            flags.enable("blade_slh_fake_bounds").unwrap();
+            flags.set("blade_slh_size_budget", budget).unwrap();
+            let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+                .unwrap()
+                .finish(settings::Flags::new(flags));
+            let mut context = Context::for_function(loads_and_branches());
+            context.compile(&*isa).unwrap();
+            context.blade_stats
+        };
+
+        // Without a budget, every load is hardened, and the code grows by the instructions
+        // computing the masks.
+        let unbounded = stats_for("0");
+        assert_eq!(unbounded.slh_budget_fallbacks, 0);
+        assert!(unbounded.slh_loads() > 1);
+        let growth = unbounded.code_size_growth.unwrap();
+        assert!(growth as usize >= unbounded.slh_insts.len());
+
+        // A budget of one instruction is exhausted by the first hardened load, so the others
+        // are fenced.
+        let stats = stats_for("1");
+        assert_eq!(stats.slh_loads(), 1);
+        assert!(stats.slh_budget_fallbacks > 0);
+        assert_eq!(
+            stats.slh_loads() + stats.fences(),
+            unbounded.slh_loads() + unbounded.fences()
+        );
+        let fenced: Vec<_> = stats
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == BladeDiagnosticKind::SlhFallback)
+            .map(|d| FencePoint::After(d.inst))
+            .collect();
+        assert_eq!(fenced, stats.fence_points);
+        assert!(stats.slh_insts.len() < unbounded.slh_insts.len());
+        assert!(stats.code_size_growth.unwrap() >= stats.fences() as u32 * LFENCE_SIZE);
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn slh_mask_through_block_params() {
+        let mut flags = settings::builder();
+        flags.set("blade", "slh").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+
+        // Only the entry block parameters have bounds. Each edge into `block1` passes one of the
+        // pointers loaded from in `block0`, so the load through the parameter of `block1` can
+        // reuse their masks without bounds of its own.
+        let mut func = Function::new();
+        for _ in 0..3 {
+            func.signature.params.push(AbiParam::new(types::I64));
+        }
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let block0 = func.dfg.make_block();
+        let ptr0 = func.dfg.append_block_param(block0, types::I64);
+        let ptr1 = func.dfg.append_block_param(block0, types::I64);
+        let n = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+        let p = func.dfg.append_block_param(block1, types::I64);
+        for &ptr in &[ptr0, ptr1] {
+            func.dfg.bounds[ptr] = Some(Bounds::Dynamic {
+                lower: ptr,
+                upper: n,
+            });
+        }
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let a = cur.ins().load(types::I64, MemFlags::new(), ptr0, 8);
+        let b = cur.ins().load(types::I64, MemFlags::new(), ptr1, 8);
+        let sum = cur.ins().iadd(a, b);
+        cur.ins().brz(sum, block1, &[ptr0]);
+        cur.ins().jump(block1, &[ptr1]);
+
+        cur.insert_block(block1);
+        let c = cur.ins().load(types::I64, MemFlags::new(), p, 8);
+        let d = cur.ins().load(types::I64, MemFlags::new(), c, 0);
+        cur.ins().return_(&[d]);
+
+        let mut context = Context::for_function(func);
+        context.compile(&*isa).unwrap();
+        let mitigations = &context.blade_stats.mitigations;
+        assert_eq!(mitigations.len(), 3);
+        assert!(mitigations.iter().all(|m| m.kind == MitigationKind::Slh));
+        assert_eq!(context.func.dfg.num_block_params(block1), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn slh_extending_load() {
+        let mut flags = settings::builder();
+        flags.set("blade", "slh").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+
+        // The load reads the last byte in bounds, even though its result is wider.
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        func.dfg.bounds[ptr] = Some(Bounds::Static {
+            base: ptr,
+            size: 16,
+        });
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let index = cur.ins().uload8(types::I64, MemFlags::new(), ptr, 15);
+        let value = cur.ins().load(types::I64, MemFlags::new(), index, 0);
+        cur.ins().return_(&[value]);
+
+        let mut context = Context::for_function(func);
+        context.compile(&*isa).unwrap();
+        let func = &context.func;
+        let selects = func
+            .layout
+            .blocks()
+            .flat_map(|block| func.layout.block_insts(block))
+            .filter(|&inst| func.dfg[inst].opcode() == Opcode::Selectif)
+            .inspect(|&inst| assert!(func.branchless[inst]))
+            .count();
+        assert_eq!(selects, 1, "the load must be masked by a bounds check");
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn slh_wide_offset() {
+        let mut flags = settings::builder();
+        flags.set("blade", "slh").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+
+        // An 8 GiB region, as for a 64-bit memory, accessed beyond the range of a load offset.
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        func.dfg.bounds[ptr] = Some(Bounds::Static {
+            base: ptr,
+            size: 8 << 30,
+        });
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let addr = cur.ins().iadd_imm(ptr, 5 << 30);
+        let index = cur.ins().load(types::I64, MemFlags::new(), addr, 8);
+        let value = cur.ins().load(types::I64, MemFlags::new(), index, 0);
+        cur.ins().return_(&[value]);
+
+        let mut context = Context::for_function(func);
+        context.compile(&*isa).unwrap();
+        let mitigations = &context.blade_stats.mitigations;
+        assert_eq!(mitigations.len(), 1);
+        assert_eq!(mitigations[0].kind, MitigationKind::Slh);
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn slh_unreachable_load() {
+        let mut flags = settings::builder();
+        flags.set("blade", "slh").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+
+        // `block1` has no predecessors. Unlike `compile`, legalizing and running Blade on their
+        // own leave it in place.
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        func.dfg.bounds[ptr] = Some(Bounds::Static {
+            base: ptr,
+            size: 16,
+        });
+        let block1 = func.dfg.make_block();
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let index = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let value = cur.ins().load(types::I64, MemFlags::new(), index, 0);
+        cur.ins().return_(&[value]);
+        cur.insert_block(block1);
+        let index = cur.ins().load(types::I64, MemFlags::new(), ptr, 8);
+        let unreachable_load = cur.func.dfg.value_def(index).unwrap_inst();
+        let value = cur.ins().load(types::I64, MemFlags::new(), index, 0);
+        cur.ins().return_(&[value]);
+
+        let mut context = Context::for_function(func);
+        context.compute_cfg();
+        context.legalize(&*isa).unwrap();
+        context.blade(&*isa).unwrap();
+        let mitigations = &context.blade_stats.mitigations;
+        assert_eq!(mitigations.len(), 2);
+        assert!(mitigations.iter().all(|m| m.kind == MitigationKind::Slh));
+        assert!(mitigations.iter().any(|m| m.inst == unreachable_load));
+    }
+
+    /// Build a function with a heap access checked against a dynamic bound and a table access
+    /// checked against a constant one, as cranelift-wasm emits them, with no bounds on the
+    /// addresses.
+    #[cfg(feature = "x86")]
+    fn trap_guarded_loads() -> Function {
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let block0 = func.dfg.make_block();
+        let base = func.dfg.append_block_param(block0, types::I64);
+        let index = func.dfg.append_block_param(block0, types::I32);
+        let bound = func.dfg.append_block_param(block0, types::I32);
+        let block1 = func.dfg.make_block();
+        let block2 = func.dfg.make_block();
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let oob = cur
+            .ins()
+            .icmp(IntCC::UnsignedGreaterThanOrEqual, index, bound);
+        cur.ins().brz(oob, block1, &[]);
+        cur.ins().jump(block2, &[]);
+
+        cur.insert_block(block2);
+        cur.ins().trap(TrapCode::HeapOutOfBounds);
+
+        cur.insert_block(block1);
+        let offset = cur.ins().uextend(types::I64, index);
+        let addr = cur.ins().iadd(base, offset);
+        let entry = cur.ins().load(types::I64, MemFlags::new(), addr, 4);
+        let oob = cur
+            .ins()
+            .icmp_imm(IntCC::UnsignedGreaterThan, index, 0xffff);
+        cur.ins().trapnz(oob, TrapCode::TableOutOfBounds);
+        let addr = cur.ins().iadd(entry, offset);
+        let value = cur.ins().load(types::I64, MemFlags::new(), addr, 0);
+        let value = cur.ins().load(types::I64, MemFlags::new(), value, 0);
+        cur.ins().return_(&[value]);
+
+        func
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn slh_trap_guarded_load() {
+        let mut flags = settings::builder();
+        flags.set("blade", "slh").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+
+        // Without bounds, SLH would give up on the loads.
+        let mut context = Context::for_function(trap_guarded_loads());
+        context.compile(&*isa).unwrap();
+        let mitigations = &context.blade_stats.mitigations;
+        assert!(!mitigations.is_empty());
+        assert!(mitigations.iter().all(|m| m.kind == MitigationKind::Slh));
+        // None of them is hardened against fake bounds.
+        assert!(context
+            .blade_stats
+            .diagnostics
+            .iter()
+            .all(|d| d.kind != BladeDiagnosticKind::FakeBounds));
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn slh_size_budget_counts_bounds() {
+        let mut flags = settings::builder();
+        flags.set("blade", "slh").unwrap();
+        flags.set("blade_slh_size_budget", "1").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+
+        // The bounds computed from the checks exhaust the budget of one instruction before any
+        // load is hardened.
+        let mut context = Context::for_function(trap_guarded_loads());
+        context.compile(&*isa).unwrap();
+        let stats = &context.blade_stats;
+        assert_eq!(stats.slh_loads(), 0);
+        assert!(stats.slh_budget_fallbacks > 0);
+        assert!(!stats.mitigations.is_empty());
+        assert!(stats
+            .mitigations
+            .iter()
+            .all(|m| m.kind == MitigationKind::FenceAfter));
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn slh_generic_guarded_load() {
+        let mut flags = settings::builder();
+        flags.set("blade", "slh").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+
+        // An array of 8-byte elements checked against its length, branching to a block which
+        // reports the error before trapping, then an array of 4-byte offsets checked against a
+        // constant length, as a frontend for a language with explicit bounds checks emits them.
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let sig = func.import_signature(Signature::new(CallConv::SystemV));
+        let report = func.import_function(ExtFuncData {
+            name: ExternalName::testcase("report"),
+            signature: sig,
+            colocated: false,
+        });
+        let block0 = func.dfg.make_block();
+        let base = func.dfg.append_block_param(block0, types::I64);
+        let index = func.dfg.append_block_param(block0, types::I32);
+        let len = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+        let block2 = func.dfg.make_block();
+        let panic = func.dfg.make_block();
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let wide = cur.ins().uextend(types::I64, index);
+        let oob = cur.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, wide, len);
+        cur.ins().brnz(oob, panic, &[]);
+        cur.ins().jump(block1, &[]);
+
+        cur.insert_block(panic);
+        cur.ins().call(report, &[]);
+        cur.ins().trap(TrapCode::User(0));
+
+        // The guard dominates the access through a chain of single predecessors.
+        cur.insert_block(block1);
+        cur.ins().jump(block2, &[]);
+
+        cur.insert_block(block2);
+        let offset = cur.ins().ishl_imm(wide, 3);
+        let addr = cur.ins().iadd(base, offset);
+        let entry = cur.ins().load(types::I64, MemFlags::new(), addr, 0);
+        let oob = cur.ins().icmp_imm(IntCC::UnsignedGreaterThan, index, 99);
+        cur.ins().trapnz(oob, TrapCode::User(1));
+        let offset = cur.ins().imul_imm(wide, 4);
+        let addr = cur.ins().iadd(entry, offset);
+        let value = cur.ins().load(types::I64, MemFlags::new(), addr, 0);
+        let value = cur.ins().load(types::I64, MemFlags::new(), value, 0);
+        cur.ins().return_(&[value]);
+
+        // Without bounds, SLH would give up on the loads.
+        let mut context = Context::for_function(func);
+        context.compile(&*isa).unwrap();
+        let mitigations = &context.blade_stats.mitigations;
+        assert_eq!(mitigations.len(), 2);
+        assert!(mitigations.iter().all(|m| m.kind == MitigationKind::Slh));
+        // None of them is hardened against fake bounds.
+        assert!(context
+            .blade_stats
+            .diagnostics
+            .iter()
+            .all(|d| d.kind != BladeDiagnosticKind::FakeBounds));
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn select_arm_fenced() {
+        let mut flags = settings::builder();
+        flags.set("blade", "lfence").unwrap();
+        flags.set("blade_weight_fence_load", "10").unwrap();
+        flags.set("blade_weight_fence_sink", "10").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+
+        // A branchless choice between a loaded value and a constant, which is then stored. The
+        // choice is a `selectif`, since legalization turns a `select` into branches on x86.
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.params.push(AbiParam::new(types::I64));
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let n = func.dfg.append_block_param(block0, types::I64);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let loaded = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let load = cur.func.dfg.value_def(loaded).unwrap_inst();
+        let constant = cur.ins().iconst(types::I64, 0);
+        let cmp = cur.ins().ifcmp_imm(n, 0);
+        let chosen = cur
+            .ins()
+            .selectif(types::I64, IntCC::NotEqual, cmp, loaded, constant);
+        cur.ins().store(MemFlags::new(), chosen, ptr, 8);
+        cur.ins().return_(&[]);
+
+        // The cheapest cut is the flow of the loaded arm into the select, which is fenced right
+        // after the load rather than before the select.
+        let mut context = Context::for_function(func);
+        context.compile(&*isa).unwrap();
+        assert_eq!(context.blade_stats.fence_points, [FencePoint::After(load)]);
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn early_placement() {
+        use super::do_blade;
+
+        let isa = |placement: &str| {
+            let mut flags = settings::builder();
+            flags.set("blade", "lfence").unwrap();
+            flags.set("blade_weight_fence_load", "10").unwrap();
+            flags.set("blade_weight_fence_sink", "10").unwrap();
+            flags.set("blade_placement", placement).unwrap();
+            isa::lookup(Triple::from_str("x86_64").unwrap())
+                .unwrap()
+                .finish(settings::Flags::new(flags))
+        };
+
+        // The sum of two loaded values, offset and then used as the address of two stores.
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.params.push(AbiParam::new(types::I64));
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let n = func.dfg.append_block_param(block0, types::I64);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let a = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let b = cur.ins().load(types::I64, MemFlags::new(), ptr, 8);
+        let sum = cur.ins().iadd(a, b);
+        let add = cur.func.dfg.value_def(sum).unwrap_inst();
+        let addr = cur.ins().iadd_imm(sum, 16);
+        let offset = cur.func.dfg.value_def(addr).unwrap_inst();
+        cur.ins().store(MemFlags::new(), n, addr, 0);
+        cur.ins().store(MemFlags::new(), n, addr, 8);
+        cur.ins().return_(&[]);
+
+        // The cheapest cut is the flow of the sum into the offset address. It is fenced right
+        // before the offset by default, and right after the sum is computed with early placement.
+        let mut late = func.clone();
+        let stats = do_blade(&mut late, &*isa("late"), None, None, None).unwrap();
+        assert_eq!(stats.fence_points, [FencePoint::Before(offset)]);
+        assert!(late.pre_lfence[offset]);
+        assert!(!late.post_lfence[add]);
+
+        let mut early = func;
+        let stats = do_blade(&mut early, &*isa("early"), None, None, None).unwrap();
+        assert_eq!(stats.fence_points, [FencePoint::After(add)]);
+        assert!(early.post_lfence[add]);
+        assert!(!early.pre_lfence[offset]);
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn slh_flags_live_across_load() {
+        let mut flags = settings::builder();
+        flags.set("blade", "slh").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+
+        // A load between a comparison and the branch testing it, which masking would separate.
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let n = func.dfg.append_block_param(block0, types::I64);
+        func.dfg.bounds[ptr] = Some(Bounds::Static {
+            base: ptr,
+            size: 16,
+        });
+        let block1 = func.dfg.make_block();
+        let block2 = func.dfg.make_block();
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let cond = cur.ins().ifcmp_imm(n, 0);
+        let index = cur.ins().load(types::I64, MemFlags::new(), ptr, 8);
+        let load = cur.func.dfg.value_def(index).unwrap_inst();
+        cur.ins().brif(IntCC::Equal, cond, block1, &[]);
+        cur.ins().jump(block2, &[]);
+        cur.insert_block(block1);
+        cur.ins().return_(&[n]);
+        cur.insert_block(block2);
+        let value = cur.ins().load(types::I64, MemFlags::new(), index, 0);
+        cur.ins().return_(&[value]);
+
+        let mut context = Context::for_function(func);
+        context.compile(&*isa).unwrap();
+        let mitigations = &context.blade_stats.mitigations;
+        assert_eq!(mitigations.len(), 1);
+        assert_eq!(mitigations[0].inst, load);
+        assert_eq!(mitigations[0].kind, MitigationKind::FenceAfter);
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn slh_sources_deduplicated() {
+        let mut flags = settings::builder();
+        flags.set("blade", "slh").unwrap();
+        flags.set("blade_weight_slh", "10").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+
+        // A loaded pointer leaked by two loads, which are cheaper to cut than hardening it.
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        func.dfg.bounds[ptr] = Some(Bounds::Static {
+            base: ptr,
+            size: 16,
+        });
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let index = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let load = cur.func.dfg.value_def(index).unwrap_inst();
+        let a = cur.ins().load(types::I64, MemFlags::new(), index, 0);
+        let b = cur.ins().load(types::I64, MemFlags::new(), index, 8);
+        let sum = cur.ins().iadd(a, b);
+        cur.ins().return_(&[sum]);
+
+        let mut context = Context::for_function(func);
+        context.compile(&*isa).unwrap();
+        let stats = &context.blade_stats;
+        assert_eq!(stats.cut_size, 2);
+        assert_eq!(stats.slh_sources, 1);
+        assert_eq!(stats.slh_points, [load]);
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn slh_complex_load() {
+        let compile_load = |fake_bounds: bool, bounded: bool| {
+            let mut flags = settings::builder();
+            flags.set("blade", "slh").unwrap();
+            flags.set("opt_level", "speed").unwrap();
+            if fake_bounds {
+                flags.enable("blade_slh_fake_bounds").unwrap();
+            }
+            let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+                .unwrap()
+                .finish(settings::Flags::new(flags));
+
+            // Post-optimization folds the `iadd` into a complex load, whose sum has no bounds
+            // even if the base has.
+            let mut func = Function::new();
+            for _ in 0..2 {
+                func.signature.params.push(AbiParam::new(types::I64));
+            }
+            func.signature.returns.push(AbiParam::new(types::I64));
+            let block0 = func.dfg.make_block();
+            let base = func.dfg.append_block_param(block0, types::I64);
+            let index = func.dfg.append_block_param(block0, types::I64);
+            let bounds = Bounds::Static { base, size: 4096 };
+            if bounded {
+                func.dfg.bounds[base] = Some(bounds);
+            }
+            let mut cur = FuncCursor::new(&mut func);
+            cur.insert_block(block0);
+            let addr = cur.ins().iadd(base, index);
+            let ptr = cur.ins().load(types::I64, MemFlags::new(), addr, 8);
+            let value = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+            cur.ins().return_(&[value]);
+
+            let mut context = Context::for_function(func);
+            context.compile(&*isa).unwrap();
+            (context, bounds)
+        };
+
+        // The sum gets the bounds of the base, and the load from it is hardened against them.
+        let (context, bounds) = compile_load(false, true);
+        let mitigations = &context.blade_stats.mitigations;
+        assert_eq!(mitigations.len(), 1);
+        assert_eq!(mitigations[0].kind, MitigationKind::Slh);
+        let func = &context.func;
+        let load = mitigations[0].inst;
+        assert_eq!(func.dfg[load].opcode(), Opcode::Load);
+        let masked = func.dfg.inst_args(load)[0];
+        let sum = match func.dfg.value_def(masked) {
+            ValueDef::Result(band, _) => func.dfg.inst_args(band)[0],
+            ValueDef::Param(..) => panic!("the address of the load must be masked"),
+        };
+        assert_eq!(func.dfg.bounds[sum], Some(bounds));
+
+        // So is any sum with fake bounds.
+        let (context, _) = compile_load(true, false);
+        let mitigations = &context.blade_stats.mitigations;
+        assert_eq!(mitigations.len(), 1);
+        assert_eq!(mitigations[0].kind, MitigationKind::Slh);
+        assert_eq!(context.func.dfg[mitigations[0].inst].opcode(), Opcode::Load);
+
+        // Without bounds, the complex load is fenced rather than rejected.
+        let (context, _) = compile_load(false, false);
+        let mitigations = &context.blade_stats.mitigations;
+        assert_eq!(mitigations.len(), 1);
+        assert_eq!(mitigations[0].kind, MitigationKind::FenceAfter);
+        assert_eq!(
+            context.func.dfg[mitigations[0].inst].opcode(),
+            Opcode::LoadComplex
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn split_edges() {
+        let isa_for = |split: bool| {
+            let mut flags = settings::builder();
+            flags.set("blade", "lfence").unwrap();
+            flags.set("blade_weight_fence_load", "3").unwrap();
+            flags.set("blade_weight_fence_sink", "3").unwrap();
+            flags
+                .set("blade_split_edges", if split { "true" } else { "false" })
+                .unwrap();
+            isa::lookup(Triple::from_str("x86_64").unwrap())
+                .unwrap()
+                .finish(settings::Flags::new(flags))
+        };
+
+        // Only the conditional edge into `block1` passes a transient value, and the cheapest cut
+        // is its flow into the parameter of `block1`.
+        let mut func = Function::new();
+        for _ in 0..2 {
+            func.signature.params.push(AbiParam::new(types::I64));
+        }
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let n = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+        let x = func.dfg.append_block_param(block1, types::I64);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let a = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        cur.ins().brz(n, block1, &[a]);
+        cur.ins().jump(block1, &[n]);
+        cur.insert_block(block1);
+        cur.ins().store(MemFlags::new(), n, x, 0);
+        cur.ins().return_(&[]);
+
+        // The whole of `block1` is fenced by default.
+        let mut context = Context::for_function(func.clone());
+        context.compile(&*isa_for(false)).unwrap();
+        let stats = &context.blade_stats;
+        assert_eq!(stats.split_edges, 0);
+        assert_eq!(stats.mitigations.len(), 1);
+        let fenced = stats.mitigations[0].inst;
+        assert_eq!(context.func.layout.inst_block(fenced), Some(block1));
+
+        // Only the edge of the conditional branch is fenced once it is split.
+        let mut context = Context::for_function(func);
+        context.compile(&*isa_for(true)).unwrap();
+        let stats = &context.blade_stats;
+        assert_eq!(stats.split_edges, 1);
+        assert_eq!(stats.mitigations.len(), 1);
+        assert_eq!(stats.mitigations[0].kind, MitigationKind::FenceBefore);
+        let fenced = stats.mitigations[0].inst;
+        assert!(context.func.dfg[fenced].opcode().is_terminator());
+        let block = context.func.layout.inst_block(fenced);
+        assert!(block != Some(block0) && block != Some(block1));
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn engines() {
+        let stats_for = |blade: &str, engine: &str| {
+            let mut flags = settings::builder();
+            flags.set("blade", blade).unwrap();
+            flags.set("blade_engine", engine).unwrap();
+            flags.enable("blade_slh_fake_bounds").unwrap();
+            let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+                .unwrap()
+                .finish(settings::Flags::new(flags));
+            let mut context = Context::for_function(loads_and_branches());
+            context.compile(&*isa).unwrap();
+            context.blade_stats
+        };
+
+        // The protections derived from the minimal cut type check.
+        for &blade in &["lfence", "slh", "slh_with_fence_fallback", "analyze"] {
+            let checked = stats_for(blade, "check");
+            assert_eq!(
+                checked,
+                super::BladeStats {
+                    time: checked.time,
+                    solver_time: checked.solver_time,
+                    ..stats_for(blade, "min_cut")
+                }
+            );
+        }
+
+        // Typing only fences sinks, and needs no graph.
+        let typed = stats_for("lfence", "dataflow");
+        assert!(typed.fences() > 0);
+        assert_eq!(typed.graph_edges, 0);
+        for point in &typed.fence_points {
+            match *point {
+                FencePoint::Before(_) => {}
+                FencePoint::After(inst) => panic!("fence after {}", inst),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn deterministic_x86_64() {
+        check_deterministic("x86_64");
+    }
+
+    #[test]
+    #[cfg(feature = "arm64")]
+    fn deterministic_aarch64() {
+        check_deterministic("aarch64");
+    }
+}
@@ -0,0 +1,101 @@
+//! The phases of the Blade pass as separate entry points.
+//!
+//! These are under the "testing_hooks" feature, and are only suitable for use by benchmarks which
+//! measure the cost of building the Blade graph, computing its minimal cut, and inserting the
+//! mitigations on their own. Use `Context::blade` to run the pass.
+
+use super::graph::BladeGraph;
+use super::maxflow::Node;
+use super::strategy::builtin_strategy;
+use super::BladeStats;
+use crate::ir::Function;
+use crate::isa::TargetIsa;
+use crate::result::CodegenResult;
+use crate::settings::{Blade, BladeCutBias};
+use alloc::vec::Vec;
+
+/// The Blade graph of a function.
+pub struct Graph {
+    graph: BladeGraph,
+    blade: Blade,
+    cut_bias: BladeCutBias,
+}
+
+impl Graph {
+    /// Get the number of edges in the graph.
+    pub fn num_edges(&self) -> usize {
+        self.graph.num_edges()
+    }
+}
+
+/// A minimal cut of a Blade graph.
+pub struct Cut(Vec<(Node, Node)>);
+
+impl Cut {
+    /// Get the number of edges in the cut.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Is the cut empty?
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Build the Blade graph of `func`, as configured by the flags of `isa`.
+///
+/// The `blade` setting must be one which inserts mitigations.
+pub fn build_graph(func: &Function, isa: &dyn TargetIsa) -> Graph {
+    let blade = isa.flags().blade();
+    assert!(
+        blade != Blade::None && blade != Blade::Analyze,
+        "blade={} inserts no mitigations",
+        blade
+    );
+    Graph {
+        graph: super::build_graph(
+            func,
+            isa.flags(),
+            builtin_strategy(isa.flags(), blade).as_ref(),
+            None,
+        ),
+        blade,
+        cut_bias: isa.flags().blade_cut_bias(),
+    }
+}
+
+/// Compute a minimal cut of `graph`.
+pub fn min_cut(graph: &Graph) -> Cut {
+    Cut(graph.graph.min_cut(graph.cut_bias))
+}
+
+/// Protect the edges of `cut` in `func`, which `graph` must have been built from.
+///
+/// This never falls back to fencing every block, whatever the size of `graph`.
+pub fn insert_mitigations(
+    func: &mut Function,
+    isa: &dyn TargetIsa,
+    graph: &Graph,
+    cut: &Cut,
+) -> CodegenResult<BladeStats> {
+    let mut stats = BladeStats {
+        values: super::num_defined_values(func),
+        graph_nodes: graph.graph.num_nodes(),
+        graph_edges: graph.graph.num_edges(),
+        sources: graph.graph.num_sources(),
+        sinks: graph.graph.num_sinks(),
+        cut_size: cut.len(),
+        ..BladeStats::default()
+    };
+    let mut strategy = builtin_strategy(isa.flags(), graph.blade);
+    super::insert_mitigations(
+        func,
+        isa,
+        strategy.as_mut(),
+        &graph.graph,
+        &cut.0,
+        &mut stats,
+    )?;
+    Ok(stats)
+}
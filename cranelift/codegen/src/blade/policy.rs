@@ -0,0 +1,509 @@
+//! The threat model of Blade: which instructions introduce transient values, and which leak them.
+//!
+//! The Blade graph asks a `BladePolicy` for the role of every instruction, so that alternative
+//! threat models can be plugged in without patching the pass. `DefaultBladePolicy` implements
+//! the one configured by the `blade` family of settings, along with the classes declared in
+//! `Function::blade_class`.
+
+use super::speculation::SpeculativeRegions;
+use super::BladeDiagnosticKind;
+use crate::ir::{BladeClass, Function, Inst, InstructionData, Opcode, StackSlot, Value, ValueDef};
+use crate::settings::{BladeLeakageModel, BladeStackPointerAccesses, BladeTransientParams, Flags};
+use alloc::vec::Vec;
+use core::ops::BitOr;
+
+/// The role of an instruction in the Blade graph.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstClassification {
+    /// Does the instruction produce transient values? If so, its results are sources, unless
+    /// they are declared trusted in `Function::blade_taint`.
+    pub source: bool,
+    /// The operands of the instruction which it may leak through a side channel.
+    pub sinks: Vec<Value>,
+    /// The ways in which this classification falls short of modeling the instruction.
+    pub diagnostics: Vec<BladeDiagnosticKind>,
+}
+
+/// A classification of instructions into sources and sinks of transient values.
+///
+/// The Blade graph treats the loads and stores of stack slots that don't escape as neither
+/// sources nor sinks, and honors the taints declared in `Function::blade_taint`, whatever the
+/// policy says.
+pub trait BladePolicy {
+    /// Classify `inst`, an instruction of `func`.
+    fn classify_inst(&self, func: &Function, inst: Inst) -> InstClassification;
+
+    /// Is `param`, a block parameter of `func`, transient whatever flows into it?
+    ///
+    /// By default, only the values passed to a block parameter flow into it.
+    fn is_source_param(&self, _func: &Function, _param: Value) -> bool {
+        false
+    }
+}
+
+/// Which loads, besides those of `Default` class, and which block parameters are sources.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceOptions {
+    /// Loads of trusted runtime data, marked with the `Trusted` class.
+    pub trusted_loads: bool,
+    /// Loads which can't execute speculatively, assuming the function isn't entered
+    /// speculatively.
+    pub non_speculative_loads: bool,
+    /// The parameters of every block but the entry block, as an over-approximation of the
+    /// dataflow into them.
+    pub block_params: bool,
+    /// The parameters of the function.
+    pub function_params: bool,
+    /// Loads of Wasm globals, marked with the `Global` class.
+    pub global_loads: bool,
+    /// Loads of fixed stack slots, through `stack_load` or the address of a `stack_addr`.
+    pub stack_loads: bool,
+    /// Accesses through the stack pointer which aren't operands: pops, and the stack pointer
+    /// itself, which is then an untracked implicit operand of `ifcmp_sp`.
+    pub stack_pointer: bool,
+}
+
+impl Default for SourceOptions {
+    fn default() -> Self {
+        Self {
+            trusted_loads: true,
+            non_speculative_loads: true,
+            block_params: false,
+            function_params: false,
+            global_loads: true,
+            stack_loads: true,
+            stack_pointer: true,
+        }
+    }
+}
+
+/// A set of classes of operands which may be sinks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SinkClasses {
+    bits: u8,
+}
+
+impl SinkClasses {
+    /// The addresses of loads.
+    pub const LOAD_ADDRESSES: Self = Self { bits: 1 << 0 };
+    /// The addresses of stores.
+    pub const STORE_ADDRESSES: Self = Self { bits: 1 << 1 };
+    /// The values being stored.
+    pub const STORE_VALUES: Self = Self { bits: 1 << 2 };
+    /// The conditions of branches, and the indexes of jump tables.
+    pub const BRANCH_CONDITIONS: Self = Self { bits: 1 << 3 };
+    /// The arguments of calls, besides the callee.
+    pub const CALL_ARGUMENTS: Self = Self { bits: 1 << 4 };
+    /// The targets of indirect calls and jumps.
+    pub const INDIRECT_TARGETS: Self = Self { bits: 1 << 5 };
+    /// The operands deciding whether an instruction traps, such as the conditions of
+    /// conditional traps and the divisors of divisions.
+    pub const TRAP_CONDITIONS: Self = Self { bits: 1 << 6 };
+
+    /// The operands which may leak transient values into the contents of memory: the values
+    /// being stored, and call arguments, which the callee may store without protecting them
+    /// since it assumes its parameters are stable.
+    pub const MEMORY_CONTENTS: Self = Self {
+        bits: Self::STORE_VALUES.bits | Self::CALL_ARGUMENTS.bits,
+    };
+
+    /// The empty set.
+    pub fn empty() -> Self {
+        Self { bits: 0 }
+    }
+
+    /// The set of every class.
+    pub fn all() -> Self {
+        Self { bits: (1 << 7) - 1 }
+    }
+
+    /// Create a set from its bits, as in the `blade_sink_classes` setting, ignoring unknown
+    /// bits.
+    pub fn from_bits_truncate(bits: u8) -> Self {
+        Self {
+            bits: bits & Self::all().bits,
+        }
+    }
+
+    /// Get the classes of sinks of the leakage model `flags` select.
+    pub(super) fn from_flags(flags: &Flags) -> Self {
+        match flags.blade_leakage_model() {
+            BladeLeakageModel::Cache => Self::from_bits_truncate(flags.blade_sink_classes()),
+            BladeLeakageModel::MemoryContents => Self::MEMORY_CONTENTS,
+        }
+    }
+
+    /// Get the bits of the set.
+    pub fn bits(self) -> u8 {
+        self.bits
+    }
+
+    /// Does this set contain every class of `other`?
+    pub fn contains(self, other: Self) -> bool {
+        self.bits & other.bits == other.bits
+    }
+
+    /// Get this set without the classes of `other`.
+    pub fn without(self, other: Self) -> Self {
+        Self {
+            bits: self.bits & !other.bits,
+        }
+    }
+}
+
+impl BitOr for SinkClasses {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self {
+            bits: self.bits | other.bits,
+        }
+    }
+}
+
+impl Default for SinkClasses {
+    /// Every class but the trap conditions, as in the default `blade_sink_classes`.
+    fn default() -> Self {
+        Self::all().without(Self::TRAP_CONDITIONS)
+    }
+}
+
+/// Which operands are sinks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SinkOptions {
+    /// The classes of the operands of memory accesses, branches and calls which are sinks.
+    pub classes: SinkClasses,
+    /// Operands of integer divisions and remainders, whose latency depends on them.
+    pub division: bool,
+    /// Operands of the floating-point operations whose latency depends on them, e.g. when they
+    /// are subnormal.
+    pub float: bool,
+    /// Addresses of the accesses to Wasm globals, marked with the `Global` class. The values
+    /// stored into globals are sinks either way.
+    pub global_addresses: bool,
+    /// Values stored into fixed stack slots, through `stack_store` or the address of a
+    /// `stack_addr`.
+    pub stack_stores: bool,
+}
+
+impl Default for SinkOptions {
+    fn default() -> Self {
+        Self {
+            classes: SinkClasses::default(),
+            division: false,
+            float: false,
+            global_addresses: true,
+            stack_stores: true,
+        }
+    }
+}
+
+/// The policy of the Blade paper: every load is a source, and the addresses of memory accesses,
+/// stored values and branch conditions are sinks, as refined by `SourceOptions` and
+/// `SinkOptions`.
+///
+/// Instructions with an explicit `BladeClass` are classified as their producer asked.
+pub struct DefaultBladePolicy {
+    sources: SourceOptions,
+    sinks: SinkOptions,
+    regions: SpeculativeRegions,
+}
+
+impl DefaultBladePolicy {
+    /// Create the policy for classifying the instructions of `func`.
+    pub fn new(func: &Function, sources: SourceOptions, sinks: SinkOptions) -> Self {
+        let regions = if sources.non_speculative_loads {
+            SpeculativeRegions::everywhere()
+        } else {
+            SpeculativeRegions::for_function(func)
+        };
+        Self {
+            sources,
+            sinks,
+            regions,
+        }
+    }
+
+    /// Create the policy configured by `flags` for classifying the instructions of `func`.
+    pub fn from_flags(func: &Function, flags: &Flags) -> Self {
+        let params = flags.blade_transient_params();
+        let globals = !flags.blade_exclude_global_accesses();
+        let stack = !flags.blade_exclude_stack_accesses();
+        let classes = SinkClasses::from_flags(flags);
+        let timing = flags.blade_leakage_model() == BladeLeakageModel::Cache;
+        Self::new(
+            func,
+            SourceOptions {
+                trusted_loads: !flags.blade_exclude_trusted_loads(),
+                non_speculative_loads: !flags.blade_exclude_non_speculative_loads(),
+                block_params: params != BladeTransientParams::None,
+                function_params: params == BladeTransientParams::All,
+                global_loads: globals,
+                stack_loads: stack,
+                stack_pointer: flags.blade_stack_pointer_accesses()
+                    == BladeStackPointerAccesses::Strict,
+            },
+            SinkOptions {
+                classes,
+                division: timing && flags.blade_division_is_sink(),
+                float: timing && flags.blade_float_is_sink(),
+                global_addresses: globals,
+                stack_stores: stack,
+            },
+        )
+    }
+
+    /// Does `inst` produce transient values?
+    fn is_source(&self, func: &Function, inst: Inst) -> bool {
+        let opcode = func.dfg[inst].opcode();
+        let load = opcode.can_load()
+            && self.regions.is_speculative(inst)
+            && (self.sources.stack_loads || fixed_stack_slot(func, inst).is_none())
+            && (self.sources.stack_pointer || !is_pop(opcode));
+        match func.blade_class[inst] {
+            BladeClass::Default => load,
+            BladeClass::Trusted => self.sources.trusted_loads && load,
+            BladeClass::Global => self.sources.global_loads && load,
+            BladeClass::Source => true,
+            BladeClass::Sink | BladeClass::Neither => false,
+        }
+    }
+
+    /// Get the operands of `inst` which it may leak through a side channel.
+    fn sink_operands(&self, func: &Function, inst: Inst) -> Vec<Value> {
+        let opcode = func.dfg[inst].opcode();
+        let args = func.dfg.inst_args(inst);
+        let classes = self.sinks.classes;
+        let addresses = SinkClasses::LOAD_ADDRESSES | SinkClasses::STORE_ADDRESSES;
+        let mut sinks = Vec::new();
+        match func.blade_class[inst] {
+            BladeClass::Default | BladeClass::Trusted => {
+                if fixed_stack_slot(func, inst).is_some() {
+                    // Only the value being stored, as the address is fixed.
+                    if self.sinks.stack_stores {
+                        memory_sinks(opcode, args, classes.without(addresses), &mut sinks);
+                    }
+                } else if opcode.can_load() || opcode.can_store() {
+                    // Memory addresses, and the values being stored.
+                    memory_sinks(opcode, args, classes, &mut sinks);
+                } else if opcode.is_branch() {
+                    // Branch conditions and indirect branch targets. Block arguments are not
+                    // leaked.
+                    let fixed_args = func.dfg.inst_fixed_args(inst);
+                    if let Some(target) = indirect_target(func, inst) {
+                        if classes.contains(SinkClasses::INDIRECT_TARGETS) {
+                            sinks.push(target);
+                        }
+                    } else if classes.contains(SinkClasses::BRANCH_CONDITIONS) {
+                        sinks.extend_from_slice(fixed_args);
+                    }
+                } else if opcode.is_call() {
+                    let callee = indirect_target(func, inst);
+                    if let Some(callee) = callee {
+                        // A transient callee would steer speculative execution anywhere.
+                        if classes.contains(SinkClasses::INDIRECT_TARGETS) {
+                            sinks.push(callee);
+                        }
+                    }
+                    if classes.contains(SinkClasses::CALL_ARGUMENTS) {
+                        let skip = if callee.is_some() { 1 } else { 0 };
+                        sinks.extend_from_slice(&args[skip..]);
+                    }
+                } else if is_division(opcode) && self.sinks.division {
+                    // Dividends and divisors, through the timing of the division. This includes
+                    // the divisor deciding whether it traps.
+                    sinks.extend_from_slice(args);
+                } else if opcode.can_trap() && classes.contains(SinkClasses::TRAP_CONDITIONS) {
+                    // Whether the instruction traps, which transfers control to the trap handler
+                    // even under speculation.
+                    sinks.extend_from_slice(trap_conditions(opcode, args));
+                } else if is_variable_latency_float(opcode) && self.sinks.float {
+                    // Floating-point operands, through the timing of the operation.
+                    sinks.extend_from_slice(args);
+                }
+            }
+            BladeClass::Global => {
+                let classes = if self.sinks.global_addresses {
+                    classes
+                } else {
+                    // Only the value being stored.
+                    classes.without(addresses)
+                };
+                memory_sinks(opcode, args, classes, &mut sinks);
+            }
+            BladeClass::Sink => sinks.extend_from_slice(args),
+            BladeClass::Source | BladeClass::Neither => {}
+        }
+        sinks
+    }
+
+    /// Get the ways in which the Blade graph falls short of modeling `inst`.
+    fn diagnose(&self, func: &Function, inst: Inst) -> Vec<BladeDiagnosticKind> {
+        let mut kinds = Vec::new();
+        // Instructions with an explicit class are modeled exactly as the producer asked.
+        match func.blade_class[inst] {
+            BladeClass::Default | BladeClass::Trusted | BladeClass::Global => {}
+            BladeClass::Source | BladeClass::Sink | BladeClass::Neither => return kinds,
+        }
+
+        let opcode = func.dfg[inst].opcode();
+        let has_args = !func.dfg.inst_args(inst).is_empty();
+        let handled = opcode.can_load()
+            || opcode.can_store()
+            || opcode.is_branch()
+            || (opcode.is_call() && self.sinks.classes.contains(SinkClasses::CALL_ARGUMENTS))
+            || (opcode.can_trap() && self.sinks.classes.contains(SinkClasses::TRAP_CONDITIONS))
+            || (is_division(opcode) && self.sinks.division)
+            || (is_variable_latency_float(opcode) && self.sinks.float);
+        let side_effect = opcode.can_trap()
+            || opcode.other_side_effects()
+            || opcode.is_call()
+            || opcode.is_return();
+        if has_args && side_effect && !handled {
+            kinds.push(BladeDiagnosticKind::UnhandledSideEffect);
+        }
+
+        match opcode {
+            Opcode::GetPinnedReg => kinds.push(BladeDiagnosticKind::ImplicitOperand),
+            Opcode::IfcmpSp if self.sources.stack_pointer => {
+                kinds.push(BladeDiagnosticKind::ImplicitOperand);
+            }
+            _ => {}
+        }
+        kinds
+    }
+}
+
+impl BladePolicy for DefaultBladePolicy {
+    fn classify_inst(&self, func: &Function, inst: Inst) -> InstClassification {
+        InstClassification {
+            source: self.is_source(func, inst),
+            sinks: self.sink_operands(func, inst),
+            diagnostics: self.diagnose(func, inst),
+        }
+    }
+
+    fn is_source_param(&self, func: &Function, param: Value) -> bool {
+        match func.dfg.value_def(param) {
+            ValueDef::Param(block, _) if func.layout.entry_block() == Some(block) => {
+                self.sources.function_params
+            }
+            _ => self.sources.block_params,
+        }
+    }
+}
+
+/// Get the stack slot accessed by `inst`, if it is a `stack_load` or `stack_store`, or a load or
+/// store of the address computed by a `stack_addr`.
+fn fixed_stack_slot(func: &Function, inst: Inst) -> Option<StackSlot> {
+    let slot = match func.dfg[inst] {
+        InstructionData::StackLoad {
+            opcode: Opcode::StackLoad,
+            stack_slot,
+            ..
+        }
+        | InstructionData::StackStore { stack_slot, .. } => stack_slot,
+        InstructionData::Load { arg: addr, .. }
+        | InstructionData::Store {
+            args: [_, addr], ..
+        } => match func.dfg.value_def(func.dfg.resolve_aliases(addr)) {
+            ValueDef::Result(def, _) => match func.dfg[def] {
+                InstructionData::StackLoad {
+                    opcode: Opcode::StackAddr,
+                    stack_slot,
+                    ..
+                } => stack_slot,
+                _ => return None,
+            },
+            ValueDef::Param(..) => return None,
+        },
+        _ => return None,
+    };
+    // The slot is an immediate, so the address can't depend on any value.
+    debug_assert!(
+        func.stack_slots.is_valid(slot),
+        "{} accesses the undeclared {}",
+        inst,
+        slot
+    );
+    Some(slot)
+}
+
+/// Add the operands `args` of a load or store with `opcode` which belong to `classes` to `sinks`.
+fn memory_sinks(opcode: Opcode, args: &[Value], classes: SinkClasses, sinks: &mut Vec<Value>) {
+    if opcode.can_store() {
+        // Stores take the value being stored first, followed by the address.
+        if let Some((&value, addresses)) = args.split_first() {
+            if classes.contains(SinkClasses::STORE_VALUES) {
+                sinks.push(value);
+            }
+            if classes.contains(SinkClasses::STORE_ADDRESSES) {
+                sinks.extend_from_slice(addresses);
+            }
+        }
+    } else if classes.contains(SinkClasses::LOAD_ADDRESSES) {
+        sinks.extend_from_slice(args);
+    }
+}
+
+/// Get the target address of an indirect call or jump.
+fn indirect_target(func: &Function, inst: Inst) -> Option<Value> {
+    match func.dfg[inst].opcode() {
+        Opcode::CallIndirect | Opcode::IndirectJumpTableBr => Some(func.dfg.inst_args(inst)[0]),
+        _ => None,
+    }
+}
+
+/// Get the operands `args` of an `opcode` instruction which decide whether it traps.
+///
+/// Divisions trap on a zero divisor, so their dividend is left out. Conditional traps and
+/// conversions to integers trap depending on their only operand.
+fn trap_conditions(opcode: Opcode, args: &[Value]) -> &[Value] {
+    match opcode {
+        Opcode::Udiv | Opcode::Sdiv | Opcode::Urem | Opcode::Srem => &args[1..],
+        #[cfg(feature = "x86")]
+        Opcode::X86Udivmodx | Opcode::X86Sdivmodx => &args[2..],
+        Opcode::Trapz
+        | Opcode::Trapnz
+        | Opcode::ResumableTrapnz
+        | Opcode::Trapif
+        | Opcode::Trapff
+        | Opcode::FcvtToUint
+        | Opcode::FcvtToSint => args,
+        _ => &[],
+    }
+}
+
+/// Is `opcode` a pop, loading from the stack pointer?
+fn is_pop(opcode: Opcode) -> bool {
+    match opcode {
+        #[cfg(feature = "x86")]
+        Opcode::X86Pop => true,
+        _ => false,
+    }
+}
+
+/// Is `opcode` an integer division or remainder?
+fn is_division(opcode: Opcode) -> bool {
+    match opcode {
+        Opcode::Udiv
+        | Opcode::Sdiv
+        | Opcode::Urem
+        | Opcode::Srem
+        | Opcode::UdivImm
+        | Opcode::SdivImm
+        | Opcode::UremImm
+        | Opcode::SremImm => true,
+        #[cfg(feature = "x86")]
+        Opcode::X86Udivmodx | Opcode::X86Sdivmodx => true,
+        _ => false,
+    }
+}
+
+/// Is `opcode` a floating-point operation with data-dependent latency?
+fn is_variable_latency_float(opcode: Opcode) -> bool {
+    match opcode {
+        Opcode::Fmul | Opcode::Fdiv | Opcode::Sqrt | Opcode::Fma => true,
+        _ => false,
+    }
+}
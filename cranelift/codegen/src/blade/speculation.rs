@@ -0,0 +1,298 @@
+//! The regions of a function which may execute speculatively.
+//!
+//! Execution can only go down a mispredicted path at a conditional branch, an indirect jump, a
+//! conditional trap, or when returning from a call, whose callee may itself have mispredicted a
+//! branch, and it stops at a speculation barrier. Assuming the function is entered
+//! non-speculatively, the instructions which no such speculation point can reach without
+//! crossing a barrier, e.g. the straight-line code at its beginning, always execute
+//! architecturally. Loads there only ever read the memory they are meant to.
+//!
+//! Pruning by post-dominance instead, i.e. treating the code after a conditional branch and its
+//! join as non-speculative, would be unsound: a mispredicted branch may skip the code which
+//! clamps an index before the join, and a load there then reads out of bounds. Dominance is only
+//! used to rank the speculative regions by how deeply they are nested.
+
+use crate::dominator_tree::DominatorTree;
+use crate::entity::{EntitySet, SecondaryMap};
+use crate::flowgraph::ControlFlowGraph;
+use crate::ir::instructions::BranchInfo;
+use crate::ir::{Block, Function, Inst, Opcode};
+use alloc::vec::Vec;
+
+/// The instructions of a function which can't execute speculatively.
+pub struct SpeculativeRegions {
+    non_speculative: EntitySet<Inst>,
+}
+
+impl SpeculativeRegions {
+    /// Assume every instruction of a function may execute speculatively, as when the function
+    /// itself may be entered speculatively.
+    pub fn everywhere() -> Self {
+        Self {
+            non_speculative: EntitySet::new(),
+        }
+    }
+
+    /// Find the instructions of `func` which no speculation point can reach without crossing a
+    /// speculation barrier.
+    ///
+    /// A block may be entered speculatively when a branch which may execute speculatively, or
+    /// which is a speculation point itself, leads to it. Within a block, execution becomes
+    /// speculative after a speculation point and stops being so at a barrier, so code dominated
+    /// by a fenced call is non-speculative up to the next speculation point.
+    pub fn for_function(func: &Function) -> Self {
+        let mut entered = EntitySet::with_capacity(func.dfg.num_blocks());
+        let mut worklist: Vec<Block> = func.layout.blocks().collect();
+        while let Some(block) = worklist.pop() {
+            let mut speculative = entered.contains(block);
+            for inst in func.layout.block_insts(block) {
+                speculative = speculative_after(func, inst, speculative, |dest| {
+                    if entered.insert(dest) {
+                        worklist.push(dest);
+                    }
+                });
+            }
+        }
+
+        let mut non_speculative = EntitySet::with_capacity(func.dfg.num_insts());
+        for block in func.layout.blocks() {
+            let mut speculative = entered.contains(block);
+            for inst in func.layout.block_insts(block) {
+                if !speculative || func.pre_lfence[inst] {
+                    non_speculative.insert(inst);
+                }
+                speculative = speculative_after(func, inst, speculative, |_| {});
+            }
+        }
+        Self { non_speculative }
+    }
+
+    /// May `inst` execute speculatively?
+    pub fn is_speculative(&self, inst: Inst) -> bool {
+        !self.non_speculative.contains(inst)
+    }
+}
+
+/// Get whether execution may be speculative right after `inst`, given whether it may be before
+/// `inst`, calling `entered` on every block which `inst` may speculatively branch to.
+fn speculative_after(
+    func: &Function,
+    inst: Inst,
+    speculative: bool,
+    mut entered: impl FnMut(Block),
+) -> bool {
+    let speculative = (speculative && !func.pre_lfence[inst]) || is_speculation_point(func, inst);
+    if speculative {
+        match func.dfg.analyze_branch(inst) {
+            BranchInfo::NotABranch => {}
+            BranchInfo::SingleDest(dest, _) => entered(dest),
+            BranchInfo::Table(jt, default) => {
+                default.into_iter().for_each(&mut entered);
+                func.jump_tables[jt].iter().copied().for_each(&mut entered);
+            }
+        }
+    }
+    // A barrier after a branch only covers the fall-through path.
+    speculative && !func.post_lfence[inst]
+}
+
+/// Get the number of speculation points which dominate each block of `func`, i.e. the depth of
+/// the speculative regions nested around it.
+pub fn nesting_depths(func: &Function) -> SecondaryMap<Block, u32> {
+    let cfg = ControlFlowGraph::with_function(func);
+    let domtree = DominatorTree::with_function(func, &cfg);
+    let mut depths = SecondaryMap::with_capacity(func.dfg.num_blocks());
+    // Reverse post-order visits every immediate dominator before the blocks it dominates.
+    for &block in domtree.cfg_postorder().iter().rev() {
+        if let Some(idom) = domtree.idom(block) {
+            let idom_block = func.layout.inst_block(idom).unwrap();
+            let enclosing = func
+                .layout
+                .block_insts(idom_block)
+                .take_while(|&inst| inst != idom)
+                .chain(Some(idom))
+                .filter(|&inst| is_speculation_point(func, inst))
+                .count();
+            depths[block] = depths[idom_block] + enclosing as u32;
+        }
+    }
+    depths
+}
+
+/// May execution continue speculatively down the wrong path after `inst`?
+fn is_speculation_point(func: &Function, inst: Inst) -> bool {
+    let opcode = func.dfg[inst].opcode();
+    if opcode.is_call() {
+        return true;
+    }
+    match opcode {
+        Opcode::Brz
+        | Opcode::Brnz
+        | Opcode::BrIcmp
+        | Opcode::Brif
+        | Opcode::Brff
+        | Opcode::BrTable
+        | Opcode::IndirectJumpTableBr
+        | Opcode::Trapz
+        | Opcode::Trapnz
+        | Opcode::ResumableTrapnz
+        | Opcode::Trapif
+        | Opcode::Trapff => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::{types, ExtFuncData, ExternalName, InstBuilder, MemFlags, Signature, TrapCode};
+    use crate::isa::CallConv;
+
+    #[test]
+    fn straight_line_prefix() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+        let block2 = func.dfg.make_block();
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let a = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let load_a = cur.func.dfg.value_def(a).unwrap_inst();
+        let trap = cur.ins().trapz(a, TrapCode::HeapOutOfBounds);
+        let b = cur.ins().load(types::I64, MemFlags::new(), ptr, 8);
+        let load_b = cur.func.dfg.value_def(b).unwrap_inst();
+        cur.ins().jump(block1, &[]);
+
+        cur.insert_block(block1);
+        let c = cur.ins().load(types::I64, MemFlags::new(), ptr, 16);
+        let load_c = cur.func.dfg.value_def(c).unwrap_inst();
+        cur.ins().jump(block2, &[]);
+
+        cur.insert_block(block2);
+        cur.ins().return_(&[]);
+
+        let regions = SpeculativeRegions::for_function(&func);
+        assert!(!regions.is_speculative(load_a));
+        assert!(!regions.is_speculative(trap));
+        assert!(regions.is_speculative(load_b));
+        assert!(regions.is_speculative(load_c));
+
+        // Without the conditional trap, nothing can execute speculatively.
+        func.layout.remove_inst(trap);
+        let regions = SpeculativeRegions::for_function(&func);
+        assert!(!regions.is_speculative(load_b));
+        assert!(!regions.is_speculative(load_c));
+    }
+
+    #[test]
+    fn loop_header() {
+        // The header of a loop is reachable from the conditional branch closing the loop.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+        let block2 = func.dfg.make_block();
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        cur.ins().jump(block1, &[]);
+
+        cur.insert_block(block1);
+        let a = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let load = cur.func.dfg.value_def(a).unwrap_inst();
+        cur.ins().brnz(a, block1, &[]);
+        cur.ins().jump(block2, &[]);
+
+        cur.insert_block(block2);
+        cur.ins().return_(&[]);
+
+        let regions = SpeculativeRegions::for_function(&func);
+        assert!(regions.is_speculative(load));
+    }
+
+    #[test]
+    fn fenced_call() {
+        // Speculation past a call stops at a barrier after it.
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let ptr = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+        let block2 = func.dfg.make_block();
+        let sig = func.import_signature(Signature::new(CallConv::SystemV));
+        let callee = func.import_function(ExtFuncData {
+            name: ExternalName::testcase("callee"),
+            signature: sig,
+            colocated: false,
+        });
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let call = cur.ins().call(callee, &[]);
+        let a = cur.ins().load(types::I64, MemFlags::new(), ptr, 0);
+        let load_a = cur.func.dfg.value_def(a).unwrap_inst();
+        cur.ins().jump(block1, &[]);
+
+        cur.insert_block(block1);
+        let b = cur.ins().load(types::I64, MemFlags::new(), ptr, 8);
+        let load_b = cur.func.dfg.value_def(b).unwrap_inst();
+        cur.ins().brz(b, block2, &[]);
+        let c = cur.ins().load(types::I64, MemFlags::new(), ptr, 16);
+        let load_c = cur.func.dfg.value_def(c).unwrap_inst();
+        cur.ins().jump(block2, &[]);
+
+        cur.insert_block(block2);
+        let d = cur.ins().load(types::I64, MemFlags::new(), ptr, 24);
+        let load_d = cur.func.dfg.value_def(d).unwrap_inst();
+        cur.ins().return_(&[]);
+
+        let regions = SpeculativeRegions::for_function(&func);
+        assert!(regions.is_speculative(load_a));
+        assert!(regions.is_speculative(load_b));
+
+        // The barrier dominates both loads, and no speculation point comes between.
+        func.post_lfence[call] = true;
+        let regions = SpeculativeRegions::for_function(&func);
+        assert!(!regions.is_speculative(load_a));
+        assert!(!regions.is_speculative(load_b));
+        assert!(regions.is_speculative(load_c));
+        // The join post-dominates the branch, but a mispredicted branch still reaches it.
+        assert!(regions.is_speculative(load_d));
+
+        // A barrier before a load ends speculation there too.
+        func.pre_lfence[load_d] = true;
+        let regions = SpeculativeRegions::for_function(&func);
+        assert!(!regions.is_speculative(load_d));
+    }
+
+    #[test]
+    fn nesting() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let x = func.dfg.append_block_param(block0, types::I64);
+        let block1 = func.dfg.make_block();
+        let block2 = func.dfg.make_block();
+        let block3 = func.dfg.make_block();
+
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        cur.ins().brz(x, block3, &[]);
+        cur.ins().jump(block1, &[]);
+        cur.insert_block(block1);
+        cur.ins().brnz(x, block3, &[]);
+        cur.ins().jump(block2, &[]);
+        cur.insert_block(block2);
+        cur.ins().jump(block3, &[]);
+        cur.insert_block(block3);
+        cur.ins().return_(&[]);
+
+        let depths = nesting_depths(&func);
+        assert_eq!(depths[block0], 0);
+        assert_eq!(depths[block1], 1);
+        assert_eq!(depths[block2], 2);
+        // Only the first branch dominates the join.
+        assert_eq!(depths[block3], 1);
+    }
+}
@@ -0,0 +1,441 @@
+//! How Blade protects the minimal cut of a function.
+//!
+//! Once the minimal cut separating the transient sources from the sinks is known, Blade asks a
+//! `BladeStrategy` to protect each of its edges, so that new mitigation primitives can be plugged
+//! in without patching the pass. `FenceStrategy` and `SlhStrategy` implement the ones selected by
+//! the `blade` setting.
+
+use super::graph::{is_select_arm, BladeGraph, BladeNode, EdgeWeights};
+use super::guards::bound_guarded_loads;
+use super::maxflow::Node;
+use super::{
+    after_def, before_def, beginning_of_block, branches_into_param, fence_edge, insert_fence,
+    BladeDiagnostic, BladeDiagnosticKind, BladeStats, FencePoint, SLHContext,
+};
+use crate::dominator_tree::DominatorTree;
+use crate::entity::{EntityRef, EntitySet};
+use crate::flowgraph::ControlFlowGraph;
+use crate::ir::{Function, Inst, Value, ValueDef};
+use crate::isa::TargetIsa;
+use crate::result::CodegenResult;
+use crate::settings::{Blade, BladePlacement, BladeSlhMask, Flags};
+use crate::HashMap;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use log::debug;
+
+/// A way of protecting the edges of the minimal cut of the Blade graph.
+///
+/// The edges of the cut are passed to the strategy one at a time, in a deterministic order, and
+/// then `finish` is called. A strategy may protect each edge as it comes, or collect them and
+/// protect them all at once.
+pub trait BladeStrategy {
+    /// Get the costs of protecting the edges of each class, which the minimal cut minimizes.
+    ///
+    /// By default, these are the costs of speculation barriers set by the `blade_weight_*`
+    /// settings, prioritizing nested speculative regions as `blade_prioritize_nested_regions`
+    /// says.
+    fn edge_weights(&self, flags: &Flags) -> EdgeWeights {
+        let weight = |setting: u8| u32::from(setting.max(1));
+        EdgeWeights {
+            source: weight(flags.blade_weight_fence_load()),
+            dependency: weight(flags.blade_weight_fence_value()),
+            sink: weight(flags.blade_weight_fence_sink()),
+            branch: weight(flags.blade_weight_fence_branch()),
+            prioritize_nested: flags.blade_prioritize_nested_regions(),
+        }
+    }
+
+    /// Protect the transient `value`, produced by a load or declared transient, cutting the
+    /// edge from the source node into it.
+    fn protect_source(&mut self, cut: &mut BladeCut, value: Value) -> CodegenResult<()>;
+
+    /// Protect the flow of the value `from` into `to`, either a value computed from it or an
+    /// instruction leaking it.
+    fn protect_edge(&mut self, cut: &mut BladeCut, from: Value, to: BladeNode)
+        -> CodegenResult<()>;
+
+    /// Protect the operands which `inst` leaks, cutting the edge from it into the sink node.
+    fn protect_sink(&mut self, cut: &mut BladeCut, inst: Inst) -> CodegenResult<()>;
+
+    /// Finish protecting the function, once every edge of the cut was passed to the other
+    /// methods.
+    fn finish(&mut self, _cut: &mut BladeCut) -> CodegenResult<()> {
+        Ok(())
+    }
+}
+
+/// A function whose minimal cut a `BladeStrategy` protects.
+pub struct BladeCut<'a> {
+    /// The function being protected.
+    pub func: &'a mut Function,
+    /// The target ISA of the function.
+    pub isa: &'a dyn TargetIsa,
+    graph: &'a BladeGraph,
+    nodes: HashMap<BladeNode, Node>,
+    stats: &'a mut BladeStats,
+}
+
+impl<'a> BladeCut<'a> {
+    pub(super) fn new(
+        func: &'a mut Function,
+        isa: &'a dyn TargetIsa,
+        graph: &'a BladeGraph,
+        stats: &'a mut BladeStats,
+    ) -> Self {
+        let nodes = (0..graph.num_nodes() + 2)
+            .filter_map(|node| graph.bladenode(node).map(|bladenode| (bladenode, node)))
+            .collect();
+        Self {
+            func,
+            isa,
+            graph,
+            nodes,
+            stats,
+        }
+    }
+
+    /// Place a speculation barrier at `point` and record it, unless there is one already.
+    ///
+    /// A barrier after a conditional branch only executes when the branch falls through, so the
+    /// edge taken by the branch is split to hold a barrier of its own. A barrier after any other
+    /// branch would never execute, so it goes right before the branch instead.
+    ///
+    /// Returns whether a new barrier was placed.
+    pub fn insert_fence(&mut self, point: FencePoint) -> bool {
+        match point {
+            FencePoint::After(branch) if self.func.dfg[branch].opcode().is_branch() => {
+                if self.func.dfg[branch].opcode().is_terminator() {
+                    return self.insert_one_fence(FencePoint::Before(branch));
+                }
+                if !self.insert_one_fence(point) {
+                    // The taken edge was split when this barrier was placed.
+                    return false;
+                }
+                let jump = self.split_edge(branch);
+                self.insert_one_fence(FencePoint::Before(jump));
+                true
+            }
+            _ => self.insert_one_fence(point),
+        }
+    }
+
+    fn insert_one_fence(&mut self, point: FencePoint) -> bool {
+        let inserted = insert_fence(self.func, point);
+        if inserted {
+            self.stats.fence_points.push(point);
+        }
+        inserted
+    }
+
+    /// Record that `load` was protected with speculative load hardening.
+    pub fn record_slh(&mut self, load: Inst) {
+        self.stats.slh_points.push(load);
+    }
+
+    /// Report an instruction which couldn't be protected as requested.
+    pub fn report(&mut self, diagnostic: BladeDiagnostic) {
+        debug!(
+            "Blade: {} in {}: {}",
+            self.func.dfg.display_inst(diagnostic.inst, None),
+            self.func.name,
+            diagnostic.kind
+        );
+        self.stats.diagnostics.push(diagnostic);
+    }
+
+    /// Get the transient values from which any of `nodes` is reachable in the Blade graph.
+    ///
+    /// Every value is returned at most once.
+    pub fn transient_sources(&self, nodes: impl IntoIterator<Item = BladeNode>) -> Vec<Value> {
+        let nodes = nodes
+            .into_iter()
+            .map(|bladenode| self.nodes[&bladenode])
+            .collect::<Vec<_>>();
+        self.graph.ancestors_of_all(nodes)
+    }
+
+    /// Get the unconditional jump through which `branch` passes its block arguments, where a
+    /// speculation barrier only stalls the paths taking `branch`.
+    ///
+    /// A conditional branch is redirected to a new block at the end of the function, which
+    /// jumps to its destination with its arguments.
+    pub fn split_edge(&mut self, branch: Inst) -> Inst {
+        fence_edge(self.func, self.isa, branch, self.stats)
+    }
+}
+
+/// Protect the cut with speculation barriers, as `blade=lfence` and `blade=lfence_per_block` do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FenceStrategy {
+    /// Where to fence the flows between two values.
+    placement: BladePlacement,
+    /// Whether to move every barrier to the beginning of its basic block.
+    per_block: bool,
+    /// Whether to fence the flows into block parameters on the control flow edges passing them.
+    split_edges: bool,
+}
+
+impl FenceStrategy {
+    /// Get the strategy configured by `flags`.
+    pub fn from_flags(flags: &Flags) -> Self {
+        let placement = flags.blade_placement();
+        Self {
+            placement,
+            per_block: flags.blade() == Blade::LfencePerBlock,
+            split_edges: flags.blade_split_edges()
+                && !flags.blade_coarsen()
+                && placement == BladePlacement::Late,
+        }
+    }
+
+    /// Get the point where this strategy places the barrier which `point` asks for.
+    pub(super) fn place(&self, func: &Function, point: FencePoint) -> FencePoint {
+        if self.per_block {
+            beginning_of_block(func, point)
+        } else {
+            point
+        }
+    }
+
+    /// Get the point where a barrier cuts the flow of the value `from` into `to`, leaving aside
+    /// the control flow edges which `blade_split_edges` fences instead.
+    pub(super) fn edge_point(&self, func: &Function, from: Value, to: BladeNode) -> FencePoint {
+        match (self.placement, to) {
+            // Fence right after the arm of a select, leaving its other arms unfenced.
+            (BladePlacement::Late, BladeNode::ValueDef(value))
+                if is_select_arm(func, from, value) =>
+            {
+                after_def(func, from)
+            }
+            // Fence right before the definition of `to`.
+            (BladePlacement::Late, BladeNode::Sink(inst)) => FencePoint::Before(inst),
+            (BladePlacement::Late, BladeNode::ValueDef(value)) => before_def(func, value),
+            // Fence right after the definition of `from`.
+            (BladePlacement::Early, _) => after_def(func, from),
+        }
+    }
+
+    fn fence(&self, cut: &mut BladeCut, point: FencePoint) {
+        let point = self.place(cut.func, point);
+        cut.insert_fence(point);
+    }
+}
+
+impl BladeStrategy for FenceStrategy {
+    fn protect_source(&mut self, cut: &mut BladeCut, value: Value) -> CodegenResult<()> {
+        // Fence right after the load producing the transient value, or at the beginning of the
+        // block of a parameter declared transient.
+        let point = after_def(cut.func, value);
+        self.fence(cut, point);
+        Ok(())
+    }
+
+    fn protect_edge(
+        &mut self,
+        cut: &mut BladeCut,
+        from: Value,
+        to: BladeNode,
+    ) -> CodegenResult<()> {
+        if let (true, BladeNode::ValueDef(param)) = (self.split_edges, to) {
+            let branches = branches_into_param(cut.func, from, param);
+            if !branches.is_empty() {
+                for branch in branches {
+                    let jump = cut.split_edge(branch);
+                    self.fence(cut, FencePoint::Before(jump));
+                }
+                return Ok(());
+            }
+        }
+        let point = self.edge_point(cut.func, from, to);
+        self.fence(cut, point);
+        Ok(())
+    }
+
+    fn protect_sink(&mut self, cut: &mut BladeCut, inst: Inst) -> CodegenResult<()> {
+        // Fence right before the leaking instruction.
+        self.fence(cut, FencePoint::Before(inst));
+        Ok(())
+    }
+}
+
+/// Protect the cut with speculative load hardening, as `blade=slh` and
+/// `blade=slh_with_fence_fallback` do.
+///
+/// SLH can only protect the loads themselves, so this hardens every load whose result flows
+/// through an edge of the cut. The edges are only collected as the cut is walked, and traced back
+/// to their loads all at once when it is finished, so that each load is hardened once however
+/// many edges it flows through.
+#[derive(Clone, Debug)]
+pub struct SlhStrategy {
+    /// Whether to use fake bounds for loads without bounds.
+    fake_bounds: bool,
+    /// Whether to protect loads without bounds with a speculation barrier instead.
+    fence_unbounded: bool,
+    /// What to mask when possible.
+    mask: BladeSlhMask,
+    /// The most instructions to add, as a percentage of those of the function, or 0 for no
+    /// limit.
+    size_budget: u8,
+    /// The nodes at the tail of the edges of the cut.
+    cut_nodes: Vec<BladeNode>,
+}
+
+impl SlhStrategy {
+    /// Get the strategy configured by `flags`.
+    pub fn from_flags(flags: &Flags) -> Self {
+        Self {
+            fake_bounds: flags.blade_slh_fake_bounds(),
+            fence_unbounded: flags.blade() == Blade::SlhWithFenceFallback,
+            mask: flags.blade_slh_mask(),
+            size_budget: flags.blade_slh_size_budget(),
+            cut_nodes: Vec::new(),
+        }
+    }
+}
+
+impl BladeStrategy for SlhStrategy {
+    fn edge_weights(&self, flags: &Flags) -> EdgeWeights {
+        // Cutting an edge from the source node means hardening a load.
+        EdgeWeights {
+            source: u32::from(flags.blade_weight_slh().max(1)),
+            ..FenceStrategy::from_flags(flags).edge_weights(flags)
+        }
+    }
+
+    fn protect_source(&mut self, _cut: &mut BladeCut, value: Value) -> CodegenResult<()> {
+        self.cut_nodes.push(BladeNode::ValueDef(value));
+        Ok(())
+    }
+
+    fn protect_edge(
+        &mut self,
+        _cut: &mut BladeCut,
+        from: Value,
+        _to: BladeNode,
+    ) -> CodegenResult<()> {
+        self.cut_nodes.push(BladeNode::ValueDef(from));
+        Ok(())
+    }
+
+    fn protect_sink(&mut self, _cut: &mut BladeCut, inst: Inst) -> CodegenResult<()> {
+        self.cut_nodes.push(BladeNode::Sink(inst));
+        Ok(())
+    }
+
+    fn finish(&mut self, cut: &mut BladeCut) -> CodegenResult<()> {
+        let first_new_inst = cut.func.dfg.num_insts();
+        let budget = if self.size_budget > 0 {
+            let insts: usize = cut
+                .func
+                .layout
+                .blocks()
+                .map(|block| cut.func.layout.block_insts(block).count())
+                .sum();
+            Some((insts * usize::from(self.size_budget) + 99) / 100)
+        } else {
+            None
+        };
+        let mut slh_ctx = SLHContext::new(
+            cut.func,
+            self.fake_bounds,
+            self.fence_unbounded,
+            self.mask,
+            budget,
+        );
+        let mut loads = Vec::new();
+        let mut to_protect = EntitySet::with_capacity(cut.func.dfg.num_insts());
+        // Trace every edge of the cut back to its sources at once, so that a source behind
+        // several edges is found and protected only once.
+        let num_cut_nodes = self.cut_nodes.len();
+        let sources = cut.transient_sources(self.cut_nodes.drain(..));
+        let mut num_params = 0;
+        for value in sources {
+            match cut.func.dfg.value_def(value) {
+                ValueDef::Result(load, _) => {
+                    if to_protect.insert(load) {
+                        loads.push(load);
+                    }
+                }
+                // A block parameter declared transient has no load to harden.
+                ValueDef::Param(..) => {
+                    num_params += 1;
+                    let point = before_def(cut.func, value);
+                    cut.insert_fence(point);
+                    let inst = match point {
+                        FencePoint::Before(inst) | FencePoint::After(inst) => inst,
+                    };
+                    slh_ctx.diagnostics.push(BladeDiagnostic {
+                        inst,
+                        kind: BladeDiagnosticKind::SlhFallback,
+                        srcloc: cut.func.srclocs[inst],
+                    });
+                }
+            }
+        }
+        cut.stats.slh_sources = loads.len() + num_params;
+        debug!(
+            "Blade SLH: {} cut edges trace back to {} sources in {}",
+            num_cut_nodes, cut.stats.slh_sources, cut.func.name
+        );
+        // The bounds checks of the loads give them bounds to mask their addresses against.
+        let cfg = ControlFlowGraph::with_function(cut.func);
+        let first_bound_inst = cut.func.dfg.num_insts();
+        bound_guarded_loads(cut.func, cut.isa, &cfg, &loads);
+        // The instructions computing the bounds count towards the size budget.
+        slh_ctx.added_insts += cut.func.dfg.num_insts() - first_bound_inst;
+        // Protect the loads in reverse post-order, so that the masks computed for a load are
+        // available to the loads it dominates and to the block parameters it flows into.
+        let domtree = DominatorTree::with_function(cut.func, &cfg);
+        for &block in domtree.cfg_postorder().iter().rev() {
+            let mut next = cut.func.layout.first_inst(block);
+            while let Some(inst) = next {
+                next = cut.func.layout.next_inst(inst);
+                if to_protect.contains(inst) {
+                    slh_ctx.do_slh_on(cut.func, cut.isa, &cfg, &domtree, inst)?;
+                }
+            }
+        }
+        // Loads in unreachable blocks.
+        for load in loads {
+            slh_ctx.do_slh_on(cut.func, cut.isa, &cfg, &domtree, load)?;
+        }
+        if slh_ctx.budget_fallbacks > 0 {
+            debug!(
+                "Blade SLH: {} loads fenced past the size budget of {} instructions in {}",
+                slh_ctx.budget_fallbacks,
+                budget.unwrap_or_default(),
+                cut.func.name
+            );
+        }
+        cut.stats.slh_budget_fallbacks = slh_ctx.budget_fallbacks;
+        for diagnostic in slh_ctx.diagnostics {
+            cut.report(diagnostic);
+        }
+        for load in slh_ctx.hardened_loads {
+            cut.record_slh(load);
+        }
+        // Every instruction created since the masking started belongs to it.
+        for block in cut.func.layout.blocks() {
+            for inst in cut.func.layout.block_insts(block) {
+                if inst.index() >= first_new_inst {
+                    cut.stats.slh_insts.push(inst);
+                }
+            }
+        }
+        // The fallback barriers are already in place.
+        cut.stats
+            .fence_points
+            .extend(slh_ctx.fenced_loads.into_iter().map(FencePoint::After));
+        Ok(())
+    }
+}
+
+/// Get the strategy implementing the `blade` mode, as configured by `flags`.
+pub(super) fn builtin_strategy(flags: &Flags, blade: Blade) -> Box<dyn BladeStrategy> {
+    if blade.uses_slh() {
+        Box::new(SlhStrategy::from_flags(flags))
+    } else {
+        Box::new(FenceStrategy::from_flags(flags))
+    }
+}
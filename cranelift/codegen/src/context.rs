@@ -10,13 +10,17 @@
 //! single ISA instance.
 
 use crate::binemit::{
-    relax_branches, shrink_instructions, CodeInfo, MemoryCodeSink, RelocSink, StackmapSink,
-    TrapSink,
+    relax_branches, shrink_instructions, CodeInfo, CodeOffset, MemoryCodeSink, RelocSink,
+    StackmapSink, TrapSink, LFENCE_SIZE,
+};
+use crate::blade::{
+    do_blade, BladeCutCache, BladeMitigation, BladePolicy, BladeStats, BladeStrategy,
 };
 use crate::dce::do_dce;
 use crate::dominator_tree::DominatorTree;
+use crate::entity::{EntitySet, SecondaryMap};
 use crate::flowgraph::ControlFlowGraph;
-use crate::ir::Function;
+use crate::ir::{Function, Inst};
 use crate::isa::TargetIsa;
 use crate::legalize_function;
 use crate::legalizer::simple_legalize;
@@ -28,6 +32,7 @@ use crate::postopt::do_postopt;
 use crate::redundant_reload_remover::RedundantReloadRemover;
 use crate::regalloc;
 use crate::remove_constant_phis::do_remove_constant_phis;
+use crate::repair_bounds::do_repair_bounds;
 use crate::result::CodegenResult;
 use crate::settings::{FlagsOrIsa, OptLevel};
 use crate::simple_gvn::do_simple_gvn;
@@ -36,6 +41,7 @@ use crate::timing;
 use crate::unreachable_code::eliminate_unreachable_code;
 use crate::value_label::{build_value_labels_ranges, ComparableSourceLoc, ValueLabelsRanges};
 use crate::verifier::{verify_context, verify_locations, VerifierErrors, VerifierResult};
+use alloc::boxed::Box;
 use alloc::vec::Vec;
 use log::debug;
 
@@ -64,6 +70,25 @@ pub struct Context {
 
     /// Flag: do we want a disassembly with the MachCompileResult?
     pub want_disasm: bool,
+
+    /// Statistics about the Spectre mitigations inserted by Blade, if it ran.
+    pub blade_stats: BladeStats,
+
+    /// Flag: do we leave the function out of the Blade pass, whatever the `blade` setting?
+    pub skip_blade: bool,
+
+    /// The classification of sources and sinks used by the Blade pass, instead of the default
+    /// one of the `blade` settings. Unlike the function data, this is kept by `clear`.
+    pub blade_policy: Option<Box<dyn BladePolicy>>,
+
+    /// The protection of the minimal cut used by the Blade pass, instead of the built-in one of
+    /// the `blade` setting. Unlike the function data, this is kept by `clear`.
+    pub blade_strategy: Option<Box<dyn BladeStrategy>>,
+
+    /// The minimal cuts the Blade pass shares between isomorphic graphs, if any. Unlike the
+    /// function data, this is kept by `clear`, so that it fills up over the functions compiled
+    /// with this context.
+    pub blade_cut_cache: Option<BladeCutCache>,
 }
 
 impl Context {
@@ -89,6 +114,11 @@ impl Context {
             redundant_reload_remover: RedundantReloadRemover::new(),
             mach_compile_result: None,
             want_disasm: false,
+            blade_stats: BladeStats::default(),
+            skip_blade: false,
+            blade_policy: None,
+            blade_strategy: None,
+            blade_cut_cache: None,
         }
     }
 
@@ -102,6 +132,8 @@ impl Context {
         self.redundant_reload_remover.clear();
         self.mach_compile_result = None;
         self.want_disasm = false;
+        self.blade_stats = BladeStats::default();
+        self.skip_blade = false;
     }
 
     /// Set the flag to request a disassembly when compiling with a
@@ -110,6 +142,30 @@ impl Context {
         self.want_disasm = val;
     }
 
+    /// Set the flag to leave the function out of the Blade Spectre mitigation pass when
+    /// compiling.
+    pub fn set_skip_blade(&mut self, val: bool) {
+        self.skip_blade = val;
+    }
+
+    /// Set the policy classifying the sources and sinks of transient values in the Blade pass,
+    /// replacing the default one of the `blade` settings.
+    pub fn set_blade_policy(&mut self, policy: Box<dyn BladePolicy>) {
+        self.blade_policy = Some(policy);
+    }
+
+    /// Set the strategy protecting the minimal cut computed by the Blade pass, replacing the
+    /// built-in one of the `blade` setting.
+    pub fn set_blade_strategy(&mut self, strategy: Box<dyn BladeStrategy>) {
+        self.blade_strategy = Some(strategy);
+    }
+
+    /// Share the minimal cuts of isomorphic graphs between the functions compiled with this
+    /// context in the Blade pass, starting from the cuts of `cache`.
+    pub fn set_blade_cut_cache(&mut self, cache: BladeCutCache) {
+        self.blade_cut_cache = Some(cache);
+    }
+
     /// Compile the function, and emit machine code into a `Vec<u8>`.
     ///
     /// Run the function through all the passes necessary to generate code for the target ISA
@@ -182,6 +238,10 @@ impl Context {
 
         self.remove_constant_phis(isa)?;
 
+        if !self.skip_blade {
+            self.blade(isa)?;
+        }
+
         if let Some(backend) = isa.get_mach_backend() {
             let result = backend.compile_function(&self.func, self.want_disasm)?;
             let info = result.code_info();
@@ -196,10 +256,102 @@ impl Context {
             if opt_level == OptLevel::SpeedAndSize {
                 self.shrink_instructions(isa)?;
             }
-            let result = self.relax_branches(isa);
+            let info = self.relax_branches(isa)?;
+            self.set_blade_offsets(isa);
 
             debug!("Compiled:\n{}", self.func.display(isa));
-            result
+            Ok(info)
+        }
+    }
+
+    /// Run the Blade Spectre mitigations on the function, as configured by the `blade` setting.
+    ///
+    /// The function must already be legalized for `isa`, and no pass which rewrites or moves
+    /// instructions may run between this one and code emission: the fences are attached to
+    /// individual instructions, and speculative load hardening relies on the bounds recorded
+    /// by heap and table legalization. Dead code should be removed beforehand, since Blade
+    /// would otherwise protect it too.
+    ///
+    /// The statistics of the run are kept in `blade_stats`.
+    pub fn blade(&mut self, isa: &dyn TargetIsa) -> CodegenResult<()> {
+        if isa.flags().blade().uses_slh() {
+            // Passes since the last repair may have stranded the bounds the hardening needs.
+            if !self.domtree.is_valid() {
+                self.compute_cfg();
+                self.compute_domtree();
+            }
+            self.repair_bounds(isa)?;
+        }
+        self.blade_stats = do_blade(
+            &mut self.func,
+            isa,
+            self.blade_policy.as_deref(),
+            self.blade_strategy
+                .as_mut()
+                .map(|strategy| strategy.as_mut() as &mut dyn BladeStrategy),
+            self.blade_cut_cache.as_mut(),
+        )?;
+        if self.blade_stats.split_edges > 0 {
+            // Barriers placed on control flow edges may have split some of them.
+            self.compute_cfg();
+            self.compute_domtree();
+        }
+        self.verify_if(isa)
+    }
+
+    /// Get every Spectre mitigation Blade placed in the function, after `compile`.
+    ///
+    /// With the legacy backends, each mitigation also has the offset of its instruction in the
+    /// emitted code. The new backends don't keep track of instruction offsets, so mitigations
+    /// have none there.
+    pub fn blade_report(&self) -> &[BladeMitigation] {
+        &self.blade_stats.mitigations
+    }
+
+    /// Record the code offsets of the Blade mitigations, of the speculation barriers and of the
+    /// instructions of the Blade graph, and the size of the code Blade added, once the code
+    /// layout is computed.
+    fn set_blade_offsets(&mut self, isa: &dyn TargetIsa) {
+        let stats = &self.blade_stats;
+        if stats.mitigations.is_empty()
+            && stats.fence_points.is_empty()
+            && stats.source_sites.is_empty()
+            && stats.sink_sites.is_empty()
+        {
+            self.blade_stats.code_size_growth = Some(0);
+            return;
+        }
+        let mut slh_insts = EntitySet::new();
+        for &inst in &stats.slh_insts {
+            slh_insts.insert(inst);
+        }
+        let mut offsets = SecondaryMap::<Inst, Option<CodeOffset>>::new();
+        let mut barrier_offsets = Vec::new();
+        let mut slh_size = 0;
+        let encinfo = isa.encoding_info();
+        for block in self.func.layout.blocks() {
+            for (offset, inst, size) in self.func.inst_offsets(block, &encinfo) {
+                offsets[inst] = Some(offset);
+                if self.func.pre_lfence[inst] {
+                    barrier_offsets.push(offset);
+                }
+                if self.func.post_lfence[inst] {
+                    barrier_offsets.push(offset + size - LFENCE_SIZE);
+                }
+                if slh_insts.contains(inst) {
+                    slh_size += size;
+                }
+            }
+        }
+        self.blade_stats.code_size_growth =
+            Some(barrier_offsets.len() as CodeOffset * LFENCE_SIZE + slh_size);
+        self.blade_stats.barrier_offsets = barrier_offsets;
+        for mitigation in &mut self.blade_stats.mitigations {
+            mitigation.offset = offsets[mitigation.inst];
+        }
+        let stats = &mut self.blade_stats;
+        for site in stats.source_sites.iter_mut().chain(&mut stats.sink_sites) {
+            site.offset = offsets[site.inst];
         }
     }
 
@@ -290,6 +442,11 @@ impl Context {
 
     /// Perform dead-code elimination on the function.
     pub fn dce<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
+        if !self.domtree.is_valid() {
+            self.flowgraph();
+        }
+        // Bounds left on aliases would not keep their values live.
+        do_repair_bounds(&mut self.func, &self.domtree);
         do_dce(&mut self.func, &mut self.domtree);
         self.verify_if(fisa)?;
         Ok(())
@@ -367,6 +524,13 @@ impl Context {
     /// Perform simple GVN on the function.
     pub fn simple_gvn<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
         do_simple_gvn(&mut self.func, &mut self.domtree);
+        do_repair_bounds(&mut self.func, &self.domtree);
+        self.verify_if(fisa)
+    }
+
+    /// Move the address bounds left on aliases by earlier passes to the values they resolve to.
+    pub fn repair_bounds<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
+        do_repair_bounds(&mut self.func, &self.domtree);
         self.verify_if(fisa)
     }
 
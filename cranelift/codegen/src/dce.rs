@@ -26,6 +26,12 @@ pub fn do_dce(func: &mut Function, domtree: &mut DominatorTree) {
                     for arg in pos.func.dfg.inst_args(inst) {
                         let v = pos.func.dfg.resolve_aliases(*arg);
                         live[v.index()] = true;
+                        // The bounds of a live pointer may be used to harden its accesses later.
+                        if let Some(bounds) = pos.func.dfg.bounds[v] {
+                            for bound in bounds.values() {
+                                live[pos.func.dfg.resolve_aliases(bound).index()] = true;
+                            }
+                        }
                     }
                     continue;
                 }
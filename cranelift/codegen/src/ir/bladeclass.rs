@@ -0,0 +1,89 @@
+//! Blade classification of instructions and values.
+
+use core::fmt;
+
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
+
+/// How the Blade Spectre mitigation pass treats an instruction.
+///
+/// Classes are attached to instructions through `Function::blade_class`. Producers which know
+/// more about a memory access than its opcode, such as whether it reads trusted runtime data or
+/// guest memory, can use them to override the classification Blade would otherwise infer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub enum BladeClass {
+    /// Classify the instruction from its opcode: loads produce transient values, and the
+    /// operands of loads, stores, branches and (optionally) calls are sinks.
+    Default,
+    /// The results of the instruction are transient, but none of its operands are sinks.
+    Source,
+    /// Every operand of the instruction is a sink, but its results are not transient.
+    Sink,
+    /// The instruction is neither a source nor a sink.
+    Neither,
+    /// The instruction loads trusted runtime data at an address derived from the VM context,
+    /// such as the base of a heap or table, or the address of a global. It is classified like a
+    /// load of `Default` class, unless the `blade_exclude_trusted_loads` setting says its results
+    /// are not transient.
+    Trusted,
+    /// The instruction loads or stores a Wasm global, at a fixed offset from the VM context or
+    /// from the address of an imported global. The guest can't steer its address, so it is safe
+    /// by construction. It is classified like an access of `Default` class, unless the
+    /// `blade_exclude_global_accesses` setting says that only the value it stores is a sink.
+    Global,
+}
+
+impl Default for BladeClass {
+    fn default() -> Self {
+        Self::Default
+    }
+}
+
+impl fmt::Display for BladeClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Default => "default",
+            Self::Source => "source",
+            Self::Sink => "sink",
+            Self::Neither => "neither",
+            Self::Trusted => "trusted",
+            Self::Global => "global",
+        })
+    }
+}
+
+/// Whether a value carries transient data, as declared by the producer of a function.
+///
+/// Taints are attached to values through `Function::blade_taint`. They let producers which know
+/// which inputs are attacker-controlled, or which values are safe, override what Blade infers
+/// from the instructions computing them. Taints are lost when the value is replaced, e.g. by an
+/// alias, so they are best declared on block parameters and on values which legalization
+/// doesn't rewrite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub enum BladeTaint {
+    /// Infer whether the value is transient from the instruction defining it and the values it
+    /// is computed from.
+    Default,
+    /// The value is transient, like the result of a load.
+    Source,
+    /// The value is stable, i.e. never transient, whatever it is defined by or computed from.
+    Stable,
+}
+
+impl Default for BladeTaint {
+    fn default() -> Self {
+        Self::Default
+    }
+}
+
+impl fmt::Display for BladeTaint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Default => "default",
+            Self::Source => "source",
+            Self::Stable => "stable",
+        })
+    }
+}
@@ -0,0 +1,64 @@
+//! Address bounds.
+
+use crate::ir::Value;
+use core::fmt;
+
+/// Bounds on the addresses that may be accessed through a pointer value.
+///
+/// Bounds are attached to pointer values through `DataFlowGraph::bounds`. They are not
+/// interpreted by Cranelift itself, but the Blade Spectre mitigation pass uses them to clamp the
+/// addresses of loads it protects with speculative load hardening.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Bounds {
+    /// Bounds which are only known at runtime.
+    Dynamic {
+        /// The lowest address that may be accessed, inclusive.
+        lower: Value,
+
+        /// The highest address that may be accessed, exclusive.
+        upper: Value,
+    },
+
+    /// Bounds of a region whose size is known at compile time.
+    Static {
+        /// The lowest address that may be accessed, inclusive.
+        base: Value,
+
+        /// The number of bytes above `base` that may be accessed.
+        size: u64,
+    },
+}
+
+impl Bounds {
+    /// Get the values these bounds refer to.
+    pub fn values(&self) -> impl Iterator<Item = Value> {
+        let (first, second) = match *self {
+            Self::Dynamic { lower, upper } => (lower, Some(upper)),
+            Self::Static { base, .. } => (base, None),
+        };
+        core::iter::once(first).chain(second)
+    }
+
+    /// Get the same bounds, with each of their values replaced by `f` of it.
+    pub fn map_values(self, mut f: impl FnMut(Value) -> Value) -> Self {
+        match self {
+            Self::Dynamic { lower, upper } => Self::Dynamic {
+                lower: f(lower),
+                upper: f(upper),
+            },
+            Self::Static { base, size } => Self::Static {
+                base: f(base),
+                size,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Bounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Dynamic { lower, upper } => write!(f, "bounds({}, {})", lower, upper),
+            Self::Static { base, size } => write!(f, "bounds({}, {})", base, size),
+        }
+    }
+}
@@ -3,12 +3,14 @@
 //! The `Function` struct defined in this module owns all of its basic blocks and
 //! instructions.
 
-use crate::binemit::CodeOffset;
+use crate::binemit::{lfence_size, CodeOffset};
+use crate::blade::BladeStats;
 use crate::entity::{PrimaryMap, SecondaryMap};
 use crate::ir;
 use crate::ir::{
-    Block, ExtFuncData, FuncRef, GlobalValue, GlobalValueData, Heap, HeapData, Inst, JumpTable,
-    JumpTableData, Opcode, SigRef, StackSlot, StackSlotData, Table, TableData,
+    BladeClass, BladeTaint, Block, ExtFuncData, FuncRef, GlobalValue, GlobalValueData, Heap,
+    HeapData, Inst, JumpTable, JumpTableData, Opcode, SigRef, StackSlot, StackSlotData, Table,
+    TableData, Value,
 };
 use crate::ir::{BlockOffsets, InstEncodings, SourceLocs, StackSlots, ValueLocations};
 use crate::ir::{DataFlowGraph, ExternalName, Layout, Signature};
@@ -102,6 +104,53 @@ pub struct Function {
     /// ensure that a trap happens if the stack pointer goes below the
     /// threshold specified here.
     pub stack_limit: Option<ir::GlobalValue>,
+
+    /// Instructions that must be preceded by a speculation barrier (`lfence`).
+    ///
+    /// This is filled in by the Blade Spectre mitigation pass and honored during binary emission.
+    pub pre_lfence: SecondaryMap<Inst, bool>,
+
+    /// Instructions that must be followed by a speculation barrier (`lfence`).
+    ///
+    /// This is filled in by the Blade Spectre mitigation pass and honored during binary emission.
+    pub post_lfence: SecondaryMap<Inst, bool>,
+
+    /// Instructions that must be preceded by a conditional speculation barrier (AArch64 `csdb`).
+    ///
+    /// This prevents the results of the conditional selects before the instruction from being
+    /// speculated, which speculative load hardening relies on. Targets whose conditional selects
+    /// are never speculated ignore it.
+    pub pre_csdb: SecondaryMap<Inst, bool>,
+
+    /// Conditional selects that must be lowered without branches.
+    ///
+    /// This is filled in by the Blade Spectre mitigation pass for the selects computing the masks
+    /// of speculative load hardening: a branch there would be predicted, and let the hardened
+    /// load run with an unmasked address. The verifier and code emission check that these are
+    /// honored.
+    pub branchless: SecondaryMap<Inst, bool>,
+
+    /// How the Blade Spectre mitigation pass must treat each instruction.
+    ///
+    /// Instructions default to `BladeClass::Default`, which lets Blade classify them from their
+    /// opcodes.
+    pub blade_class: SecondaryMap<Inst, BladeClass>,
+
+    /// Whether each value carries transient data, for the Blade Spectre mitigation pass.
+    ///
+    /// Values default to `BladeTaint::Default`, which lets Blade infer it from the instructions
+    /// defining them.
+    pub blade_taint: SecondaryMap<Value, BladeTaint>,
+
+    /// How many times each block executed in a profile of the function, for the Blade Spectre
+    /// mitigation pass.
+    ///
+    /// Blade scales the cost of every barrier by the count of the block it would be placed in,
+    /// so that it minimizes the number of barriers executed rather than the number of barriers
+    /// in the code. Blocks default to a count of zero, which Blade treats like a count of one,
+    /// so without a profile every barrier costs the same. Blocks created after the counts were
+    /// recorded, e.g. by legalization, count as executing once.
+    pub blade_block_counts: SecondaryMap<Block, u64>,
 }
 
 impl Function {
@@ -127,6 +176,13 @@ impl Function {
             prologue_end: None,
             epilogues_start: Vec::new(),
             stack_limit: None,
+            pre_lfence: SecondaryMap::new(),
+            post_lfence: SecondaryMap::new(),
+            pre_csdb: SecondaryMap::new(),
+            branchless: SecondaryMap::new(),
+            blade_class: SecondaryMap::new(),
+            blade_taint: SecondaryMap::new(),
+            blade_block_counts: SecondaryMap::new(),
         }
     }
 
@@ -149,6 +205,13 @@ impl Function {
         self.prologue_end = None;
         self.epilogues_start.clear();
         self.stack_limit = None;
+        self.pre_lfence.clear();
+        self.post_lfence.clear();
+        self.pre_csdb.clear();
+        self.branchless.clear();
+        self.blade_class.clear();
+        self.blade_taint.clear();
+        self.blade_block_counts.clear();
     }
 
     /// Create a new empty, anonymous function with a Fast calling convention.
@@ -342,6 +405,10 @@ pub struct DisplayFunctionAnnotations<'a> {
 
     /// Enable value labels annotations.
     pub value_ranges: Option<&'a ValueLabelsRanges>,
+
+    /// Enable comments describing what the Blade pass did to the function, from the statistics
+    /// of its run.
+    pub blade_stats: Option<&'a BladeStats>,
 }
 
 impl<'a> From<Option<&'a dyn TargetIsa>> for DisplayFunctionAnnotations<'a> {
@@ -349,6 +416,7 @@ impl<'a> From<Option<&'a dyn TargetIsa>> for DisplayFunctionAnnotations<'a> {
         DisplayFunctionAnnotations {
             isa,
             value_ranges: None,
+            blade_stats: None,
         }
     }
 }
@@ -392,7 +460,8 @@ impl<'a> Iterator for InstOffsetIter<'a> {
             self.divert.apply(&self.func.dfg[inst]);
             let byte_size =
                 self.encinfo
-                    .byte_size(self.encodings[inst], inst, &self.divert, self.func);
+                    .byte_size(self.encodings[inst], inst, &self.divert, self.func)
+                    + lfence_size(self.func, inst);
             let offset = self.offset;
             self.offset += byte_size;
             (offset, inst, byte_size)
@@ -1,5 +1,7 @@
 //! Representation of Cranelift IR functions.
 
+mod bladeclass;
+mod bounds;
 mod builder;
 pub mod constant;
 pub mod dfg;
@@ -26,6 +28,8 @@ mod valueloc;
 #[cfg(feature = "enable-serde")]
 use serde::{Deserialize, Serialize};
 
+pub use crate::ir::bladeclass::{BladeClass, BladeTaint};
+pub use crate::ir::bounds::Bounds;
 pub use crate::ir::builder::{
     InsertBuilder, InstBuilder, InstBuilderBase, InstInserterBase, ReplaceBuilder,
 };
@@ -1488,6 +1488,13 @@ impl MachInstEmit for Inst {
             &Inst::Brk => {
                 sink.put4(0xd4200000);
             }
+            &Inst::Csdb => {
+                sink.put4(0xd503229f);
+            }
+            &Inst::SpeculationBarrier => {
+                sink.put4(0xd5033f9f); // dsb sy
+                sink.put4(0xd5033fdf); // isb
+            }
             &Inst::Udf { trap_info } => {
                 let (srcloc, code) = trap_info;
                 sink.add_trap(srcloc, code);
@@ -2698,6 +2698,8 @@ fn test_aarch64_binemit() {
     ));
 
     insns.push((Inst::Brk, "000020D4", "brk #0"));
+    insns.push((Inst::Csdb, "9F2203D5", "csdb"));
+    insns.push((Inst::SpeculationBarrier, "9F3F03D5DF3F03D5", "dsb sy ; isb"));
 
     insns.push((
         Inst::Adr {
@@ -843,6 +843,14 @@ pub enum Inst {
     /// A "break" instruction, used for e.g. traps and debug breakpoints.
     Brk,
 
+    /// A conditional speculation dependency barrier, which prevents the results of preceding
+    /// conditional selects from being speculated.
+    Csdb,
+
+    /// A full speculation barrier, `dsb sy ; isb`, which no instruction may be speculatively
+    /// executed past.
+    SpeculationBarrier,
+
     /// An instruction guaranteed to always be undefined and to trigger an illegal instruction at
     /// runtime.
     Udf {
@@ -1357,6 +1365,7 @@ fn aarch64_get_regs(inst: &Inst, collector: &mut RegUsageCollector) {
         }
         &Inst::Nop0 | Inst::Nop4 => {}
         &Inst::Brk => {}
+        &Inst::Csdb | &Inst::SpeculationBarrier => {}
         &Inst::Udf { .. } => {}
         &Inst::Adr { rd, .. } => {
             collector.add_def(rd);
@@ -1955,7 +1964,12 @@ fn aarch64_map_regs<RUM: RegUsageMapper>(inst: &mut Inst, mapper: &RUM) {
         &mut Inst::IndirectBr { ref mut rn, .. } => {
             map_use(mapper, rn);
         }
-        &mut Inst::Nop0 | &mut Inst::Nop4 | &mut Inst::Brk | &mut Inst::Udf { .. } => {}
+        &mut Inst::Nop0
+        | &mut Inst::Nop4
+        | &mut Inst::Brk
+        | &mut Inst::Csdb
+        | &mut Inst::SpeculationBarrier
+        | &mut Inst::Udf { .. } => {}
         &mut Inst::Adr { ref mut rd, .. } => {
             map_def(mapper, rd);
         }
@@ -2905,6 +2919,8 @@ impl ShowWithRRU for Inst {
                 format!("br {}", rn)
             }
             &Inst::Brk => "brk #0".to_string(),
+            &Inst::Csdb => "csdb".to_string(),
+            &Inst::SpeculationBarrier => "dsb sy ; isb".to_string(),
             &Inst::Udf { .. } => "udf".to_string(),
             &Inst::Adr { rd, off } => {
                 let rd = rd.show_rru(mb_rru);
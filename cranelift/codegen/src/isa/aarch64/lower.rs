@@ -1054,4 +1054,12 @@ impl LowerBackend for AArch64Backend {
     fn maybe_pinned_reg(&self) -> Option<Reg> {
         Some(xreg(PINNED_REG))
     }
+
+    fn gen_csdb(&self) -> Option<Inst> {
+        Some(Inst::Csdb)
+    }
+
+    fn gen_speculation_barrier(&self) -> Option<Inst> {
+        Some(Inst::SpeculationBarrier)
+    }
 }
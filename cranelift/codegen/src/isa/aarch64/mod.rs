@@ -106,6 +106,10 @@ impl MachBackend for AArch64Backend {
         // opposite of x86).
         IntCC::UnsignedLessThan
     }
+
+    fn supports_blade(&self) -> bool {
+        true
+    }
 }
 
 /// Create a new `isa::Builder`.
@@ -54,6 +54,7 @@ pub use crate::isa::registers::{regs_overlap, RegClass, RegClassIndex, RegInfo,
 pub use crate::isa::stack::{StackBase, StackBaseMask, StackRef};
 
 use crate::binemit;
+use crate::blade::BladeCapabilities;
 use crate::flowgraph;
 use crate::ir;
 #[cfg(feature = "unwind")]
@@ -399,6 +400,27 @@ pub trait TargetIsa: fmt::Display + Send + Sync {
     /// IntCC condition for Unsigned Subtraction Overflow (Borrow/Carry).
     fn unsigned_sub_overflow_condition(&self) -> ir::condcodes::IntCC;
 
+    /// Can this ISA compile functions hardened by every Blade Spectre mitigation?
+    ///
+    /// This requires emitting the speculation barriers requested by `Function::pre_lfence` and
+    /// `Function::post_lfence`, and lowering the conditional selects used by speculative load
+    /// hardening without branches.
+    fn supports_blade(&self) -> bool {
+        false
+    }
+
+    /// Get the Blade mitigations this ISA can emit, and so the `blade` strategies it can compile.
+    ///
+    /// This is every mitigation if `supports_blade` holds, and none otherwise. ISAs which only
+    /// emit some of them override it.
+    fn blade_capabilities(&self) -> BladeCapabilities {
+        if self.supports_blade() {
+            BladeCapabilities::ALL
+        } else {
+            BladeCapabilities::default()
+        }
+    }
+
     /// Creates unwind information for the function.
     ///
     /// Returns `None` if there is no unwind information for the function.
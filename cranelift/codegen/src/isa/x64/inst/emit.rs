@@ -910,6 +910,12 @@ pub(crate) fn emit(inst: &Inst, sink: &mut MachBuffer<Inst>) {
             sink.put1(0x58 + (encDst & 7));
         }
 
+        Inst::Lfence => {
+            sink.put1(0x0F);
+            sink.put1(0xAE);
+            sink.put1(0xE8);
+        }
+
         Inst::CallUnknown { dest } => {
             match dest {
                 RegMem::Reg { reg } => {
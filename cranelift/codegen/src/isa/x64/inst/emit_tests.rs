@@ -2250,6 +2250,10 @@ fn test_x64_emit() {
     insns.push((Inst::pop64(w_r8), "4158", "popq    %r8"));
     insns.push((Inst::pop64(w_r15), "415F", "popq    %r15"));
 
+    // ========================================================
+    // Lfence
+    insns.push((Inst::lfence(), "0FAEE8", "lfence"));
+
     // ========================================================
     // CallKnown skipped for now
 
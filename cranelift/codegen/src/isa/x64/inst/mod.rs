@@ -113,6 +113,9 @@ pub(crate) enum Inst {
     /// popq reg
     Pop64 { dst: Writable<Reg> },
 
+    /// lfence, which no instruction is speculatively executed past.
+    Lfence,
+
     /// call simm32
     CallKnown {
         dest: ExternalName,
@@ -288,6 +291,10 @@ impl Inst {
         Inst::Pop64 { dst }
     }
 
+    pub(crate) fn lfence() -> Inst {
+        Inst::Lfence
+    }
+
     pub(crate) fn call_unknown(dest: RegMem) -> Inst {
         Inst::CallUnknown { dest }
     }
@@ -476,6 +483,7 @@ impl ShowWithRRU for Inst {
             Inst::Pop64 { dst } => {
                 format!("{} {}", ljustify("popq".to_string()), dst.show_rru(mb_rru))
             }
+            Inst::Lfence => "lfence".to_string(),
             //Inst::CallKnown { target } => format!("{} {:?}", ljustify("call".to_string()), target),
             Inst::CallKnown { .. } => "**CallKnown**".to_string(),
             Inst::CallUnknown { dest } => format!(
@@ -597,6 +605,7 @@ fn x64_get_regs(inst: &Inst, collector: &mut RegUsageCollector) {
         Inst::Pop64 { dst } => {
             collector.add_def(*dst);
         }
+        Inst::Lfence => {}
         Inst::CallKnown {
             dest: _,
             uses: _,
@@ -776,6 +785,7 @@ fn x64_map_regs<RUM: RegUsageMapper>(inst: &mut Inst, mapper: &RUM) {
         Inst::Pop64 { ref mut dst } => {
             map_def(mapper, dst);
         }
+        Inst::Lfence => {}
         Inst::CallKnown {
             dest: _,
             uses: _,
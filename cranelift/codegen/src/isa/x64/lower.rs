@@ -350,4 +350,8 @@ impl LowerBackend for X64Backend {
 
         Ok(())
     }
+
+    fn gen_speculation_barrier(&self) -> Option<Inst> {
+        Some(Inst::lfence())
+    }
 }
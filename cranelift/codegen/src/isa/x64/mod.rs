@@ -5,6 +5,7 @@ use alloc::boxed::Box;
 use regalloc::RealRegUniverse;
 use target_lexicon::Triple;
 
+use crate::blade::BladeCapabilities;
 use crate::ir::condcodes::IntCC;
 use crate::ir::Function;
 use crate::isa::Builder as IsaBuilder;
@@ -97,6 +98,15 @@ impl MachBackend for X64Backend {
         // underflow of a subtract (carry is borrow for subtract).
         IntCC::UnsignedGreaterThanOrEqual
     }
+
+    fn blade_capabilities(&self) -> BladeCapabilities {
+        // Speculation barriers are lowered to `lfence`, but conditional selects aren't lowered
+        // yet, let alone without branches, so speculative load hardening isn't supported.
+        BladeCapabilities {
+            barriers: true,
+            slh: false,
+        }
+    }
 }
 
 /// Create a new `isa::Builder`.
@@ -10,10 +10,54 @@ use crate::ir::{
 };
 use crate::isa::{RegUnit, StackBase, StackBaseMask, StackRef, TargetIsa};
 use crate::regalloc::RegDiversions;
+use crate::settings::BladeBarrier;
 use cranelift_codegen_shared::isa::x86::EncodingBits;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-x86.rs"));
 
+/// Emit `inst`, along with the speculation barriers requested by `Function::pre_lfence` and
+/// `Function::post_lfence`.
+///
+/// The verifier already rejects branchless selects encoded as branches, so this only checks it
+/// again in debug builds.
+pub fn emit_inst_with_lfences<CS: CodeSink + ?Sized>(
+    func: &Function,
+    inst: Inst,
+    divert: &mut RegDiversions,
+    sink: &mut CS,
+    isa: &dyn TargetIsa,
+) {
+    debug_assert!(
+        !func.branchless[inst]
+            || isa
+                .encoding_info()
+                .branch_range(func.encodings[inst])
+                .is_none(),
+        "branchless {} is encoded as a branch",
+        func.dfg.display_inst(inst, isa)
+    );
+    let barrier = isa.flags().blade_barrier();
+    if func.pre_lfence[inst] {
+        put_barrier(barrier, sink);
+    }
+    emit_inst(func, inst, divert, sink, isa);
+    if func.post_lfence[inst] {
+        put_barrier(barrier, sink);
+    }
+}
+
+/// Emit a speculation barrier instruction.
+fn put_barrier<CS: CodeSink + ?Sized>(barrier: BladeBarrier, sink: &mut CS) {
+    let bytes = match barrier {
+        BladeBarrier::Lfence => [0x0f, 0xae, 0xe8],
+        BladeBarrier::Mfence => [0x0f, 0xae, 0xf0],
+        BladeBarrier::Serialize => [0x0f, 0x01, 0xe8],
+    };
+    for &byte in &bytes {
+        sink.put1(byte);
+    }
+}
+
 // Convert a stack base to the corresponding register.
 fn stk_base(base: StackBase) -> RegUnit {
     let ru = match base {
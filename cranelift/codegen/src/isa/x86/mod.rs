@@ -153,11 +153,11 @@ impl TargetIsa for Isa {
         divert: &mut regalloc::RegDiversions,
         sink: &mut dyn CodeSink,
     ) {
-        binemit::emit_inst(func, inst, divert, sink, self)
+        binemit::emit_inst_with_lfences(func, inst, divert, sink, self)
     }
 
     fn emit_function_to_memory(&self, func: &ir::Function, sink: &mut MemoryCodeSink) {
-        emit_function(func, binemit::emit_inst, sink, self)
+        emit_function(func, binemit::emit_inst_with_lfences, sink, self)
     }
 
     fn prologue_epilogue(&self, func: &mut ir::Function) -> CodegenResult<()> {
@@ -173,6 +173,10 @@ impl TargetIsa for Isa {
         ir::condcodes::IntCC::UnsignedLessThan
     }
 
+    fn supports_blade(&self) -> bool {
+        true
+    }
+
     #[cfg(feature = "unwind")]
     fn create_unwind_info(
         &self,
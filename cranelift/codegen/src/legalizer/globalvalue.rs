@@ -120,6 +120,25 @@ fn load_addr(
         .dfg
         .replace(inst)
         .load(global_type, mflags, base_addr, offset);
+
+    // Runtime data reached from the VM context can't be steered by the guest, so let Blade
+    // know it may trust it.
+    if pos.func.blade_class[inst] == ir::BladeClass::Default && is_vmctx_derived(pos.func, base) {
+        pos.func.blade_class[inst] = ir::BladeClass::Trusted;
+    }
+}
+
+/// Is `gv` computed from the VM context through loads and offsets only?
+fn is_vmctx_derived(func: &ir::Function, mut gv: ir::GlobalValue) -> bool {
+    loop {
+        match func.global_values[gv] {
+            ir::GlobalValueData::VMContext => return true,
+            ir::GlobalValueData::Load { base, .. } | ir::GlobalValueData::IAddImm { base, .. } => {
+                gv = base
+            }
+            ir::GlobalValueData::Symbol { .. } => return false,
+        }
+    }
 }
 
 /// Expand a `global_value` instruction for a symbolic name global.
@@ -6,7 +6,7 @@
 use crate::cursor::{Cursor, FuncCursor};
 use crate::flowgraph::ControlFlowGraph;
 use crate::ir::condcodes::IntCC;
-use crate::ir::{self, InstBuilder};
+use crate::ir::{self, Bounds, InstBuilder};
 use crate::isa::TargetIsa;
 
 /// Expand a `heap_addr` instruction according to the definition of the heap.
@@ -94,7 +94,22 @@ fn dynamic_addr(
     }
     pos.ins().trapnz(oob, ir::TrapCode::HeapOutOfBounds);
 
-    compute_addr(isa, inst, heap, addr_ty, offset, offset_ty, pos.func);
+    let base = compute_addr(isa, inst, heap, addr_ty, offset, offset_ty, pos.func);
+
+    // Blade's speculative load hardening masks the accesses against the current heap size,
+    // reusing the bound loaded for the bounds check, unless the producer provided its own bounds.
+    let addr = func.dfg.first_result(inst);
+    if isa.flags().blade().uses_slh() && func.dfg.bounds[addr].is_none() {
+        let mut pos = FuncCursor::new(func).at_inst(inst);
+        pos.use_srcloc(inst);
+        let bound = if offset_ty != addr_ty {
+            pos.ins().uextend(addr_ty, bound)
+        } else {
+            bound
+        };
+        let upper = pos.ins().iadd(base, bound);
+        pos.func.dfg.bounds[addr] = Some(Bounds::Dynamic { lower: base, upper });
+    }
 }
 
 /// Expand a `heap_addr` for a static heap.
@@ -159,10 +174,18 @@ fn static_addr(
         pos.ins().trapnz(oob, ir::TrapCode::HeapOutOfBounds);
     }
 
-    compute_addr(isa, inst, heap, addr_ty, offset, offset_ty, pos.func);
+    let base = compute_addr(isa, inst, heap, addr_ty, offset, offset_ty, pos.func);
+
+    // Blade's speculative load hardening masks the accesses against the static heap bound.
+    let addr = func.dfg.first_result(inst);
+    if isa.flags().blade().uses_slh() && func.dfg.bounds[addr].is_none() {
+        func.dfg.bounds[addr] = Some(Bounds::Static { base, size: bound });
+    }
 }
 
 /// Emit code for the base address computation of a `heap_addr` instruction.
+///
+/// Returns the base address of the heap.
 fn compute_addr(
     isa: &dyn TargetIsa,
     inst: ir::Inst,
@@ -171,7 +194,7 @@ fn compute_addr(
     mut offset: ir::Value,
     offset_ty: ir::Type,
     func: &mut ir::Function,
-) {
+) -> ir::Value {
     let mut pos = FuncCursor::new(func).at_inst(inst);
     pos.use_srcloc(inst);
 
@@ -199,4 +222,236 @@ fn compute_addr(
     };
 
     pos.func.dfg.replace(inst).iadd(base, offset);
+    base
+}
+
+#[cfg(all(test, feature = "x86"))]
+mod tests {
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::flowgraph::ControlFlowGraph;
+    use crate::ir::immediates::Offset32;
+    use crate::ir::{
+        types, AbiParam, ArgumentPurpose, Bounds, Function, GlobalValue, GlobalValueData, HeapData,
+        HeapStyle, InstBuilder, InstructionData, MemFlags, Opcode, Value,
+    };
+    use crate::isa;
+    use crate::legalize_function;
+    use crate::settings::{self, Configurable};
+    use alloc::vec::Vec;
+    use core::str::FromStr;
+    use target_lexicon::Triple;
+
+    #[test]
+    fn slh_bounds_per_heap() {
+        // Two dynamic heaps, as for two linear memories, each with its own bound in the VM
+        // context.
+        let mut func = Function::new();
+        func.signature
+            .params
+            .push(AbiParam::special(types::I64, ArgumentPurpose::VMContext));
+        func.signature.params.push(AbiParam::new(types::I64));
+        let vmctx = func.create_global_value(GlobalValueData::VMContext);
+        let heaps: Vec<_> = (0..2)
+            .map(|i| {
+                let load = |offset| GlobalValueData::Load {
+                    base: vmctx,
+                    offset: Offset32::new(offset),
+                    global_type: types::I64,
+                    readonly: false,
+                };
+                let base = func.create_global_value(load(16 * i));
+                let bound_gv = func.create_global_value(load(16 * i + 8));
+                func.create_heap(HeapData {
+                    base,
+                    min_size: 0.into(),
+                    offset_guard_size: 0.into(),
+                    style: HeapStyle::Dynamic { bound_gv },
+                    index_type: types::I64,
+                })
+            })
+            .collect();
+
+        let block0 = func.dfg.make_block();
+        func.dfg.append_block_param(block0, types::I64);
+        let index = func.dfg.append_block_param(block0, types::I64);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let addrs: Vec<_> = heaps
+            .iter()
+            .map(|&heap| {
+                let addr = cur.ins().heap_addr(types::I64, heap, index, 8);
+                cur.ins().load(types::I64, MemFlags::new(), addr, 0);
+                addr
+            })
+            .collect();
+        cur.ins().return_(&[]);
+
+        let mut flags = settings::builder();
+        flags.set("blade", "slh").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+        let mut cfg = ControlFlowGraph::with_function(&func);
+        legalize_function(&mut func, &mut cfg, &*isa);
+
+        // The upper bound of each access is computed from the bound of its own heap.
+        for (i, &addr) in addrs.iter().enumerate() {
+            let upper = match func.dfg.bounds[addr] {
+                Some(Bounds::Dynamic { upper, .. }) => upper,
+                bounds => panic!("unexpected bounds {:?}", bounds),
+            };
+            let bound = func.dfg.inst_args(func.dfg.value_def(upper).unwrap_inst())[1];
+            match func.dfg[func.dfg.value_def(bound).unwrap_inst()] {
+                InstructionData::Load { offset, .. } => {
+                    assert_eq!(offset, Offset32::new(16 * i as i32 + 8))
+                }
+                ref data => panic!("unexpected bound {:?}", data),
+            }
+        }
+    }
+
+    /// Legalize a one-byte load from a heap of `style`, indexed by a 32-bit offset, with `blade`,
+    /// returning the function and the address of the access.
+    fn legalize_heap_load(
+        blade: &str,
+        style: impl FnOnce(GlobalValue) -> HeapStyle,
+    ) -> (Function, Value) {
+        let mut func = Function::new();
+        func.signature
+            .params
+            .push(AbiParam::special(types::I64, ArgumentPurpose::VMContext));
+        func.signature.params.push(AbiParam::new(types::I32));
+        let vmctx = func.create_global_value(GlobalValueData::VMContext);
+        let load = |offset, global_type| GlobalValueData::Load {
+            base: vmctx,
+            offset: Offset32::new(offset),
+            global_type,
+            readonly: false,
+        };
+        let base = func.create_global_value(load(0, types::I64));
+        let bound_gv = func.create_global_value(load(8, types::I32));
+        let heap = func.create_heap(HeapData {
+            base,
+            min_size: 0.into(),
+            offset_guard_size: 0.into(),
+            style: style(bound_gv),
+            index_type: types::I32,
+        });
+
+        let block0 = func.dfg.make_block();
+        func.dfg.append_block_param(block0, types::I64);
+        let index = func.dfg.append_block_param(block0, types::I32);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let addr = cur.ins().heap_addr(types::I64, heap, index, 1);
+        cur.ins().uload8(types::I64, MemFlags::new(), addr, 0);
+        cur.ins().return_(&[]);
+
+        let mut flags = settings::builder();
+        flags.set("blade", blade).unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+        let mut cfg = ControlFlowGraph::with_function(&func);
+        legalize_function(&mut func, &mut cfg, &*isa);
+        (func, addr)
+    }
+
+    #[test]
+    fn slh_dynamic_bounds() {
+        let (func, addr) = legalize_heap_load("slh", |bound_gv| HeapStyle::Dynamic { bound_gv });
+        let (lower, upper) = match func.dfg.bounds[addr] {
+            Some(Bounds::Dynamic { lower, upper }) => (lower, upper),
+            bounds => panic!("unexpected bounds {:?}", bounds),
+        };
+        let def = |value| func.dfg.value_def(value).unwrap_inst();
+
+        // The heap starts at its base, which the address is computed from...
+        assert_eq!(func.dfg.inst_args(def(addr))[0], lower);
+
+        // ...and ends after its current size, reusing the bound loaded for the bounds check.
+        assert_eq!(func.dfg.inst_args(def(upper))[0], lower);
+        let extended = func.dfg.inst_args(def(upper))[1];
+        assert_eq!(func.dfg[def(extended)].opcode(), Opcode::Uextend);
+        let bound = func.dfg.inst_args(def(extended))[0];
+        let checks_bound = func.layout.blocks().any(|block| {
+            func.layout.block_insts(block).any(|inst| {
+                func.dfg[inst].opcode() == Opcode::Icmp && func.dfg.inst_args(inst)[1] == bound
+            })
+        });
+        assert!(checks_bound);
+
+        let (func, addr) = legalize_heap_load("lfence", |bound_gv| HeapStyle::Dynamic { bound_gv });
+        assert_eq!(func.dfg.bounds[addr], None);
+    }
+
+    #[test]
+    fn slh_static_bounds() {
+        let static_heap = |_: GlobalValue| HeapStyle::Static {
+            bound: 0x1_0000.into(),
+        };
+        let (func, addr) = legalize_heap_load("slh", static_heap);
+
+        // The heap's size is known, so only its base needs to be live for the hardening.
+        let base = func.dfg.inst_args(func.dfg.value_def(addr).unwrap_inst())[0];
+        assert_eq!(
+            func.dfg.bounds[addr],
+            Some(Bounds::Static {
+                base,
+                size: 0x1_0000
+            })
+        );
+
+        let (func, addr) = legalize_heap_load("lfence", static_heap);
+        assert_eq!(func.dfg.bounds[addr], None);
+    }
+
+    #[test]
+    fn hot_access_keeps_count() {
+        // A heap access in a hot block, whose bounds check x86 turns into a branch over a trap,
+        // splitting the block.
+        let mut func = Function::new();
+        func.signature
+            .params
+            .push(AbiParam::special(types::I64, ArgumentPurpose::VMContext));
+        func.signature.params.push(AbiParam::new(types::I32));
+        let vmctx = func.create_global_value(GlobalValueData::VMContext);
+        let load = |offset, global_type| GlobalValueData::Load {
+            base: vmctx,
+            offset: Offset32::new(offset),
+            global_type,
+            readonly: false,
+        };
+        let base = func.create_global_value(load(0, types::I64));
+        let bound_gv = func.create_global_value(load(8, types::I32));
+        let heap = func.create_heap(HeapData {
+            base,
+            min_size: 0.into(),
+            offset_guard_size: 0.into(),
+            style: HeapStyle::Dynamic { bound_gv },
+            index_type: types::I32,
+        });
+
+        let block0 = func.dfg.make_block();
+        func.dfg.append_block_param(block0, types::I64);
+        let index = func.dfg.append_block_param(block0, types::I32);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let addr = cur.ins().heap_addr(types::I64, heap, index, 4);
+        let value = cur.ins().load(types::I32, MemFlags::new(), addr, 0);
+        let access = cur.func.dfg.value_def(value).unwrap_inst();
+        cur.ins().return_(&[]);
+        func.blade_block_counts[block0] = 1000;
+
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+        let mut cfg = ControlFlowGraph::with_function(&func);
+        legalize_function(&mut func, &mut cfg, &*isa);
+
+        // The access now lives in a block of its own, which is just as hot.
+        let block = func.layout.inst_block(access).unwrap();
+        assert_ne!(block, block0);
+        assert_eq!(func.blade_block_counts[block], 1000);
+    }
 }
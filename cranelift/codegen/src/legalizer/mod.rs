@@ -15,6 +15,7 @@
 
 use crate::bitset::BitSet;
 use crate::cursor::{Cursor, FuncCursor};
+use crate::entity::EntityRef;
 use crate::flowgraph::ControlFlowGraph;
 use crate::ir::types::{I32, I64};
 use crate::ir::{self, InstBuilder, MemFlags};
@@ -140,6 +141,7 @@ fn legalize_inst(
 pub fn legalize_function(func: &mut ir::Function, cfg: &mut ControlFlowGraph, isa: &dyn TargetIsa) {
     let _tt = timing::legalize();
     debug_assert!(cfg.is_valid());
+    let num_blocks = func.dfg.num_blocks();
 
     boundary::legalize_signatures(func, isa);
 
@@ -194,11 +196,31 @@ pub fn legalize_function(func: &mut ir::Function, cfg: &mut ControlFlowGraph, is
     if !isa.flags().enable_jump_tables() {
         pos.func.jump_tables.clear();
     }
+
+    inherit_block_counts(pos.func, num_blocks);
+}
+
+/// Give the blocks which legalization split off the first `num_blocks` blocks of `func` the
+/// execution count of the block they were split off, so that Blade doesn't mistake the code of
+/// a hot block for cold code once it is split, e.g. after a bounds check.
+///
+/// Legalization inserts the blocks it creates right after the block it splits, so that is the
+/// last of the first `num_blocks` blocks before them in the layout.
+fn inherit_block_counts(func: &mut ir::Function, num_blocks: usize) {
+    let mut count = 0;
+    for block in func.layout.blocks() {
+        if block.index() < num_blocks {
+            count = func.blade_block_counts[block];
+        } else {
+            func.blade_block_counts[block] = count;
+        }
+    }
 }
 
 /// Perform a simple legalization by expansion of the function, without
 /// platform-specific transforms.
 pub fn simple_legalize(func: &mut ir::Function, cfg: &mut ControlFlowGraph, isa: &dyn TargetIsa) {
+    let num_blocks = func.dfg.num_blocks();
     let mut pos = FuncCursor::new(func);
     let func_begin = pos.position();
     pos.set_position(func_begin);
@@ -244,6 +266,8 @@ pub fn simple_legalize(func: &mut ir::Function, cfg: &mut ControlFlowGraph, isa:
             }
         }
     }
+
+    inherit_block_counts(pos.func, num_blocks);
 }
 
 // Include legalization patterns that were generated by `gen_legalizer.rs` from the
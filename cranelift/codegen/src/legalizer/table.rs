@@ -7,7 +7,7 @@ use crate::cursor::{Cursor, FuncCursor};
 use crate::flowgraph::ControlFlowGraph;
 use crate::ir::condcodes::IntCC;
 use crate::ir::immediates::Offset32;
-use crate::ir::{self, InstBuilder};
+use crate::ir::{self, Bounds, InstBuilder};
 use crate::isa::TargetIsa;
 
 /// Expand a `table_addr` instruction according to the definition of the table.
@@ -15,7 +15,7 @@ pub fn expand_table_addr(
     inst: ir::Inst,
     func: &mut ir::Function,
     _cfg: &mut ControlFlowGraph,
-    _isa: &dyn TargetIsa,
+    isa: &dyn TargetIsa,
 ) {
     // Unpack the instruction.
     let (table, index, element_offset) = match func.dfg[inst] {
@@ -31,7 +31,9 @@ pub fn expand_table_addr(
         _ => panic!("Wanted table_addr: {}", func.dfg.display_inst(inst, None)),
     };
 
-    dynamic_addr(inst, table, index, element_offset, func);
+    // Blade's speculative load hardening masks the element addresses against the table bounds.
+    let with_bounds = isa.flags().blade().uses_slh();
+    dynamic_addr(inst, table, index, element_offset, with_bounds, func);
 }
 
 /// Expand a `table_addr` for a dynamic table.
@@ -40,6 +42,7 @@ fn dynamic_addr(
     table: ir::Table,
     index: ir::Value,
     element_offset: Offset32,
+    with_bounds: bool,
     func: &mut ir::Function,
 ) {
     let bound_gv = func.tables[table].bound_gv;
@@ -57,7 +60,7 @@ fn dynamic_addr(
         .icmp(IntCC::UnsignedGreaterThanOrEqual, index, bound);
     pos.ins().trapnz(oob, ir::TrapCode::TableOutOfBounds);
 
-    compute_addr(
+    let base = compute_addr(
         inst,
         table,
         addr_ty,
@@ -66,9 +69,26 @@ fn dynamic_addr(
         element_offset,
         pos.func,
     );
+
+    if with_bounds {
+        // Every element lies between the base address and the end of the `bound`th element.
+        let mut pos = FuncCursor::new(func).at_inst(inst);
+        pos.use_srcloc(inst);
+        let bound = if index_ty != addr_ty {
+            pos.ins().uextend(addr_ty, bound)
+        } else {
+            bound
+        };
+        let size = scale_index(&mut pos, table, bound);
+        let upper = pos.ins().iadd(base, size);
+        let addr = pos.func.dfg.first_result(inst);
+        pos.func.dfg.bounds[addr] = Some(Bounds::Dynamic { lower: base, upper });
+    }
 }
 
 /// Emit code for the base address computation of a `table_addr` instruction.
+///
+/// Returns the base address of the table.
 fn compute_addr(
     inst: ir::Inst,
     table: ir::Table,
@@ -77,7 +97,7 @@ fn compute_addr(
     index_ty: ir::Type,
     element_offset: Offset32,
     func: &mut ir::Function,
-) {
+) -> ir::Value {
     let mut pos = FuncCursor::new(func).at_inst(inst);
     pos.use_srcloc(inst);
 
@@ -90,18 +110,7 @@ fn compute_addr(
     let base_gv = pos.func.tables[table].base_gv;
     let base = pos.ins().global_value(addr_ty, base_gv);
 
-    let element_size = pos.func.tables[table].element_size;
-    let mut offset;
-    let element_size: u64 = element_size.into();
-    if element_size == 1 {
-        offset = index;
-    } else if element_size.is_power_of_two() {
-        offset = pos
-            .ins()
-            .ishl_imm(index, i64::from(element_size.trailing_zeros()));
-    } else {
-        offset = pos.ins().imul_imm(index, element_size as i64);
-    }
+    let mut offset = scale_index(&mut pos, table, index);
 
     if element_offset == Offset32::new(0) {
         pos.func.dfg.replace(inst).iadd(base, offset);
@@ -110,4 +119,119 @@ fn compute_addr(
         offset = pos.ins().iadd(base, offset);
         pos.func.dfg.replace(inst).iadd_imm(offset, imm);
     }
+
+    base
+}
+
+/// Emit code multiplying `index` by the element size of `table`.
+fn scale_index(pos: &mut FuncCursor, table: ir::Table, index: ir::Value) -> ir::Value {
+    let element_size: u64 = pos.func.tables[table].element_size.into();
+    if element_size == 1 {
+        index
+    } else if element_size.is_power_of_two() {
+        pos.ins()
+            .ishl_imm(index, i64::from(element_size.trailing_zeros()))
+    } else {
+        pos.ins().imul_imm(index, element_size as i64)
+    }
+}
+
+#[cfg(all(test, feature = "x86"))]
+mod tests {
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::flowgraph::ControlFlowGraph;
+    use crate::ir::immediates::Offset32;
+    use crate::ir::{
+        types, AbiParam, ArgumentPurpose, Bounds, Function, GlobalValueData, InstBuilder,
+        InstructionData, MemFlags, Opcode, TableData, Value,
+    };
+    use crate::isa;
+    use crate::legalize_function;
+    use crate::settings::{self, Configurable};
+    use core::str::FromStr;
+    use target_lexicon::Triple;
+
+    /// Legalize a load from an element of a table of 8-byte elements with `blade`, returning the
+    /// function and the address of the element.
+    fn legalize_element_load(blade: &str) -> (Function, Value) {
+        let mut func = Function::new();
+        func.signature
+            .params
+            .push(AbiParam::special(types::I64, ArgumentPurpose::VMContext));
+        func.signature.params.push(AbiParam::new(types::I32));
+        let vmctx = func.create_global_value(GlobalValueData::VMContext);
+        let load = |offset, global_type| GlobalValueData::Load {
+            base: vmctx,
+            offset: Offset32::new(offset),
+            global_type,
+            readonly: false,
+        };
+        let base_gv = func.create_global_value(load(0, types::I64));
+        let bound_gv = func.create_global_value(load(8, types::I32));
+        let table = func.create_table(TableData {
+            base_gv,
+            min_size: 0.into(),
+            bound_gv,
+            element_size: 8.into(),
+            index_type: types::I32,
+        });
+
+        let block0 = func.dfg.make_block();
+        func.dfg.append_block_param(block0, types::I64);
+        let index = func.dfg.append_block_param(block0, types::I32);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_block(block0);
+        let addr = cur.ins().table_addr(types::I64, table, index, 0);
+        cur.ins().load(types::I64, MemFlags::new(), addr, 0);
+        cur.ins().return_(&[]);
+
+        let mut flags = settings::builder();
+        flags.set("blade", blade).unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+        let mut cfg = ControlFlowGraph::with_function(&func);
+        legalize_function(&mut func, &mut cfg, &*isa);
+        (func, addr)
+    }
+
+    #[test]
+    fn slh_element_bounds() {
+        let (func, addr) = legalize_element_load("slh");
+        let (lower, upper) = match func.dfg.bounds[addr] {
+            Some(Bounds::Dynamic { lower, upper }) => (lower, upper),
+            bounds => panic!("unexpected bounds {:?}", bounds),
+        };
+        let def = |value| func.dfg.value_def(value).unwrap_inst();
+
+        // The elements start at the base of the table, which the address is computed from...
+        assert_eq!(func.dfg.inst_args(def(addr))[0], lower);
+
+        // ...and end after `bound` elements of 8 bytes, with the bound of the bounds check.
+        assert_eq!(func.dfg.inst_args(def(upper))[0], lower);
+        let size = func.dfg.inst_args(def(upper))[1];
+        let bound = match func.dfg[def(size)] {
+            InstructionData::BinaryImm64 {
+                opcode: Opcode::IshlImm,
+                arg,
+                imm,
+            } => {
+                assert_eq!(imm.bits(), 3);
+                func.dfg.inst_args(def(arg))[0]
+            }
+            ref data => panic!("unexpected size {:?}", data),
+        };
+        let checks_bound = func.layout.blocks().any(|block| {
+            func.layout.block_insts(block).any(|inst| {
+                func.dfg[inst].opcode() == Opcode::Icmp && func.dfg.inst_args(inst)[1] == bound
+            })
+        });
+        assert!(checks_bound);
+    }
+
+    #[test]
+    fn no_element_bounds_without_slh() {
+        let (func, addr) = legalize_element_load("lfence");
+        assert_eq!(func.dfg.bounds[addr], None);
+    }
 }
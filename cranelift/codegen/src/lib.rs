@@ -59,6 +59,15 @@ use hashbrown::{hash_map, HashMap, HashSet};
 #[cfg(feature = "std")]
 use std::collections::{hash_map, HashMap, HashSet};
 
+#[cfg(feature = "testing_hooks")]
+pub use crate::blade::phases as blade_phases;
+pub use crate::blade::{
+    check_blade_settings, BladeCapabilities, BladeContract, BladeCostEstimate, BladeCut,
+    BladeCutCache, BladeDiagnostic, BladeDiagnosticKind, BladeMitigation, BladeNode, BladePolicy,
+    BladeSite, BladeStats, BladeStrategy, DefaultBladePolicy, EdgeWeights, FencePoint,
+    FenceStrategy, InstClassification, MitigationKind, SinkClasses, SinkOptions, SlhStrategy,
+    SourceOptions,
+};
 pub use crate::context::Context;
 pub use crate::legalizer::legalize_function;
 pub use crate::value_label::{ValueLabelsRanges, ValueLocRange};
@@ -69,6 +78,7 @@ pub use cranelift_bforest as bforest;
 pub use cranelift_entity as entity;
 
 pub mod binemit;
+pub mod blade;
 pub mod cfg_printer;
 pub mod cursor;
 pub mod dbg;
@@ -104,6 +114,7 @@ mod predicates;
 mod redundant_reload_remover;
 mod regalloc;
 mod remove_constant_phis;
+mod repair_bounds;
 mod result;
 mod scoped_hash_map;
 mod simple_gvn;
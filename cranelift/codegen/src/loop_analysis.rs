@@ -71,6 +71,17 @@ impl LoopAnalysis {
         self.loops[lp].parent.expand()
     }
 
+    /// Returns the number of loops containing `block`, zero if it isn't in a loop.
+    pub fn loop_depth(&self, block: Block) -> usize {
+        let mut depth = 0;
+        let mut finger = self.block_loop_map[block].expand();
+        while let Some(lp) = finger {
+            depth += 1;
+            finger = self.loop_parent(lp);
+        }
+        depth
+    }
+
     /// Determine if a Block belongs to a loop by running a finger along the loop tree.
     ///
     /// Returns `true` if `block` is in loop `lp`.
@@ -286,6 +297,10 @@ mod tests {
         assert_eq!(loop_analysis.is_in_loop(block2, loops[0]), true);
         assert_eq!(loop_analysis.is_in_loop(block3, loops[0]), true);
         assert_eq!(loop_analysis.is_in_loop(block0, loops[1]), false);
+        assert_eq!(loop_analysis.loop_depth(block0), 1);
+        assert_eq!(loop_analysis.loop_depth(block1), 2);
+        assert_eq!(loop_analysis.loop_depth(block2), 2);
+        assert_eq!(loop_analysis.loop_depth(block3), 1);
     }
 
     #[test]
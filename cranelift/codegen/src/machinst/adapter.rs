@@ -1,6 +1,7 @@
 //! Adapter for a `MachBackend` to implement the `TargetIsa` trait.
 
 use crate::binemit;
+use crate::blade::BladeCapabilities;
 use crate::ir;
 use crate::isa::{EncInfo, Encoding, Encodings, Legalize, RegClass, RegInfo, TargetIsa};
 use crate::machinst::*;
@@ -121,6 +122,14 @@ impl TargetIsa for TargetIsaAdapter {
         Some(&*self.backend)
     }
 
+    fn supports_blade(&self) -> bool {
+        self.backend.supports_blade()
+    }
+
+    fn blade_capabilities(&self) -> BladeCapabilities {
+        self.backend.blade_capabilities()
+    }
+
     fn unsigned_add_overflow_condition(&self) -> ir::condcodes::IntCC {
         self.backend.unsigned_add_overflow_condition()
     }
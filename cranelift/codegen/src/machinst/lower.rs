@@ -12,10 +12,10 @@ use crate::ir::{
     Inst, InstructionData, MemFlags, Opcode, Signature, SourceLoc, Type, Value, ValueDef,
 };
 use crate::machinst::{
-    ABIBody, BlockIndex, BlockLoweringOrder, LoweredBlock, MachLabel, VCode, VCodeBuilder,
-    VCodeInst,
+    ABIBody, BlockIndex, BlockLoweringOrder, LoweredBlock, MachLabel, MachTerminator, VCode,
+    VCodeBuilder, VCodeInst,
 };
-use crate::CodegenResult;
+use crate::{CodegenError, CodegenResult};
 
 use regalloc::{Reg, RegClass, VirtualReg, Writable};
 
@@ -204,6 +204,24 @@ pub trait LowerBackend {
     fn maybe_pinned_reg(&self) -> Option<Reg> {
         None
     }
+
+    /// Generate a conditional speculation barrier, if the target needs one to prevent the results
+    /// of conditional selects from being speculated. See `Function::pre_csdb`.
+    fn gen_csdb(&self) -> Option<Self::MInst> {
+        None
+    }
+
+    /// Generate a barrier which stops all speculative execution, if the target has one. See
+    /// `Function::pre_lfence` and `Function::post_lfence`.
+    fn gen_speculation_barrier(&self) -> Option<Self::MInst> {
+        None
+    }
+}
+
+/// Does Blade request a speculation barrier before or after `inst`? See `Function::pre_lfence`
+/// and `Function::post_lfence`.
+fn is_fenced(f: &Function, inst: Inst) -> bool {
+    f.pre_lfence[inst] || f.post_lfence[inst]
 }
 
 /// Machine-independent lowering driver / machine-instruction container. Maintains a correspondence
@@ -332,7 +350,9 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
         }
 
         // Compute instruction colors, find constant instructions, and find instructions with
-        // side-effects, in one combined pass.
+        // side-effects, in one combined pass. Speculation barriers requested by Blade also start
+        // new colors, so that no load is merged across them, and their instructions are always
+        // lowered where they are.
         let mut cur_color = 0;
         let mut inst_colors = SecondaryMap::with_default(InstColor::new(0));
         let mut inst_constants = FxHashMap::default();
@@ -342,12 +362,23 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
             for inst in f.layout.block_insts(bb) {
                 let side_effect = has_side_effect_or_load(f, inst);
 
-                // Assign colors. A new color is chosen *after* any side-effecting instruction.
+                // Assign colors. A new color is chosen *after* any side-effecting instruction,
+                // and around speculation barriers.
+                if f.pre_lfence[inst] {
+                    cur_color += 1;
+                }
                 inst_colors[inst] = InstColor::new(cur_color);
                 debug!("bb {} inst {} has color {}", bb, inst, cur_color);
                 if side_effect {
                     debug!(" -> side-effecting");
+                }
+                if is_fenced(f, inst) {
+                    debug!(" -> fenced");
+                }
+                if side_effect || is_fenced(f, inst) {
                     inst_needed[inst] = true;
+                }
+                if side_effect || f.post_lfence[inst] {
                     cur_color += 1;
                 }
 
@@ -557,9 +588,34 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
             }
             // Normal instruction: codegen if eager bit is set. (Other instructions may also be
             // codegened if not eager when they are used by another instruction.)
+            //
+            // Instructions with speculation barriers requested by Blade are always eager and
+            // never merged into their users, so the barriers are right around their code.
+            if self.f.pre_lfence[inst] {
+                self.emit_speculation_barrier(backend)?;
+            }
             if self.inst_needed[inst] || value_needed {
                 debug!("lowering: inst {}: {:?}", inst, self.f.dfg[inst]);
+                if self.f.pre_csdb[inst] {
+                    if let Some(csdb) = backend.gen_csdb() {
+                        self.emit(csdb);
+                    }
+                }
+                let first_lowered = self.ir_insts.len();
                 backend.lower(self, inst)?;
+                if self.f.branchless[inst]
+                    && self.ir_insts[first_lowered..]
+                        .iter()
+                        .any(|i| i.is_term() != MachTerminator::None)
+                {
+                    return Err(CodegenError::Unsupported(format!(
+                        "{} must be lowered without branches",
+                        self.f.dfg.display_inst(inst, None)
+                    )));
+                }
+            }
+            if self.f.post_lfence[inst] {
+                self.emit_speculation_barrier(backend)?;
             }
             if data.opcode().is_return() {
                 // Return: handle specially, using ABI-appropriate sequence.
@@ -578,6 +634,40 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
         Ok(())
     }
 
+    /// Emit a speculation barrier, as requested by `Function::pre_lfence` and
+    /// `Function::post_lfence`.
+    fn emit_speculation_barrier<B: LowerBackend<MInst = I>>(
+        &mut self,
+        backend: &B,
+    ) -> CodegenResult<()> {
+        match backend.gen_speculation_barrier() {
+            Some(barrier) => {
+                self.emit(barrier);
+                Ok(())
+            }
+            None => Err(CodegenError::Unsupported(
+                "speculation barriers are not supported by this backend".into(),
+            )),
+        }
+    }
+
+    /// Does the CFG edge taken by the branch `inst` need a speculation barrier?
+    ///
+    /// A branch group is lowered as a unit, so there is no place for a barrier between two of its
+    /// branches. Any barrier requested there is emitted on the edges of the later branches instead.
+    fn edge_needs_speculation_barrier(&self, inst: Inst) -> bool {
+        let mut needed = false;
+        let mut branch = inst;
+        while let Some(prev) = self.f.layout.prev_inst(branch) {
+            if !self.f.dfg[prev].opcode().is_branch() {
+                break;
+            }
+            needed |= self.f.pre_lfence[branch] || self.f.post_lfence[prev];
+            branch = prev;
+        }
+        needed
+    }
+
     fn finish_ir_inst(&mut self, loc: SourceLoc) {
         for inst in self.ir_insts.drain(..).rev() {
             self.bb_insts.push((loc, inst));
@@ -615,6 +705,9 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
             "lower_clif_branches: block {} branches {:?} targets {:?} maybe_fallthrough {:?}",
             block, branches, targets, maybe_fallthrough
         );
+        if self.f.pre_lfence[branches[0]] {
+            self.emit_speculation_barrier(backend)?;
+        }
         backend.lower_branch_group(self, branches, targets, maybe_fallthrough)?;
         let loc = self.srcloc(branches[0]);
         self.finish_ir_inst(loc);
@@ -707,6 +800,11 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
             if let Some((pred, inst, succ)) = lb.out_edge() {
                 self.lower_edge(pred, inst, succ)?;
                 self.finish_ir_inst(SourceLoc::default());
+                if self.edge_needs_speculation_barrier(inst) {
+                    // The barrier belongs to the branch taking this edge.
+                    self.emit_speculation_barrier(backend)?;
+                    self.finish_ir_inst(self.srcloc(inst));
+                }
             }
             // Original block body.
             if let Some(bb) = lb.orig_block() {
@@ -716,6 +814,11 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
             if let Some((pred, inst, succ)) = lb.in_edge() {
                 self.lower_edge(pred, inst, succ)?;
                 self.finish_ir_inst(SourceLoc::default());
+                if self.edge_needs_speculation_barrier(inst) {
+                    // The barrier belongs to the branch taking this edge.
+                    self.emit_speculation_barrier(backend)?;
+                    self.finish_ir_inst(self.srcloc(inst));
+                }
             }
 
             if bindex == 0 {
@@ -746,8 +849,9 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
         assert!(reg.is_valid());
         let mut inst = match self.f.dfg.value_def(val) {
             // OK to merge source instruction if (i) we have a source
-            // instruction, and either (ii-a) it has no side effects, or (ii-b)
-            // it has the same color as this instruction.
+            // instruction, (ii) it has no speculation barrier, and either
+            // (iii-a) it has no side effects, or (iii-b) it has the same color
+            // as this instruction.
             ValueDef::Result(src_inst, result_idx) => {
                 debug!(" -> src inst {}", src_inst);
                 debug!(
@@ -759,7 +863,9 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
                     self.inst_color(at_inst),
                     self.inst_color(src_inst)
                 );
-                if !has_side_effect_or_load(self.f, src_inst)
+                if is_fenced(self.f, src_inst) {
+                    None
+                } else if !has_side_effect_or_load(self.f, src_inst)
                     || self.inst_color(at_inst) == self.inst_color(src_inst)
                 {
                     Some((src_inst, result_idx))
@@ -97,6 +97,7 @@
 //! ```
 
 use crate::binemit::{CodeInfo, CodeOffset};
+use crate::blade::BladeCapabilities;
 use crate::ir::condcodes::IntCC;
 use crate::ir::{Function, Type};
 use crate::result::CodegenResult;
@@ -314,4 +315,18 @@ pub trait MachBackend {
     /// Machine-specific condcode info needed by TargetIsa.
     /// Condition that will be true when an IsubIfcout overflows.
     fn unsigned_sub_overflow_condition(&self) -> IntCC;
+
+    /// Can this backend compile functions hardened by Blade? See `TargetIsa::supports_blade`.
+    fn supports_blade(&self) -> bool {
+        false
+    }
+
+    /// Get the Blade mitigations this backend can emit. See `TargetIsa::blade_capabilities`.
+    fn blade_capabilities(&self) -> BladeCapabilities {
+        if self.supports_blade() {
+            BladeCapabilities::ALL
+        } else {
+            BladeCapabilities::default()
+        }
+    }
 }
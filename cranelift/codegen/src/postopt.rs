@@ -211,6 +211,11 @@ fn optimize_complex_addresses(pos: &mut EncCursor, inst: Inst, isa: &dyn TargetI
         _ => return,
     };
 
+    // Keep addresses with bounds intact, so that Blade can still harden the access against them.
+    if pos.func.dfg.bounds[info.arg].is_some() {
+        return;
+    }
+
     // Examine the instruction that defines the address operand.
     if let ValueDef::Result(result_inst, _) = pos.func.dfg.value_def(info.arg) {
         match pos.func.dfg[result_inst] {
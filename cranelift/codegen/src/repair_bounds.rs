@@ -0,0 +1,233 @@
+//! Repair the address bounds stranded by optimizations.
+//!
+//! Bounds are attached to values through `DataFlowGraph::bounds`, but several passes replace
+//! values with aliases of equivalent ones: GVN merges redundant address computations, and the
+//! pre-legalization and legalization rewrites forward results to simpler values. The accesses
+//! then go through the value the alias resolves to, and bounds left on the alias are never found
+//! again, so speculative load hardening can't clamp those accesses anymore. Dead code elimination
+//! only keeps the bound values of the pointers it sees live, so stranded bounds may also end up
+//! referring to removed instructions.
+//!
+//! This pass moves the bounds of every alias to the value it resolves to, when that value has no
+//! bounds of its own and the bound values are defined before it, so they still dominate every
+//! access through it. It also resolves the aliases among the bound values themselves, and drops
+//! bounds referring to values that are no longer defined in the function.
+
+use crate::dominator_tree::DominatorTree;
+use crate::ir::{Bounds, ExpandedProgramPoint, Function, Value, ValueDef};
+use crate::timing;
+use alloc::vec::Vec;
+use log::debug;
+
+/// Repair the bounds of the values of `func`.
+pub fn do_repair_bounds(func: &mut Function, domtree: &DominatorTree) {
+    let _tt = timing::repair_bounds();
+    debug_assert!(domtree.is_valid());
+
+    let bounded: Vec<(Value, Bounds)> = func
+        .dfg
+        .bounds
+        .iter()
+        .filter_map(|(v, &bounds)| bounds.map(|bounds| (v, bounds)))
+        .collect();
+    if bounded.is_empty() {
+        return;
+    }
+
+    let mut moved = 0;
+    let mut dropped = 0;
+    // Values with bounds of their own come first, so they win over the bounds of their aliases.
+    let (aliases, originals): (Vec<_>, Vec<_>) = bounded
+        .into_iter()
+        .partition(|&(v, _)| func.dfg.resolve_aliases(v) != v);
+    for (v, bounds) in originals {
+        let bounds = bounds.map_values(|bound| func.dfg.resolve_aliases(bound));
+        if is_defined(func, v) && bounds.values().all(|bound| is_defined(func, bound)) {
+            func.dfg.bounds[v] = Some(bounds);
+        } else {
+            func.dfg.bounds[v] = None;
+            dropped += 1;
+        }
+    }
+    for (alias, bounds) in aliases {
+        func.dfg.bounds[alias] = None;
+        let original = func.dfg.resolve_aliases(alias);
+        let bounds = bounds.map_values(|bound| func.dfg.resolve_aliases(bound));
+        if func.dfg.bounds[original].is_none()
+            && is_defined(func, original)
+            && bounds.values().all(|bound| {
+                bound != original
+                    && is_defined(func, bound)
+                    && domtree.dominates(
+                        ExpandedProgramPoint::from(func.dfg.value_def(bound)),
+                        ExpandedProgramPoint::from(func.dfg.value_def(original)),
+                        &func.layout,
+                    )
+            })
+        {
+            func.dfg.bounds[original] = Some(bounds);
+            moved += 1;
+        } else {
+            dropped += 1;
+        }
+    }
+    debug!(
+        "repair_bounds: {}: moved the bounds of {} aliases, dropped {} bounds",
+        func.name, moved, dropped
+    );
+}
+
+/// Is `v` still defined by an instruction or block of the layout of `func`?
+fn is_defined(func: &Function, v: Value) -> bool {
+    if !func.dfg.value_is_valid(v) || !func.dfg.value_is_attached(v) {
+        return false;
+    }
+    match func.dfg.value_def(v) {
+        ValueDef::Result(inst, _) => func.layout.inst_block(inst).is_some(),
+        ValueDef::Param(block, _) => func.layout.is_block_inserted(block),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::flowgraph::ControlFlowGraph;
+    use crate::ir::{types, AbiParam, ExternalName, InstBuilder, MemFlags, Signature};
+    use crate::isa::CallConv;
+    use crate::settings;
+    use crate::simple_gvn::do_simple_gvn;
+    use crate::Context;
+
+    #[test]
+    fn gvn_keeps_bounds() {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(types::I64));
+        sig.params.push(AbiParam::new(types::I64));
+        sig.returns.push(AbiParam::new(types::I32));
+        let mut func = Function::with_name_signature(ExternalName::testcase("bounds"), sig);
+        let block0 = func.dfg.make_block();
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_block(block0);
+        let base = pos.func.dfg.append_block_param(block0, types::I64);
+        let index = pos.func.dfg.append_block_param(block0, types::I64);
+        // Only the second, redundant address is bounded, as if the first one were computed for
+        // an unrelated purpose.
+        let first = pos.ins().iadd(base, index);
+        let second = pos.ins().iadd(base, index);
+        let upper = pos.ins().iadd_imm(base, 0x1000);
+        let loaded = pos.ins().load(types::I32, MemFlags::new(), second, 0);
+        pos.ins().store(MemFlags::new(), loaded, first, 0);
+        pos.ins().return_(&[loaded]);
+        pos.func.dfg.bounds[second] = Some(Bounds::Dynamic { lower: base, upper });
+
+        let cfg = ControlFlowGraph::with_function(&func);
+        let mut domtree = DominatorTree::with_function(&func, &cfg);
+        do_simple_gvn(&mut func, &mut domtree);
+        assert_eq!(func.dfg.resolve_aliases(second), first);
+
+        // `upper` is defined after `first`, so it can't bound accesses through it.
+        do_repair_bounds(&mut func, &domtree);
+        assert_eq!(func.dfg.bounds[second], None);
+        assert_eq!(func.dfg.bounds[first], None);
+
+        // A bound defined before `first` is moved to it.
+        func.dfg.bounds[second] = Some(Bounds::Static { base, size: 0x1000 });
+        do_repair_bounds(&mut func, &domtree);
+        assert_eq!(func.dfg.bounds[second], None);
+        assert_eq!(
+            func.dfg.bounds[first],
+            Some(Bounds::Static { base, size: 0x1000 })
+        );
+    }
+
+    #[test]
+    fn dce_keeps_bounds() {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(types::I64));
+        sig.params.push(AbiParam::new(types::I64));
+        sig.returns.push(AbiParam::new(types::I32));
+        let mut func = Function::with_name_signature(ExternalName::testcase("bounds"), sig);
+        let block0 = func.dfg.make_block();
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_block(block0);
+        let base = pos.func.dfg.append_block_param(block0, types::I64);
+        let index = pos.func.dfg.append_block_param(block0, types::I64);
+        // The upper bound is only used by the bounds of the second address.
+        let upper = pos.ins().iadd_imm(base, 0x1000);
+        let first = pos.ins().iadd(base, index);
+        let second = pos.ins().iadd(base, index);
+        let loaded = pos.ins().load(types::I32, MemFlags::new(), second, 0);
+        pos.ins().store(MemFlags::new(), loaded, first, 0);
+        pos.ins().return_(&[loaded]);
+        pos.func.dfg.bounds[second] = Some(Bounds::Dynamic { lower: base, upper });
+
+        // Forward the second address to the first, as an earlier rewrite could have.
+        let redundant = func.dfg.value_def(second).unwrap_inst();
+        func.dfg.clear_results(redundant);
+        func.dfg.change_to_alias(second, first);
+        func.layout.remove_inst(redundant);
+
+        // The bounds are moved to the first address before DCE, so they keep `upper` live. No
+        // dominator tree was computed beforehand.
+        let mut context = Context::for_function(func);
+        context
+            .dce(&settings::Flags::new(settings::builder()))
+            .unwrap();
+        let func = &context.func;
+        assert_eq!(func.dfg.bounds[second], None);
+        assert_eq!(
+            func.dfg.bounds[first],
+            Some(Bounds::Dynamic { lower: base, upper })
+        );
+        let upper_def = func.dfg.value_def(upper).unwrap_inst();
+        assert_eq!(func.layout.inst_block(upper_def), Some(block0));
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn legalization_keeps_bounds() {
+        use crate::ir::{ArgumentPurpose, GlobalValueData};
+        use crate::isa;
+        use crate::settings::Configurable;
+        use core::str::FromStr;
+        use target_lexicon::Triple;
+
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(types::I64));
+        sig.params
+            .push(AbiParam::special(types::I64, ArgumentPurpose::VMContext));
+        sig.returns.push(AbiParam::new(types::I32));
+        let mut func = Function::with_name_signature(ExternalName::testcase("bounds"), sig);
+        let vmctx_gv = func.create_global_value(GlobalValueData::VMContext);
+        let block0 = func.dfg.make_block();
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_block(block0);
+        let base = pos.func.dfg.append_block_param(block0, types::I64);
+        let vmctx = pos.func.dfg.append_block_param(block0, types::I64);
+        // Legalization forwards the address of the VM context to the parameter holding it.
+        let addr = pos.ins().global_value(types::I64, vmctx_gv);
+        let loaded = pos.ins().load(types::I32, MemFlags::new(), addr, 0);
+        pos.ins().return_(&[loaded]);
+        pos.func.dfg.bounds[addr] = Some(Bounds::Static { base, size: 0x100 });
+
+        let mut flags = settings::builder();
+        flags.set("blade", "slh").unwrap();
+        let isa = isa::lookup(Triple::from_str("x86_64").unwrap())
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+        let mut context = Context::for_function(func);
+        context.compute_cfg();
+        context.legalize(&*isa).unwrap();
+        assert_eq!(context.func.dfg.resolve_aliases(addr), vmctx);
+
+        // Legalization leaves no dominator tree behind, so Blade computes one for the repair.
+        context.blade(&*isa).unwrap();
+        let func = &context.func;
+        assert_eq!(func.dfg.bounds[addr], None);
+        assert_eq!(
+            func.dfg.bounds[vmctx],
+            Some(Bounds::Static { base, size: 0x100 })
+        );
+    }
+}
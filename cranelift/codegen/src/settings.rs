@@ -336,6 +336,17 @@ pub mod detail {
 // `cranelift-codegen/meta/src/shared/settings.rs`.
 include!(concat!(env!("OUT_DIR"), "/settings.rs"));
 
+impl Blade {
+    /// Does this strategy protect loads with speculative load hardening, so that legalization
+    /// must attach bounds to the addresses of heap and table accesses?
+    pub fn uses_slh(self) -> bool {
+        match self {
+            Self::Slh | Self::SlhWithFenceFallback => true,
+            Self::None | Self::Lfence | Self::LfencePerBlock | Self::Analyze => false,
+        }
+    }
+}
+
 /// Wrapper containing flags and optionally a `TargetIsa` trait object.
 ///
 /// A few passes need to access the flags but only optionally a target ISA. The `FlagsOrIsa`
@@ -384,6 +395,23 @@ tls_model = "none"
 libcall_call_conv = "isa_default"
 baldrdash_prologue_words = 0
 probestack_size_log2 = 12
+blade = "none"
+blade_placement = "late"
+blade_cut_bias = "sources"
+blade_engine = "min_cut"
+blade_slh_mask = "address"
+blade_barrier = "lfence"
+blade_transient_params = "none"
+blade_max_edges_log2 = 20
+blade_weight_fence_load = 1
+blade_weight_fence_value = 1
+blade_weight_fence_sink = 1
+blade_weight_fence_branch = 1
+blade_weight_slh = 1
+blade_slh_size_budget = 0
+blade_sink_classes = 63
+blade_leakage_model = "cache"
+blade_stack_pointer_accesses = "strict"
 enable_verifier = true
 is_pic = false
 use_colocated_libcalls = false
@@ -399,6 +427,17 @@ emit_all_ones_funcaddrs = false
 enable_probestack = true
 probestack_func_adjusts_sp = false
 enable_jump_tables = true
+blade_coarsen = false
+blade_split_edges = false
+blade_prioritize_nested_regions = false
+blade_division_is_sink = false
+blade_float_is_sink = false
+blade_exclude_trusted_loads = false
+blade_exclude_non_speculative_loads = false
+blade_exclude_global_accesses = false
+blade_exclude_stack_accesses = false
+blade_slh_fake_bounds = false
+blade_fence_after_calls = false
 "#
         );
         assert_eq!(f.opt_level(), super::OptLevel::None);
@@ -63,6 +63,12 @@ define_passes! {
     licm: "Loop invariant code motion",
     unreachable_code: "Remove unreachable blocks",
     remove_constant_phis: "Remove constant phi-nodes",
+    repair_bounds: "Repair address bounds",
+    blade: "Blade Spectre mitigations",
+    blade_graph: "Blade graph construction",
+    blade_min_cut: "Blade minimal cut",
+    blade_insert: "Blade mitigation insertion",
+    blade_typing: "Blade dataflow typing",
 
     regalloc: "Register allocation",
     ra_liveness: "RA liveness analysis",
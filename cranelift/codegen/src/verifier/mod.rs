@@ -21,6 +21,13 @@
 //! - Values must be defined by an instruction that exists and that is inserted in
 //!   a block, or be an argument of an existing block.
 //! - Values used by an instruction must dominate the instruction.
+//! - The bounds of the addresses accessed by an instruction must dominate it, and have the same
+//!   type as the addresses.
+//!
+//! Speculation hardening
+//!
+//! - Selects that must be lowered without branches must be integer selects. Once the function is
+//!   encoded, their encodings must not be branches.
 //!
 //! Control flow graph and dominator tree integrity:
 //!
@@ -945,6 +952,82 @@ impl<'a> Verifier<'a> {
         }
     }
 
+    /// Check the bounds of the addresses accessed by `inst`, which Blade may use at `inst`.
+    fn verify_bounds(&self, inst: Inst, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
+        let dfg = &self.func.dfg;
+        let opcode = dfg[inst].opcode();
+        if !opcode.can_load() && !opcode.can_store() {
+            return Ok(());
+        }
+
+        for &arg in dfg.inst_args(inst) {
+            let arg = dfg.resolve_aliases(arg);
+            let bounds = match dfg.bounds[arg] {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+            let arg_type = dfg.value_type(arg);
+            for bound in bounds.values() {
+                self.verify_inst_arg(inst, bound, errors)?;
+                let bound_type = dfg.value_type(bound);
+                if bound_type != arg_type {
+                    errors.report((
+                        inst,
+                        self.context(inst),
+                        format!(
+                            "bounds of {} use {} of type {}, expected {}",
+                            arg, bound, bound_type, arg_type
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that `inst` can be lowered without branches, if `Function::branchless` requires it.
+    fn verify_branchless(&self, inst: Inst, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
+        if !self.func.branchless[inst] {
+            return Ok(());
+        }
+
+        let opcode = self.func.dfg[inst].opcode();
+        let ctrl_type = self.func.dfg.ctrl_typevar(inst);
+        if (opcode != Opcode::Select && opcode != Opcode::Selectif) || !ctrl_type.is_int() {
+            return errors.nonfatal((
+                inst,
+                self.context(inst),
+                format!("{}.{} can't be lowered without branches", opcode, ctrl_type),
+            ));
+        }
+
+        // Legalizing a select may expand it into branches, so once the legacy backends encode
+        // the function, a branchless select must have an encoding of its own.
+        let isa = match self.isa {
+            Some(isa) if isa.get_mach_backend().is_none() && !self.func.encodings.is_empty() => isa,
+            _ => return Ok(()),
+        };
+        let encoding = self.func.encodings[inst];
+        if !encoding.is_legal() {
+            return errors.nonfatal((
+                inst,
+                self.context(inst),
+                "branchless select must be encoded",
+            ));
+        }
+        if isa.encoding_info().branch_range(encoding).is_some() {
+            return errors.nonfatal((
+                inst,
+                self.context(inst),
+                format!(
+                    "branchless select is encoded as a branch: {}",
+                    isa.encoding_info().display(encoding)
+                ),
+            ));
+        }
+        Ok(())
+    }
+
     fn verify_inst_arg(
         &self,
         loc_inst: Inst,
@@ -2010,6 +2093,8 @@ impl<'a> Verifier<'a> {
                 self.instruction_integrity(inst, errors)?;
                 self.verify_safepoint_unused(inst, errors)?;
                 self.typecheck(inst, errors)?;
+                self.verify_bounds(inst, errors)?;
+                self.verify_branchless(inst, errors)?;
                 self.verify_encoding(inst, errors)?;
                 self.immediate_constraints(inst, errors)?;
             }
@@ -2033,9 +2118,11 @@ impl<'a> Verifier<'a> {
 #[cfg(test)]
 mod tests {
     use super::{Verifier, VerifierError, VerifierErrors};
+    use crate::cursor::{Cursor, FuncCursor};
     use crate::entity::EntityList;
+    use crate::ir::immediates::Ieee64;
     use crate::ir::instructions::{InstructionData, Opcode};
-    use crate::ir::{types, AbiParam, Function};
+    use crate::ir::{types, AbiParam, Function, InstBuilder};
     use crate::settings;
 
     macro_rules! assert_err_with_msg {
@@ -2164,4 +2251,26 @@ mod tests {
 
         assert_err_with_msg!(errors, "block0 cannot be empty");
     }
+
+    #[test]
+    fn branchless_float_select() {
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_block(block0);
+        let cond = pos.ins().iconst(types::I32, 1);
+        let x = pos.ins().f64const(Ieee64::with_float(1.0));
+        let y = pos.ins().f64const(Ieee64::with_float(2.0));
+        let v = pos.ins().select(cond, x, y);
+        let select = pos.func.dfg.value_def(v).unwrap_inst();
+        pos.ins().return_(&[]);
+        func.branchless[select] = true;
+
+        let flags = &settings::Flags::new(settings::builder());
+        let verifier = Verifier::new(&func, flags.into());
+        let mut errors = VerifierErrors::default();
+        let _ = verifier.run(&mut errors);
+
+        assert_err_with_msg!(errors, "select.f64 can't be lowered without branches");
+    }
 }
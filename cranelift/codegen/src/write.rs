@@ -3,7 +3,8 @@
 //! The `write` module provides the `write_function` function which converts an IR `Function` to an
 //! equivalent textual form. This textual form can be read back by the `cranelift-reader` crate.
 
-use crate::entity::SecondaryMap;
+use crate::blade::{BladeStats, MitigationKind};
+use crate::entity::{EntityRef, SecondaryMap};
 use crate::ir::entities::AnyEntity;
 use crate::ir::{
     Block, DataFlowGraph, DisplayFunctionAnnotations, Function, Inst, SigRef, Type, Value,
@@ -13,7 +14,7 @@ use crate::isa::{RegInfo, TargetIsa};
 use crate::packed_option::ReservedValue;
 use crate::value_label::ValueLabelsRanges;
 use crate::HashSet;
-use alloc::string::String;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use core::fmt::{self, Write};
 
@@ -205,12 +206,27 @@ pub fn decorate_function<FW: FuncWriter>(
     write_spec(w, func, regs)?;
     writeln!(w, " {{")?;
     let aliases = alias_map(func);
+    let blade_comments = match annotations.blade_stats {
+        Some(stats) => {
+            write_blade_summary(w, stats)?;
+            blade_comments(func, stats)
+        }
+        None => SecondaryMap::new(),
+    };
     let mut any = func_w.write_preamble(w, func, regs)?;
     for block in &func.layout {
         if any {
             writeln!(w)?;
         }
-        decorate_block(func_w, w, func, &aliases, annotations, block)?;
+        decorate_block(
+            func_w,
+            w,
+            func,
+            &aliases,
+            &blade_comments,
+            annotations,
+            block,
+        )?;
         any = true;
     }
     writeln!(w, "}}")
@@ -224,6 +240,99 @@ fn write_spec(w: &mut dyn Write, func: &Function, regs: Option<&RegInfo>) -> fmt
     write!(w, "{}{}", func.name, func.signature.display(regs))
 }
 
+//----------------------------------------------------------------------
+//
+// Blade comments.
+
+/// Write a summary of the run of the Blade pass described by `stats`, as comments.
+fn write_blade_summary(w: &mut dyn Write, stats: &BladeStats) -> fmt::Result {
+    writeln!(
+        w,
+        "    ; blade: {} graph nodes, {} edges, {} sources, {} sinks",
+        stats.graph_nodes, stats.graph_edges, stats.sources, stats.sinks
+    )?;
+    if stats.fell_back {
+        writeln!(
+            w,
+            "    ; blade: the graph exceeds blade_max_edges_log2, every block is fenced"
+        )?;
+    } else {
+        writeln!(
+            w,
+            "    ; blade: cut of {} edges, max flow {}",
+            stats.cut_size, stats.max_flow
+        )?;
+    }
+    writeln!(
+        w,
+        "    ; blade: {} speculation barriers, {} hardened loads of {} sources, {} split edges",
+        stats.fences(),
+        stats.slh_loads(),
+        stats.slh_sources,
+        stats.split_edges
+    )?;
+    if stats.slh_budget_fallbacks > 0 {
+        writeln!(
+            w,
+            "    ; blade: {} loads fenced over blade_slh_size_budget",
+            stats.slh_budget_fallbacks
+        )?;
+    }
+    if let Some(growth) = stats.code_size_growth {
+        writeln!(
+            w,
+            "    ; blade: {} bytes of code added, {} instructions by speculative load hardening",
+            growth,
+            stats.slh_insts.len()
+        )?;
+    }
+    Ok(())
+}
+
+/// Collect the comments describing what the Blade pass did to each instruction of `func`.
+fn blade_comments(func: &Function, stats: &BladeStats) -> SecondaryMap<Inst, Vec<String>> {
+    let mut comments = SecondaryMap::<_, Vec<_>>::new();
+    let exists =
+        |inst: Inst| func.dfg.num_insts() > inst.index() && func.layout.inst_block(inst).is_some();
+    // `blade=analyze` reports barriers which it didn't place.
+    for mitigation in &stats.mitigations {
+        let inst = mitigation.inst;
+        let placed = match mitigation.kind {
+            MitigationKind::FenceBefore => func.pre_lfence[inst],
+            MitigationKind::FenceAfter => func.post_lfence[inst],
+            MitigationKind::Slh => true,
+        };
+        if !placed && exists(inst) {
+            comments[inst].push(format!("{} (not placed)", mitigation.kind));
+        }
+    }
+    for &load in &stats.slh_points {
+        comments[load].push("hardened load".to_string());
+    }
+    for &inst in &stats.slh_insts {
+        comments[inst].push("inserted by speculative load hardening".to_string());
+    }
+    for diagnostic in &stats.diagnostics {
+        if exists(diagnostic.inst) {
+            comments[diagnostic.inst].push(diagnostic.kind.to_string());
+        }
+    }
+    comments
+}
+
+/// Write the Blade comments of `inst`, on the lines before it.
+fn write_blade_comments(
+    w: &mut dyn Write,
+    blade_comments: &SecondaryMap<Inst, Vec<String>>,
+    inst: Inst,
+    indent: usize,
+) -> fmt::Result {
+    for comment in &blade_comments[inst] {
+        writeln!(w, "{1:0$}; blade: {2}", indent, "", comment)?;
+    }
+    Ok(())
+}
+
 //----------------------------------------------------------------------
 //
 // Basic blocks
@@ -324,6 +433,7 @@ fn decorate_block<FW: FuncWriter>(
     w: &mut dyn Write,
     func: &Function,
     aliases: &SecondaryMap<Value, Vec<Value>>,
+    blade_comments: &SecondaryMap<Inst, Vec<String>>,
     annotations: &DisplayFunctionAnnotations,
     block: Block,
 ) -> fmt::Result {
@@ -338,6 +448,7 @@ fn decorate_block<FW: FuncWriter>(
     func_w.write_block_header(w, func, isa, block, indent)?;
     for a in func.dfg.block_params(block).iter().cloned() {
         write_value_aliases(w, aliases, a, indent)?;
+        write_value_bounds(w, func, a, indent)?;
     }
 
     if let Some(isa) = isa {
@@ -345,6 +456,7 @@ fn decorate_block<FW: FuncWriter>(
             let encinfo = isa.encoding_info();
             let regs = &isa.register_info();
             for (offset, inst, size) in func.inst_offsets(block, &encinfo) {
+                write_blade_comments(w, blade_comments, inst, indent)?;
                 func_w.write_instruction(w, func, aliases, Some(isa), inst, indent)?;
                 if size > 0 {
                     if let Some(val_ranges) = annotations.value_ranges {
@@ -357,6 +469,7 @@ fn decorate_block<FW: FuncWriter>(
     }
 
     for inst in func.layout.block_insts(block) {
+        write_blade_comments(w, blade_comments, inst, indent)?;
         func_w.write_instruction(w, func, aliases, isa, inst, indent)?;
     }
 
@@ -419,6 +532,18 @@ fn write_value_aliases(
     Ok(())
 }
 
+fn write_value_bounds(
+    w: &mut dyn Write,
+    func: &Function,
+    value: Value,
+    indent: usize,
+) -> fmt::Result {
+    if let Some(bounds) = func.dfg.bounds[value] {
+        writeln!(w, "{1:0$}{2} {3}", indent, "", value, bounds)?;
+    }
+    Ok(())
+}
+
 fn write_instruction(
     w: &mut dyn Write,
     func: &Function,
@@ -453,6 +578,20 @@ fn write_instruction(
         }
     }
 
+    // Then the speculation barriers and branchless selects placed by Blade.
+    if func.pre_lfence[inst] {
+        write!(s, "lfence_before ")?;
+    }
+    if func.post_lfence[inst] {
+        write!(s, "lfence_after ")?;
+    }
+    if func.pre_csdb[inst] {
+        write!(s, "csdb_before ")?;
+    }
+    if func.branchless[inst] {
+        write!(s, "branchless ")?;
+    }
+
     // Write out prefix and indent the instruction.
     write!(w, "{1:0$}", indent, s)?;
 
@@ -481,9 +620,10 @@ fn write_instruction(
     write_operands(w, &func.dfg, isa, inst)?;
     writeln!(w)?;
 
-    // Value aliases come out on lines after the instruction defining the referent.
+    // Value aliases and bounds come out on lines after the instruction defining the referent.
     for r in func.dfg.inst_results(inst) {
         write_value_aliases(w, aliases, *r, indent)?;
+        write_value_bounds(w, func, *r, indent)?;
     }
     Ok(())
 }
@@ -847,4 +987,81 @@ mod tests {
             "function u0:0() fast {\nblock0(v3: i32):\n    v0 -> v3\n    v2 -> v0\n    v4 = iconst.i32 42\n    v5 = iadd v0, v0\n    v1 -> v5\n    v6 = iconst.i32 23\n    v7 = iadd v1, v1\n}\n"
         );
     }
+
+    #[test]
+    fn blade_comments() {
+        use crate::blade::{BladeDiagnostic, BladeDiagnosticKind, BladeMitigation, BladeStats};
+        use crate::blade::{FencePoint, MitigationKind};
+        use crate::ir::{DisplayFunctionAnnotations, MemFlags, SourceLoc};
+
+        let mut func = Function::new();
+        let block0 = func.dfg.make_block();
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_block(block0);
+        let v0 = pos.func.dfg.append_block_param(block0, types::I64);
+        let mask = pos.ins().iconst(types::I64, -1);
+        let masked = pos.ins().band(v0, mask);
+        let v3 = pos.ins().load(types::I64, MemFlags::new(), masked, 0);
+        let ret = pos.ins().return_(&[v3]);
+        let mask = pos.func.dfg.value_def(mask).unwrap_inst();
+        let masked = pos.func.dfg.value_def(masked).unwrap_inst();
+        let load = pos.func.dfg.value_def(v3).unwrap_inst();
+        func.pre_csdb[load] = true;
+
+        let stats = BladeStats {
+            cut_size: 1,
+            max_flow: 1,
+            fence_points: vec![FencePoint::Before(ret)],
+            slh_points: vec![load],
+            slh_sources: 1,
+            slh_insts: vec![mask, masked],
+            slh_budget_fallbacks: 1,
+            code_size_growth: Some(10),
+            diagnostics: vec![BladeDiagnostic {
+                inst: ret,
+                kind: BladeDiagnosticKind::SlhFallback,
+                srcloc: SourceLoc::default(),
+            }],
+            mitigations: vec![BladeMitigation {
+                inst: ret,
+                kind: MitigationKind::FenceBefore,
+                srcloc: SourceLoc::default(),
+                offset: None,
+            }],
+            ..BladeStats::default()
+        };
+        let text = func
+            .display_with(DisplayFunctionAnnotations {
+                isa: None,
+                value_ranges: None,
+                blade_stats: Some(&stats),
+            })
+            .to_string();
+        assert_eq!(
+            text,
+            format!(
+                "function u0:0() fast {{
+    ; blade: 0 graph nodes, 0 edges, 0 sources, 0 sinks
+    ; blade: cut of 1 edges, max flow 1
+    ; blade: 1 speculation barriers, 1 hardened loads of 1 sources, 0 split edges
+    ; blade: 1 loads fenced over blade_slh_size_budget
+    ; blade: 10 bytes of code added, 2 instructions by speculative load hardening
+block0(v0: i64):
+    ; blade: inserted by speculative load hardening
+    v1 = iconst.i64 -1
+    ; blade: inserted by speculative load hardening
+    v2 = band v0, v1
+    ; blade: hardened load
+csdb_before v3 = load.i64 v2
+    ; blade: fence before (not placed)
+    ; blade: {}
+    return v3
+}}
+",
+                BladeDiagnosticKind::SlhFallback
+            )
+        );
+        // The comments are only printed on request.
+        assert!(!func.to_string().contains("blade"));
+    }
 }
@@ -38,6 +38,7 @@ mod runone;
 mod subtest;
 
 mod test_binemit;
+mod test_blade;
 mod test_cat;
 mod test_compile;
 mod test_dce;
@@ -122,6 +123,7 @@ pub fn run_passes(
 fn new_subtest(parsed: &TestCommand) -> subtest::SubtestResult<Box<dyn subtest::SubTest>> {
     match parsed.command {
         "binemit" => test_binemit::subtest(parsed),
+        "blade" => test_blade::subtest(parsed),
         "cat" => test_cat::subtest(parsed),
         "compile" => test_compile::subtest(parsed),
         "dce" => test_dce::subtest(parsed),
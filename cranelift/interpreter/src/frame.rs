@@ -16,6 +16,9 @@ pub struct Frame<'a> {
     pub function: &'a Function,
     /// The current mapping of SSA value-references to their actual values.
     registers: HashMap<ValueRef, DataValue>,
+    /// The current mapping of SSA references to CPU flags, represented by the pair of values
+    /// that were compared to produce them.
+    flags: HashMap<ValueRef, (DataValue, DataValue)>,
 }
 
 impl<'a> Frame<'a> {
@@ -27,6 +30,7 @@ impl<'a> Frame<'a> {
         Self {
             function,
             registers: HashMap::with_capacity(function.dfg.num_values()),
+            flags: HashMap::new(),
         }
     }
 
@@ -59,6 +63,22 @@ impl<'a> Frame<'a> {
         }
     }
 
+    /// Retrieve the pair of values compared to produce the flags of an SSA reference.
+    #[inline]
+    pub fn get_flags(&self, name: &ValueRef) -> &(DataValue, DataValue) {
+        trace!("Get flags {}", name);
+        self.flags
+            .get(name)
+            .unwrap_or_else(|| panic!("unknown flags: {}", name))
+    }
+
+    /// Assign the comparison of `lhs` and `rhs` to the flags SSA reference `name`.
+    #[inline]
+    pub fn set_flags(&mut self, name: ValueRef, lhs: DataValue, rhs: DataValue) {
+        trace!("Set flags {} -> ({}, {})", name, lhs, rhs);
+        self.flags.insert(name, (lhs, rhs));
+    }
+
     /// Rename all of the SSA references in `old_names` to those in `new_names`. This will remove
     /// any old references that are not in `old_names`. TODO This performs an extra allocation that
     /// could be removed if we copied the values in the right order (i.e. when modifying in place,
@@ -72,6 +92,8 @@ impl<'a> Frame<'a> {
             registers.insert(*nn, v);
         }
         self.registers = registers;
+        // Flags can't be passed as block arguments.
+        self.flags.clear();
     }
 }
 
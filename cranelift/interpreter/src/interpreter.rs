@@ -7,12 +7,12 @@ use crate::frame::Frame;
 use crate::interpreter::Trap::InvalidType;
 use cranelift_codegen::ir::condcodes::IntCC;
 use cranelift_codegen::ir::{
-    Block, FuncRef, Function, Inst, InstructionData, InstructionData::*, Opcode, Opcode::*, Type,
-    Value as ValueRef, ValueList,
+    Block, FuncRef, Function, Inst, InstructionData, InstructionData::*, Opcode, Opcode::*,
+    TrapCode, Type, Value as ValueRef, ValueList,
 };
 use cranelift_reader::{DataValue, DataValueCastFailure};
 use log::trace;
-use std::ops::{Add, Div, Mul, Sub};
+use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Sub};
 use thiserror::Error;
 
 /// The valid control flow states.
@@ -47,6 +47,8 @@ pub enum Trap {
     Unsupported(Inst),
     #[error("reached an unreachable statement")]
     Unreachable,
+    #[error("trapped: {0}")]
+    User(TrapCode),
     #[error("invalid control flow: {0}")]
     InvalidControlFlow(String),
     #[error("invalid function reference: {0}")]
@@ -140,12 +142,24 @@ impl Interpreter {
 
     /// Interpret a single [instruction](Inst). This contains a `match`-based dispatch to the
     /// implementations.
+    ///
+    /// Speculation barriers attached to the instruction (see [Function::pre_lfence] and
+    /// [Function::post_lfence]) have no architectural effect, so they are ignored.
     fn inst(&self, frame: &mut Frame, inst: Inst) -> Result<ControlFlow, Trap> {
         use ControlFlow::{Continue, ContinueAt};
         trace!("Inst: {}", &frame.function.dfg.display_inst(inst, None));
 
         let data = &frame.function.dfg[inst];
         match data {
+            Binary {
+                opcode: Ifcmp,
+                args,
+            } => {
+                let lhs = frame.get(&args[0]).clone();
+                let rhs = frame.get(&args[1]).clone();
+                frame.set_flags(first_result(frame.function, inst), lhs, rhs);
+                Ok(Continue)
+            }
             Binary { opcode, args } => {
                 let arg1 = frame.get(&args[0]);
                 let arg2 = frame.get(&args[1]);
@@ -153,6 +167,9 @@ impl Interpreter {
                     Iadd => binary_op!(Add::add[arg1, arg2]; [I8, I16, I32, I64]; inst),
                     Isub => binary_op!(Sub::sub[arg1, arg2]; [I8, I16, I32, I64]; inst),
                     Imul => binary_op!(Mul::mul[arg1, arg2]; [I8, I16, I32, I64]; inst),
+                    Band => binary_op!(BitAnd::bitand[arg1, arg2]; [I8, I16, I32, I64]; inst),
+                    Bor => binary_op!(BitOr::bitor[arg1, arg2]; [I8, I16, I32, I64]; inst),
+                    Bxor => binary_op!(BitXor::bitxor[arg1, arg2]; [I8, I16, I32, I64]; inst),
                     Fadd => binary_op!(Add::add[arg1, arg2]; [F32, F64]; inst),
                     Fsub => binary_op!(Sub::sub[arg1, arg2]; [F32, F64]; inst),
                     Fmul => binary_op!(Mul::mul[arg1, arg2]; [F32, F64]; inst),
@@ -268,6 +285,36 @@ impl Interpreter {
                 }
                 _ => unimplemented!("interpreter does not support opcode yet: {}", opcode),
             },
+            IntCondTrap {
+                opcode,
+                arg,
+                cond,
+                code,
+            } => match opcode {
+                Trapif => {
+                    let (lhs, rhs) = frame.get_flags(arg);
+                    if compare(*cond, lhs, rhs, inst)? {
+                        Err(Trap::User(*code))
+                    } else {
+                        Ok(Continue)
+                    }
+                }
+                _ => unimplemented!("interpreter does not support opcode yet: {}", opcode),
+            },
+            IntSelect { opcode, args, cond } => match opcode {
+                Selectif => {
+                    let (lhs, rhs) = frame.get_flags(&args[0]);
+                    let chosen = if compare(*cond, lhs, rhs, inst)? {
+                        args[1]
+                    } else {
+                        args[2]
+                    };
+                    let result = frame.get(&chosen).clone();
+                    frame.set(first_result(frame.function, inst), result);
+                    Ok(Continue)
+                }
+                _ => unimplemented!("interpreter does not support opcode yet: {}", opcode),
+            },
             MultiAry { opcode, args } => match opcode {
                 Return => {
                     let rs: Vec<DataValue> = args
@@ -306,6 +353,42 @@ impl Interpreter {
     }
 }
 
+/// Evaluate the integer condition `cond` on `lhs` and `rhs`, as set in the flags by `ifcmp`.
+fn compare(cond: IntCC, lhs: &DataValue, rhs: &DataValue, inst: Inst) -> Result<bool, Trap> {
+    // Extend both values to 64 bits, as signed and as unsigned integers.
+    let extend = |value: &DataValue| match *value {
+        DataValue::I8(i) => Ok((i64::from(i), u64::from(i as u8))),
+        DataValue::I16(i) => Ok((i64::from(i), u64::from(i as u16))),
+        DataValue::I32(i) => Ok((i64::from(i), u64::from(i as u32))),
+        DataValue::I64(i) => Ok((i, i as u64)),
+        _ => Err(Trap::Unsupported(inst)),
+    };
+    let (signed_lhs, unsigned_lhs) = extend(lhs)?;
+    let (signed_rhs, unsigned_rhs) = extend(rhs)?;
+    // Whether `lhs - rhs` overflows as a signed integer of the width of the operands.
+    let overflows = || match (lhs, rhs) {
+        (DataValue::I8(a), DataValue::I8(b)) => Ok(a.checked_sub(*b).is_none()),
+        (DataValue::I16(a), DataValue::I16(b)) => Ok(a.checked_sub(*b).is_none()),
+        (DataValue::I32(a), DataValue::I32(b)) => Ok(a.checked_sub(*b).is_none()),
+        (DataValue::I64(a), DataValue::I64(b)) => Ok(a.checked_sub(*b).is_none()),
+        _ => Err(Trap::Unsupported(inst)),
+    };
+    Ok(match cond {
+        IntCC::Equal => signed_lhs == signed_rhs,
+        IntCC::NotEqual => signed_lhs != signed_rhs,
+        IntCC::SignedLessThan => signed_lhs < signed_rhs,
+        IntCC::SignedGreaterThanOrEqual => signed_lhs >= signed_rhs,
+        IntCC::SignedGreaterThan => signed_lhs > signed_rhs,
+        IntCC::SignedLessThanOrEqual => signed_lhs <= signed_rhs,
+        IntCC::UnsignedLessThan => unsigned_lhs < unsigned_rhs,
+        IntCC::UnsignedGreaterThanOrEqual => unsigned_lhs >= unsigned_rhs,
+        IntCC::UnsignedGreaterThan => unsigned_lhs > unsigned_rhs,
+        IntCC::UnsignedLessThanOrEqual => unsigned_lhs <= unsigned_rhs,
+        IntCC::Overflow => overflows()?,
+        IntCC::NotOverflow => !overflows()?,
+    })
+}
+
 /// Return the first result of an instruction.
 ///
 /// This helper cushions the interpreter from changes to the [Function] API.
@@ -373,4 +456,30 @@ mod tests {
 
         assert_eq!(result, vec![DataValue::B(true)])
     }
+
+    #[test]
+    fn trapif_overflow() {
+        let code = "function %test(i8, i8) -> i8 {
+        block0(v0: i8, v1: i8):
+            v2 = ifcmp v0, v1
+            trapif of v2, int_ovf
+            v3 = isub v0, v1
+            return v3
+        }";
+
+        let func = parse_functions(code).unwrap().into_iter().next().unwrap();
+        let mut env = Environment::default();
+        env.add(func.name.to_string(), func);
+        let interpreter = Interpreter::new(env);
+        let call = |a, b| interpreter.call_by_name("%test", &[DataValue::I8(a), DataValue::I8(b)]);
+
+        assert_eq!(
+            call(100, 27).unwrap().unwrap_return(),
+            vec![DataValue::I8(73)]
+        );
+        match call(-128, 1) {
+            Err(Trap::User(TrapCode::IntegerOverflow)) => {}
+            _ => panic!("expected an integer overflow trap"),
+        }
+    }
 }
@@ -1993,6 +1993,18 @@ impl<'a> Parser<'a> {
             }
         }
 
+        for (value, bounds) in ctx.function.dfg.bounds.iter() {
+            for bound in bounds.iter().flat_map(ir::Bounds::values) {
+                if !ctx.map.contains_value(bound) {
+                    return err!(
+                        ctx.map.location(AnyEntity::Value(value)).unwrap(),
+                        "undefined bounds value {}",
+                        bound
+                    );
+                }
+            }
+        }
+
         for alias in &ctx.aliases {
             if !ctx.function.dfg.set_alias_type_for_parser(*alias) {
                 let loc = ctx.map.location(AnyEntity::Value(*alias)).unwrap();
@@ -2039,6 +2051,9 @@ impl<'a> Parser<'a> {
         } {
             let srcloc = self.optional_srcloc()?;
             let (encoding, result_locations) = self.parse_instruction_encoding(ctx)?;
+            let (pre_lfence, post_lfence) = self.parse_lfence_markers();
+            let pre_csdb = self.optional(Token::Identifier("csdb_before"));
+            let branchless = self.optional(Token::Identifier("branchless"));
 
             // We need to parse instruction results here because they are shared
             // between the parsing of value aliases and the parsing of instructions.
@@ -2053,9 +2068,27 @@ impl<'a> Parser<'a> {
             }
 
             match self.token() {
+                Some(Token::Arrow) if pre_lfence || post_lfence || pre_csdb => {
+                    return err!(self.loc, "value aliases can't have speculation barriers");
+                }
+                Some(Token::Arrow) if branchless => {
+                    return err!(self.loc, "value aliases can't be branchless");
+                }
                 Some(Token::Arrow) => {
                     self.consume();
                     self.parse_value_alias(&results, ctx)?;
+                    continue;
+                }
+                Some(Token::Identifier("bounds")) if pre_lfence || post_lfence || pre_csdb => {
+                    return err!(self.loc, "value bounds can't have speculation barriers");
+                }
+                Some(Token::Identifier("bounds")) if branchless => {
+                    return err!(self.loc, "value bounds can't be branchless");
+                }
+                Some(Token::Identifier("bounds")) if !results.is_empty() => {
+                    self.consume();
+                    self.parse_value_bounds(&results, ctx)?;
+                    continue;
                 }
                 Some(Token::Equal) => {
                     self.consume();
@@ -2078,11 +2111,28 @@ impl<'a> Parser<'a> {
                     block,
                 )?,
             }
+
+            // The instruction just parsed is the last one of the block.
+            let inst = ctx.function.layout.last_inst(block).unwrap();
+            ctx.function.pre_lfence[inst] = pre_lfence;
+            ctx.function.post_lfence[inst] = post_lfence;
+            ctx.function.pre_csdb[inst] = pre_csdb;
+            ctx.function.branchless[inst] = branchless;
         }
 
         Ok(())
     }
 
+    // Parse the optional speculation barrier markers of an instruction, in the order `write`
+    // prints them.
+    //
+    // lfence-markers ::= * ["lfence_before"] ["lfence_after"]
+    fn parse_lfence_markers(&mut self) -> (bool, bool) {
+        let pre_lfence = self.optional(Token::Identifier("lfence_before"));
+        let post_lfence = self.optional(Token::Identifier("lfence_after"));
+        (pre_lfence, post_lfence)
+    }
+
     // Parse parenthesized list of block parameters. Returns a vector of (u32, Type) pairs with the
     // value numbers of the defined values and the defined types.
     //
@@ -2285,6 +2335,46 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
+    // Parse the bounds of a value, which must already be defined.
+    //
+    // value-bounds ::= Value(v) "bounds" * "(" Value(base) "," ( Value(upper) | Integer(size) ) ")"
+    fn parse_value_bounds(&mut self, results: &[Value], ctx: &mut Context) -> ParseResult<()> {
+        if results.len() != 1 {
+            return err!(self.loc, "wrong number of values with bounds");
+        }
+        let value = results[0];
+        if !ctx.map.contains_value(value) {
+            return err!(self.loc, "value {} is not yet defined", value);
+        }
+        if ctx.function.dfg.bounds[value].is_some() {
+            return err!(self.loc, "value {} already has bounds", value);
+        }
+
+        self.match_token(Token::LPar, "expected '(' before bounds")?;
+        let base = self.match_value("expected base value of bounds")?;
+        self.match_token(Token::Comma, "expected ',' between bounds")?;
+        let bounds = if let Some(Token::Integer(_)) = self.token() {
+            let size = self.match_uimm64("expected bounds size")?;
+            ir::Bounds::Static {
+                base,
+                size: size.into(),
+            }
+        } else {
+            let upper = self.match_value("expected upper bound value or bounds size")?;
+            ir::Bounds::Dynamic { lower: base, upper }
+        };
+        self.match_token(Token::RPar, "expected ')' after bounds")?;
+
+        // The bounds may refer to values defined further down.
+        for bound in bounds.values() {
+            while ctx.function.dfg.num_values() <= bound.index() {
+                ctx.function.dfg.make_invalid_value_for_parser();
+            }
+        }
+        ctx.function.dfg.bounds[value] = Some(bounds);
+        Ok(())
+    }
+
     // Parse an instruction, append it to `block`.
     //
     // instruction ::= [inst-results "="] Opcode(opc) ["." Type] ...
@@ -3208,7 +3298,7 @@ mod tests {
     use cranelift_codegen::ir::entities::AnyEntity;
     use cranelift_codegen::ir::types;
     use cranelift_codegen::ir::StackSlotKind;
-    use cranelift_codegen::ir::{ArgumentExtension, ArgumentPurpose};
+    use cranelift_codegen::ir::{ArgumentExtension, ArgumentPurpose, Bounds};
     use cranelift_codegen::isa::CallConv;
 
     #[test]
@@ -3369,6 +3459,111 @@ mod tests {
         assert_eq!(func.dfg.value_type(block4_args[0]), types::I32);
     }
 
+    #[test]
+    fn lfence_markers() {
+        let (func, _) = Parser::new(
+            "function %lfence(i64) system_v {
+                block0(v0: i64):
+                    lfence_before v1 = load.i64 v0
+                    lfence_after v2 = iadd_imm v1, 8
+                    return
+                }",
+        )
+        .parse_function(None)
+        .unwrap();
+
+        let insts: Vec<_> = func
+            .layout
+            .block_insts(func.layout.entry_block().unwrap())
+            .collect();
+        assert!(func.pre_lfence[insts[0]] && !func.post_lfence[insts[0]]);
+        assert!(!func.pre_lfence[insts[1]] && func.post_lfence[insts[1]]);
+        assert!(!func.pre_lfence[insts[2]] && !func.post_lfence[insts[2]]);
+
+        // Aliases are not instructions, so they can't have barriers.
+        assert!(Parser::new(
+            "function %lfence(i64) system_v {
+                block0(v0: i64):
+                    lfence_before v1 -> v0
+                    return
+                }",
+        )
+        .parse_function(None)
+        .is_err());
+    }
+
+    #[test]
+    fn branchless_marker() {
+        let (func, _) = Parser::new(
+            "function %branchless(i64, i64) system_v {
+                block0(v0: i64, v1: i64):
+                    v2 = iconst.i64 0
+                    v3 = iconst.i64 -1
+                    v4 = ifcmp v0, v1
+                    branchless v5 = selectif.i64 ugt v4, v2, v3
+                    csdb_before return
+                }",
+        )
+        .parse_function(None)
+        .unwrap();
+
+        let insts: Vec<_> = func
+            .layout
+            .block_insts(func.layout.entry_block().unwrap())
+            .collect();
+        assert!(!func.branchless[insts[2]]);
+        assert!(func.branchless[insts[3]]);
+        assert!(!func.post_lfence[insts[3]]);
+        assert!(!func.pre_csdb[insts[3]]);
+        assert!(func.pre_csdb[insts[4]]);
+    }
+
+    #[test]
+    fn value_bounds() {
+        let (func, _) = Parser::new(
+            "function %bounds(i64, i64) system_v {
+                block0(v0: i64, v1: i64):
+                    v0 bounds(v1, 4096)
+                    v2 = iadd_imm v1, 8
+                    v2 bounds(v1, v3)
+                    v3 = iadd_imm v1, 4096
+                    return
+                }",
+        )
+        .parse_function(None)
+        .unwrap();
+
+        let v0 = Value::from_u32(0);
+        let v1 = Value::from_u32(1);
+        let v2 = Value::from_u32(2);
+        let v3 = Value::from_u32(3);
+        assert_eq!(
+            func.dfg.bounds[v0],
+            Some(Bounds::Static {
+                base: v1,
+                size: 4096
+            })
+        );
+        assert_eq!(
+            func.dfg.bounds[v2],
+            Some(Bounds::Dynamic {
+                lower: v1,
+                upper: v3
+            })
+        );
+
+        // Bounds can only refer to values defined somewhere.
+        assert!(Parser::new(
+            "function %bounds(i64) system_v {
+                block0(v0: i64):
+                    v0 bounds(v0, v5)
+                    return
+                }",
+        )
+        .parse_function(None)
+        .is_err());
+    }
+
     #[test]
     fn duplicate_block() {
         let ParseError {
@@ -0,0 +1,146 @@
+//! The `blade` sub-command.
+//!
+//! Read a sequence of Cranelift IR files, run only the Blade Spectre mitigation pass on their
+//! functions, and print the transformed functions followed by a report of the mitigations Blade
+//! placed. The report is made of comments, so the output can be fed back to other commands.
+//!
+//! Blade expects legalized code, so the functions are legalized first. This is also what attaches
+//! bounds to the heap and table addresses used by speculative load hardening.
+//!
+//! This module also writes the per-function statistics requested with `--blade-csv`, here and in
+//! the `compile` and `wasm` sub-commands, and decides whether the functions they print come with
+//! comments describing what Blade did.
+
+use crate::utils::{parse_sets_and_triple, read_to_string};
+use crate::CommandResult;
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::print_errors::pretty_error;
+use cranelift_codegen::settings::Blade;
+use cranelift_codegen::{BladeStats, Context};
+use cranelift_reader::{parse_test, ParseOptions};
+use std::fs;
+
+pub fn run(
+    files: &[String],
+    flag_set: &[String],
+    flag_isa: &str,
+    flag_blade_csv: Option<&str>,
+) -> CommandResult {
+    let parsed = parse_sets_and_triple(flag_set, flag_isa)?;
+    let mut csv = BladeCsv::new(flag_blade_csv);
+
+    for (i, f) in files.iter().enumerate() {
+        if i != 0 {
+            println!();
+        }
+        blade_one(f, parsed.as_fisa().isa, &mut csv)?
+    }
+    csv.write()
+}
+
+fn blade_one(filename: &str, isa: Option<&dyn TargetIsa>, csv: &mut BladeCsv) -> CommandResult {
+    let buffer = read_to_string(&filename).map_err(|e| format!("{}: {}", filename, e))?;
+    let test_file =
+        parse_test(&buffer, ParseOptions::default()).map_err(|e| format!("{}: {}", filename, e))?;
+
+    // If we have an isa from the command-line, use that. Otherwise if the
+    // file contains a unique isa, use that.
+    let isa = match isa.or(test_file.isa_spec.unique_isa()) {
+        Some(isa) => isa,
+        None => return Err(String::from("the Blade pass requires a target isa")),
+    };
+
+    for (idx, (func, _)) in test_file.functions.into_iter().enumerate() {
+        if idx != 0 {
+            println!();
+        }
+
+        let mut context = Context::new();
+        context.func = func;
+        context.compute_cfg();
+        context
+            .legalize(isa)
+            .and_then(|()| context.blade(isa))
+            .map_err(|err| pretty_error(&context.func, Some(isa), err))?;
+
+        print!("{}", context.func.display(isa));
+        print_report(&context, isa);
+        csv.add(&context.func.name.to_string(), &context.blade_stats);
+    }
+
+    Ok(())
+}
+
+/// Print the cut Blade computed for `context.func`, and where it placed mitigations.
+fn print_report(context: &Context, isa: &dyn TargetIsa) {
+    let func = &context.func;
+    let stats = &context.blade_stats;
+    println!(
+        "; blade={}: cut of {} edges, {} fences, {} slh loads",
+        isa.flags().blade(),
+        stats.cut_size,
+        stats.fences(),
+        stats.slh_loads()
+    );
+    if stats.fell_back {
+        println!("; the graph was too large, so every block was fenced instead");
+    }
+    for mitigation in context.blade_report() {
+        println!(
+            "; {} {} {}: {}",
+            mitigation.kind,
+            mitigation.inst,
+            mitigation.srcloc,
+            func.dfg.display_inst(mitigation.inst, isa)
+        );
+    }
+    for diagnostic in &stats.diagnostics {
+        println!(
+            "; approximated: {}: {}",
+            func.dfg.display_inst(diagnostic.inst, isa),
+            diagnostic.kind
+        );
+    }
+}
+
+/// Get the statistics of the Blade run on `context.func` to print as comments along with the
+/// compiled function, unless Blade was disabled and left it untouched.
+pub fn printed_stats<'a>(context: &'a Context, isa: &dyn TargetIsa) -> Option<&'a BladeStats> {
+    if isa.flags().blade() != Blade::None || !context.blade_report().is_empty() {
+        Some(&context.blade_stats)
+    } else {
+        None
+    }
+}
+
+/// The rows of statistics requested with `--blade-csv`, one per function Blade ran on.
+pub struct BladeCsv {
+    path: Option<String>,
+    text: String,
+}
+
+impl BladeCsv {
+    /// Collect rows to write to `path`, or nothing without a path.
+    pub fn new(path: Option<&str>) -> Self {
+        Self {
+            path: path.map(String::from),
+            text: format!("{}\n", BladeStats::CSV_HEADER),
+        }
+    }
+
+    /// Add the row of the function named `name`, which Blade ran on with the results in `stats`.
+    pub fn add(&mut self, name: &str, stats: &BladeStats) {
+        if self.path.is_some() {
+            self.text.push_str(&stats.csv_row(name));
+            self.text.push('\n');
+        }
+    }
+
+    /// Write the rows, if there is a path to write them to.
+    pub fn write(&self) -> CommandResult {
+        match &self.path {
+            Some(path) => fs::write(path, &self.text).map_err(|e| format!("{}: {}", path, e)),
+            None => Ok(()),
+        }
+    }
+}
@@ -20,6 +20,7 @@ use std::io::{self, Write};
 use std::option::Option;
 use std::process;
 
+mod blade;
 mod bugpoint;
 mod cat;
 mod compile;
@@ -108,6 +109,14 @@ fn add_debug_flag<'a>() -> clap::Arg<'a, 'a> {
         .help("Enable debug output on stderr/stdout")
 }
 
+fn add_blade_csv_flag<'a>() -> clap::Arg<'a, 'a> {
+    Arg::with_name("blade-csv")
+        .long("blade-csv")
+        .takes_value(true)
+        .value_name("file")
+        .help("Write the Blade statistics of every function to a CSV file")
+}
+
 fn add_just_decode_flag<'a>() -> clap::Arg<'a, 'a> {
     Arg::with_name("just-decode")
         .short("t")
@@ -152,6 +161,7 @@ fn add_wasm_or_compile<'a>(cmd: &str) -> clap::App<'a, 'a> {
         .arg(add_debug_flag())
         .arg(add_just_decode_flag())
         .arg(add_check_translation_flag())
+        .arg(add_blade_csv_flag())
 }
 
 fn handle_debug_flag(debug: bool) {
@@ -216,6 +226,15 @@ fn main() {
                 .arg(add_debug_flag())
                 .arg(add_time_flag()),
         )
+        .subcommand(
+            SubCommand::with_name("blade")
+                .about("Run only the Blade Spectre mitigation pass and report its cut")
+                .arg(add_input_file_arg())
+                .arg(add_set_flag())
+                .arg(add_target_flag())
+                .arg(add_debug_flag())
+                .arg(add_blade_csv_flag()),
+        )
         .subcommand(
             SubCommand::with_name("bugpoint")
                 .about("Reduce size of clif file causing panic during compilation.")
@@ -292,6 +311,7 @@ fn main() {
                 rest_cmd.is_present("time-passes"),
                 &get_vec(rest_cmd.values_of("set")),
                 target_val,
+                rest_cmd.value_of("blade-csv"),
             )
         }
         ("wasm", Some(rest_cmd)) => {
@@ -316,6 +336,7 @@ fn main() {
                     rest_cmd.is_present("print-size"),
                     rest_cmd.is_present("time-passes"),
                     rest_cmd.is_present("value-ranges"),
+                    rest_cmd.value_of("blade-csv"),
                 )
             };
 
@@ -324,6 +345,21 @@ fn main() {
 
             result
         }
+        ("blade", Some(rest_cmd)) => {
+            handle_debug_flag(rest_cmd.is_present("debug"));
+
+            let mut target_val: &str = "";
+            if let Some(clap_target) = rest_cmd.value_of("target") {
+                target_val = clap_target;
+            }
+
+            blade::run(
+                &get_vec(rest_cmd.values_of("file")),
+                &get_vec(rest_cmd.values_of("set")),
+                target_val,
+                rest_cmd.value_of("blade-csv"),
+            )
+        }
         ("bugpoint", Some(rest_cmd)) => {
             let mut target_val: &str = "";
             if let Some(clap_target) = rest_cmd.value_of("target") {
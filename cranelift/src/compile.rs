@@ -1,7 +1,9 @@
 //! CLI tool to read Cranelift IR files and compile them into native code.
 
-use crate::disasm::{print_all, PrintRelocs, PrintStackmaps, PrintTraps};
+use crate::blade::{printed_stats, BladeCsv};
+use crate::disasm::{print_all, PrintBlade, PrintRelocs, PrintStackmaps, PrintTraps};
 use crate::utils::{parse_sets_and_triple, read_to_string};
+use cranelift_codegen::ir::DisplayFunctionAnnotations;
 use cranelift_codegen::print_errors::pretty_error;
 use cranelift_codegen::settings::FlagsOrIsa;
 use cranelift_codegen::timing;
@@ -17,8 +19,10 @@ pub fn run(
     flag_report_times: bool,
     flag_set: &[String],
     flag_isa: &str,
+    flag_blade_csv: Option<&str>,
 ) -> Result<(), String> {
     let parsed = parse_sets_and_triple(flag_set, flag_isa)?;
+    let mut csv = BladeCsv::new(flag_blade_csv);
 
     for filename in files {
         let path = Path::new(&filename);
@@ -30,9 +34,10 @@ pub fn run(
             &path.to_path_buf(),
             &name,
             parsed.as_fisa(),
+            &mut csv,
         )?;
     }
-    Ok(())
+    csv.write()
 }
 
 fn handle_module(
@@ -42,6 +47,7 @@ fn handle_module(
     path: &PathBuf,
     name: &str,
     fisa: FlagsOrIsa,
+    csv: &mut BladeCsv,
 ) -> Result<(), String> {
     let buffer = read_to_string(&path).map_err(|e| format!("{}: {}", name, e))?;
     let test_file =
@@ -69,12 +75,21 @@ fn handle_module(
             let code_info = context
                 .compile_and_emit(isa, &mut mem, &mut relocs, &mut traps, &mut stackmaps)
                 .map_err(|err| pretty_error(&context.func, Some(isa), err))?;
+            csv.add(&context.func.name.to_string(), &context.blade_stats);
 
             if flag_print {
-                println!("{}", context.func.display(isa));
+                println!(
+                    "{}",
+                    context.func.display_with(DisplayFunctionAnnotations {
+                        isa: Some(isa),
+                        value_ranges: None,
+                        blade_stats: printed_stats(&context, isa),
+                    })
+                );
             }
 
             if flag_disasm {
+                let blade = PrintBlade::new(flag_print, &context, isa);
                 print_all(
                     isa,
                     &mem,
@@ -83,6 +98,7 @@ fn handle_module(
                     &relocs,
                     &traps,
                     &stackmaps,
+                    &blade,
                 )?;
             }
         }
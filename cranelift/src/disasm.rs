@@ -1,6 +1,8 @@
 use cfg_if::cfg_if;
+use cranelift_codegen::entity::SecondaryMap;
 use cranelift_codegen::isa::TargetIsa;
-use cranelift_codegen::{binemit, ir};
+use cranelift_codegen::{binemit, ir, Context, MitigationKind};
+use std::collections::HashMap;
 use std::fmt::Write;
 
 pub struct PrintRelocs {
@@ -119,6 +121,64 @@ impl binemit::StackmapSink for PrintStackmaps {
     }
 }
 
+/// The Spectre mitigations Blade placed in a compiled function.
+pub struct PrintBlade {
+    pub text: String,
+    /// Comments for the disassembly, by the offset of the machine instruction they describe: the
+    /// barrier itself for fences, and the first instruction of the load for SLH.
+    comments: HashMap<binemit::CodeOffset, Vec<String>>,
+}
+
+impl PrintBlade {
+    /// Collect the mitigations of `context.func`, which must have been compiled for `isa`.
+    ///
+    /// Only the legacy backends record where the mitigations are in the code, so there are no
+    /// comments for the disassembly with the others.
+    pub fn new(flag_print: bool, context: &Context, isa: &dyn TargetIsa) -> Self {
+        let report = context.blade_report();
+        let mut inst_ends = SecondaryMap::<ir::Inst, binemit::CodeOffset>::new();
+        if report.iter().any(|mitigation| mitigation.offset.is_some()) {
+            let encinfo = isa.encoding_info();
+            for block in context.func.layout.blocks() {
+                for (offset, inst, size) in context.func.inst_offsets(block, &encinfo) {
+                    inst_ends[inst] = offset + size;
+                }
+            }
+        }
+
+        let mut text = String::new();
+        let mut comments = HashMap::new();
+        for mitigation in report {
+            let description = format!("blade: {} {}", mitigation.kind, mitigation.inst);
+            let offset = mitigation.offset.map(|offset| match mitigation.kind {
+                MitigationKind::FenceAfter => inst_ends[mitigation.inst] - binemit::LFENCE_SIZE,
+                MitigationKind::FenceBefore | MitigationKind::Slh => offset,
+            });
+            if flag_print {
+                match offset {
+                    Some(offset) => writeln!(&mut text, "{} at {}", description, offset).unwrap(),
+                    None => writeln!(&mut text, "{}", description).unwrap(),
+                }
+            }
+            if let Some(offset) = offset {
+                comments
+                    .entry(offset)
+                    .or_insert_with(Vec::new)
+                    .push(description);
+            }
+        }
+        Self { text, comments }
+    }
+
+    /// Get the comment for the machine instruction at `offset`, if Blade placed a mitigation
+    /// there.
+    pub fn comment(&self, offset: binemit::CodeOffset) -> Option<String> {
+        self.comments
+            .get(&offset)
+            .map(|descriptions| descriptions.join(", "))
+    }
+}
+
 cfg_if! {
     if #[cfg(feature = "disas")] {
         use capstone::prelude::*;
@@ -170,7 +230,11 @@ cfg_if! {
             cs.map_err(|err| err.to_string())
         }
 
-        pub fn print_disassembly(isa: &dyn TargetIsa, mem: &[u8]) -> Result<(), String> {
+        pub fn print_disassembly(
+            isa: &dyn TargetIsa,
+            mem: &[u8],
+            blade: &PrintBlade,
+        ) -> Result<(), String> {
             let cs = get_disassembler(isa)?;
 
             println!("\nDisassembly of {} bytes:", mem.len());
@@ -204,12 +268,20 @@ cfg_if! {
                     write!(&mut line, "{}", s).unwrap();
                 }
 
+                if let Some(comment) = blade.comment(i.address() as binemit::CodeOffset) {
+                    write!(&mut line, "\t; {}", comment).unwrap();
+                }
+
                 println!("{}", line);
             }
             Ok(())
         }
     } else {
-        pub fn print_disassembly(_: &dyn TargetIsa, _: &[u8]) -> Result<(), String> {
+        pub fn print_disassembly(
+            _: &dyn TargetIsa,
+            _: &[u8],
+            _: &PrintBlade,
+        ) -> Result<(), String> {
             println!("\nNo disassembly available.");
             Ok(())
         }
@@ -224,11 +296,15 @@ pub fn print_all(
     relocs: &PrintRelocs,
     traps: &PrintTraps,
     stackmaps: &PrintStackmaps,
+    blade: &PrintBlade,
 ) -> Result<(), String> {
     print_bytes(&mem);
-    print_disassembly(isa, &mem[0..code_size as usize])?;
+    print_disassembly(isa, &mem[0..code_size as usize], blade)?;
     print_readonly_data(&mem[code_size as usize..(code_size + rodata_size) as usize]);
-    println!("\n{}\n{}\n{}", &relocs.text, &traps.text, &stackmaps.text);
+    println!(
+        "\n{}\n{}\n{}\n{}",
+        &relocs.text, &traps.text, &stackmaps.text, &blade.text
+    );
     Ok(())
 }
 
@@ -1,5 +1,6 @@
 //! Utility functions.
 
+use cranelift_codegen::check_blade_settings;
 use cranelift_codegen::isa;
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::settings::{self, FlagsOrIsa};
@@ -93,9 +94,10 @@ pub fn parse_sets_and_triple(
         parse_options(words, &mut isa_builder, Location { line_number: 0 })
             .map_err(|err| ParseError::from(err).to_string())?;
 
-        Ok(OwnedFlagsOrIsa::Isa(
-            isa_builder.finish(settings::Flags::new(flag_builder)),
-        ))
+        let isa = isa_builder.finish(settings::Flags::new(flag_builder));
+        // Reject Blade settings the target can't compile now, rather than on the first function.
+        check_blade_settings(&*isa).map_err(|err| err.to_string())?;
+        Ok(OwnedFlagsOrIsa::Isa(isa))
     } else {
         if !unknown_settings.is_empty() {
             return Err(format!(
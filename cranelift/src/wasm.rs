@@ -7,7 +7,8 @@
     allow(clippy::too_many_arguments, clippy::cognitive_complexity)
 )]
 
-use crate::disasm::{print_all, PrintRelocs, PrintStackmaps, PrintTraps};
+use crate::blade::{printed_stats, BladeCsv};
+use crate::disasm::{print_all, PrintBlade, PrintRelocs, PrintStackmaps, PrintTraps};
 use crate::utils::parse_sets_and_triple;
 use cranelift_codegen::ir::DisplayFunctionAnnotations;
 use cranelift_codegen::print_errors::{pretty_error, pretty_verifier_error};
@@ -49,8 +50,10 @@ pub fn run(
     flag_print_size: bool,
     flag_report_times: bool,
     flag_calc_value_ranges: bool,
+    flag_blade_csv: Option<&str>,
 ) -> Result<(), String> {
     let parsed = parse_sets_and_triple(flag_set, flag_triple)?;
+    let mut csv = BladeCsv::new(flag_blade_csv);
     for filename in files {
         let path = Path::new(&filename);
         let name = String::from(path.as_os_str().to_string_lossy());
@@ -66,9 +69,10 @@ pub fn run(
             &path.to_path_buf(),
             &name,
             parsed.as_fisa(),
+            &mut csv,
         )?;
     }
-    Ok(())
+    csv.write()
 }
 
 fn handle_module(
@@ -83,6 +87,7 @@ fn handle_module(
     path: &PathBuf,
     name: &str,
     fisa: FlagsOrIsa,
+    csv: &mut BladeCsv,
 ) -> Result<(), String> {
     let mut terminal = term::stdout().unwrap();
     let _ = terminal.fg(term::color::YELLOW);
@@ -185,6 +190,11 @@ fn handle_module(
             let code_info = context
                 .compile_and_emit(isa, &mut mem, &mut relocs, &mut traps, &mut stackmaps)
                 .map_err(|err| pretty_error(&context.func, fisa.isa, err))?;
+            let func_name = match dummy_environ.get_func_name(FuncIndex::new(func_index)) {
+                Some(func_name) => func_name.to_string(),
+                None => func_index.to_string(),
+            };
+            csv.add(&func_name, &context.blade_stats);
 
             if flag_print_size {
                 println!(
@@ -233,12 +243,18 @@ fn handle_module(
                 context.func.display_with(DisplayFunctionAnnotations {
                     isa: fisa.isa,
                     value_ranges: value_ranges.as_ref(),
+                    blade_stats: if flag_check_translation {
+                        None
+                    } else {
+                        printed_stats(&context, isa)
+                    },
                 })
             );
             vprintln!(flag_verbose, "");
         }
 
         if let Some((code_size, rodata_size)) = saved_sizes {
+            let blade = PrintBlade::new(flag_print, &context, isa);
             print_all(
                 isa,
                 &mem,
@@ -247,6 +263,7 @@ fn handle_module(
                 &relocs,
                 &traps,
                 &stackmaps,
+                &blade,
             )?;
         }
 
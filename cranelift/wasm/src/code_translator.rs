@@ -110,7 +110,11 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
                 GlobalVariable::Memory { gv, offset, ty } => {
                     let addr = builder.ins().global_value(environ.pointer_type(), gv);
                     let flags = ir::MemFlags::trusted();
-                    builder.ins().load(ty, flags, addr, offset)
+                    let val = builder.ins().load(ty, flags, addr, offset);
+                    let load = builder.func.dfg.value_def(val).unwrap_inst();
+                    builder.func.blade_class[load] =
+                        environ.blade_classify_global_access(GlobalIndex::from_u32(*global_index));
+                    val
                 }
                 GlobalVariable::Custom => environ.translate_custom_global_get(
                     builder.cursor(),
@@ -131,7 +135,9 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
                         val = optionally_bitcast_vector(val, I8X16, builder);
                     }
                     debug_assert_eq!(ty, builder.func.dfg.value_type(val));
-                    builder.ins().store(flags, val, addr, offset);
+                    let store = builder.ins().store(flags, val, addr, offset);
+                    builder.func.blade_class[store] =
+                        environ.blade_classify_global_access(GlobalIndex::from_u32(*global_index));
                 }
                 GlobalVariable::Custom => {
                     let val = state.pop1();
@@ -583,120 +589,78 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
          * Wasm specifies an integer alignment flag but we drop it in Cranelift.
          * The memory base address is provided by the environment.
          ************************************************************************************/
-        Operator::I32Load8U {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Uload8, I32, builder, state, environ)?;
+        Operator::I32Load8U { memarg } => {
+            translate_load(memarg, ir::Opcode::Uload8, I32, builder, state, environ)?;
         }
-        Operator::I32Load16U {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Uload16, I32, builder, state, environ)?;
+        Operator::I32Load16U { memarg } => {
+            translate_load(memarg, ir::Opcode::Uload16, I32, builder, state, environ)?;
         }
-        Operator::I32Load8S {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Sload8, I32, builder, state, environ)?;
+        Operator::I32Load8S { memarg } => {
+            translate_load(memarg, ir::Opcode::Sload8, I32, builder, state, environ)?;
         }
-        Operator::I32Load16S {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Sload16, I32, builder, state, environ)?;
+        Operator::I32Load16S { memarg } => {
+            translate_load(memarg, ir::Opcode::Sload16, I32, builder, state, environ)?;
         }
-        Operator::I64Load8U {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Uload8, I64, builder, state, environ)?;
+        Operator::I64Load8U { memarg } => {
+            translate_load(memarg, ir::Opcode::Uload8, I64, builder, state, environ)?;
         }
-        Operator::I64Load16U {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Uload16, I64, builder, state, environ)?;
+        Operator::I64Load16U { memarg } => {
+            translate_load(memarg, ir::Opcode::Uload16, I64, builder, state, environ)?;
         }
-        Operator::I64Load8S {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Sload8, I64, builder, state, environ)?;
+        Operator::I64Load8S { memarg } => {
+            translate_load(memarg, ir::Opcode::Sload8, I64, builder, state, environ)?;
         }
-        Operator::I64Load16S {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Sload16, I64, builder, state, environ)?;
+        Operator::I64Load16S { memarg } => {
+            translate_load(memarg, ir::Opcode::Sload16, I64, builder, state, environ)?;
         }
-        Operator::I64Load32S {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Sload32, I64, builder, state, environ)?;
+        Operator::I64Load32S { memarg } => {
+            translate_load(memarg, ir::Opcode::Sload32, I64, builder, state, environ)?;
         }
-        Operator::I64Load32U {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Uload32, I64, builder, state, environ)?;
+        Operator::I64Load32U { memarg } => {
+            translate_load(memarg, ir::Opcode::Uload32, I64, builder, state, environ)?;
         }
-        Operator::I32Load {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Load, I32, builder, state, environ)?;
+        Operator::I32Load { memarg } => {
+            translate_load(memarg, ir::Opcode::Load, I32, builder, state, environ)?;
         }
-        Operator::F32Load {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Load, F32, builder, state, environ)?;
+        Operator::F32Load { memarg } => {
+            translate_load(memarg, ir::Opcode::Load, F32, builder, state, environ)?;
         }
-        Operator::I64Load {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Load, I64, builder, state, environ)?;
+        Operator::I64Load { memarg } => {
+            translate_load(memarg, ir::Opcode::Load, I64, builder, state, environ)?;
         }
-        Operator::F64Load {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Load, F64, builder, state, environ)?;
+        Operator::F64Load { memarg } => {
+            translate_load(memarg, ir::Opcode::Load, F64, builder, state, environ)?;
         }
-        Operator::V128Load {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(*offset, ir::Opcode::Load, I8X16, builder, state, environ)?;
+        Operator::V128Load { memarg } => {
+            translate_load(memarg, ir::Opcode::Load, I8X16, builder, state, environ)?;
         }
-        Operator::I16x8Load8x8S {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            let (flags, base, offset) = prepare_load(*offset, 8, builder, state, environ)?;
+        Operator::I16x8Load8x8S { memarg } => {
+            let (flags, base, offset, _) = prepare_load(memarg, 8, builder, state, environ)?;
             let loaded = builder.ins().sload8x8(flags, base, offset);
             state.push1(loaded);
         }
-        Operator::I16x8Load8x8U {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            let (flags, base, offset) = prepare_load(*offset, 8, builder, state, environ)?;
+        Operator::I16x8Load8x8U { memarg } => {
+            let (flags, base, offset, _) = prepare_load(memarg, 8, builder, state, environ)?;
             let loaded = builder.ins().uload8x8(flags, base, offset);
             state.push1(loaded);
         }
-        Operator::I32x4Load16x4S {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            let (flags, base, offset) = prepare_load(*offset, 8, builder, state, environ)?;
+        Operator::I32x4Load16x4S { memarg } => {
+            let (flags, base, offset, _) = prepare_load(memarg, 8, builder, state, environ)?;
             let loaded = builder.ins().sload16x4(flags, base, offset);
             state.push1(loaded);
         }
-        Operator::I32x4Load16x4U {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            let (flags, base, offset) = prepare_load(*offset, 8, builder, state, environ)?;
+        Operator::I32x4Load16x4U { memarg } => {
+            let (flags, base, offset, _) = prepare_load(memarg, 8, builder, state, environ)?;
             let loaded = builder.ins().uload16x4(flags, base, offset);
             state.push1(loaded);
         }
-        Operator::I64x2Load32x2S {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            let (flags, base, offset) = prepare_load(*offset, 8, builder, state, environ)?;
+        Operator::I64x2Load32x2S { memarg } => {
+            let (flags, base, offset, _) = prepare_load(memarg, 8, builder, state, environ)?;
             let loaded = builder.ins().sload32x2(flags, base, offset);
             state.push1(loaded);
         }
-        Operator::I64x2Load32x2U {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            let (flags, base, offset) = prepare_load(*offset, 8, builder, state, environ)?;
+        Operator::I64x2Load32x2U { memarg } => {
+            let (flags, base, offset, _) = prepare_load(memarg, 8, builder, state, environ)?;
             let loaded = builder.ins().uload32x2(flags, base, offset);
             state.push1(loaded);
         }
@@ -704,45 +668,23 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
          * Wasm specifies an integer alignment flag but we drop it in Cranelift.
          * The memory base address is provided by the environment.
          ************************************************************************************/
-        Operator::I32Store {
-            memarg: MemoryImmediate { flags: _, offset },
+        Operator::I32Store { memarg }
+        | Operator::I64Store { memarg }
+        | Operator::F32Store { memarg }
+        | Operator::F64Store { memarg } => {
+            translate_store(memarg, ir::Opcode::Store, builder, state, environ)?;
         }
-        | Operator::I64Store {
-            memarg: MemoryImmediate { flags: _, offset },
+        Operator::I32Store8 { memarg } | Operator::I64Store8 { memarg } => {
+            translate_store(memarg, ir::Opcode::Istore8, builder, state, environ)?;
         }
-        | Operator::F32Store {
-            memarg: MemoryImmediate { flags: _, offset },
+        Operator::I32Store16 { memarg } | Operator::I64Store16 { memarg } => {
+            translate_store(memarg, ir::Opcode::Istore16, builder, state, environ)?;
         }
-        | Operator::F64Store {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_store(*offset, ir::Opcode::Store, builder, state, environ)?;
-        }
-        Operator::I32Store8 {
-            memarg: MemoryImmediate { flags: _, offset },
-        }
-        | Operator::I64Store8 {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_store(*offset, ir::Opcode::Istore8, builder, state, environ)?;
-        }
-        Operator::I32Store16 {
-            memarg: MemoryImmediate { flags: _, offset },
+        Operator::I64Store32 { memarg } => {
+            translate_store(memarg, ir::Opcode::Istore32, builder, state, environ)?;
         }
-        | Operator::I64Store16 {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_store(*offset, ir::Opcode::Istore16, builder, state, environ)?;
-        }
-        Operator::I64Store32 {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_store(*offset, ir::Opcode::Istore32, builder, state, environ)?;
-        }
-        Operator::V128Store {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_store(*offset, ir::Opcode::Store, builder, state, environ)?;
+        Operator::V128Store { memarg } => {
+            translate_store(memarg, ir::Opcode::Store, builder, state, environ)?;
         }
         /****************************** Nullary Operators ************************************/
         Operator::I32Const { value } => state.push1(builder.ins().iconst(I32, i64::from(*value))),
@@ -1259,23 +1201,15 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             let splatted = builder.ins().splat(type_of(op), state.pop1());
             state.push1(splatted)
         }
-        Operator::V8x16LoadSplat {
-            memarg: MemoryImmediate { flags: _, offset },
-        }
-        | Operator::V16x8LoadSplat {
-            memarg: MemoryImmediate { flags: _, offset },
-        }
-        | Operator::V32x4LoadSplat {
-            memarg: MemoryImmediate { flags: _, offset },
-        }
-        | Operator::V64x2LoadSplat {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
+        Operator::V8x16LoadSplat { memarg }
+        | Operator::V16x8LoadSplat { memarg }
+        | Operator::V32x4LoadSplat { memarg }
+        | Operator::V64x2LoadSplat { memarg } => {
             // TODO: For spec compliance, this is initially implemented as a combination of `load +
             // splat` but could be implemented eventually as a single instruction (`load_splat`).
             // See https://github.com/bytecodealliance/wasmtime/issues/1175.
             translate_load(
-                *offset,
+                memarg,
                 ir::Opcode::Load,
                 type_of(op).lane_type(),
                 builder,
@@ -1707,14 +1641,24 @@ fn translate_unreachable_operator<FE: FuncEnvironment + ?Sized>(
 }
 
 /// Get the address+offset to use for a heap access.
+///
+/// The offset is 64 bits wide to leave room for the offsets of 64-bit memories, even though the
+/// version of wasmparser we use only decodes 32-bit ones. `heap_addr` only checks 32-bit sizes,
+/// so wider offsets are unsupported.
 fn get_heap_addr(
     heap: ir::Heap,
     addr32: ir::Value,
-    offset: u32,
+    offset: u64,
     width: u32,
     addr_ty: Type,
     builder: &mut FunctionBuilder,
-) -> (ir::Value, i32) {
+) -> WasmResult<(ir::Value, i32)> {
+    if u32::try_from(offset).is_err() {
+        return Err(wasm_unsupported!(
+            "memory access offset {:#x} wider than 32 bits",
+            offset
+        ));
+    }
     let offset_guard_size: u64 = builder.func.heaps[heap].offset_guard_size.into();
 
     // How exactly the bounds check is performed here and what it's performed
@@ -1768,66 +1712,81 @@ fn get_heap_addr(
     // offsets we're checking here are zero. This means that we'll hit the fast
     // path and emit zero conditional traps for bounds checks
     let adjusted_offset = if offset_guard_size == 0 {
-        u64::from(offset) + u64::from(width)
+        offset.saturating_add(u64::from(width))
     } else {
         assert!(width < 1024);
-        cmp::max(u64::from(offset) / offset_guard_size * offset_guard_size, 1)
+        cmp::max(offset / offset_guard_size * offset_guard_size, 1)
     };
     debug_assert!(adjusted_offset > 0); // want to bounds check at least 1 byte
+
+    // Only a 32-bit offset plus the width can overflow here. Checking `u32::MAX` bytes instead
+    // always fails for heaps of at most 4 GiB, as checking all of them would.
     let check_size = u32::try_from(adjusted_offset).unwrap_or(u32::MAX);
     let base = builder.ins().heap_addr(addr_ty, heap, addr32, check_size);
 
     // Native load/store instructions take a signed `Offset32` immediate, so adjust the base
-    // pointer if necessary.
-    if offset > i32::MAX as u32 {
+    // pointer by whatever doesn't fit in it. Blade folds this constant adjustment back into the
+    // offset when it masks the address, so it must not be truncated to 32 bits.
+    let low = offset & i32::MAX as u64;
+    if low != offset {
         // Offset doesn't fit in the load/store instruction.
-        let adj = builder.ins().iadd_imm(base, i64::from(i32::MAX) + 1);
-        (adj, (offset - (i32::MAX as u32 + 1)) as i32)
+        let adj = builder.ins().iadd_imm(base, (offset - low) as i64);
+        Ok((adj, low as i32))
     } else {
-        (base, offset as i32)
+        Ok((base, offset as i32))
     }
 }
 
+/// Get the index of the linear memory accessed through `memarg`.
+///
+/// The multi-memory proposal adds a memory index to `memarg`, but the version of wasmparser we
+/// use doesn't decode it yet, so every access is to the first memory for now. `translate_module`
+/// rejects the modules with more than one memory, whose accesses this would misdirect.
+fn memory_index(_memarg: &MemoryImmediate) -> u32 {
+    0
+}
+
 /// Prepare for a load; factors out common functionality between load and load_extend operations.
+///
+/// Also returns the heap of the accessed memory, whose bounds the address is checked against.
 fn prepare_load<FE: FuncEnvironment + ?Sized>(
-    offset: u32,
+    memarg: &MemoryImmediate,
     loaded_bytes: u32,
     builder: &mut FunctionBuilder,
     state: &mut FuncTranslationState,
     environ: &mut FE,
-) -> WasmResult<(MemFlags, Value, Offset32)> {
+) -> WasmResult<(MemFlags, Value, Offset32, ir::Heap)> {
     let addr32 = state.pop1();
 
-    // We don't yet support multiple linear memories.
-    let heap = state.get_heap(builder.func, 0, environ)?;
+    let heap = state.get_heap(builder.func, memory_index(memarg), environ)?;
     let (base, offset) = get_heap_addr(
         heap,
         addr32,
-        offset,
+        u64::from(memarg.offset),
         loaded_bytes,
         environ.pointer_type(),
         builder,
-    );
+    )?;
 
     // Note that we don't set `is_aligned` here, even if the load instruction's
     // alignment immediate says it's aligned, because WebAssembly's immediate
     // field is just a hint, while Cranelift's aligned flag needs a guarantee.
     let flags = MemFlags::new();
 
-    Ok((flags, base, offset.into()))
+    Ok((flags, base, offset.into(), heap))
 }
 
 /// Translate a load instruction.
 fn translate_load<FE: FuncEnvironment + ?Sized>(
-    offset: u32,
+    memarg: &MemoryImmediate,
     opcode: ir::Opcode,
     result_ty: Type,
     builder: &mut FunctionBuilder,
     state: &mut FuncTranslationState,
     environ: &mut FE,
 ) -> WasmResult<()> {
-    let (flags, base, offset) = prepare_load(
-        offset,
+    let (flags, base, offset, heap) = prepare_load(
+        memarg,
         mem_op_size(opcode, result_ty),
         builder,
         state,
@@ -1835,12 +1794,13 @@ fn translate_load<FE: FuncEnvironment + ?Sized>(
     )?;
     let (load, dfg) = builder.ins().Load(opcode, result_ty, flags, offset, base);
     state.push1(dfg.first_result(load));
+    builder.func.blade_class[load] = environ.blade_classify_heap_access(heap);
     Ok(())
 }
 
 /// Translate a store instruction.
 fn translate_store<FE: FuncEnvironment + ?Sized>(
-    offset: u32,
+    memarg: &MemoryImmediate,
     opcode: ir::Opcode,
     builder: &mut FunctionBuilder,
     state: &mut FuncTranslationState,
@@ -1849,21 +1809,21 @@ fn translate_store<FE: FuncEnvironment + ?Sized>(
     let (addr32, val) = state.pop2();
     let val_ty = builder.func.dfg.value_type(val);
 
-    // We don't yet support multiple linear memories.
-    let heap = state.get_heap(builder.func, 0, environ)?;
+    let heap = state.get_heap(builder.func, memory_index(memarg), environ)?;
     let (base, offset) = get_heap_addr(
         heap,
         addr32,
-        offset,
+        u64::from(memarg.offset),
         mem_op_size(opcode, val_ty),
         environ.pointer_type(),
         builder,
-    );
+    )?;
     // See the comments in `translate_load` about the flags.
     let flags = MemFlags::new();
-    builder
+    let (store, _) = builder
         .ins()
         .Store(opcode, val_ty, flags, offset.into(), val, base);
+    builder.func.blade_class[store] = environ.blade_classify_heap_access(heap);
     Ok(())
 }
 
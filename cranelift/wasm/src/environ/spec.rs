@@ -436,6 +436,25 @@ pub trait FuncEnvironment: TargetEnvironment {
         val: ir::Value,
     ) -> WasmResult<()>;
 
+    /// Classify the accesses to `heap` emitted by wasm loads and stores for Blade.
+    ///
+    /// `heap` is the heap of the memory named by the `memarg` of each access, as returned by
+    /// `make_heap`.
+    ///
+    /// By default, Blade classifies them from their opcodes: loads produce transient values, and
+    /// the addresses and values of loads and stores are sinks.
+    fn blade_classify_heap_access(&self, _heap: ir::Heap) -> ir::BladeClass {
+        ir::BladeClass::Default
+    }
+
+    /// Classify the loads and stores emitted by `global.get` and `global.set` for Blade, for a
+    /// global that lives in memory.
+    ///
+    /// By default, Blade classifies them from their opcodes, like heap accesses.
+    fn blade_classify_global_access(&self, _global_index: GlobalIndex) -> ir::BladeClass {
+        ir::BladeClass::Default
+    }
+
     /// Emit code at the beginning of every wasm loop.
     ///
     /// This can be used to insert explicit interrupt or safepoint checking at
@@ -7,6 +7,7 @@ use crate::sections_translator::{
     parse_name_section, parse_start_section, parse_table_section, parse_type_section,
 };
 use crate::state::ModuleTranslationState;
+use crate::wasm_unsupported;
 use cranelift_codegen::timing;
 use wasmparser::{CustomSectionContent, ModuleReader, SectionContent};
 
@@ -19,6 +20,9 @@ pub fn translate_module<'data>(
     let _tt = timing::wasm_translate_module();
     let mut reader = ModuleReader::new(data)?;
     let mut module_translation_state = ModuleTranslationState::new();
+    // Memory accesses are always translated as accesses to the first memory, see
+    // `code_translator::memory_index`, so any other memory is unsupported.
+    let mut memories = 0;
 
     while !reader.eof() {
         let section = reader.read()?;
@@ -28,7 +32,8 @@ pub fn translate_module<'data>(
             }
 
             SectionContent::Import(imports) => {
-                parse_import_section(imports, environ)?;
+                memories = memories.saturating_add(parse_import_section(imports, environ)?);
+                check_memories(memories)?;
             }
 
             SectionContent::Function(functions) => {
@@ -39,8 +44,10 @@ pub fn translate_module<'data>(
                 parse_table_section(tables, environ)?;
             }
 
-            SectionContent::Memory(memories) => {
-                parse_memory_section(memories, environ)?;
+            SectionContent::Memory(section) => {
+                memories = memories.saturating_add(section.get_count());
+                check_memories(memories)?;
+                parse_memory_section(section, environ)?;
             }
 
             SectionContent::Global(globals) => {
@@ -91,3 +98,14 @@ pub fn translate_module<'data>(
 
     Ok(module_translation_state)
 }
+
+/// Check that a module declaring `memories` memories can be translated.
+fn check_memories(memories: u32) -> WasmResult<()> {
+    if memories > 1 {
+        return Err(wasm_unsupported!(
+            "{} memories: only modules with a single memory are supported",
+            memories
+        ));
+    }
+    Ok(())
+}
@@ -60,12 +60,13 @@ pub fn parse_type_section(
     Ok(())
 }
 
-/// Parses the Import section of the wasm module.
+/// Parses the Import section of the wasm module, returning the number of memories it imports.
 pub fn parse_import_section<'data>(
     imports: ImportSectionReader<'data>,
     environ: &mut dyn ModuleEnvironment<'data>,
-) -> WasmResult<()> {
+) -> WasmResult<u32> {
     environ.reserve_imports(imports.get_count())?;
+    let mut memories = 0;
 
     for entry in imports {
         let import = entry?;
@@ -84,6 +85,7 @@ pub fn parse_import_section<'data>(
                 limits: ref memlimits,
                 shared,
             }) => {
+                memories += 1;
                 environ.declare_memory_import(
                     Memory {
                         minimum: memlimits.initial,
@@ -123,7 +125,7 @@ pub fn parse_import_section<'data>(
     }
 
     environ.finish_imports()?;
-    Ok(())
+    Ok(memories)
 }
 
 /// Parses the Function section of the wasm module.
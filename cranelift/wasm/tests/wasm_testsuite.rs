@@ -2,7 +2,7 @@ use cranelift_codegen::isa;
 use cranelift_codegen::print_errors::pretty_verifier_error;
 use cranelift_codegen::settings::{self, Flags};
 use cranelift_codegen::verifier;
-use cranelift_wasm::{translate_module, DummyEnvironment, FuncIndex, ReturnMode};
+use cranelift_wasm::{translate_module, DummyEnvironment, FuncIndex, ReturnMode, WasmError};
 use std::fs;
 use std::fs::File;
 use std::io;
@@ -69,6 +69,31 @@ fn use_name_section() {
     );
 }
 
+#[test]
+fn multiple_memories_unsupported() {
+    let flags = Flags::new(settings::builder());
+    let triple = triple!("riscv64");
+    let isa = isa::lookup(triple).unwrap().finish(flags.clone());
+    let translate = |wat: &str| {
+        let data = wat::parse_str(wat).unwrap();
+        let mut dummy_environ =
+            DummyEnvironment::new(isa.frontend_config(), ReturnMode::NormalReturns, false);
+        translate_module(data.as_ref(), &mut dummy_environ).map(|_| ())
+    };
+
+    assert!(translate(r#"(module (import "env" "mem" (memory 1)))"#).is_ok());
+    for wat in &[
+        r#"(module (memory 1) (memory 1))"#,
+        r#"(module (import "env" "mem" (memory 1)) (memory 1))"#,
+        r#"(module (import "env" "a" (memory 1)) (import "env" "b" (memory 1)))"#,
+    ] {
+        match translate(wat) {
+            Err(WasmError::Unsupported(_)) => {}
+            result => panic!("unexpected result {:?} for {}", result, wat),
+        }
+    }
+}
+
 fn read_file(path: &Path) -> io::Result<Vec<u8>> {
     let mut buf: Vec<u8> = Vec::new();
     let mut file = File::open(path)?;
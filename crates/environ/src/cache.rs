@@ -84,7 +84,9 @@ impl<'config> ModuleCacheEntry<'config> {
             None => return compute(state).map(ModuleCacheData::from_tuple),
         };
 
-        if let Some(cached_val) = inner.get_data(&hash) {
+        if let Some(mut cached_val) = inner.get_data(&hash) {
+            // The cached timings are those of the compilation which filled the cache.
+            cached_val.compilation.clear_blade_times();
             let mod_cache_path = inner.root_path.join(&hash);
             inner.cache_config.on_cache_get_async(&mod_cache_path); // call on success
             return Ok(cached_val);
@@ -1,7 +1,10 @@
 use super::config::tests::test_prolog;
 use super::*;
-use cranelift_entity::PrimaryMap;
+use crate::compilation::CompiledFunction;
+use cranelift_codegen::BladeStats;
+use cranelift_entity::{EntityRef, PrimaryMap};
 use std::fs;
+use std::time::Duration;
 
 // Since cache system is a global thing, each test needs to be run in seperate process.
 // So, init() tests are run as integration tests.
@@ -92,6 +95,58 @@ fn test_write_read_cache() {
     entry2.get_data::<_, i32>(1, |_| panic!()).unwrap();
 }
 
+#[test]
+fn test_cached_blade_times() {
+    let (_tempdir, cache_dir, config_path) = test_prolog();
+    let cache_config = load_config!(
+        config_path,
+        "[cache]\n\
+         enabled = true\n\
+         directory = {cache_dir}\n",
+        cache_dir
+    );
+    let entry = ModuleCacheEntry::from_inner(ModuleCacheEntryInner::new("test", &cache_config));
+
+    fn timed_module_cache_data(_: i32) -> Result<ModuleCacheDataTupleType, ()> {
+        let mut data = new_module_cache_data()?;
+        let mut functions = PrimaryMap::new();
+        functions.push(CompiledFunction {
+            body: vec![0xc3],
+            jt_offsets: Default::default(),
+            unwind_info: None,
+            blade_stats: BladeStats {
+                fell_back: true,
+                time: Duration::from_millis(5),
+                solver_time: Duration::from_millis(2),
+                ..BladeStats::default()
+            },
+            calls: Default::default(),
+        });
+        data.0 = Compilation::new(functions);
+        Ok(data)
+    }
+
+    // The compilation which fills the cache reports its own timings...
+    let (compiled, ..) = entry
+        .get_data(1, timed_module_cache_data)
+        .unwrap()
+        .into_tuple();
+    let stats = &compiled.get(DefinedFuncIndex::new(0)).blade_stats;
+    assert_eq!(stats.time, Duration::from_millis(5));
+    assert_eq!(stats.solver_time, Duration::from_millis(2));
+
+    // ...but those loading it don't report them again.
+    let (cached, ..) = entry
+        .get_data::<_, ()>(1, |_| panic!())
+        .unwrap()
+        .into_tuple();
+    let stats = &cached.get(DefinedFuncIndex::new(0)).blade_stats;
+    assert_eq!(stats.time, Duration::default());
+    assert_eq!(stats.solver_time, Duration::default());
+    assert!(stats.fell_back);
+    assert_eq!(cached.get(DefinedFuncIndex::new(0)).body, [0xc3]);
+}
+
 fn new_module_cache_data() -> Result<ModuleCacheDataTupleType, ()> {
     Ok((
         Compilation::new(PrimaryMap::new()),
@@ -4,14 +4,16 @@
 use crate::cache::ModuleCacheDataTupleType;
 use crate::CacheConfig;
 use crate::ModuleTranslation;
-use cranelift_codegen::{binemit, ir, isa, isa::unwind::UnwindInfo};
+use cranelift_codegen::{binemit, ir, isa, isa::unwind::UnwindInfo, BladeCostEstimate, BladeStats};
 use cranelift_entity::PrimaryMap;
 use cranelift_wasm::{DefinedFuncIndex, FuncIndex, WasmError};
 use serde::{Deserialize, Serialize};
 use std::ops::Range;
+use std::time::Duration;
 use thiserror::Error;
 
-/// Compiled function: machine code body, jump table offsets, and unwind information.
+/// Compiled function: machine code body, jump table offsets, unwind information, and statistics
+/// about its Spectre mitigations.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct CompiledFunction {
     /// The function body.
@@ -22,6 +24,24 @@ pub struct CompiledFunction {
 
     /// The unwind information.
     pub unwind_info: Option<UnwindInfo>,
+
+    /// The statistics of the Blade Spectre mitigations inserted in the function.
+    pub blade_stats: BladeStats,
+
+    /// The calls the function makes.
+    pub calls: FunctionCalls,
+}
+
+/// The calls a compiled function makes, so that the Blade contract of every
+/// caller can be checked against those of its callees.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionCalls {
+    /// The functions called directly, imported or defined, in index order and
+    /// without duplicates.
+    pub direct: Vec<FuncIndex>,
+
+    /// Whether the function also calls through tables.
+    pub indirect: bool,
 }
 
 type Functions = PrimaryMap<DefinedFuncIndex, CompiledFunction>;
@@ -51,6 +71,8 @@ impl Compilation {
                     body: buffer[body_range].to_vec(),
                     jt_offsets,
                     unwind_info: None, // not implemented for lightbeam currently
+                    blade_stats: BladeStats::default(),
+                    calls: FunctionCalls::default(),
                 })
                 .collect(),
         )
@@ -78,6 +100,58 @@ impl Compilation {
             .map(|(_, func)| func.jt_offsets.clone())
             .collect::<PrimaryMap<DefinedFuncIndex, _>>()
     }
+
+    /// Overwrites every speculation barrier that Blade placed in the function
+    /// bodies with `nop`, which must be as long as a barrier.
+    ///
+    /// Returns the number of barriers patched out.
+    pub fn patch_blade_barriers(&mut self, nop: &[u8]) -> usize {
+        assert_eq!(nop.len(), binemit::LFENCE_SIZE as usize);
+        let mut patched = 0;
+        for (_, func) in self.functions.iter_mut() {
+            for &offset in &func.blade_stats.barrier_offsets {
+                let offset = offset as usize;
+                func.body[offset..offset + nop.len()].copy_from_slice(nop);
+                patched += 1;
+            }
+        }
+        patched
+    }
+
+    /// Zeroes the time spent in Blade by every function, which only the
+    /// compilation that measured it can report, and not one which loads the
+    /// functions from the cache.
+    pub fn clear_blade_times(&mut self) {
+        for (_, func) in self.functions.iter_mut() {
+            func.blade_stats.time = Duration::default();
+            func.blade_stats.solver_time = Duration::default();
+        }
+    }
+
+    /// Gets the static estimate of the run-time cost of the Blade mitigations
+    /// of every function together, see `BladeCostEstimate::slowdown`.
+    pub fn blade_estimate(&self) -> BladeCostEstimate {
+        self.functions
+            .values()
+            .map(|func| func.blade_stats.estimate)
+            .sum()
+    }
+
+    /// Gets functions Blade statistics.
+    pub fn get_blade_stats(&self) -> PrimaryMap<DefinedFuncIndex, BladeStats> {
+        self.functions
+            .iter()
+            .map(|(_, func)| func.blade_stats.clone())
+            .collect::<PrimaryMap<DefinedFuncIndex, _>>()
+    }
+
+    /// Gets the calls each function makes.
+    pub fn get_calls(&self) -> PrimaryMap<DefinedFuncIndex, FunctionCalls> {
+        self.functions
+            .iter()
+            .map(|(_, func)| func.calls.clone())
+            .collect::<PrimaryMap<DefinedFuncIndex, _>>()
+    }
 }
 
 impl<'a> IntoIterator for &'a Compilation {
@@ -96,12 +96,13 @@ use crate::{CacheConfig, FunctionBodyData, ModuleLocal, ModuleTranslation, Tunab
 use cranelift_codegen::ir::{self, ExternalName};
 use cranelift_codegen::machinst::buffer::MachSrcLoc;
 use cranelift_codegen::print_errors::pretty_error;
-use cranelift_codegen::{binemit, isa, Context};
+use cranelift_codegen::{binemit, isa, BladeCutCache, Context};
 use cranelift_entity::PrimaryMap;
 use cranelift_wasm::{DefinedFuncIndex, FuncIndex, FuncTranslator, ModuleTranslationState};
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use std::convert::TryFrom;
 use std::hash::{Hash, Hasher};
+use std::mem;
 
 /// Implementation of a relocation sink that just saves all the information for later
 pub struct RelocSink {
@@ -292,6 +293,19 @@ impl crate::compilation::Compiler for Cranelift {
     ) -> Result<ModuleCacheDataTupleType, CompileError> {
         let cache_entry = ModuleCacheEntry::new("cranelift", cache_config);
 
+        // The names of functions aren't part of the cache key, so key on the functions the
+        // Blade filter selects instead.
+        let module = &translation.module;
+        let skip_blade = translation
+            .function_body_inputs
+            .keys()
+            .map(|i| {
+                let index = module.local.func_index(i);
+                let name = module.func_names.get(&index).map(String::as_str);
+                !translation.tunables.blade_filter.includes(index, name)
+            })
+            .collect();
+
         let data = cache_entry.get_data(
             CompileEnv {
                 local: &translation.module.local,
@@ -301,6 +315,7 @@ impl crate::compilation::Compiler for Cranelift {
                 function_body_inputs: &translation.function_body_inputs,
                 isa: Isa(isa),
                 tunables: &translation.tunables,
+                skip_blade,
             },
             compile,
         )?;
@@ -322,101 +337,113 @@ fn compile(env: CompileEnv<'_>) -> Result<ModuleCacheDataTupleType, CompileError
         .into_iter()
         .collect::<Vec<(DefinedFuncIndex, &FunctionBodyData<'_>)>>()
         .par_iter()
-        .map_init(FuncTranslator::new, |func_translator, (i, input)| {
-            let func_index = env.local.func_index(*i);
-            let mut context = Context::new();
-            context.func.name = get_func_name(func_index);
-            context.func.signature = env.local.native_func_signature(func_index).clone();
-            if env.tunables.debug_info {
-                context.func.collect_debug_info();
-            }
-
-            let mut func_env = FuncEnvironment::new(isa.frontend_config(), env.local, env.tunables);
-
-            // We use these as constant offsets below in
-            // `stack_limit_from_arguments`, so assert their values here. This
-            // allows the closure below to get coerced to a function pointer, as
-            // needed by `ir::Function`.
-            //
-            // Otherwise our stack limit is specially calculated from the vmctx
-            // argument, where we need to load the `*const VMInterrupts`
-            // pointer, and then from that pointer we need to load the stack
-            // limit itself. Note that manual register allocation is needed here
-            // too due to how late in the process this codegen happens.
-            //
-            // For more information about interrupts and stack checks, see the
-            // top of this file.
-            let vmctx = context
-                .func
-                .create_global_value(ir::GlobalValueData::VMContext);
-            let interrupts_ptr = context.func.create_global_value(ir::GlobalValueData::Load {
-                base: vmctx,
-                offset: i32::try_from(func_env.offsets.vmctx_interrupts())
-                    .unwrap()
-                    .into(),
-                global_type: isa.pointer_type(),
-                readonly: true,
-            });
-            let stack_limit = context.func.create_global_value(ir::GlobalValueData::Load {
-                base: interrupts_ptr,
-                offset: i32::try_from(func_env.offsets.vminterrupts_stack_limit())
-                    .unwrap()
-                    .into(),
-                global_type: isa.pointer_type(),
-                readonly: false,
-            });
-            context.func.stack_limit = Some(stack_limit);
-            func_translator.translate(
-                env.module_translation.0,
-                input.data,
-                input.module_offset,
-                &mut context.func,
-                &mut func_env,
-            )?;
-
-            let mut code_buf: Vec<u8> = Vec::new();
-            let mut reloc_sink = RelocSink::new(func_index);
-            let mut trap_sink = TrapSink::new();
-            let mut stack_map_sink = StackMapSink::default();
-            context
-                .compile_and_emit(
+        .map_init(
+            || (FuncTranslator::new(), BladeCutCache::new()),
+            |(func_translator, cut_cache), (i, input)| {
+                let func_index = env.local.func_index(*i);
+                let mut context = Context::new();
+                context.set_skip_blade(env.skip_blade[*i]);
+                context.func.name = get_func_name(func_index);
+                context.func.signature = env.local.native_func_signature(func_index).clone();
+                if env.tunables.debug_info {
+                    context.func.collect_debug_info();
+                }
+
+                let mut func_env =
+                    FuncEnvironment::new(isa.frontend_config(), env.local, env.tunables);
+
+                // We use these as constant offsets below in
+                // `stack_limit_from_arguments`, so assert their values here. This
+                // allows the closure below to get coerced to a function pointer, as
+                // needed by `ir::Function`.
+                //
+                // Otherwise our stack limit is specially calculated from the vmctx
+                // argument, where we need to load the `*const VMInterrupts`
+                // pointer, and then from that pointer we need to load the stack
+                // limit itself. Note that manual register allocation is needed here
+                // too due to how late in the process this codegen happens.
+                //
+                // For more information about interrupts and stack checks, see the
+                // top of this file.
+                let vmctx = context
+                    .func
+                    .create_global_value(ir::GlobalValueData::VMContext);
+                let interrupts_ptr = context.func.create_global_value(ir::GlobalValueData::Load {
+                    base: vmctx,
+                    offset: i32::try_from(func_env.offsets.vmctx_interrupts())
+                        .unwrap()
+                        .into(),
+                    global_type: isa.pointer_type(),
+                    readonly: true,
+                });
+                let stack_limit = context.func.create_global_value(ir::GlobalValueData::Load {
+                    base: interrupts_ptr,
+                    offset: i32::try_from(func_env.offsets.vminterrupts_stack_limit())
+                        .unwrap()
+                        .into(),
+                    global_type: isa.pointer_type(),
+                    readonly: false,
+                });
+                context.func.stack_limit = Some(stack_limit);
+                func_translator.translate(
+                    env.module_translation.0,
+                    input.data,
+                    input.module_offset,
+                    &mut context.func,
+                    &mut func_env,
+                )?;
+
+                let mut code_buf: Vec<u8> = Vec::new();
+                let mut reloc_sink = RelocSink::new(func_index);
+                let mut trap_sink = TrapSink::new();
+                let mut stack_map_sink = StackMapSink::default();
+                // Thunks and accessors often repeat throughout a module, so share the cuts of their
+                // Blade graphs between the functions compiled on this thread. The cache is handed
+                // back even if the function fails to compile, for the next one.
+                context.set_blade_cut_cache(mem::take(cut_cache));
+                let result = context.compile_and_emit(
                     isa,
                     &mut code_buf,
                     &mut reloc_sink,
                     &mut trap_sink,
                     &mut stack_map_sink,
-                )
-                .map_err(|error| {
+                );
+                *cut_cache = context.blade_cut_cache.take().unwrap_or_default();
+                result.map_err(|error| {
                     CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
                 })?;
 
-            let unwind_info = context.create_unwind_info(isa).map_err(|error| {
-                CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
-            })?;
-
-            let address_transform = get_function_address_map(&context, input, code_buf.len(), isa);
-
-            let ranges = if env.tunables.debug_info {
-                let ranges = context.build_value_labels_ranges(isa).map_err(|error| {
+                let unwind_info = context.create_unwind_info(isa).map_err(|error| {
                     CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
                 })?;
-                Some(ranges)
-            } else {
-                None
-            };
-
-            Ok((
-                code_buf,
-                context.func.jt_offsets,
-                reloc_sink.func_relocs,
-                address_transform,
-                ranges,
-                context.func.stack_slots,
-                trap_sink.traps,
-                unwind_info,
-                stack_map_sink.finish(),
-            ))
-        })
+
+                let address_transform =
+                    get_function_address_map(&context, input, code_buf.len(), isa);
+
+                let ranges = if env.tunables.debug_info {
+                    let ranges = context.build_value_labels_ranges(isa).map_err(|error| {
+                        CompileError::Codegen(pretty_error(&context.func, Some(isa), error))
+                    })?;
+                    Some(ranges)
+                } else {
+                    None
+                };
+
+                Ok((
+                    code_buf,
+                    context.func.jt_offsets,
+                    reloc_sink.func_relocs,
+                    address_transform,
+                    ranges,
+                    context.func.stack_slots,
+                    trap_sink.traps,
+                    unwind_info,
+                    stack_map_sink.finish(),
+                    context.blade_stats,
+                    func_env.calls,
+                ))
+            },
+        )
         .collect::<Result<Vec<_>, CompileError>>()?
         .into_iter()
         .for_each(
@@ -430,11 +457,15 @@ fn compile(env: CompileEnv<'_>) -> Result<ModuleCacheDataTupleType, CompileError
                 function_traps,
                 unwind_info,
                 stack_map,
+                blade_stats,
+                calls,
             )| {
                 functions.push(CompiledFunction {
                     body: function,
                     jt_offsets: func_jt_offsets,
                     unwind_info,
+                    blade_stats,
+                    calls,
                 });
                 relocations.push(relocs);
                 address_transforms.push(address_transform);
@@ -465,6 +496,8 @@ struct CompileEnv<'a> {
     function_body_inputs: &'a PrimaryMap<DefinedFuncIndex, FunctionBodyData<'a>>,
     isa: Isa<'a, 'a>,
     tunables: &'a Tunables,
+    /// Whether to leave each function out of the Blade pass.
+    skip_blade: PrimaryMap<DefinedFuncIndex, bool>,
 }
 
 /// This is a wrapper struct to hash the specific bits of `TargetIsa` that
@@ -481,6 +514,10 @@ impl Hash for Isa<'_, '_> {
         // a native hashing ability of flags into cranelift itself, but
         // compilation and/or cache loading is relatively expensive so seems
         // unlikely.
+        //
+        // This covers the Blade settings, which must be part of the key: the
+        // cached code embeds the Spectre mitigations they select, along with
+        // the report of where they are.
         self.0.flags().to_string().hash(hasher);
 
         // TODO: ... and should we hash anything else? There's a lot of stuff in
@@ -503,3 +540,65 @@ impl Hash for HashedModuleTranslationState<'_> {
         // nothing to hash right now
     }
 }
+
+#[cfg(test)]
+#[cfg(target_arch = "x86_64")]
+mod tests {
+    use super::Isa;
+    use cranelift_codegen::isa;
+    use cranelift_codegen::settings::{self, Configurable};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn isa_hash(settings: &[(&str, &str)]) -> u64 {
+        let mut flags = settings::builder();
+        for (name, value) in settings {
+            flags.set(name, value).unwrap();
+        }
+        let isa = isa::lookup_by_name("x86_64")
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+        let mut hasher = DefaultHasher::new();
+        Isa(&*isa).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn blade_settings_change_cache_key() {
+        let default = isa_hash(&[]);
+        assert_eq!(isa_hash(&[]), default);
+        assert_eq!(isa_hash(&[("blade", "none")]), default);
+
+        let mut hashes = vec![default];
+        for settings in &[
+            &[("blade", "lfence")][..],
+            &[("blade", "lfence_per_block")],
+            &[("blade", "slh")],
+            &[("blade", "slh"), ("blade_slh_fake_bounds", "true")],
+            &[("blade", "slh"), ("blade_slh_mask", "value")],
+            &[("blade", "slh"), ("blade_slh_size_budget", "50")],
+            &[("blade", "slh_with_fence_fallback")],
+            &[("blade", "lfence"), ("blade_placement", "early")],
+            &[("blade", "lfence"), ("blade_cut_bias", "sinks")],
+            &[("blade", "lfence"), ("blade_barrier", "mfence")],
+            &[("blade", "lfence"), ("blade_weight_fence_branch", "4")],
+            &[
+                ("blade", "lfence"),
+                ("blade_leakage_model", "memory_contents"),
+            ],
+            &[
+                ("blade", "lfence"),
+                ("blade_stack_pointer_accesses", "sandbox"),
+            ],
+            &[("blade_fence_after_calls", "true")],
+        ] {
+            let hash = isa_hash(settings);
+            assert!(
+                !hashes.contains(&hash),
+                "{:?} reuses the cache key of other settings",
+                settings
+            );
+            hashes.push(hash);
+        }
+    }
+}
@@ -3,12 +3,19 @@
 pub mod ir {
     pub use cranelift_codegen::binemit::Stackmap;
     pub use cranelift_codegen::ir::{
-        types, AbiParam, ArgumentPurpose, Signature, SourceLoc, StackSlots, TrapCode, Type,
+        types, AbiParam, ArgumentPurpose, Inst, Signature, SourceLoc, StackSlots, TrapCode, Type,
         ValueLabel, ValueLoc,
     };
     pub use cranelift_codegen::{ValueLabelsRanges, ValueLocRange};
 }
 
+pub mod blade {
+    pub use cranelift_codegen::{
+        check_blade_settings, BladeCapabilities, BladeContract, BladeCostEstimate,
+        BladeDiagnosticKind, BladeMitigation, BladeSite, BladeStats, FencePoint, MitigationKind,
+    };
+}
+
 pub mod settings {
     pub use cranelift_codegen::settings::{builder, Builder, Configurable, Flags, SetError};
 }
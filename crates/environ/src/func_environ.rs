@@ -1,6 +1,6 @@
 use crate::module::{MemoryPlan, MemoryStyle, ModuleLocal, TableStyle};
 use crate::vmoffsets::VMOffsets;
-use crate::{Tunables, INTERRUPTED, WASM_PAGE_SIZE};
+use crate::{FunctionCalls, Tunables, INTERRUPTED, WASM_PAGE_SIZE};
 use cranelift_codegen::cursor::FuncCursor;
 use cranelift_codegen::ir;
 use cranelift_codegen::ir::condcodes::*;
@@ -195,6 +195,9 @@ pub struct FuncEnvironment<'module_environment> {
     pub(crate) offsets: VMOffsets,
 
     tunables: &'module_environment Tunables,
+
+    /// The calls made by the function translated so far.
+    pub(crate) calls: FunctionCalls,
 }
 
 impl<'module_environment> FuncEnvironment<'module_environment> {
@@ -219,6 +222,7 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
             builtin_function_signatures,
             offsets: VMOffsets::new(target_config.pointer_bytes(), module),
             tunables,
+            calls: FunctionCalls::default(),
         }
     }
 
@@ -402,7 +406,7 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         // Load the callee address.
         let body_offset =
             i32::try_from(self.offsets.vmctx_builtin_function(callee_func_idx)).unwrap();
-        let func_addr = pos.ins().load(pointer_type, mem_flags, base, body_offset);
+        let func_addr = load_trusted(pos, pointer_type, mem_flags, base, body_offset);
 
         (base, func_addr)
     }
@@ -691,6 +695,12 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         ))
     }
 
+    fn blade_classify_global_access(&self, _global_index: GlobalIndex) -> ir::BladeClass {
+        // Globals live at fixed offsets from the VM context, or from the address of an
+        // imported global, which the guest can't steer.
+        ir::BladeClass::Global
+    }
+
     fn translate_custom_global_get(
         &mut self,
         _: cranelift_codegen::cursor::FuncCursor<'_>,
@@ -854,6 +864,7 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         callee: ir::Value,
         call_args: &[ir::Value],
     ) -> WasmResult<ir::Inst> {
+        self.calls.indirect = true;
         let pointer_type = self.pointer_type();
 
         let table_entry_addr = pos.ins().table_addr(pointer_type, table, callee, 0);
@@ -883,7 +894,7 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
                 // Load the caller ID.
                 let mut mem_flags = ir::MemFlags::trusted();
                 mem_flags.set_readonly();
-                let caller_sig_id = pos.ins().load(sig_id_type, mem_flags, base, offset);
+                let caller_sig_id = load_trusted(&mut pos, sig_id_type, mem_flags, base, offset);
 
                 // Load the callee ID.
                 let mem_flags = ir::MemFlags::trusted();
@@ -926,6 +937,9 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         callee: ir::FuncRef,
         call_args: &[ir::Value],
     ) -> WasmResult<ir::Inst> {
+        if let Err(i) = self.calls.direct.binary_search(&callee_index) {
+            self.calls.direct.insert(i, callee_index);
+        }
         let mut real_call_args = Vec::with_capacity(call_args.len() + 2);
         let caller_vmctx = pos.func.special_param(ArgumentPurpose::VMContext).unwrap();
 
@@ -956,12 +970,12 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         // Load the callee address.
         let body_offset =
             i32::try_from(self.offsets.vmctx_vmfunction_import_body(callee_index)).unwrap();
-        let func_addr = pos.ins().load(pointer_type, mem_flags, base, body_offset);
+        let func_addr = load_trusted(&mut pos, pointer_type, mem_flags, base, body_offset);
 
         // First append the callee vmctx address.
         let vmctx_offset =
             i32::try_from(self.offsets.vmctx_vmfunction_import_vmctx(callee_index)).unwrap();
-        let vmctx = pos.ins().load(pointer_type, mem_flags, base, vmctx_offset);
+        let vmctx = load_trusted(&mut pos, pointer_type, mem_flags, base, vmctx_offset);
         real_call_args.push(vmctx);
         real_call_args.push(caller_vmctx);
 
@@ -1186,10 +1200,15 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         let pointer_type = self.pointer_type();
         let base = pos.ins().global_value(pointer_type, vmctx);
         let offset = i32::try_from(self.offsets.vmctx_interrupts()).unwrap();
-        let interrupt_ptr = pos
-            .ins()
-            .load(pointer_type, ir::MemFlags::trusted(), base, offset);
-        let interrupt = pos.ins().load(
+        let interrupt_ptr = load_trusted(
+            &mut pos,
+            pointer_type,
+            ir::MemFlags::trusted(),
+            base,
+            offset,
+        );
+        let interrupt = load_trusted(
+            &mut pos,
             pointer_type,
             ir::MemFlags::trusted(),
             interrupt_ptr,
@@ -1205,3 +1224,18 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         Ok(())
     }
 }
+
+/// Load runtime data which the guest has no control over, at a fixed offset from `base`, and
+/// mark the load for Blade, which may then exclude it from the sources of transient values.
+fn load_trusted(
+    pos: &mut FuncCursor<'_>,
+    ty: ir::Type,
+    flags: ir::MemFlags,
+    base: ir::Value,
+    offset: i32,
+) -> ir::Value {
+    let value = pos.ins().load(ty, flags, base, offset);
+    let inst = pos.func.dfg.value_def(value).unwrap_inst();
+    pos.func.blade_class[inst] = ir::BladeClass::Trusted;
+    value
+}
@@ -46,8 +46,8 @@ pub use crate::address_map::{
 pub use crate::cache::create_new_config as cache_create_new_config;
 pub use crate::cache::CacheConfig;
 pub use crate::compilation::{
-    Compilation, CompileError, CompiledFunction, Compiler, Relocation, RelocationTarget,
-    Relocations, StackMapInformation, StackMaps, TrapInformation, Traps,
+    Compilation, CompileError, CompiledFunction, Compiler, FunctionCalls, Relocation,
+    RelocationTarget, Relocations, StackMapInformation, StackMaps, TrapInformation, Traps,
 };
 pub use crate::cranelift::Cranelift;
 pub use crate::data_structures::*;
@@ -61,7 +61,7 @@ pub use crate::module_environ::{
     translate_signature, DataInitializer, DataInitializerLocation, FunctionBodyData,
     ModuleEnvironment, ModuleTranslation,
 };
-pub use crate::tunables::Tunables;
+pub use crate::tunables::{BladeFilter, BladeProfile, BladeProfileError, Tunables};
 pub use crate::vmoffsets::{TargetSharedSignatureIndex, VMOffsets, INTERRUPTED};
 
 /// WebAssembly page sizes are defined to be 64KiB.
@@ -1,3 +1,9 @@
+use cranelift_wasm::FuncIndex;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use thiserror::Error;
+
 /// Tunable parameters for WebAssembly compilation.
 #[derive(Clone, Hash)]
 pub struct Tunables {
@@ -20,6 +26,9 @@ pub struct Tunables {
     /// calls and interrupts are implemented through the `VMInterrupts`
     /// structure, or `InterruptHandle` in the `wasmtime` crate.
     pub interruptable: bool,
+
+    /// Which functions the Blade Spectre mitigation pass runs on.
+    pub blade_filter: BladeFilter,
 }
 
 impl Default for Tunables {
@@ -53,6 +62,154 @@ impl Default for Tunables {
 
             debug_info: false,
             interruptable: false,
+            blade_filter: BladeFilter::default(),
+        }
+    }
+}
+
+/// A restriction of the Blade Spectre mitigation pass to some of the functions of a module.
+///
+/// Functions are matched by their name in the name section, if they have one, and by their
+/// index, written in decimal. A regular expression matches a function if it matches anywhere in
+/// either, so anchor it to match whole names or indices. With a profile, functions are also
+/// matched by their hotness.
+#[derive(Clone, Debug, Default)]
+pub struct BladeFilter {
+    only: Option<Regex>,
+    exclude: Option<Regex>,
+    hot: Option<(BladeProfile, u64)>,
+}
+
+impl BladeFilter {
+    /// Only run Blade on the functions matching `pattern`.
+    pub fn only(&mut self, pattern: &str) -> Result<(), regex::Error> {
+        self.only = Some(Regex::new(pattern)?);
+        Ok(())
+    }
+
+    /// Don't run Blade on the functions matching `pattern`, even if they match the pattern
+    /// given to `only`.
+    pub fn exclude(&mut self, pattern: &str) -> Result<(), regex::Error> {
+        self.exclude = Some(Regex::new(pattern)?);
+        Ok(())
+    }
+
+    /// Only run Blade on the functions which `profile` counts at least `threshold` times.
+    ///
+    /// Functions the profile doesn't mention count zero times, so they are left out unless
+    /// `threshold` is zero. This combines with the patterns given to `only` and `exclude`, e.g.
+    /// to only harden the hot functions which handle untrusted data.
+    pub fn hot(&mut self, profile: BladeProfile, threshold: u64) {
+        self.hot = Some((profile, threshold));
+    }
+
+    /// Does Blade run on the function at `index`, named `name`?
+    pub fn includes(&self, index: FuncIndex, name: Option<&str>) -> bool {
+        let hot = self.hot.as_ref().map_or(true, |(profile, threshold)| {
+            profile.count(index, name) >= *threshold
+        });
+        let index = index.as_u32().to_string();
+        let matches = |regex: &Regex| {
+            regex.is_match(&index) || name.map_or(false, |name| regex.is_match(name))
+        };
+        hot && self.only.as_ref().map_or(true, matches)
+            && !self.exclude.as_ref().map_or(false, matches)
+    }
+}
+
+impl Hash for BladeFilter {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        self.only.as_ref().map(Regex::as_str).hash(hasher);
+        self.exclude.as_ref().map(Regex::as_str).hash(hasher);
+        self.hot.hash(hasher);
+    }
+}
+
+/// An execution profile of a module, counting how many times each of its functions ran, e.g.
+/// how many times it was called in a previous run.
+///
+/// Its text format has one function per line: the name of the function in the name section, or
+/// its index in decimal, followed by whitespace and its count. Empty lines and lines starting
+/// with `#` are ignored. A function named in the profile is looked up by its name before its
+/// index.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct BladeProfile {
+    counts: BTreeMap<String, u64>,
+}
+
+/// An error in the text of a `BladeProfile`.
+#[derive(Error, Debug)]
+#[error("line {line} of the Blade profile: {message}")]
+pub struct BladeProfileError {
+    line: usize,
+    message: String,
+}
+
+impl BladeProfile {
+    /// Parse a profile from its text format.
+    pub fn parse(text: &str) -> Result<Self, BladeProfileError> {
+        let mut counts = BTreeMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| BladeProfileError {
+                line: i + 1,
+                message,
+            };
+            let mut fields = line.rsplitn(2, char::is_whitespace);
+            let count = fields.next().unwrap();
+            let function = match fields.next() {
+                Some(function) => function.trim_end(),
+                None => return Err(error(format!("missing the count of `{}`", line))),
+            };
+            let count = count
+                .parse()
+                .map_err(|_| error(format!("invalid count `{}`", count)))?;
+            counts.insert(function.to_string(), count);
         }
+        Ok(Self { counts })
+    }
+
+    /// Set the count of `function`, a name or an index in decimal.
+    pub fn set_count(&mut self, function: &str, count: u64) {
+        self.counts.insert(function.to_string(), count);
+    }
+
+    /// Get the count of the function at `index`, named `name`.
+    pub fn count(&self, index: FuncIndex, name: Option<&str>) -> u64 {
+        name.and_then(|name| self.counts.get(name))
+            .or_else(|| self.counts.get(&index.as_u32().to_string()))
+            .cloned()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_profile() {
+        let profile = BladeProfile::parse(
+            "# calls\n\
+             parse 1000\n\
+             \n\
+             1   3\n\
+             has spaces\t7\n",
+        )
+        .unwrap();
+        assert_eq!(profile.count(FuncIndex::from_u32(0), Some("parse")), 1000);
+        assert_eq!(profile.count(FuncIndex::from_u32(1), Some("kernel")), 3);
+        assert_eq!(profile.count(FuncIndex::from_u32(2), Some("has spaces")), 7);
+        assert_eq!(profile.count(FuncIndex::from_u32(3), None), 0);
+
+        let error = BladeProfile::parse("parse 1\nkernel\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 2 of the Blade profile: missing the count of `kernel`"
+        );
+        assert!(BladeProfile::parse("parse many").is_err());
     }
 }
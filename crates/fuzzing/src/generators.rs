@@ -10,6 +10,7 @@
 
 #[cfg(feature = "binaryen")]
 pub mod api;
+pub mod blade;
 
 use arbitrary::{Arbitrary, Unstructured};
 
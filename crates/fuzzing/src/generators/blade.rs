@@ -0,0 +1,335 @@
+//! Generating Cranelift IR functions which stress the Blade Spectre mitigation pass.
+//!
+//! Blade's graph is built from loads, stores and branches, and from the values that flow between
+//! them, including through block parameters. Wasm test cases produce these only incidentally, so
+//! this generator builds functions from them directly.
+
+use arbitrary::Arbitrary;
+use cranelift_codegen::ir::immediates::{Offset32, Uimm64};
+use cranelift_codegen::ir::types::{I32, I64};
+use cranelift_codegen::ir::{
+    AbiParam, ArgumentPurpose, ExternalName, Function, GlobalValueData, Heap, HeapData, HeapStyle,
+    InstBuilder, MemFlags, Signature, Value,
+};
+use cranelift_codegen::isa::CallConv;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+
+/// The number of variables the generated instructions read and write.
+const NUM_VARIABLES: u8 = 4;
+
+/// A function made of heap accesses, arithmetic and control flow on the accessed values, along
+/// with the Blade configuration to compile it with.
+#[derive(Arbitrary, Clone, Debug)]
+pub struct BladeFunction {
+    /// How Blade protects the function.
+    pub config: BladeConfig,
+    dynamic_heap: bool,
+    ops: Vec<BladeOp>,
+}
+
+/// The Blade settings to compile a `BladeFunction` with.
+#[derive(Arbitrary, Clone, Debug)]
+pub struct BladeConfig {
+    mode: BladeMode,
+    engine: Engine,
+    opt_level: OptLevel,
+    early_placement: bool,
+    cut_near_sinks: bool,
+    slh_mask_value: bool,
+    coarsen: bool,
+    split_edges: bool,
+    division_is_sink: bool,
+    slh_fake_bounds: bool,
+    exclude_trusted_loads: bool,
+    exclude_non_speculative_loads: bool,
+    transient_params: TransientParams,
+    sink_classes: u8,
+}
+
+#[derive(Arbitrary, Clone, Copy, Debug)]
+enum BladeMode {
+    Lfence,
+    LfencePerBlock,
+    Slh,
+    SlhWithFenceFallback,
+    Analyze,
+}
+
+#[derive(Arbitrary, Clone, Copy, Debug)]
+enum Engine {
+    MinCut,
+    Dataflow,
+    Check,
+}
+
+#[derive(Arbitrary, Clone, Copy, Debug)]
+enum TransientParams {
+    None,
+    Blocks,
+    All,
+}
+
+#[derive(Arbitrary, Clone, Copy, Debug)]
+enum OptLevel {
+    None,
+    Speed,
+    SpeedAndSize,
+}
+
+/// An operation on the variables of a `BladeFunction`. Variable numbers wrap around.
+#[derive(Arbitrary, Clone, Copy, Debug)]
+enum BladeOp {
+    /// Load from the heap at index `addr`.
+    HeapLoad { dst: u8, addr: u8, offset: u8 },
+    /// Store `value` to the heap at index `addr`.
+    HeapStore { addr: u8, value: u8 },
+    /// Load from the VM context, which has no bounds.
+    VmctxLoad { dst: u8, offset: u8 },
+    /// Add two variables.
+    Add { dst: u8, a: u8, b: u8 },
+    /// Divide two variables.
+    Div { dst: u8, a: u8, b: u8 },
+    /// Branch on `cond`, assigning `src` to `dst` on one side only, so that the join block takes
+    /// `dst` as a parameter.
+    Diamond { cond: u8, dst: u8, src: u8 },
+    /// Loop while `counter` is nonzero, loading `dst` from the heap at index `addr` and
+    /// decrementing `counter` in the body.
+    Loop { counter: u8, dst: u8, addr: u8 },
+}
+
+impl BladeFunction {
+    /// Build the Cranelift IR function.
+    pub fn to_function(&self) -> Function {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params
+            .push(AbiParam::special(I64, ArgumentPurpose::VMContext));
+        sig.returns.push(AbiParam::new(I64));
+        let mut func = Function::with_name_signature(ExternalName::user(0, 0), sig);
+
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut func, &mut fn_ctx);
+        let heap = self.declare_heap(&mut builder);
+
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+        let vmctx = builder.block_params(entry)[0];
+        for i in 0..NUM_VARIABLES {
+            builder.declare_var(var(i), I64);
+            let value = if i == 0 {
+                vmctx
+            } else {
+                builder.ins().iconst(I64, i64::from(i))
+            };
+            builder.def_var(var(i), value);
+        }
+
+        for op in &self.ops {
+            match *op {
+                BladeOp::HeapLoad { dst, addr, offset } => {
+                    let value = heap_load(&mut builder, heap, addr, offset);
+                    builder.def_var(var(dst), value);
+                }
+                BladeOp::HeapStore { addr, value } => {
+                    let index = heap_index(&mut builder, addr);
+                    let addr = builder.ins().heap_addr(I64, heap, index, 8);
+                    let value = builder.use_var(var(value));
+                    builder.ins().store(MemFlags::new(), value, addr, 0);
+                }
+                BladeOp::VmctxLoad { dst, offset } => {
+                    let offset = i32::from(offset % 8) * 8;
+                    let value = builder.ins().load(I64, MemFlags::trusted(), vmctx, offset);
+                    builder.def_var(var(dst), value);
+                }
+                BladeOp::Add { dst, a, b } => {
+                    let a = builder.use_var(var(a));
+                    let b = builder.use_var(var(b));
+                    let value = builder.ins().iadd(a, b);
+                    builder.def_var(var(dst), value);
+                }
+                BladeOp::Div { dst, a, b } => {
+                    let a = builder.use_var(var(a));
+                    let b = builder.use_var(var(b));
+                    let value = builder.ins().udiv(a, b);
+                    builder.def_var(var(dst), value);
+                }
+                BladeOp::Diamond { cond, dst, src } => {
+                    let side = builder.create_block();
+                    let join = builder.create_block();
+                    let cond = builder.use_var(var(cond));
+                    builder.ins().brz(cond, join, &[]);
+                    builder.ins().jump(side, &[]);
+                    builder.seal_block(side);
+
+                    builder.switch_to_block(side);
+                    let value = builder.use_var(var(src));
+                    builder.def_var(var(dst), value);
+                    builder.ins().jump(join, &[]);
+                    builder.seal_block(join);
+                    builder.switch_to_block(join);
+                }
+                BladeOp::Loop { counter, dst, addr } => {
+                    let header = builder.create_block();
+                    let body = builder.create_block();
+                    let exit = builder.create_block();
+                    builder.ins().jump(header, &[]);
+
+                    builder.switch_to_block(header);
+                    let count = builder.use_var(var(counter));
+                    builder.ins().brz(count, exit, &[]);
+                    builder.ins().jump(body, &[]);
+                    builder.seal_block(body);
+
+                    builder.switch_to_block(body);
+                    let value = heap_load(&mut builder, heap, addr, 0);
+                    builder.def_var(var(dst), value);
+                    let count = builder.use_var(var(counter));
+                    let count = builder.ins().iadd_imm(count, -1);
+                    builder.def_var(var(counter), count);
+                    builder.ins().jump(header, &[]);
+                    builder.seal_block(header);
+                    builder.seal_block(exit);
+                    builder.switch_to_block(exit);
+                }
+            }
+        }
+
+        let result = builder.use_var(var(0));
+        builder.ins().return_(&[result]);
+        builder.finalize();
+        func
+    }
+
+    /// Declare the heap the function accesses, in the same way as Wasm linear memories.
+    fn declare_heap(&self, builder: &mut FunctionBuilder) -> Heap {
+        let vmctx = builder.create_global_value(GlobalValueData::VMContext);
+        let base = builder.create_global_value(GlobalValueData::Load {
+            base: vmctx,
+            offset: Offset32::new(0),
+            global_type: I64,
+            readonly: true,
+        });
+        let style = if self.dynamic_heap {
+            let bound_gv = builder.create_global_value(GlobalValueData::Load {
+                base: vmctx,
+                offset: Offset32::new(8),
+                global_type: I32,
+                readonly: false,
+            });
+            HeapStyle::Dynamic { bound_gv }
+        } else {
+            HeapStyle::Static {
+                bound: Uimm64::new(0x1_0000_0000),
+            }
+        };
+        builder.create_heap(HeapData {
+            base,
+            min_size: Uimm64::new(0x1_0000),
+            offset_guard_size: Uimm64::new(0x8000_0000),
+            style,
+            index_type: I32,
+        })
+    }
+}
+
+impl BladeConfig {
+    /// Does this configuration fail to compile loads which have no bounds, rather than fencing
+    /// them or hardening them against fake bounds?
+    pub fn refuses_unbounded_loads(&self) -> bool {
+        match self.mode {
+            BladeMode::Slh => !self.slh_fake_bounds,
+            _ => false,
+        }
+    }
+
+    /// Get the shared settings builder for this configuration, with the verifier enabled.
+    pub fn flags_builder(&self) -> settings::Builder {
+        let mut builder = settings::builder();
+        let mode = match self.mode {
+            BladeMode::Lfence => "lfence",
+            BladeMode::LfencePerBlock => "lfence_per_block",
+            BladeMode::Slh => "slh",
+            BladeMode::SlhWithFenceFallback => "slh_with_fence_fallback",
+            BladeMode::Analyze => "analyze",
+        };
+        let engine = match self.engine {
+            Engine::MinCut => "min_cut",
+            Engine::Dataflow => "dataflow",
+            Engine::Check => "check",
+        };
+        let opt_level = match self.opt_level {
+            OptLevel::None => "none",
+            OptLevel::Speed => "speed",
+            OptLevel::SpeedAndSize => "speed_and_size",
+        };
+        let placement = if self.early_placement {
+            "early"
+        } else {
+            "late"
+        };
+        let cut_bias = if self.cut_near_sinks {
+            "sinks"
+        } else {
+            "sources"
+        };
+        let slh_mask = if self.slh_mask_value {
+            "value"
+        } else {
+            "address"
+        };
+        let transient_params = match self.transient_params {
+            TransientParams::None => "none",
+            TransientParams::Blocks => "blocks",
+            TransientParams::All => "all",
+        };
+        builder.set("blade", mode).unwrap();
+        builder.set("blade_engine", engine).unwrap();
+        builder.set("opt_level", opt_level).unwrap();
+        builder.set("blade_placement", placement).unwrap();
+        builder.set("blade_cut_bias", cut_bias).unwrap();
+        builder.set("blade_slh_mask", slh_mask).unwrap();
+        builder
+            .set("blade_transient_params", transient_params)
+            .unwrap();
+        builder
+            .set("blade_sink_classes", &(self.sink_classes & 127).to_string())
+            .unwrap();
+        builder.set("enable_verifier", "true").unwrap();
+        for (name, value) in &[
+            ("blade_coarsen", self.coarsen),
+            ("blade_split_edges", self.split_edges),
+            ("blade_division_is_sink", self.division_is_sink),
+            ("blade_slh_fake_bounds", self.slh_fake_bounds),
+            ("blade_exclude_trusted_loads", self.exclude_trusted_loads),
+            (
+                "blade_exclude_non_speculative_loads",
+                self.exclude_non_speculative_loads,
+            ),
+        ] {
+            builder.set(name, &value.to_string()).unwrap();
+        }
+        builder
+    }
+}
+
+fn var(index: u8) -> Variable {
+    Variable::with_u32(u32::from(index % NUM_VARIABLES))
+}
+
+/// Get a 32-bit heap index out of variable `index`.
+fn heap_index(builder: &mut FunctionBuilder, index: u8) -> Value {
+    let index = builder.use_var(var(index));
+    builder.ins().ireduce(I32, index)
+}
+
+/// Load from the heap at the index in variable `index`, plus `offset` words.
+fn heap_load(builder: &mut FunctionBuilder, heap: Heap, index: u8, offset: u8) -> Value {
+    let offset = u32::from(offset % 4) * 8;
+    let index = heap_index(builder, index);
+    let addr = builder.ins().heap_addr(I64, heap, index, offset + 8);
+    builder
+        .ins()
+        .load(I64, MemFlags::new(), addr, offset as i32)
+}
@@ -400,3 +400,37 @@ pub fn spectest(config: crate::generators::Config, test: crate::generators::Spec
         .run_buffer(test.file, test.contents.as_bytes())
         .unwrap();
 }
+
+/// Compile a generated function with the Blade Spectre mitigations, and fail if Blade panics,
+/// produces code which the verifier rejects, or, with `blade_engine=check`, places protections
+/// which don't type check.
+pub fn blade(func: &crate::generators::blade::BladeFunction) {
+    use cranelift_codegen::print_errors::pretty_error;
+    use cranelift_codegen::{check_blade_settings, settings, CodegenError, Context};
+
+    crate::init_fuzzing();
+
+    let isa_builder = match cranelift_native::builder() {
+        Ok(builder) => builder,
+        Err(_) => return,
+    };
+    let isa = isa_builder.finish(settings::Flags::new(func.config.flags_builder()));
+    // The native ISA may not support every Blade configuration, which it reports before compiling.
+    if let Err(err) = check_blade_settings(&*isa) {
+        log::debug!("Unsupported settings: {}", err);
+        return;
+    }
+
+    let mut context = Context::for_function(func.to_function());
+    log::debug!("Compiling with Blade:\n{}", context.func.display(&*isa));
+    match context.compile(&*isa) {
+        Ok(_) => {}
+        // Strict speculative load hardening refuses loads it has no bounds for, and functions may
+        // get too large to compile; neither is a bug. Any other unsupported code is.
+        Err(CodegenError::Unsupported(msg)) if func.config.refuses_unbounded_loads() => {
+            log::debug!("Unsupported: {}", msg)
+        }
+        Err(CodegenError::ImplLimitExceeded) | Err(CodegenError::CodeTooLarge) => {}
+        Err(err) => panic!("{}", pretty_error(&context.func, Some(&*isa), err)),
+    }
+}
@@ -8,14 +8,16 @@ use cranelift_codegen::print_errors::pretty_error;
 use cranelift_codegen::Context;
 use cranelift_codegen::{binemit, ir};
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use target_lexicon::Architecture;
 use wasmtime_debug::{emit_dwarf, DebugInfoData, DwarfSection};
+use wasmtime_environ::blade::BladeStats;
 use wasmtime_environ::entity::{EntityRef, PrimaryMap};
 use wasmtime_environ::isa::{TargetFrontendConfig, TargetIsa};
 use wasmtime_environ::wasm::{DefinedFuncIndex, DefinedMemoryIndex, MemoryIndex, SignatureIndex};
 use wasmtime_environ::{
-    CacheConfig, CompileError, CompiledFunction, Compiler as _C, Module, ModuleAddressMap,
-    ModuleMemoryOffset, ModuleTranslation, ModuleVmctxInfo, Relocation, RelocationTarget,
-    Relocations, StackMaps, Traps, Tunables, VMOffsets, ValueLabelsRanges,
+    CacheConfig, CompileError, CompiledFunction, Compiler as _C, FunctionCalls, Module,
+    ModuleAddressMap, ModuleMemoryOffset, ModuleTranslation, ModuleVmctxInfo, Relocation,
+    RelocationTarget, Relocations, StackMaps, Traps, Tunables, VMOffsets, ValueLabelsRanges,
 };
 use wasmtime_runtime::{InstantiationError, VMFunctionBody, VMTrampoline};
 
@@ -136,6 +138,8 @@ pub struct Compilation {
     pub code_range: (*const u8, usize),
     pub trampolines: PrimaryMap<SignatureIndex, VMTrampoline>,
     pub jt_offsets: PrimaryMap<DefinedFuncIndex, ir::JumpTableOffsets>,
+    pub blade_stats: PrimaryMap<DefinedFuncIndex, BladeStats>,
+    pub calls: PrimaryMap<DefinedFuncIndex, FunctionCalls>,
     pub dwarf_sections: Vec<DwarfSection>,
     pub traps: Traps,
     pub stack_maps: StackMaps,
@@ -159,13 +163,17 @@ impl Compiler {
     }
 
     /// Compile the given function bodies.
+    ///
+    /// With `unfenced`, the compiled code is also loaded a second time with
+    /// the speculation barriers that Blade placed replaced by no-ops, found
+    /// with the offsets recorded in the Blade statistics of each function.
+    /// This fails if the backend doesn't record them.
     pub(crate) fn compile<'data>(
         &self,
         translation: &ModuleTranslation,
         debug_data: Option<DebugInfoData>,
-    ) -> Result<Compilation, SetupError> {
-        let mut code_memory = CodeMemory::new();
-
+        unfenced: bool,
+    ) -> Result<(Compilation, Option<Compilation>), SetupError> {
         let (
             compilation,
             relocations,
@@ -195,15 +203,71 @@ impl Compiler {
         }
         .map_err(SetupError::Compile)?;
 
+        let unfenced = if unfenced {
+            let nop = barrier_nop(&*self.isa).ok_or_else(|| {
+                SetupError::Compile(CompileError::Codegen(
+                    "the backend doesn't record the offsets of the Blade speculation barriers, \
+                     which the unfenced variant is loaded with"
+                        .to_string(),
+                ))
+            })?;
+            let mut unfenced = compilation.clone();
+            unfenced.patch_blade_barriers(nop);
+            Some(self.load(
+                translation,
+                &debug_data,
+                &unfenced,
+                &relocations,
+                address_transform.clone(),
+                &value_ranges,
+                stack_slots.clone(),
+                traps.clone(),
+                stack_maps.clone(),
+            )?)
+        } else {
+            None
+        };
+
+        let compilation = self.load(
+            translation,
+            &debug_data,
+            &compilation,
+            &relocations,
+            address_transform,
+            &value_ranges,
+            stack_slots,
+            traps,
+            stack_maps,
+        )?;
+        Ok((compilation, unfenced))
+    }
+
+    /// Allocate the compiled function bodies into executable memory, along
+    /// with the trampolines of the module.
+    #[allow(clippy::too_many_arguments)]
+    fn load(
+        &self,
+        translation: &ModuleTranslation,
+        debug_data: &Option<DebugInfoData>,
+        compilation: &wasmtime_environ::Compilation,
+        relocations: &Relocations,
+        address_transform: ModuleAddressMap,
+        value_ranges: &ValueLabelsRanges,
+        stack_slots: PrimaryMap<DefinedFuncIndex, ir::StackSlots>,
+        traps: Traps,
+        stack_maps: StackMaps,
+    ) -> Result<Compilation, SetupError> {
+        let mut code_memory = CodeMemory::new();
+
         let dwarf_sections = if debug_data.is_some() && !compilation.is_empty() {
             transform_dwarf_data(
                 &*self.isa,
                 &translation.module,
                 debug_data.as_ref().unwrap(),
                 &address_transform,
-                &value_ranges,
+                value_ranges,
                 stack_slots,
-                &compilation,
+                compilation,
             )?
         } else {
             vec![]
@@ -211,13 +275,13 @@ impl Compiler {
 
         // Allocate all of the compiled functions into executable memory,
         // copying over their contents.
-        let finished_functions = allocate_functions(&mut code_memory, &compilation, &relocations)
+        let finished_functions = allocate_functions(&mut code_memory, compilation, relocations)
             .map_err(|message| {
-            SetupError::Instantiate(InstantiationError::Resource(format!(
-                "failed to allocate memory for functions: {}",
-                message
-            )))
-        })?;
+                SetupError::Instantiate(InstantiationError::Resource(format!(
+                    "failed to allocate memory for functions: {}",
+                    message
+                )))
+            })?;
 
         // Eagerly generate a entry trampoline for every type signature in the
         // module. This should be "relatively lightweight" for most modules and
@@ -237,7 +301,9 @@ impl Compiler {
         }
 
         let jt_offsets = compilation.get_jt_offsets();
-        let code_range = get_code_range(&compilation, &finished_functions);
+        let blade_stats = compilation.get_blade_stats();
+        let calls = compilation.get_calls();
+        let code_range = get_code_range(compilation, &finished_functions);
 
         Ok(Compilation {
             code_memory,
@@ -245,6 +311,8 @@ impl Compiler {
             code_range,
             trampolines,
             jt_offsets,
+            blade_stats,
+            calls,
             dwarf_sections,
             traps,
             stack_maps,
@@ -253,6 +321,19 @@ impl Compiler {
     }
 }
 
+/// Get a no-op as long as the speculation barriers of `isa`, if Blade records
+/// where it places them, which only the old x86 backend does.
+fn barrier_nop(isa: &dyn TargetIsa) -> Option<&'static [u8]> {
+    if isa.get_mach_backend().is_some() {
+        return None;
+    }
+    match isa.triple().architecture {
+        // `nopl (%rax)`, which is as long as `lfence`, `mfence` and `serialize`.
+        Architecture::X86_32(_) | Architecture::X86_64 => Some(&[0x0f, 0x1f, 0x00]),
+        _ => None,
+    }
+}
+
 /// Create a trampoline for invoking a function.
 pub fn make_trampoline(
     isa: &dyn TargetIsa,
@@ -373,6 +454,8 @@ pub fn make_trampoline(
                 body: code_buf,
                 jt_offsets: context.func.jt_offsets,
                 unwind_info,
+                blade_stats: context.blade_stats,
+                calls: FunctionCalls::default(),
             },
             reloc_sink.relocs.iter(),
         )
@@ -12,13 +12,14 @@ use std::any::Any;
 use std::collections::HashMap;
 use std::sync::Arc;
 use thiserror::Error;
-use wasmtime_debug::{read_debuginfo, write_debugsections_image, DwarfSection};
+use wasmtime_debug::{read_debuginfo, write_debugsections_image, DebugInfoData, DwarfSection};
+use wasmtime_environ::blade::{BladeContract, BladeStats};
 use wasmtime_environ::entity::{BoxedSlice, PrimaryMap};
 use wasmtime_environ::isa::TargetIsa;
-use wasmtime_environ::wasm::{DefinedFuncIndex, SignatureIndex};
+use wasmtime_environ::wasm::{DefinedFuncIndex, FuncIndex, SignatureIndex};
 use wasmtime_environ::{
-    CompileError, DataInitializer, DataInitializerLocation, Module, ModuleAddressMap,
-    ModuleEnvironment, ModuleTranslation, StackMaps, Traps,
+    CompileError, DataInitializer, DataInitializerLocation, EntityIndex, FunctionCalls, Module,
+    ModuleAddressMap, ModuleEnvironment, ModuleTranslation, StackMaps, Traps,
 };
 use wasmtime_profiling::ProfilingAgent;
 use wasmtime_runtime::VMInterrupts;
@@ -71,6 +72,21 @@ pub struct CompiledModule {
     traps: Traps,
     stack_maps: StackMaps,
     address_transform: ModuleAddressMap,
+    blade_stats: PrimaryMap<DefinedFuncIndex, BladeStats>,
+    calls: PrimaryMap<DefinedFuncIndex, FunctionCalls>,
+}
+
+/// A call between two functions whose Blade contracts don't match, see
+/// `BladeContract::may_call`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BladeContractViolation {
+    /// The calling function.
+    pub caller: FuncIndex,
+    /// The called function.
+    pub callee: FuncIndex,
+    /// Whether the call goes through a table, so that the callee is only one
+    /// of the functions the caller may reach there.
+    pub indirect: bool,
 }
 
 impl CompiledModule {
@@ -80,29 +96,56 @@ impl CompiledModule {
         data: &'data [u8],
         profiler: &dyn ProfilingAgent,
     ) -> Result<Self, SetupError> {
-        let environ = ModuleEnvironment::new(compiler.frontend_config(), compiler.tunables());
-
-        let translation = environ
-            .translate(data)
-            .map_err(|error| SetupError::Compile(CompileError::Wasm(error)))?;
+        let translation = translate(compiler, data)?;
+        let debug_data = read_debug_data(compiler, data)?;
+        let (compilation, _) = compiler.compile(&translation, debug_data, false)?;
+        Self::from_compilation(compiler, translation, compilation, profiler)
+    }
 
-        let mut debug_data = None;
-        if compiler.tunables().debug_info {
-            // TODO Do we want to ignore invalid DWARF data?
-            debug_data = Some(read_debuginfo(&data)?);
-        }
+    /// Compile a data buffer into a `CompiledModule`, and load the compiled
+    /// code a second time with the speculation barriers that Blade placed
+    /// replaced by no-ops, as the unfenced variant of the module.
+    ///
+    /// The module is only compiled once. This fails if the backend doesn't
+    /// record the offsets of the barriers, see `BladeStats::barrier_offsets`.
+    pub fn new_with_unfenced<'data>(
+        compiler: &Compiler,
+        data: &'data [u8],
+        profiler: &dyn ProfilingAgent,
+    ) -> Result<(Self, Self), SetupError> {
+        let translation = translate(compiler, data)?;
+        let debug_data = read_debug_data(compiler, data)?;
+        let (compilation, unfenced) = compiler.compile(&translation, debug_data, true)?;
+        let unfenced = unfenced.expect("unfenced compilation");
+
+        // Each variant owns its `Module`, and translating the module again is
+        // cheap next to compiling it.
+        let unfenced_translation = translate(compiler, data)?;
+        Ok((
+            Self::from_compilation(compiler, translation, compilation, profiler)?,
+            Self::from_compilation(compiler, unfenced_translation, unfenced, profiler)?,
+        ))
+    }
 
+    fn from_compilation(
+        compiler: &Compiler,
+        translation: ModuleTranslation<'_>,
+        compilation: Compilation,
+        profiler: &dyn ProfilingAgent,
+    ) -> Result<Self, SetupError> {
         let Compilation {
             mut code_memory,
             finished_functions,
             code_range,
             trampolines,
             jt_offsets,
+            blade_stats,
+            calls,
             dwarf_sections,
             traps,
             stack_maps,
             address_transform,
-        } = compiler.compile(&translation, debug_data)?;
+        } = compilation;
 
         let ModuleTranslation {
             module,
@@ -130,12 +173,12 @@ impl CompiledModule {
                 &finished_functions,
             )?;
 
-            profiler.module_load(&module, &finished_functions, Some(&bytes));
+            profiler.module_load(&module, &finished_functions, &blade_stats, Some(&bytes));
 
             let reg = GdbJitImageRegistration::register(bytes);
             Some(reg)
         } else {
-            profiler.module_load(&module, &finished_functions, None);
+            profiler.module_load(&module, &finished_functions, &blade_stats, None);
             None
         };
 
@@ -153,6 +196,8 @@ impl CompiledModule {
             traps,
             stack_maps,
             address_transform,
+            blade_stats,
+            calls,
         })
     }
 
@@ -251,6 +296,73 @@ impl CompiledModule {
         &self.address_transform
     }
 
+    /// Returns the statistics of the Blade Spectre mitigations of each function.
+    pub fn blade_stats(&self) -> &PrimaryMap<DefinedFuncIndex, BladeStats> {
+        &self.blade_stats
+    }
+
+    /// Returns the calls between functions whose Blade contracts don't match,
+    /// in caller order.
+    ///
+    /// `import_contract` gives the contract of an imported function, or `None`
+    /// if it has none, e.g. for host functions, in which case calls to it
+    /// aren't checked. Calls through tables are assumed to reach every
+    /// function of the module which is exported or referenced by an element
+    /// segment; functions of other modules reached through shared tables
+    /// aren't checked.
+    pub fn blade_contract_violations(
+        &self,
+        import_contract: impl Fn(FuncIndex) -> Option<BladeContract>,
+    ) -> Vec<BladeContractViolation> {
+        let module = &self.module;
+        let contract = |func: FuncIndex| match module.local.defined_func_index(func) {
+            Some(defined) => Some(self.blade_stats[defined].contract),
+            None => import_contract(func),
+        };
+
+        let mut in_tables: Vec<FuncIndex> = module
+            .table_elements
+            .iter()
+            .flat_map(|segment| segment.elements.iter().copied())
+            .chain(
+                module
+                    .passive_elements
+                    .values()
+                    .flat_map(|s| s.iter().copied()),
+            )
+            .chain(module.exports.values().filter_map(|export| match export {
+                EntityIndex::Function(func) => Some(*func),
+                _ => None,
+            }))
+            .collect();
+        in_tables.sort_unstable();
+        in_tables.dedup();
+
+        let mut violations = Vec::new();
+        for (defined, calls) in self.calls.iter() {
+            let caller = module.local.func_index(defined);
+            let caller_contract = self.blade_stats[defined].contract;
+            let direct = calls.direct.iter().map(|&callee| (callee, false));
+            let indirect = in_tables
+                .iter()
+                .filter(|_| calls.indirect)
+                .map(|&callee| (callee, true));
+            for (callee, indirect) in direct.chain(indirect) {
+                match contract(callee) {
+                    Some(callee_contract) if !caller_contract.may_call(&callee_contract) => {
+                        violations.push(BladeContractViolation {
+                            caller,
+                            callee,
+                            indirect,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+        violations
+    }
+
     /// Returns all ranges convered by JIT code.
     pub fn jit_code_ranges<'a>(&'a self) -> impl Iterator<Item = (usize, usize)> + 'a {
         self.code.code_memory.published_ranges()
@@ -262,6 +374,28 @@ impl CompiledModule {
     }
 }
 
+fn translate<'data>(
+    compiler: &Compiler,
+    data: &'data [u8],
+) -> Result<ModuleTranslation<'data>, SetupError> {
+    let environ = ModuleEnvironment::new(compiler.frontend_config(), compiler.tunables());
+    environ
+        .translate(data)
+        .map_err(|error| SetupError::Compile(CompileError::Wasm(error)))
+}
+
+fn read_debug_data<'data>(
+    compiler: &Compiler,
+    data: &'data [u8],
+) -> Result<Option<DebugInfoData<'data>>, SetupError> {
+    if compiler.tunables().debug_info {
+        // TODO Do we want to ignore invalid DWARF data?
+        Ok(Some(read_debuginfo(&data)?))
+    } else {
+        Ok(None)
+    }
+}
+
 /// Similar to `DataInitializer`, but owns its own copy of the data rather
 /// than holding a slice of the original module.
 pub struct OwnedDataInitializer {
@@ -0,0 +1,217 @@
+//! Compile-time benchmarks of the Blade Spectre mitigation pass.
+//!
+//! Each phase of the pass is measured on its own, building the Blade graph, computing its minimal
+//! cut, and inserting the mitigations, along with the whole pass, over a corpus of large
+//! functions with the shapes of typical Wasm code. Run them with:
+//!
+//! ```text
+//! cargo bench -p blade-bench --bench pass
+//! ```
+
+use cranelift_codegen::blade_phases;
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::immediates::{Offset32, Uimm64};
+use cranelift_codegen::ir::types::{I32, I64};
+use cranelift_codegen::ir::{
+    AbiParam, ArgumentPurpose, ExternalName, Function, GlobalValueData, Heap, HeapData, HeapStyle,
+    InstBuilder, MemFlags, Signature, Value,
+};
+use cranelift_codegen::isa::{self, CallConv, TargetIsa};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+/// The Blade modes to benchmark. The graph is weighted differently for SLH.
+const MODES: &[&str] = &["lfence", "slh"];
+
+/// The numbers of repetitions of the basic pattern of each function in the corpus.
+///
+/// The minimal cut takes most of the time, and grows faster than linearly, so larger functions
+/// make the benchmarks too slow to run routinely.
+const SIZES: &[usize] = &[100, 300, 1000];
+
+/// A function appending a pattern repeated some number of times to the entry block, which takes
+/// the VM context as its parameter.
+type Shape = fn(&mut FuncCursor, Heap, Value, usize);
+
+/// The functions of the corpus, by name.
+const SHAPES: &[(&str, Shape)] = &[
+    ("pointer_chase", pointer_chase),
+    ("diamonds", diamonds),
+    ("loops", loops),
+];
+
+fn blade_benchmarks(c: &mut Criterion) {
+    for mode in MODES {
+        let isa = isa_for(mode);
+        let isa = &*isa;
+        for &(shape, body) in SHAPES {
+            let mut group = c.benchmark_group(format!("blade/{}/{}", mode, shape));
+            for &size in SIZES {
+                let func = legalized_function(isa, body, size);
+                let graph = blade_phases::build_graph(&func, isa);
+                let cut = blade_phases::min_cut(&graph);
+
+                group.bench_with_input(BenchmarkId::new("graph", size), &func, |b, func| {
+                    b.iter(|| blade_phases::build_graph(func, isa))
+                });
+                group.bench_with_input(BenchmarkId::new("min_cut", size), &graph, |b, graph| {
+                    b.iter(|| blade_phases::min_cut(graph))
+                });
+                group.bench_with_input(BenchmarkId::new("insert", size), &func, |b, func| {
+                    b.iter_batched(
+                        || func.clone(),
+                        |mut func| {
+                            blade_phases::insert_mitigations(&mut func, isa, &graph, &cut).unwrap()
+                        },
+                        BatchSize::LargeInput,
+                    )
+                });
+                group.bench_with_input(BenchmarkId::new("total", size), &func, |b, func| {
+                    b.iter_batched(
+                        || {
+                            let mut ctx = Context::new();
+                            ctx.func = func.clone();
+                            ctx
+                        },
+                        |mut ctx| ctx.blade(isa).unwrap(),
+                        BatchSize::LargeInput,
+                    )
+                });
+            }
+            group.finish();
+        }
+    }
+}
+
+criterion_group!(benches, blade_benchmarks);
+criterion_main!(benches);
+
+/// Get an x86-64 ISA running Blade in `mode`, without the verifier or the graph size limit.
+///
+/// The loads from the VM context have no bounds, so SLH uses fake ones for them.
+fn isa_for(mode: &str) -> Box<dyn TargetIsa> {
+    let mut flags = settings::builder();
+    flags.set("blade", mode).unwrap();
+    flags.set("blade_slh_fake_bounds", "true").unwrap();
+    flags.set("enable_verifier", "false").unwrap();
+    flags.set("blade_max_edges_log2", "63").unwrap();
+    isa::lookup_by_name("x86_64")
+        .unwrap()
+        .finish(settings::Flags::new(flags))
+}
+
+/// Build a function repeating the pattern of `body` `size` times, and legalize it, since Blade
+/// runs on legalized code.
+fn legalized_function(isa: &dyn TargetIsa, body: Shape, size: usize) -> Function {
+    let mut sig = Signature::new(CallConv::SystemV);
+    sig.params
+        .push(AbiParam::special(I64, ArgumentPurpose::VMContext));
+    sig.returns.push(AbiParam::new(I64));
+    let mut func = Function::with_name_signature(ExternalName::user(0, 0), sig);
+    let heap = declare_heap(&mut func);
+
+    let entry = func.dfg.make_block();
+    let vmctx = func.dfg.append_block_param(entry, I64);
+    let mut pos = FuncCursor::new(&mut func);
+    pos.insert_block(entry);
+    body(&mut pos, heap, vmctx, size);
+
+    let mut ctx = Context::for_function(func);
+    ctx.legalize(isa).unwrap();
+    ctx.func
+}
+
+/// Declare a static heap, in the same way as Wasm linear memories.
+fn declare_heap(func: &mut Function) -> Heap {
+    let vmctx = func.create_global_value(GlobalValueData::VMContext);
+    let base = func.create_global_value(GlobalValueData::Load {
+        base: vmctx,
+        offset: Offset32::new(0),
+        global_type: I64,
+        readonly: true,
+    });
+    func.create_heap(HeapData {
+        base,
+        min_size: Uimm64::new(0x1_0000),
+        offset_guard_size: Uimm64::new(0x8000_0000),
+        style: HeapStyle::Static {
+            bound: Uimm64::new(0x1_0000_0000),
+        },
+        index_type: I32,
+    })
+}
+
+/// Load a 64-bit value from `heap` at the 32-bit index `index`.
+fn heap_load(pos: &mut FuncCursor, heap: Heap, index: Value) -> Value {
+    let addr = pos.ins().heap_addr(I64, heap, index, 8);
+    pos.ins().load(I64, MemFlags::new(), addr, 0)
+}
+
+/// A single block following a chain of `size` pointers through the heap, storing every one.
+fn pointer_chase(pos: &mut FuncCursor, heap: Heap, vmctx: Value, size: usize) {
+    let mut index = pos.ins().load(I32, MemFlags::trusted(), vmctx, 16);
+    let mut value = vmctx;
+    for _ in 0..size {
+        value = heap_load(pos, heap, index);
+        index = pos.ins().ireduce(I32, value);
+        let addr = pos.ins().heap_addr(I64, heap, index, 16);
+        pos.ins().store(MemFlags::new(), value, addr, 8);
+    }
+    pos.ins().return_(&[value]);
+}
+
+/// A sequence of `size` branches on loaded values, each joining with a block parameter which is
+/// loaded on one side only.
+fn diamonds(pos: &mut FuncCursor, heap: Heap, vmctx: Value, size: usize) {
+    let mut value = pos.ins().load(I64, MemFlags::trusted(), vmctx, 16);
+    for _ in 0..size {
+        let side = pos.func.dfg.make_block();
+        let join = pos.func.dfg.make_block();
+        let joined = pos.func.dfg.append_block_param(join, I64);
+
+        let index = pos.ins().ireduce(I32, value);
+        let cond = heap_load(pos, heap, index);
+        pos.ins().brz(cond, join, &[value]);
+        pos.ins().jump(side, &[]);
+
+        pos.insert_block(side);
+        let loaded = heap_load(pos, heap, index);
+        pos.ins().jump(join, &[loaded]);
+
+        pos.insert_block(join);
+        value = joined;
+    }
+    pos.ins().return_(&[value]);
+}
+
+/// A sequence of `size` loops, each summing the heap words indexed by its counter and storing
+/// the running sum.
+fn loops(pos: &mut FuncCursor, heap: Heap, vmctx: Value, size: usize) {
+    let mut sum = pos.ins().iconst(I64, 0);
+    let count = pos.ins().load(I32, MemFlags::trusted(), vmctx, 16);
+    for _ in 0..size {
+        let header = pos.func.dfg.make_block();
+        let counter = pos.func.dfg.append_block_param(header, I32);
+        let acc = pos.func.dfg.append_block_param(header, I64);
+        let body = pos.func.dfg.make_block();
+        let exit = pos.func.dfg.make_block();
+        pos.ins().jump(header, &[count, sum]);
+
+        pos.insert_block(header);
+        pos.ins().brz(counter, exit, &[]);
+        pos.ins().jump(body, &[]);
+
+        pos.insert_block(body);
+        let value = heap_load(pos, heap, counter);
+        let acc_next = pos.ins().iadd(acc, value);
+        let addr = pos.ins().heap_addr(I64, heap, counter, 8);
+        pos.ins().store(MemFlags::new(), acc_next, addr, 0);
+        let counter_next = pos.ins().iadd_imm(counter, -1);
+        pos.ins().jump(header, &[counter_next, acc_next]);
+
+        pos.insert_block(exit);
+        sum = acc;
+    }
+    pos.ins().return_(&[sum]);
+}
@@ -0,0 +1,43 @@
+;; Binary searches for 1M keys in a sorted array of 64K 32-bit integers, with a
+;; bounds-checked array access after each search, as in Spectre variant 1.
+(module
+  (memory 5)
+
+  ;; The sorted array is at 0, and the array indexed by search results at 262144.
+  (func $init
+    (local $i i32)
+    (loop $fill
+      (i32.store (i32.shl (local.get $i) (i32.const 2)) (i32.mul (local.get $i) (i32.const 3)))
+      (i32.store8 (i32.add (i32.const 262144) (local.get $i)) (local.get $i))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $fill (i32.lt_u (local.get $i) (i32.const 65536)))))
+
+  ;; Find the index of the first element which is not less than $key.
+  (func $search (param $key i32) (result i32)
+    (local $lo i32) (local $hi i32) (local $mid i32)
+    (local.set $hi (i32.const 65536))
+    (block $done
+      (loop $halve
+        (br_if $done (i32.ge_u (local.get $lo) (local.get $hi)))
+        (local.set $mid (i32.shr_u (i32.add (local.get $lo) (local.get $hi)) (i32.const 1)))
+        (if (i32.lt_u (i32.load (i32.shl (local.get $mid) (i32.const 2))) (local.get $key))
+          (then (local.set $lo (i32.add (local.get $mid) (i32.const 1))))
+          (else (local.set $hi (local.get $mid))))
+        (br $halve)))
+    (local.get $lo))
+
+  (func (export "run") (result i32)
+    (local $n i32) (local $index i32) (local $sum i32)
+    (call $init)
+    (loop $keys
+      (local.set $index
+        (call $search (i32.and (i32.mul (local.get $n) (i32.const 2654435761)) (i32.const 262143))))
+      (if (i32.lt_u (local.get $index) (i32.const 65536))
+        (then
+          (local.set $sum
+            (i32.add (local.get $sum)
+              (i32.load8_u (i32.add (i32.const 262144) (local.get $index)))))))
+      (local.set $n (i32.add (local.get $n) (i32.const 1)))
+      (br_if $keys (i32.lt_u (local.get $n) (i32.const 1048576))))
+    (local.get $sum))
+)
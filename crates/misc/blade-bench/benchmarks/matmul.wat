@@ -0,0 +1,57 @@
+;; Multiplication of 64x64 matrices of 32-bit integers, repeated 64 times.
+(module
+  (memory 1)
+
+  ;; The matrices are stored row by row: A at 0, B at 16384, and C at 32768.
+  (func $init
+    (local $i i32)
+    (loop $fill
+      (i32.store (i32.shl (local.get $i) (i32.const 2)) (local.get $i))
+      (i32.store (i32.add (i32.const 16384) (i32.shl (local.get $i) (i32.const 2)))
+        (i32.sub (i32.const 4096) (local.get $i)))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $fill (i32.lt_u (local.get $i) (i32.const 4096)))))
+
+  ;; C = A * B, returning the sum of the elements of C.
+  (func $multiply (result i32)
+    (local $row i32) (local $col i32) (local $k i32) (local $acc i32) (local $sum i32)
+    (loop $rows
+      (local.set $col (i32.const 0))
+      (loop $cols
+        (local.set $acc (i32.const 0))
+        (local.set $k (i32.const 0))
+        (loop $dot
+          (local.set $acc
+            (i32.add (local.get $acc)
+              (i32.mul
+                (i32.load
+                  (i32.shl
+                    (i32.add (i32.shl (local.get $row) (i32.const 6)) (local.get $k))
+                    (i32.const 2)))
+                (i32.load offset=16384
+                  (i32.shl
+                    (i32.add (i32.shl (local.get $k) (i32.const 6)) (local.get $col))
+                    (i32.const 2))))))
+          (local.set $k (i32.add (local.get $k) (i32.const 1)))
+          (br_if $dot (i32.lt_u (local.get $k) (i32.const 64))))
+        (i32.store offset=32768
+          (i32.shl
+            (i32.add (i32.shl (local.get $row) (i32.const 6)) (local.get $col))
+            (i32.const 2))
+          (local.get $acc))
+        (local.set $sum (i32.add (local.get $sum) (local.get $acc)))
+        (local.set $col (i32.add (local.get $col) (i32.const 1)))
+        (br_if $cols (i32.lt_u (local.get $col) (i32.const 64))))
+      (local.set $row (i32.add (local.get $row) (i32.const 1)))
+      (br_if $rows (i32.lt_u (local.get $row) (i32.const 64))))
+    (local.get $sum))
+
+  (func (export "run") (result i32)
+    (local $round i32) (local $sum i32)
+    (call $init)
+    (loop $rounds
+      (local.set $sum (i32.add (local.get $sum) (call $multiply)))
+      (local.set $round (i32.add (local.get $round) (i32.const 1)))
+      (br_if $rounds (i32.lt_u (local.get $round) (i32.const 64))))
+    (local.get $sum))
+)
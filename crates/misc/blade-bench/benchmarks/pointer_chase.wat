@@ -0,0 +1,29 @@
+;; Traversal of a linked list of 64K nodes scattered through memory, following
+;; 16M links, where every address is the result of the previous load.
+(module
+  (memory 4)
+
+  ;; Node i is at 4 * i, and links to node (i * 40505 + 1) mod 65536, which
+  ;; visits every node.
+  (func $init
+    (local $i i32)
+    (loop $link
+      (i32.store (i32.shl (local.get $i) (i32.const 2))
+        (i32.shl
+          (i32.and
+            (i32.add (i32.mul (local.get $i) (i32.const 40505)) (i32.const 1))
+            (i32.const 65535))
+          (i32.const 2)))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $link (i32.lt_u (local.get $i) (i32.const 65536)))))
+
+  (func (export "run") (result i32)
+    (local $n i32) (local $node i32) (local $sum i32)
+    (call $init)
+    (loop $follow
+      (local.set $node (i32.load (local.get $node)))
+      (local.set $sum (i32.add (local.get $sum) (local.get $node)))
+      (local.set $n (i32.add (local.get $n) (i32.const 1)))
+      (br_if $follow (i32.lt_u (local.get $n) (i32.const 16777216))))
+    (local.get $sum))
+)
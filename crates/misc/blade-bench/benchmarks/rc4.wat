@@ -0,0 +1,59 @@
+;; RC4 key scheduling and keystream generation, whose state table is indexed by
+;; secret-dependent values, encrypting a 64 KiB buffer 64 times.
+(module
+  (memory 2)
+
+  ;; Initialize the state table at 0 from the 16-byte key at 256.
+  (func $key_schedule
+    (local $i i32) (local $j i32) (local $t i32)
+    (loop $init
+      (i32.store8 (local.get $i) (local.get $i))
+      (i32.store8 (i32.add (i32.const 256) (i32.and (local.get $i) (i32.const 15)))
+        (i32.mul (local.get $i) (i32.const 13)))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $init (i32.lt_u (local.get $i) (i32.const 256))))
+    (local.set $i (i32.const 0))
+    (loop $mix
+      (local.set $t (i32.load8_u (local.get $i)))
+      (local.set $j
+        (i32.and
+          (i32.add
+            (i32.add (local.get $j) (local.get $t))
+            (i32.load8_u (i32.add (i32.const 256) (i32.and (local.get $i) (i32.const 15)))))
+          (i32.const 255)))
+      (i32.store8 (local.get $i) (i32.load8_u (local.get $j)))
+      (i32.store8 (local.get $j) (local.get $t))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $mix (i32.lt_u (local.get $i) (i32.const 256)))))
+
+  ;; XOR the keystream into the buffer at 65536, and return a checksum of it.
+  (func $encrypt (result i32)
+    (local $i i32) (local $j i32) (local $n i32) (local $t i32) (local $sum i32)
+    (loop $byte
+      (local.set $i (i32.and (i32.add (local.get $i) (i32.const 1)) (i32.const 255)))
+      (local.set $t (i32.load8_u (local.get $i)))
+      (local.set $j (i32.and (i32.add (local.get $j) (local.get $t)) (i32.const 255)))
+      (i32.store8 (local.get $i) (i32.load8_u (local.get $j)))
+      (i32.store8 (local.get $j) (local.get $t))
+      (local.set $t
+        (i32.xor
+          (i32.load8_u (i32.add (i32.const 65536) (local.get $n)))
+          (i32.load8_u
+            (i32.and
+              (i32.add (local.get $t) (i32.load8_u (local.get $i)))
+              (i32.const 255)))))
+      (i32.store8 (i32.add (i32.const 65536) (local.get $n)) (local.get $t))
+      (local.set $sum (i32.add (local.get $sum) (local.get $t)))
+      (local.set $n (i32.add (local.get $n) (i32.const 1)))
+      (br_if $byte (i32.lt_u (local.get $n) (i32.const 65536))))
+    (local.get $sum))
+
+  (func (export "run") (result i32)
+    (local $round i32) (local $sum i32)
+    (call $key_schedule)
+    (loop $rounds
+      (local.set $sum (i32.add (local.get $sum) (call $encrypt)))
+      (local.set $round (i32.add (local.get $round) (i32.const 1)))
+      (br_if $rounds (i32.lt_u (local.get $round) (i32.const 64))))
+    (local.get $sum))
+)
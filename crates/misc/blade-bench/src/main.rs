@@ -0,0 +1,188 @@
+//! Measure the runtime overhead of the Blade Spectre mitigations.
+//!
+//! Every benchmark module is compiled once in each Blade mode, and then run a number of times.
+//! The median running time in each mode is reported relative to the first mode which succeeds,
+//! along with the number of mitigations Blade inserted into the module.
+//!
+//! A benchmark module either exports a `run` function taking no parameters, or is a WASI command,
+//! whose `_start` function is run without access to stdio or the file system. Each run uses a
+//! fresh instance, and only the call of the function is timed.
+
+use anyhow::{bail, Context as _, Result};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use wasmtime::{Config, Engine, Func, Linker, Module, Store, Trap};
+use wasmtime_wasi::{Wasi, WasiCtxBuilder};
+
+/// Measure the runtime overhead of the Blade Spectre mitigations.
+#[derive(StructOpt)]
+#[structopt(name = "blade-bench")]
+struct Options {
+    /// Number of timed runs of each benchmark in each mode
+    #[structopt(short = "n", long, default_value = "10")]
+    iterations: usize,
+
+    /// Blade modes to compare, separated by commas; slowdowns are relative
+    /// to the first one
+    #[structopt(
+        long,
+        use_delimiter = true,
+        default_value = "none,lfence,lfence_per_block,slh"
+    )]
+    modes: Vec<String>,
+
+    /// Other Cranelift flags to compile the benchmarks with in every mode,
+    /// as `name=value`
+    #[structopt(long, parse(try_from_str = parse_cranelift_flag))]
+    cranelift_flags: Vec<(String, String)>,
+
+    /// Benchmark modules (`*.wasm` or `*.wat`), or directories of them;
+    /// defaults to the benchmarks bundled with this crate
+    #[structopt(parse(from_os_str))]
+    benchmarks: Vec<PathBuf>,
+}
+
+/// The measurements of a benchmark compiled in one Blade mode.
+struct Measurement {
+    compile_time: Duration,
+    run_time: Duration,
+    fences: usize,
+    slh_loads: usize,
+}
+
+fn main() -> Result<()> {
+    let options = Options::from_args();
+    if options.iterations == 0 {
+        bail!("the number of iterations must be positive");
+    }
+    let benchmarks = find_benchmarks(&options.benchmarks)?;
+
+    println!(
+        "{:<24} {:<18} {:>12} {:>12} {:>9} {:>8} {:>10}",
+        "benchmark", "mode", "compile", "run", "slowdown", "fences", "slh loads"
+    );
+    for path in &benchmarks {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let mut baseline = None;
+        for mode in &options.modes {
+            let mut line = format!("{:<24} {:<18} ", name, mode);
+            match measure(path, &name, mode, &options) {
+                Ok(m) => {
+                    let baseline = *baseline.get_or_insert(m.run_time);
+                    write!(
+                        line,
+                        "{:>12} {:>12} {:>8.2}x {:>8} {:>10}",
+                        format_duration(m.compile_time),
+                        format_duration(m.run_time),
+                        m.run_time.as_secs_f64() / baseline.as_secs_f64(),
+                        m.fences,
+                        m.slh_loads
+                    )?;
+                }
+                Err(e) => write!(line, "failed: {:#}", e)?,
+            }
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Compile the benchmark `name` at `path` in Blade mode `mode`, and run it.
+fn measure(path: &Path, name: &str, mode: &str, options: &Options) -> Result<Measurement> {
+    let mut config = Config::new();
+    unsafe {
+        config.cranelift_other_flag("blade", mode)?;
+        for (name, value) in &options.cranelift_flags {
+            config.cranelift_other_flag(name, value)?;
+        }
+    }
+    let engine = Engine::new(&config);
+
+    let start = Instant::now();
+    let module = Module::from_file(&engine, path)?;
+    let compile_time = start.elapsed();
+    let report = module.blade_report();
+
+    let mut run_times = Vec::with_capacity(options.iterations);
+    for _ in 0..options.iterations {
+        let store = Store::new(&engine);
+        let mut linker = Linker::new(&store);
+        let wasi = Wasi::new(&store, WasiCtxBuilder::new().arg(name).build()?);
+        wasi.add_to_linker(&mut linker)?;
+        let instance = linker.instantiate(&module)?;
+        let func = instance
+            .get_func("run")
+            .or_else(|| instance.get_func("_start"))
+            .context("the benchmark exports neither `run` nor `_start`")?;
+        run_times.push(time_call(&func)?);
+    }
+    run_times.sort();
+
+    Ok(Measurement {
+        compile_time,
+        run_time: run_times[run_times.len() / 2],
+        fences: report.total_fences(),
+        slh_loads: report.total_slh_loads(),
+    })
+}
+
+/// Call `func`, ignoring its results, and return how long it ran.
+///
+/// A WASI command exiting with status zero is a success.
+fn time_call(func: &Func) -> Result<Duration> {
+    if !func.ty().params().is_empty() {
+        bail!("the benchmark function must not take parameters");
+    }
+    let start = Instant::now();
+    let result = func.call(&[]);
+    let elapsed = start.elapsed();
+    match result {
+        Ok(_) => Ok(elapsed),
+        Err(e) => match e.downcast_ref::<Trap>().and_then(Trap::i32_exit_status) {
+            Some(0) => Ok(elapsed),
+            _ => Err(e),
+        },
+    }
+}
+
+/// Collect the benchmark modules among `paths`, looking for them inside directories.
+fn find_benchmarks(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let bundled = [Path::new(env!("CARGO_MANIFEST_DIR")).join("benchmarks")];
+    let paths = if paths.is_empty() { &bundled } else { paths };
+
+    let mut benchmarks = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            benchmarks.push(path.clone());
+            continue;
+        }
+        let mut modules = Vec::new();
+        for entry in path
+            .read_dir()
+            .with_context(|| format!("failed to read directory {}", path.display()))?
+        {
+            let module = entry?.path();
+            match module.extension().and_then(|s| s.to_str()) {
+                Some("wasm") | Some("wat") => modules.push(module),
+                _ => {}
+            }
+        }
+        modules.sort();
+        benchmarks.extend(modules);
+    }
+    Ok(benchmarks)
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3}ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn parse_cranelift_flag(name_and_value: &str) -> Result<(String, String)> {
+    let mut split = name_and_value.splitn(2, '=');
+    match (split.next(), split.next()) {
+        (Some(name), Some(value)) => Ok((name.to_string(), value.to_string())),
+        _ => bail!("must be of the form `name=value`"),
+    }
+}
@@ -0,0 +1,414 @@
+//! The Blade metadata of an object file.
+//!
+//! Object files record the `blade` settings they were compiled with, and a
+//! summary of the Spectre mitigations placed in every function, in a section
+//! of their own. This lets operators check that a deployed object file was
+//! actually built with the intended protections, without recompiling it.
+//!
+//! The section holds UTF-8 text, so that tools like `objdump -s` show it as
+//! is. The first line is a version header, followed by one line per setting,
+//! in the same format as Cranelift displays its settings:
+//!
+//! ```text
+//! wasmtime-blade 1
+//! setting blade = "lfence"
+//! setting blade_coarsen = false
+//! function index=2 fences=3 slh_loads=0 fell_back=false diagnostics=1 unprotected=0 hardened=true transient_params=false stable_call_args=true
+//! ```
+//!
+//! and one line per defined function. The last three fields of a function
+//! record its Blade contract, so that linkers and loaders can check that its
+//! callers and callees were compiled with compatible settings.
+//!
+//! A *trace* of the decisions of Blade can also be written alongside the
+//! object file, so that external tools, such as speculative constant-time
+//! checkers, can independently validate the emitted code against the
+//! speculation policy. It is also UTF-8 text, with a version header and one
+//! line per function, followed by one line per load producing a transient
+//! value, instruction leaking its operands, and mitigation in the function:
+//!
+//! ```text
+//! wasmtime-blade-trace 1
+//! function index=2 symbol=_wasm_function_2
+//! source offset=4 srcloc=0x1c
+//! sink offset=12 srcloc=0x21
+//! fence_after offset=4 srcloc=0x1c
+//! ```
+//!
+//! Offsets are those of the instructions, in bytes from the function symbol,
+//! so that a barrier after an instruction follows the code at that offset.
+//! Source locations are offsets in the WebAssembly module. Either is `-` when
+//! unknown, e.g. the new backends don't record code offsets.
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use object::write::{Object, StandardSegment};
+use object::{Object as _, ObjectSection as _, SectionKind};
+use wasmtime_environ::blade::{BladeContract, BladeStats, MitigationKind};
+use wasmtime_environ::entity::EntityRef;
+use wasmtime_environ::ir::SourceLoc;
+use wasmtime_environ::settings;
+use wasmtime_environ::{Compilation, Module};
+
+/// The name of the section holding the Blade metadata.
+pub const BLADE_SECTION: &str = ".wasmtime.blade";
+
+/// The version of the format of the Blade metadata written by this crate.
+const VERSION_HEADER: &str = "wasmtime-blade 1";
+
+/// The Blade settings an object file was compiled with, and the mitigations
+/// placed in each of its functions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BladeMetadata {
+    /// The name and value of every `blade` setting, in declaration order.
+    /// Enumeration values are unquoted.
+    pub settings: Vec<(String, String)>,
+    /// The summary of every defined function, in index order.
+    pub functions: Vec<FunctionBladeMetadata>,
+}
+
+impl BladeMetadata {
+    /// Get the value of the setting `name`, if the metadata records it.
+    pub fn setting(&self, name: &str) -> Option<&str> {
+        self.settings
+            .iter()
+            .find(|(setting, _)| setting == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Get the total number of speculation barriers in the object file.
+    pub fn total_fences(&self) -> usize {
+        self.functions.iter().map(|f| f.fences).sum()
+    }
+
+    /// Get the total number of loads protected with speculative load hardening
+    /// in the object file.
+    pub fn total_slh_loads(&self) -> usize {
+        self.functions.iter().map(|f| f.slh_loads).sum()
+    }
+
+    /// Did Blade protect every function as requested?
+    pub fn is_fully_protected(&self) -> bool {
+        self.functions.iter().all(|f| f.unprotected == 0)
+    }
+}
+
+/// The summary of the Spectre mitigations placed in a function.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FunctionBladeMetadata {
+    /// The index of the function in the module, counting imported functions.
+    pub func_index: u32,
+    /// The number of speculation barriers.
+    pub fences: usize,
+    /// The number of loads protected with speculative load hardening.
+    pub slh_loads: usize,
+    /// Whether every basic block was fenced instead of the cut points.
+    pub fell_back: bool,
+    /// The number of instructions Blade skipped or only approximated.
+    pub diagnostics: usize,
+    /// The number of those approximations which may let transient values leak.
+    pub unprotected: usize,
+    /// What the function relies on from its callers and guarantees to its
+    /// callees.
+    pub contract: BladeContract,
+}
+
+/// Collect the Blade metadata of a compiled module.
+pub fn blade_metadata(
+    module: &Module,
+    compilation: &Compilation,
+    flags: &settings::Flags,
+) -> BladeMetadata {
+    let settings = flags
+        .to_string()
+        .lines()
+        .filter_map(|line| {
+            let mut split = line.splitn(2, " = ");
+            let name = split.next()?;
+            let value = split.next()?;
+            if name.starts_with("blade") {
+                Some((name.to_string(), value.trim_matches('"').to_string()))
+            } else {
+                None
+            }
+        })
+        .collect();
+    let functions = compilation
+        .get_blade_stats()
+        .iter()
+        .map(|(defined_index, stats)| FunctionBladeMetadata {
+            func_index: module.local.func_index(defined_index).index() as u32,
+            fences: stats.fences(),
+            slh_loads: stats.slh_loads(),
+            fell_back: stats.fell_back,
+            diagnostics: stats.diagnostics.len(),
+            unprotected: stats
+                .diagnostics
+                .iter()
+                .filter(|d| d.kind.is_unprotected())
+                .count(),
+            contract: stats.contract,
+        })
+        .collect();
+    BladeMetadata {
+        settings,
+        functions,
+    }
+}
+
+/// The version of the format of the Blade traces written by this crate.
+const TRACE_HEADER: &str = "wasmtime-blade-trace 1";
+
+/// Format a trace of the Blade decisions in every function of a compiled
+/// module, for external checkers.
+pub fn blade_trace(module: &Module, compilation: &Compilation) -> String {
+    let mut text = format!("{}\n", TRACE_HEADER);
+    for (defined_index, stats) in compilation.get_blade_stats().iter() {
+        let func_index = module.local.func_index(defined_index).index();
+        format_function_trace(&mut text, func_index, stats);
+    }
+    text
+}
+
+fn format_function_trace(text: &mut String, func_index: usize, stats: &BladeStats) {
+    text.push_str(&format!(
+        "function index={} symbol=_wasm_function_{}\n",
+        func_index, func_index
+    ));
+    let sites = stats
+        .source_sites
+        .iter()
+        .map(|site| ("source", site.offset, site.srcloc))
+        .chain(
+            stats
+                .sink_sites
+                .iter()
+                .map(|site| ("sink", site.offset, site.srcloc)),
+        );
+    let mitigations = stats.mitigations.iter().map(|m| {
+        let kind = match m.kind {
+            MitigationKind::FenceBefore => "fence_before",
+            MitigationKind::FenceAfter => "fence_after",
+            MitigationKind::Slh => "slh",
+        };
+        (kind, m.offset, m.srcloc)
+    });
+    for (kind, offset, srcloc) in sites.chain(mitigations) {
+        text.push_str(&format!(
+            "{} offset={} srcloc={}\n",
+            kind,
+            offset.map_or("-".to_string(), |offset| offset.to_string()),
+            trace_srcloc(srcloc)
+        ));
+    }
+}
+
+fn trace_srcloc(srcloc: SourceLoc) -> String {
+    if srcloc.is_default() {
+        "-".to_string()
+    } else {
+        format!("{:#x}", srcloc.bits())
+    }
+}
+
+/// Format the Blade metadata as the contents of its section.
+fn format_metadata(metadata: &BladeMetadata) -> String {
+    let mut text = format!("{}\n", VERSION_HEADER);
+    for (name, value) in &metadata.settings {
+        if value.parse::<u64>().is_ok() || value == "true" || value == "false" {
+            text.push_str(&format!("setting {} = {}\n", name, value));
+        } else {
+            text.push_str(&format!("setting {} = \"{}\"\n", name, value));
+        }
+    }
+    for f in &metadata.functions {
+        text.push_str(&format!(
+            "function index={} fences={} slh_loads={} fell_back={} diagnostics={} unprotected={} \
+             hardened={} transient_params={} stable_call_args={}\n",
+            f.func_index,
+            f.fences,
+            f.slh_loads,
+            f.fell_back,
+            f.diagnostics,
+            f.unprotected,
+            f.contract.hardened,
+            f.contract.transient_params,
+            f.contract.stable_call_args
+        ));
+    }
+    text
+}
+
+/// Parse the contents of the Blade metadata section.
+fn parse_metadata(text: &str) -> Result<BladeMetadata> {
+    let mut lines = text.lines();
+    match lines.next() {
+        Some(VERSION_HEADER) => {}
+        Some(header) => bail!("unsupported Blade metadata version `{}`", header),
+        None => bail!("empty Blade metadata"),
+    }
+    let mut metadata = BladeMetadata::default();
+    for line in lines {
+        if let Some(setting) = strip_prefix(line, "setting ") {
+            let mut split = setting.splitn(2, " = ");
+            let (name, value) = match (split.next(), split.next()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => bail!("malformed Blade setting `{}`", line),
+            };
+            metadata
+                .settings
+                .push((name.to_string(), value.trim_matches('"').to_string()));
+        } else if let Some(function) = strip_prefix(line, "function ") {
+            metadata.functions.push(
+                parse_function(function)
+                    .with_context(|| format!("malformed Blade function summary `{}`", line))?,
+            );
+        } else {
+            bail!("unexpected Blade metadata `{}`", line);
+        }
+    }
+    Ok(metadata)
+}
+
+fn parse_function(fields: &str) -> Result<FunctionBladeMetadata> {
+    let mut function = FunctionBladeMetadata::default();
+    for field in fields.split(' ') {
+        let mut split = field.splitn(2, '=');
+        let (key, value) = match (split.next(), split.next()) {
+            (Some(key), Some(value)) => (key, value),
+            _ => bail!("missing value for `{}`", field),
+        };
+        match key {
+            "index" => function.func_index = value.parse()?,
+            "fences" => function.fences = value.parse()?,
+            "slh_loads" => function.slh_loads = value.parse()?,
+            "fell_back" => function.fell_back = value.parse()?,
+            "diagnostics" => function.diagnostics = value.parse()?,
+            "unprotected" => function.unprotected = value.parse()?,
+            "hardened" => function.contract.hardened = value.parse()?,
+            "transient_params" => function.contract.transient_params = value.parse()?,
+            "stable_call_args" => function.contract.stable_call_args = value.parse()?,
+            // Ignore the fields added by later versions.
+            _ => {}
+        }
+    }
+    Ok(function)
+}
+
+fn strip_prefix<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    if line.starts_with(prefix) {
+        Some(&line[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+/// Emit the Blade metadata section into `obj`.
+pub fn emit_blade_metadata(obj: &mut Object, metadata: &BladeMetadata) {
+    let segment = obj.segment_name(StandardSegment::Data).to_vec();
+    let section_id = obj.add_section(
+        segment,
+        BLADE_SECTION.as_bytes().to_vec(),
+        SectionKind::Other,
+    );
+    obj.append_section_data(section_id, format_metadata(metadata).as_bytes(), 1);
+}
+
+/// Read the Blade metadata back from the bytes of an object file.
+///
+/// Returns `None` for object files without Blade metadata, such as those
+/// written by earlier versions.
+pub fn read_blade_metadata(data: &[u8]) -> Result<Option<BladeMetadata>> {
+    let file = object::File::parse(data).map_err(|e| anyhow!("{}", e))?;
+    let section = match file.section_by_name(BLADE_SECTION) {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    let bytes = section.data().map_err(|e| anyhow!("{}", e))?;
+    let text = std::str::from_utf8(bytes).context("Blade metadata is not UTF-8")?;
+    parse_metadata(text).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let metadata = BladeMetadata {
+            settings: vec![
+                ("blade".to_string(), "slh".to_string()),
+                ("blade_max_edges_log2".to_string(), "20".to_string()),
+                ("blade_coarsen".to_string(), "false".to_string()),
+            ],
+            functions: vec![
+                FunctionBladeMetadata {
+                    func_index: 1,
+                    fences: 2,
+                    slh_loads: 3,
+                    fell_back: false,
+                    diagnostics: 1,
+                    unprotected: 1,
+                    contract: BladeContract {
+                        hardened: true,
+                        transient_params: false,
+                        stable_call_args: true,
+                    },
+                },
+                FunctionBladeMetadata {
+                    func_index: 2,
+                    fell_back: true,
+                    ..Default::default()
+                },
+            ],
+        };
+        let text = format_metadata(&metadata);
+        assert!(text.contains("setting blade = \"slh\"\n"));
+        assert!(text.contains("setting blade_coarsen = false\n"));
+        assert!(text.contains("hardened=true transient_params=false stable_call_args=true\n"));
+        assert_eq!(parse_metadata(&text).unwrap(), metadata);
+        assert_eq!(metadata.setting("blade"), Some("slh"));
+        assert_eq!(metadata.total_fences(), 2);
+        assert!(!metadata.is_fully_protected());
+
+        assert!(parse_metadata("wasmtime-blade 2\n").is_err());
+        assert!(parse_metadata(&format!("{}\nfunction index=x\n", VERSION_HEADER)).is_err());
+    }
+
+    #[test]
+    fn trace() {
+        use wasmtime_environ::blade::{BladeMitigation, BladeSite};
+        use wasmtime_environ::entity::EntityRef;
+        use wasmtime_environ::ir::Inst;
+
+        let load = Inst::new(1);
+        let store = Inst::new(3);
+        let stats = BladeStats {
+            source_sites: vec![BladeSite {
+                inst: load,
+                srcloc: SourceLoc::new(0x1c),
+                offset: Some(4),
+            }],
+            sink_sites: vec![BladeSite {
+                inst: store,
+                srcloc: SourceLoc::new(0x21),
+                offset: None,
+            }],
+            mitigations: vec![BladeMitigation {
+                inst: load,
+                kind: MitigationKind::FenceAfter,
+                srcloc: SourceLoc::new(0x1c),
+                offset: Some(4),
+            }],
+            ..BladeStats::default()
+        };
+        let mut text = String::new();
+        format_function_trace(&mut text, 2, &stats);
+        assert_eq!(
+            text,
+            "function index=2 symbol=_wasm_function_2\n\
+             source offset=4 srcloc=0x1c\n\
+             sink offset=- srcloc=0x21\n\
+             fence_after offset=4 srcloc=0x1c\n"
+        );
+    }
+}
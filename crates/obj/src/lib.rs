@@ -23,12 +23,17 @@
     )
 )]
 
+mod blade;
 mod context;
 mod data_segment;
 mod function;
 mod module;
 mod table;
 
+pub use crate::blade::{
+    blade_metadata, blade_trace, emit_blade_metadata, read_blade_metadata, BladeMetadata,
+    FunctionBladeMetadata, BLADE_SECTION,
+};
 pub use crate::module::emit_module;
 
 /// Version number of this crate.
@@ -10,8 +10,12 @@
 //!     Report
 //!         sudo perf report -i perf.jit.data -F+period,srcline
 //! Note: For descriptive results, the WASM file being executed should contain dwarf debug data
+//!
+//! Without dwarf debug data, the instructions protected by Blade get debug entries of their own,
+//! named after their mitigations and numbered by their Wasm bytecode offset, so that the
+//! srcline of `perf report` and `perf annotate` shows which hot code carries them.
 
-use crate::ProfilingAgent;
+use crate::{BladeMarker, ProfilingAgent};
 use anyhow::Result;
 use object::{Object, ObjectSection};
 use scroll::{IOwrite, SizeWith, NATIVE};
@@ -25,6 +29,7 @@ use std::ptr;
 use std::sync::Mutex;
 use std::{borrow, mem, process};
 use target_lexicon::Architecture;
+use wasmtime_environ::blade::BladeStats;
 use wasmtime_environ::entity::PrimaryMap;
 use wasmtime_environ::wasm::DefinedFuncIndex;
 use wasmtime_environ::Module;
@@ -206,12 +211,13 @@ impl ProfilingAgent for JitDumpAgent {
         &self,
         module: &Module,
         functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        blade_stats: &PrimaryMap<DefinedFuncIndex, BladeStats>,
         dbg_image: Option<&[u8]>,
     ) {
         self.state
             .lock()
             .unwrap()
-            .module_load(module, functions, dbg_image);
+            .module_load(module, functions, blade_stats, dbg_image);
     }
 }
 
@@ -304,6 +310,7 @@ impl State {
         &mut self,
         module: &Module,
         functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        blade_stats: &PrimaryMap<DefinedFuncIndex, BladeStats>,
         dbg_image: Option<&[u8]>,
     ) -> () {
         let pid = process::id();
@@ -321,6 +328,10 @@ impl State {
             } else {
                 let timestamp = self.get_time_stamp();
                 let name = super::debug_name(module, idx);
+                let markers = super::blade_markers(blade_stats.get(idx));
+                if !markers.is_empty() {
+                    self.dump_blade_markers(&name, addr, &markers, timestamp);
+                }
                 self.dump_code_load_record(&name, addr, len, timestamp, pid, tid);
             }
         }
@@ -363,6 +374,37 @@ impl State {
         }
     }
 
+    /// Dump a debug info record for the function named `method_name` at `addr`, giving each of
+    /// `markers` its own entry. Every other instruction maps to line 0 of the function itself.
+    fn dump_blade_markers(
+        &mut self,
+        method_name: &str,
+        addr: *const u8,
+        markers: &[BladeMarker],
+        timestamp: u64,
+    ) {
+        let address = addr as u64;
+        let entry = |offset: u32, line: u32, filename: &str| DebugEntry {
+            address: address + u64::from(offset),
+            line,
+            discriminator: 0,
+            filename: filename.to_string(),
+        };
+        let mut debug_entries = Vec::new();
+        if markers.first().map(|marker| marker.offset) != Some(0) {
+            debug_entries.push(entry(0, 0, method_name));
+        }
+        for (i, marker) in markers.iter().enumerate() {
+            debug_entries.push(entry(marker.offset, marker.srcloc, &marker.label));
+            // The marker only covers the instruction at its offset.
+            let next = markers.get(i + 1).map(|next| next.offset);
+            if next != Some(marker.offset + 1) {
+                debug_entries.push(entry(marker.offset + 1, 0, method_name));
+            }
+        }
+        self.dump_debug_entries(address, debug_entries, timestamp);
+    }
+
     /// Attempts to dump debuginfo data structures, adding method and line level
     /// for the jitted function.
     pub fn dump_from_debug_image(
@@ -598,18 +640,7 @@ impl State {
     ) -> Result<()> {
         let timestamp = self.get_time_stamp();
         if let Some(program) = unit.line_program.clone() {
-            let mut debug_info_record = DebugInfoRecord {
-                header: RecordHeader {
-                    id: RecordId::JitCodeDebugInfo as u32,
-                    record_size: 0,
-                    timestamp: timestamp,
-                },
-                address: address,
-                count: 0,
-            };
-
             let mut debug_entries = Vec::new();
-            let mut debug_entries_total_filenames_len = 0;
             let mut rows = program.rows();
             while let Some((header, row)) = rows.next_row()? {
                 let row_file_index = row.file_index() - 1;
@@ -641,23 +672,37 @@ impl State {
                     debug_entry.filename.push_str(suffix);
                 }
 
-                debug_entries_total_filenames_len += debug_entry.filename.len() + 1;
                 debug_entries.push(debug_entry);
             }
 
-            debug_info_record.count = debug_entries.len() as u64;
-
-            let debug_entries_size = (debug_info_record.count
-                * (mem::size_of::<DebugEntry>() as u64 - mem::size_of::<String>() as u64))
-                + debug_entries_total_filenames_len as u64;
-            debug_info_record.header.record_size =
-                mem::size_of::<DebugInfoRecord>() as u32 + debug_entries_size as u32;
-
-            let _ = self.write_debug_info_record(debug_info_record);
-            let _ = self.write_debug_info_entries(debug_entries);
+            self.dump_debug_entries(address, debug_entries, timestamp);
         }
         Ok(())
     }
+
+    /// Dump a debug info record with `debug_entries` for the jitted function at `address`.
+    fn dump_debug_entries(&mut self, address: u64, debug_entries: Vec<DebugEntry>, timestamp: u64) {
+        let debug_entries_total_filenames_len: usize = debug_entries
+            .iter()
+            .map(|entry| entry.filename.len() + 1)
+            .sum();
+        let count = debug_entries.len() as u64;
+        let debug_entries_size = (count
+            * (mem::size_of::<DebugEntry>() as u64 - mem::size_of::<String>() as u64))
+            + debug_entries_total_filenames_len as u64;
+        let debug_info_record = DebugInfoRecord {
+            header: RecordHeader {
+                id: RecordId::JitCodeDebugInfo as u32,
+                record_size: mem::size_of::<DebugInfoRecord>() as u32 + debug_entries_size as u32,
+                timestamp,
+            },
+            address,
+            count,
+        };
+
+        let _ = self.write_debug_info_record(debug_info_record);
+        let _ = self.write_debug_info_entries(debug_entries);
+    }
 }
 
 impl Drop for State {
@@ -1,5 +1,6 @@
 use std::error::Error;
 use std::fmt;
+use wasmtime_environ::blade::BladeStats;
 use wasmtime_environ::entity::{EntityRef, PrimaryMap};
 use wasmtime_environ::wasm::DefinedFuncIndex;
 use wasmtime_environ::Module;
@@ -31,10 +32,14 @@ pub use crate::vtune::VTuneAgent;
 /// Common interface for profiling tools.
 pub trait ProfilingAgent: Send + Sync + 'static {
     /// Notify the profiler of a new module loaded into memory
+    ///
+    /// `blade_stats` has the Spectre mitigations Blade placed in every function, which
+    /// profilers mark in the code of the function when their offsets are known.
     fn module_load(
         &self,
         module: &Module,
         functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        blade_stats: &PrimaryMap<DefinedFuncIndex, BladeStats>,
         dbg_image: Option<&[u8]>,
     ) -> ();
 }
@@ -65,6 +70,7 @@ impl ProfilingAgent for NullProfilerAgent {
         &self,
         _module: &Module,
         _functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        _blade_stats: &PrimaryMap<DefinedFuncIndex, BladeStats>,
         _dbg_image: Option<&[u8]>,
     ) -> () {
     }
@@ -78,3 +84,99 @@ fn debug_name(module: &Module, index: DefinedFuncIndex) -> String {
         None => format!("wasm::wasm-function[{}]", index.index()),
     }
 }
+
+/// A site of Blade mitigations in the code of a function.
+#[cfg(any(
+    test,
+    all(target_os = "linux", any(feature = "jitdump", feature = "vtune"))
+))]
+struct BladeMarker {
+    /// The offset of the mitigated instruction from the beginning of the function.
+    offset: u32,
+    /// The Wasm bytecode offset of the mitigated instruction, or 0 if unknown.
+    srcloc: u32,
+    /// A description of the mitigations, e.g. `blade: fence before, slh`.
+    label: String,
+}
+
+/// Get the sites of the Blade mitigations in `stats` whose code offset is known, in code order.
+/// Several mitigations of the same instruction share a single site.
+#[cfg(any(
+    test,
+    all(target_os = "linux", any(feature = "jitdump", feature = "vtune"))
+))]
+fn blade_markers(stats: Option<&BladeStats>) -> Vec<BladeMarker> {
+    let mut mitigations: Vec<_> = stats
+        .map_or(&[][..], |stats| &stats.mitigations)
+        .iter()
+        .filter_map(|mitigation| mitigation.offset.map(|offset| (offset, mitigation)))
+        .collect();
+    mitigations.sort_by_key(|&(offset, _)| offset);
+
+    let mut markers: Vec<BladeMarker> = Vec::new();
+    for (offset, mitigation) in mitigations {
+        match markers.last_mut() {
+            Some(marker) if marker.offset == offset => {
+                marker.label.push_str(&format!(", {}", mitigation.kind));
+            }
+            _ => markers.push(BladeMarker {
+                offset,
+                srcloc: if mitigation.srcloc.is_default() {
+                    0
+                } else {
+                    mitigation.srcloc.bits()
+                },
+                label: format!("blade: {}", mitigation.kind),
+            }),
+        }
+    }
+    markers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::blade_markers;
+    use wasmtime_environ::blade::{BladeMitigation, BladeStats, MitigationKind};
+    use wasmtime_environ::entity::EntityRef;
+    use wasmtime_environ::ir::{Inst, SourceLoc};
+
+    #[test]
+    fn markers() {
+        let mitigation = |inst, kind, srcloc, offset| BladeMitigation {
+            inst: Inst::new(inst),
+            kind,
+            srcloc,
+            offset,
+        };
+        let stats = BladeStats {
+            mitigations: vec![
+                mitigation(2, MitigationKind::Slh, SourceLoc::new(0x20), Some(8)),
+                mitigation(
+                    0,
+                    MitigationKind::FenceBefore,
+                    SourceLoc::default(),
+                    Some(0),
+                ),
+                mitigation(1, MitigationKind::FenceBefore, SourceLoc::new(0x10), None),
+                mitigation(2, MitigationKind::FenceAfter, SourceLoc::new(0x20), Some(8)),
+            ],
+            ..BladeStats::default()
+        };
+
+        // Sites are sorted by offset, mitigations without one are left out, and the mitigations
+        // of the same instruction are merged.
+        let markers = blade_markers(Some(&stats));
+        let markers: Vec<_> = markers
+            .iter()
+            .map(|marker| (marker.offset, marker.srcloc, marker.label.as_str()))
+            .collect();
+        assert_eq!(
+            markers,
+            [
+                (0, 0, "blade: fence before"),
+                (8, 0x20, "blade: slh, fence after"),
+            ]
+        );
+        assert!(blade_markers(None).is_empty());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::ProfilingAgent;
 use anyhow::{bail, Result};
+use wasmtime_environ::blade::BladeStats;
 use wasmtime_environ::entity::PrimaryMap;
 use wasmtime_environ::wasm::DefinedFuncIndex;
 use wasmtime_environ::Module;
@@ -27,6 +28,7 @@ impl ProfilingAgent for VTuneAgent {
         &self,
         _module: &Module,
         _functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        _blade_stats: &PrimaryMap<DefinedFuncIndex, BladeStats>,
         _dbg_image: Option<&[u8]>,
     ) {
     }
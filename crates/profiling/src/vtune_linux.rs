@@ -6,14 +6,19 @@
 //!     amplxe-cl -run-pass-thru=--no-altstack -v -collect hotspots target/debug/wasmtime --vtune test.wasm
 //!
 //! Note: amplxe-cl is a command-line tool for Vtune which should be installed.
+//!
+//! The instructions protected by Blade are reported as source lines numbered by their Wasm
+//! bytecode offset, and all other code as line 0, so that the source view of a function separates
+//! the cost of its mitigation sites from the rest.
 
-use crate::ProfilingAgent;
+use crate::{BladeMarker, ProfilingAgent};
 use anyhow::Result;
 use core::ptr;
 use ittapi_rs::*;
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::sync::Mutex;
+use wasmtime_environ::blade::BladeStats;
 use wasmtime_environ::entity::PrimaryMap;
 use wasmtime_environ::wasm::DefinedFuncIndex;
 use wasmtime_environ::Module;
@@ -74,7 +79,9 @@ impl State {
         method_name: &str,
         addr: *const u8,
         len: usize,
+        markers: &[BladeMarker],
     ) -> () {
+        let mut line_numbers = line_number_table(markers);
         let mut jmethod = _iJIT_Method_Load {
             method_id: method_id,
             method_name: CString::new(method_name)
@@ -82,8 +89,12 @@ impl State {
                 .into_raw(),
             method_load_address: addr as *mut ::std::os::raw::c_void,
             method_size: len as u32,
-            line_number_size: 0,
-            line_number_table: ptr::null_mut(),
+            line_number_size: line_numbers.len() as u32,
+            line_number_table: if line_numbers.is_empty() {
+                ptr::null_mut()
+            } else {
+                line_numbers.as_mut_ptr()
+            },
             class_id: 0,
             class_file_name: CString::new(module_name)
                 .expect("CString::new failed")
@@ -116,12 +127,13 @@ impl ProfilingAgent for VTuneAgent {
         &self,
         module: &Module,
         functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        blade_stats: &PrimaryMap<DefinedFuncIndex, BladeStats>,
         dbg_image: Option<&[u8]>,
     ) {
         self.state
             .lock()
             .unwrap()
-            .module_load(module, functions, dbg_image);
+            .module_load(module, functions, blade_stats, dbg_image);
     }
 }
 
@@ -130,6 +142,7 @@ impl State {
         &mut self,
         module: &Module,
         functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        blade_stats: &PrimaryMap<DefinedFuncIndex, BladeStats>,
         _dbg_image: Option<&[u8]>,
     ) -> () {
         for (idx, func) in functions.iter() {
@@ -150,7 +163,68 @@ impl State {
                 &method_name,
                 addr,
                 len,
+                &super::blade_markers(blade_stats.get(idx)),
             );
         }
     }
 }
+
+/// Build the line number table of a function with `markers`, or an empty one without any.
+fn line_number_table(markers: &[BladeMarker]) -> Vec<_LineNumberInfo> {
+    let mut table = Vec::new();
+    if markers.is_empty() {
+        return table;
+    }
+    if markers[0].offset != 0 {
+        table.push(_LineNumberInfo {
+            Offset: 0,
+            LineNumber: 0,
+        });
+    }
+    for (i, marker) in markers.iter().enumerate() {
+        table.push(_LineNumberInfo {
+            Offset: marker.offset,
+            LineNumber: marker.srcloc,
+        });
+        // The marker only covers the instruction at its offset.
+        if markers.get(i + 1).map(|next| next.offset) != Some(marker.offset + 1) {
+            table.push(_LineNumberInfo {
+                Offset: marker.offset + 1,
+                LineNumber: 0,
+            });
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::line_number_table;
+    use crate::BladeMarker;
+
+    #[test]
+    fn line_numbers() {
+        let marker = |offset, srcloc| BladeMarker {
+            offset,
+            srcloc,
+            label: String::new(),
+        };
+        let lines = |markers: &[BladeMarker]| -> Vec<(u32, u32)> {
+            line_number_table(markers)
+                .iter()
+                .map(|info| (info.Offset, info.LineNumber))
+                .collect()
+        };
+        assert!(lines(&[]).is_empty());
+
+        // Every marker covers a single offset, and the code around them is line 0.
+        assert_eq!(
+            lines(&[marker(4, 0x20), marker(9, 0x30)]),
+            [(0, 0), (4, 0x20), (5, 0), (9, 0x30), (10, 0)]
+        );
+        assert_eq!(
+            lines(&[marker(0, 0x20), marker(1, 0x30)]),
+            [(0, 0x20), (1, 0x30), (2, 0)]
+        );
+    }
+}
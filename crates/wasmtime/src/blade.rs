@@ -0,0 +1,662 @@
+use std::fmt;
+use std::time::Duration;
+use wasmtime_environ::blade::{BladeCostEstimate, BladeDiagnosticKind, BladeStats, MitigationKind};
+use wasmtime_environ::entity::EntityRef;
+use wasmtime_environ::ir::SourceLoc;
+use wasmtime_jit::CompiledModule;
+
+/// A report of the Spectre mitigations that Blade inserted into a [`Module`].
+///
+/// Blade is enabled and configured with the `blade` family of Cranelift flags
+/// (see [`Config::cranelift_other_flag`]). When it is disabled, every
+/// function of the report is empty.
+///
+/// [`Module`]: crate::Module
+/// [`Config::cranelift_other_flag`]: crate::Config::cranelift_other_flag
+#[derive(Clone, Debug)]
+pub struct BladeReport {
+    functions: Vec<FunctionBladeReport>,
+    contract_violations: Vec<BladeContractViolation>,
+}
+
+impl BladeReport {
+    pub(crate) fn new(compiled: &CompiledModule) -> BladeReport {
+        let module = compiled.module();
+        let functions = compiled
+            .blade_stats()
+            .iter()
+            .map(|(defined_index, stats)| {
+                let index = module.local.func_index(defined_index);
+                let func_name = module.func_names.get(&index).cloned();
+                FunctionBladeReport {
+                    func_index: index.index() as u32,
+                    csv_row: stats.csv_row(func_name.as_deref().unwrap_or("")),
+                    func_name,
+                    values: stats.values,
+                    graph_nodes: stats.graph_nodes,
+                    graph_edges: stats.graph_edges,
+                    sources: stats.sources,
+                    sinks: stats.sinks,
+                    max_flow: stats.max_flow,
+                    cut_size: stats.cut_size,
+                    fences: stats.fences(),
+                    slh_loads: stats.slh_loads(),
+                    slh_insts: stats.slh_insts.len(),
+                    slh_budget_fallbacks: stats.slh_budget_fallbacks,
+                    code_size_growth: stats.code_size_growth,
+                    time: stats.time,
+                    solver_time: stats.solver_time,
+                    fell_back: stats.fell_back,
+                    diagnostics: stats.diagnostics.len(),
+                    estimate: stats.estimate,
+                    mitigations: stats
+                        .mitigations
+                        .iter()
+                        .map(|m| BladeMitigation {
+                            kind: match m.kind {
+                                MitigationKind::FenceBefore => BladeMitigationKind::FenceBefore,
+                                MitigationKind::FenceAfter => BladeMitigationKind::FenceAfter,
+                                MitigationKind::Slh => BladeMitigationKind::Slh,
+                            },
+                            wasm_offset: wasm_offset(m.srcloc),
+                            code_offset: m.offset,
+                        })
+                        .collect(),
+                    approximations: Some(BladeApproximation {
+                        kind: BladeApproximationKind::BudgetFallback,
+                        wasm_offset: None,
+                    })
+                    .filter(|_| stats.fell_back)
+                    .into_iter()
+                    .chain(stats.diagnostics.iter().map(|d| BladeApproximation {
+                        kind: match d.kind {
+                            BladeDiagnosticKind::UnhandledSideEffect => {
+                                BladeApproximationKind::UnhandledSideEffect
+                            }
+                            BladeDiagnosticKind::ImplicitOperand => {
+                                BladeApproximationKind::ImplicitOperand
+                            }
+                            BladeDiagnosticKind::MultipleResults => {
+                                BladeApproximationKind::MultipleResults
+                            }
+                            BladeDiagnosticKind::FakeBounds => BladeApproximationKind::FakeBounds,
+                            BladeDiagnosticKind::SlhFallback => BladeApproximationKind::SlhFallback,
+                        },
+                        wasm_offset: wasm_offset(d.srcloc),
+                    }))
+                    .collect(),
+                }
+            })
+            .collect();
+        // The contracts of imported functions are only known once the module
+        // is instantiated.
+        let contract_violations = compiled
+            .blade_contract_violations(|_| None)
+            .iter()
+            .map(BladeContractViolation::new)
+            .collect();
+        BladeReport {
+            functions,
+            contract_violations,
+        }
+    }
+
+    /// Returns the per-function breakdown of this report, for every function
+    /// defined in the module.
+    pub fn functions(&self) -> &[FunctionBladeReport] {
+        &self.functions
+    }
+
+    /// Returns the total number of speculation barriers inserted in the
+    /// module.
+    pub fn total_fences(&self) -> usize {
+        self.functions.iter().map(|f| f.fences).sum()
+    }
+
+    /// Returns the total number of loads protected with speculative load
+    /// hardening in the module.
+    pub fn total_slh_loads(&self) -> usize {
+        self.functions.iter().map(|f| f.slh_loads).sum()
+    }
+
+    /// Returns the number of functions whose Blade graph was too large, so that
+    /// every basic block was fenced instead.
+    pub fn num_fallbacks(&self) -> usize {
+        self.functions.iter().filter(|f| f.fell_back).count()
+    }
+
+    /// Returns the total number of instructions in the module which Blade
+    /// skipped or only approximated. See [`FunctionBladeReport::diagnostics`].
+    pub fn total_diagnostics(&self) -> usize {
+        self.functions.iter().map(|f| f.diagnostics).sum()
+    }
+
+    /// Returns whether Blade protected every function of the module as
+    /// requested, i.e. none of their approximations may let transient values
+    /// leak. See [`BladeApproximationKind::is_unprotected`].
+    ///
+    /// This only covers the functions Blade ran on: it is vacuously true when
+    /// Blade is disabled.
+    pub fn is_fully_protected(&self) -> bool {
+        self.functions
+            .iter()
+            .flat_map(|f| &f.approximations)
+            .all(|a| !a.kind.is_unprotected())
+    }
+
+    /// Returns a rough static estimate of the run-time slowdown caused by the
+    /// mitigations of the module, as the ratio of the run time of the
+    /// protected code to that of the unprotected code.
+    ///
+    /// Every instruction and mitigation is weighted by the estimated
+    /// execution frequency of its block, from the nesting of loops, and
+    /// mitigations are costed as a fixed number of instructions. This is no
+    /// substitute for a benchmark, but helps choosing between the `blade`
+    /// modes before running one.
+    pub fn estimated_slowdown(&self) -> f64 {
+        self.functions
+            .iter()
+            .map(|f| f.estimate)
+            .sum::<BladeCostEstimate>()
+            .slowdown()
+    }
+
+    /// Returns the calls between functions of the module which were compiled
+    /// with incompatible Blade settings, so that one of them may leak transient
+    /// values which the other relies on being protected.
+    ///
+    /// Blade assumes by default that function arguments are never transient,
+    /// which holds when every caller protects the arguments it passes, see the
+    /// `blade_sink_classes` and `blade_transient_params` Cranelift
+    /// flags. Functions left out by [`Config::blade_only`] or
+    /// [`Config::blade_exclude`] protect nothing, so calls between them and
+    /// hardened functions usually break this. Calls to imported functions are
+    /// only checked when the module is instantiated, see
+    /// [`Config::blade_strict_contracts`].
+    ///
+    /// [`Config::blade_only`]: crate::Config::blade_only
+    /// [`Config::blade_exclude`]: crate::Config::blade_exclude
+    /// [`Config::blade_strict_contracts`]: crate::Config::blade_strict_contracts
+    pub fn contract_violations(&self) -> &[BladeContractViolation] {
+        &self.contract_violations
+    }
+
+    /// Returns the total compilation time spent in Blade for the module.
+    ///
+    /// This is zero for modules loaded from the compilation cache.
+    pub fn total_time(&self) -> Duration {
+        self.functions.iter().map(|f| f.time).sum()
+    }
+
+    /// Returns the per-function statistics of this report as CSV, with a
+    /// header line followed by one line per function.
+    ///
+    /// The columns are the function index, then those of the statistics that
+    /// `clif-util blade --csv` writes for a Cranelift function: its name, the
+    /// number of values in the function, the number of edges in its Blade
+    /// graph, the size of the cut, the number of speculation barriers and of
+    /// SLH loads, whether Blade fell back to fencing every block, the time
+    /// spent in Blade in microseconds, the number of nodes, sources and sinks
+    /// of the graph, the maximum flow through it, the time spent in the
+    /// solver in microseconds, the number of instructions inserted by
+    /// speculative load hardening, and the bytes of code Blade added, which
+    /// is empty when unknown.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("func_index,{}\n", BladeStats::CSV_HEADER);
+        for func in &self.functions {
+            csv.push_str(&format!("{},{}\n", func.func_index, func.csv_row));
+        }
+        csv
+    }
+
+    /// Returns every approximation Blade made in the module as CSV, with a
+    /// header line followed by one line per approximation.
+    ///
+    /// The columns are the function index and name, keyed as in
+    /// [`BladeReport::to_csv`] so that both files can be joined, the kind of the
+    /// approximation as listed in [`BladeApproximationKind::name`], the offset
+    /// of the WebAssembly instruction in the module's binary (empty for whole
+    /// functions and unknown locations), and whether transient values may leak
+    /// through it. A module is fully protected iff the last column is `false`
+    /// on every line.
+    pub fn residual_risk_csv(&self) -> String {
+        let mut csv = String::from("func_index,function,kind,wasm_offset,unprotected\n");
+        for func in &self.functions {
+            let name = csv_field(func.func_name.as_deref().unwrap_or(""));
+            for approximation in &func.approximations {
+                csv.push_str(&format!(
+                    "{},{},{},{},{}\n",
+                    func.func_index,
+                    name,
+                    approximation.kind.name(),
+                    approximation
+                        .wasm_offset
+                        .map_or(String::new(), |offset| offset.to_string()),
+                    approximation.kind.is_unprotected()
+                ));
+            }
+        }
+        csv
+    }
+}
+
+/// Quote `field` for a CSV file if needed.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Get the offset in the module's binary of an instruction at `srcloc`.
+fn wasm_offset(srcloc: SourceLoc) -> Option<usize> {
+    // Cranelift source locations are offsets in the module's bytecode.
+    if srcloc.is_default() {
+        None
+    } else {
+        Some(srcloc.bits() as usize)
+    }
+}
+
+impl fmt::Display for BladeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Blade: {} fences, {} SLH loads, estimated slowdown {:.2}x, {:?} in {} functions \
+             ({} fallbacks, {} diagnostics)",
+            self.total_fences(),
+            self.total_slh_loads(),
+            self.estimated_slowdown(),
+            self.total_time(),
+            self.functions.len(),
+            self.num_fallbacks(),
+            self.total_diagnostics()
+        )?;
+        for violation in &self.contract_violations {
+            writeln!(f, "  contract violation: {}", violation)?;
+        }
+        for func in &self.functions {
+            write!(f, "  func {}", func.func_index)?;
+            if let Some(name) = &func.func_name {
+                write!(f, " <{}>", name)?;
+            }
+            write!(
+                f,
+                ": cut size {}, {} fences, {} SLH loads, {:?}",
+                func.cut_size, func.fences, func.slh_loads, func.time
+            )?;
+            if func.fell_back {
+                write!(f, ", fell back to fencing every block")?;
+            }
+            if func.diagnostics > 0 {
+                write!(f, ", {} diagnostics", func.diagnostics)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// The Spectre mitigations that Blade inserted into a single function of a
+/// [`Module`](crate::Module).
+#[derive(Clone, Debug)]
+pub struct FunctionBladeReport {
+    func_index: u32,
+    func_name: Option<String>,
+    csv_row: String,
+    values: usize,
+    graph_nodes: usize,
+    graph_edges: usize,
+    sources: usize,
+    sinks: usize,
+    max_flow: u64,
+    cut_size: usize,
+    fences: usize,
+    slh_loads: usize,
+    slh_insts: usize,
+    slh_budget_fallbacks: usize,
+    code_size_growth: Option<u32>,
+    time: Duration,
+    solver_time: Duration,
+    fell_back: bool,
+    diagnostics: usize,
+    estimate: BladeCostEstimate,
+    mitigations: Vec<BladeMitigation>,
+    approximations: Vec<BladeApproximation>,
+}
+
+impl FunctionBladeReport {
+    /// Returns the WebAssembly function index of this function.
+    pub fn func_index(&self) -> u32 {
+        self.func_index
+    }
+
+    /// Returns the name of this function from the `name` section, if any.
+    pub fn func_name(&self) -> Option<&str> {
+        self.func_name.as_deref()
+    }
+
+    /// Returns the number of values defined in this function before Blade ran.
+    pub fn values(&self) -> usize {
+        self.values
+    }
+
+    /// Returns the number of nodes in the Blade graph of this function.
+    pub fn graph_nodes(&self) -> usize {
+        self.graph_nodes
+    }
+
+    /// Returns the number of edges in the Blade graph of this function.
+    pub fn graph_edges(&self) -> usize {
+        self.graph_edges
+    }
+
+    /// Returns the number of values of this function which Blade treats as
+    /// transient by themselves, such as the results of loads.
+    pub fn sources(&self) -> usize {
+        self.sources
+    }
+
+    /// Returns the number of instructions of this function which Blade treats
+    /// as leaking some of their operands, such as memory accesses and
+    /// branches.
+    pub fn sinks(&self) -> usize {
+        self.sinks
+    }
+
+    /// Returns the value of the maximum flow through the Blade graph of this
+    /// function, which is the total weight of its minimal cut.
+    pub fn max_flow(&self) -> u64 {
+        self.max_flow
+    }
+
+    /// Returns the number of edges in the minimal cut Blade computed for this
+    /// function.
+    pub fn cut_size(&self) -> usize {
+        self.cut_size
+    }
+
+    /// Returns the number of speculation barriers inserted in this function.
+    pub fn fences(&self) -> usize {
+        self.fences
+    }
+
+    /// Returns the number of loads protected with speculative load hardening
+    /// in this function.
+    pub fn slh_loads(&self) -> usize {
+        self.slh_loads
+    }
+
+    /// Returns the number of instructions inserted in this function by
+    /// speculative load hardening to compute the masks and mask the addresses
+    /// of the loads.
+    pub fn slh_insts(&self) -> usize {
+        self.slh_insts
+    }
+
+    /// Returns the number of loads of this function protected with a
+    /// speculation barrier rather than hardened, because speculative load
+    /// hardening exceeded `blade_slh_size_budget`.
+    pub fn slh_budget_fallbacks(&self) -> usize {
+        self.slh_budget_fallbacks
+    }
+
+    /// Returns the number of bytes of code Blade added to this function: its
+    /// speculation barriers and the instructions inserted by speculative load
+    /// hardening.
+    ///
+    /// This is only known with the old backend, and `None` with the new one.
+    pub fn code_size_growth(&self) -> Option<u32> {
+        self.code_size_growth
+    }
+
+    /// Returns the compilation time spent in Blade for this function, or zero
+    /// if it was loaded from the compilation cache.
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    /// Returns the part of [`time`](Self::time) spent computing the minimal
+    /// cut of the Blade graph of this function.
+    pub fn solver_time(&self) -> Duration {
+        self.solver_time
+    }
+
+    /// Returns whether the Blade graph of this function was too large, so that
+    /// every basic block was fenced instead of the cut points.
+    pub fn fell_back(&self) -> bool {
+        self.fell_back
+    }
+
+    /// Returns the number of instructions of this function which Blade skipped
+    /// or only approximated, such as conditional traps whose operands are not
+    /// treated as sinks.
+    ///
+    /// Each of them is logged at the debug level, along with the reason, when
+    /// the function is compiled.
+    pub fn diagnostics(&self) -> usize {
+        self.diagnostics
+    }
+
+    /// Returns a rough static estimate of the run-time slowdown caused by the
+    /// mitigations of this function. See
+    /// [`BladeReport::estimated_slowdown`].
+    pub fn estimated_slowdown(&self) -> f64 {
+        self.estimate.slowdown()
+    }
+
+    /// Returns every mitigation inserted in this function: the speculation
+    /// barriers, then the loads protected with speculative load hardening.
+    pub fn mitigations(&self) -> &[BladeMitigation] {
+        &self.mitigations
+    }
+
+    /// Returns every place where Blade had to approximate in this function:
+    /// the budget fallback first, if any, then each instruction of
+    /// [`diagnostics`](Self::diagnostics).
+    pub fn approximations(&self) -> &[BladeApproximation] {
+        &self.approximations
+    }
+}
+
+/// A Spectre mitigation that Blade inserted into a function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BladeMitigation {
+    kind: BladeMitigationKind,
+    wasm_offset: Option<usize>,
+    code_offset: Option<u32>,
+}
+
+impl BladeMitigation {
+    /// Returns the kind of this mitigation.
+    pub fn kind(&self) -> BladeMitigationKind {
+        self.kind
+    }
+
+    /// Returns the offset, in the module's binary, of the WebAssembly
+    /// instruction which this mitigation protects.
+    ///
+    /// With the module's DWARF debug information, this offset can be mapped
+    /// back to the source line which needed the mitigation.
+    pub fn wasm_offset(&self) -> Option<usize> {
+        self.wasm_offset
+    }
+
+    /// Returns the offset of the protected instruction in the compiled code of
+    /// its function.
+    ///
+    /// This is only known with Cranelift's legacy backends.
+    pub fn code_offset(&self) -> Option<u32> {
+        self.code_offset
+    }
+}
+
+/// A call between two functions compiled with incompatible Blade settings, see
+/// [`BladeReport::contract_violations`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BladeContractViolation {
+    caller: u32,
+    callee: u32,
+    indirect: bool,
+}
+
+impl BladeContractViolation {
+    pub(crate) fn new(violation: &wasmtime_jit::BladeContractViolation) -> Self {
+        BladeContractViolation {
+            caller: violation.caller.index() as u32,
+            callee: violation.callee.index() as u32,
+            indirect: violation.indirect,
+        }
+    }
+
+    /// Returns the WebAssembly function index of the calling function.
+    pub fn caller(&self) -> u32 {
+        self.caller
+    }
+
+    /// Returns the WebAssembly function index of the called function, in the
+    /// module of the caller.
+    pub fn callee(&self) -> u32 {
+        self.callee
+    }
+
+    /// Returns whether the call goes through a table, so that the callee is
+    /// only one of the functions the caller may reach there.
+    pub fn is_indirect(&self) -> bool {
+        self.indirect
+    }
+}
+
+impl fmt::Display for BladeContractViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "func {} {} func {} with incompatible Blade settings",
+            self.caller,
+            if self.indirect { "may call" } else { "calls" },
+            self.callee
+        )
+    }
+}
+
+/// The kinds of Spectre mitigations that Blade inserts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BladeMitigationKind {
+    /// A speculation barrier before the instruction.
+    FenceBefore,
+    /// A speculation barrier after the instruction.
+    FenceAfter,
+    /// Speculative load hardening of the address of a load.
+    Slh,
+}
+
+/// A place where Blade had to approximate, so that a function is protected
+/// differently than requested, or not at all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BladeApproximation {
+    kind: BladeApproximationKind,
+    wasm_offset: Option<usize>,
+}
+
+impl BladeApproximation {
+    /// Returns the kind of this approximation.
+    pub fn kind(&self) -> BladeApproximationKind {
+        self.kind
+    }
+
+    /// Returns the offset, in the module's binary, of the WebAssembly
+    /// instruction which Blade approximated, or `None` if the approximation
+    /// covers the whole function.
+    pub fn wasm_offset(&self) -> Option<usize> {
+        self.wasm_offset
+    }
+}
+
+/// The kinds of approximations that Blade makes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BladeApproximationKind {
+    /// The Blade graph of the function was too large, so every basic block was
+    /// fenced instead of the cut points.
+    BudgetFallback,
+    /// The operands of an instruction with side effects, such as a conditional
+    /// trap, are not treated as sinks.
+    UnhandledSideEffect,
+    /// An instruction reads state which is not one of its operands, such as
+    /// the pinned register.
+    ImplicitOperand,
+    /// The results of an instruction are assumed to depend on every operand.
+    MultipleResults,
+    /// A load was hardened against fake bounds covering the whole address
+    /// space.
+    FakeBounds,
+    /// A load couldn't be protected with speculative load hardening, and was
+    /// fenced instead.
+    SlhFallback,
+}
+
+impl BladeApproximationKind {
+    /// Returns whether transient values may leak through this approximation.
+    ///
+    /// The other approximations are conservative: they only cost extra
+    /// mitigations.
+    pub fn is_unprotected(self) -> bool {
+        self.diagnostic_kind()
+            .map_or(false, BladeDiagnosticKind::is_unprotected)
+    }
+
+    /// Returns the Cranelift diagnostic this kind reports, or `None` for a
+    /// fallback of the whole function.
+    fn diagnostic_kind(self) -> Option<BladeDiagnosticKind> {
+        match self {
+            Self::BudgetFallback => None,
+            Self::UnhandledSideEffect => Some(BladeDiagnosticKind::UnhandledSideEffect),
+            Self::ImplicitOperand => Some(BladeDiagnosticKind::ImplicitOperand),
+            Self::MultipleResults => Some(BladeDiagnosticKind::MultipleResults),
+            Self::FakeBounds => Some(BladeDiagnosticKind::FakeBounds),
+            Self::SlhFallback => Some(BladeDiagnosticKind::SlhFallback),
+        }
+    }
+
+    /// Returns the name of this kind in
+    /// [`BladeReport::residual_risk_csv`].
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::BudgetFallback => "budget_fallback",
+            Self::UnhandledSideEffect => "unhandled_side_effect",
+            Self::ImplicitOperand => "implicit_operand",
+            Self::MultipleResults => "multiple_results",
+            Self::FakeBounds => "fake_bounds",
+            Self::SlhFallback => "slh_fallback",
+        }
+    }
+}
+
+/// The variants of a module's code, for modules compiled both with and
+/// without Blade, or loaded both with and without its speculation barriers.
+///
+/// See [`Config::blade_unprotected_variant`],
+/// [`Config::blade_unfenced_variant`] and [`Store::set_blade_variant`].
+///
+/// [`Config::blade_unprotected_variant`]: crate::Config::blade_unprotected_variant
+/// [`Config::blade_unfenced_variant`]: crate::Config::blade_unfenced_variant
+/// [`Store::set_blade_variant`]: crate::Store::set_blade_variant
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BladeVariant {
+    /// The code compiled with the engine's `blade` settings. Every module has
+    /// this variant, and stores instantiate it by default.
+    Protected,
+    /// The code compiled with Blade disabled.
+    Unprotected,
+    /// The code compiled with the engine's `blade` settings, with the
+    /// speculation barriers that Blade placed replaced by no-ops, for
+    /// hardware which the host knows not to speculate past them. Loads
+    /// hardened with speculative load hardening keep their masks.
+    Unfenced,
+}
+
+impl Default for BladeVariant {
+    fn default() -> Self {
+        Self::Protected
+    }
+}
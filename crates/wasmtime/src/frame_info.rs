@@ -1,6 +1,7 @@
 use std::cmp;
 use std::collections::BTreeMap;
 use std::sync::{Arc, RwLock};
+use wasmtime_environ::blade::BladeContract;
 use wasmtime_environ::entity::EntityRef;
 use wasmtime_environ::ir;
 use wasmtime_environ::wasm::FuncIndex;
@@ -51,6 +52,7 @@ struct FunctionInfo {
     index: FuncIndex,
     traps: Vec<TrapInformation>,
     instr_map: FunctionAddressMap,
+    blade_contract: BladeContract,
 }
 
 impl GlobalFrameInfo {
@@ -121,6 +123,21 @@ impl GlobalFrameInfo {
         Some(&func.traps[idx])
     }
 
+    /// Fetches the Blade contract of the function whose code starts at `pc`.
+    ///
+    /// Returns `None` if `pc` isn't the start of a function of a previously
+    /// registered module, e.g. for host functions.
+    pub fn lookup_blade_contract(&self, pc: usize) -> Option<BladeContract> {
+        // Look past `pc`, so as not to find a function ending right where this
+        // one starts.
+        let (_module, func) = self.func(pc + 1)?;
+        if func.start == pc {
+            Some(func.blade_contract)
+        } else {
+            None
+        }
+    }
+
     fn func(&self, pc: usize) -> Option<(&ModuleFrameInfo, &FunctionInfo)> {
         let (end, info) = self.ranges.range(pc..).next()?;
         if pc < info.start || *end < pc {
@@ -152,11 +169,12 @@ pub fn register(module: &CompiledModule) -> Option<GlobalFrameInfoRegistration>
     let mut min = usize::max_value();
     let mut max = 0;
     let mut functions = BTreeMap::new();
-    for (((i, allocated), traps), instrs) in module
+    for ((((i, allocated), traps), instrs), stats) in module
         .finished_functions()
         .iter()
         .zip(module.traps().values())
         .zip(module.address_transform().values())
+        .zip(module.blade_stats().values())
     {
         let (start, end) = unsafe {
             let ptr = (**allocated).as_ptr();
@@ -170,6 +188,7 @@ pub fn register(module: &CompiledModule) -> Option<GlobalFrameInfoRegistration>
             index: module.module().local.func_index(i),
             traps: traps.to_vec(),
             instr_map: (*instrs).clone(),
+            blade_contract: stats.contract,
         };
         assert!(functions.insert(end, func).is_none());
     }
@@ -1,6 +1,11 @@
+use crate::frame_info::FRAME_INFO;
 use crate::trampoline::StoreInstanceHandle;
-use crate::{Engine, Export, Extern, Func, Global, Memory, Module, Store, Table, Trap};
+use crate::{
+    BladeContractViolation, Engine, Export, Extern, Func, Global, Memory, Module, Store, Table,
+    Trap,
+};
 use anyhow::{bail, Error, Result};
+use log::warn;
 use std::any::Any;
 use std::mem;
 use wasmtime_environ::EntityIndex;
@@ -41,6 +46,8 @@ fn instantiate(
         );
     }
 
+    check_blade_contracts(store, compiled_module, imports)?;
+
     let mut resolver = SimpleResolver { imports };
     let config = store.engine().config();
     let instance = unsafe {
@@ -103,6 +110,46 @@ fn instantiate(
     Ok(instance)
 }
 
+/// Checks that the calls of `compiled_module`, between its own functions and to
+/// the functions of other modules it imports, don't break the Blade contract
+/// between callers and callees.
+fn check_blade_contracts(
+    store: &Store,
+    compiled_module: &CompiledModule,
+    imports: &[Extern],
+) -> Result<()> {
+    let module = compiled_module.module();
+    let violations = {
+        let info = FRAME_INFO.read().unwrap();
+        compiled_module.blade_contract_violations(|func| {
+            let i = module
+                .imports
+                .iter()
+                .position(|(_, _, index)| *index == EntityIndex::Function(func))?;
+            match &imports[i] {
+                Extern::Func(f) => {
+                    info.lookup_blade_contract(f.wasmtime_function().address as usize)
+                }
+                _ => None,
+            }
+        })
+    };
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    let message = violations
+        .iter()
+        .map(|violation| BladeContractViolation::new(violation).to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if store.engine().config().blade_strict_contracts {
+        bail!("Blade contract violations: {}", message);
+    }
+    warn!("Blade contract violations: {}", message);
+    Ok(())
+}
+
 /// An instantiated WebAssembly module.
 ///
 /// This type represents the instantiation of a [`Module`]. Once instantiated
@@ -172,6 +219,8 @@ impl Instance {
     ///   [`ExternType`] entry that it maps to.
     /// * The `start` function in the instance, if present, traps.
     /// * Module/instance resource limits are exceeded.
+    /// * The `store` selects a [`BladeVariant`](crate::BladeVariant) which the
+    ///   `module` wasn't compiled in.
     ///
     /// When instantiation fails it's recommended to inspect the return value to
     /// see why it failed, or bubble it upwards. If you'd like to specifically
@@ -185,14 +234,16 @@ impl Instance {
             bail!("cross-`Engine` instantiation is not currently supported");
         }
 
+        let variant = store.blade_variant();
+        let compiled_module = module.compiled_variant(variant)?;
         let host_info = Box::new({
-            let frame_info_registration = module.register_frame_info();
-            store.register_jit_code(module.compiled_module().jit_code_ranges());
-            store.register_stack_maps(&module);
+            let frame_info_registration = module.register_frame_info(variant);
+            store.register_jit_code(compiled_module.jit_code_ranges());
+            store.register_stack_maps(compiled_module);
             frame_info_registration
         });
 
-        let handle = instantiate(store, module.compiled_module(), imports, host_info)?;
+        let handle = instantiate(store, compiled_module, imports, host_info)?;
 
         Ok(Instance {
             handle,
@@ -10,6 +10,7 @@
 #![doc(test(attr(deny(warnings))))]
 #![doc(test(attr(allow(dead_code, unused_variables, unused_mut))))]
 
+mod blade;
 mod externals;
 mod frame_info;
 mod func;
@@ -23,6 +24,10 @@ mod trap;
 mod types;
 mod values;
 
+pub use crate::blade::{
+    BladeApproximation, BladeApproximationKind, BladeContractViolation, BladeMitigation,
+    BladeMitigationKind, BladeReport, BladeVariant, FunctionBladeReport,
+};
 pub use crate::externals::*;
 pub use crate::frame_info::FrameInfo;
 pub use crate::func::*;
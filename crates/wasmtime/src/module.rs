@@ -1,7 +1,8 @@
+use crate::blade::{BladeReport, BladeVariant};
 use crate::frame_info::GlobalFrameInfoRegistration;
 use crate::runtime::Engine;
 use crate::types::{EntityType, ExportType, ExternType, ImportType};
-use anyhow::{Error, Result};
+use anyhow::{bail, Error, Result};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 use wasmparser::validate;
@@ -80,6 +81,15 @@ pub struct Module {
     engine: Engine,
     compiled: Arc<CompiledModule>,
     frame_info_registration: Arc<Mutex<Option<Option<Arc<GlobalFrameInfoRegistration>>>>>,
+    /// The module compiled without Blade, if the engine compiles both
+    /// variants.
+    unprotected: Option<Arc<CompiledModule>>,
+    unprotected_frame_info_registration:
+        Arc<Mutex<Option<Option<Arc<GlobalFrameInfoRegistration>>>>>,
+    /// The module with its speculation barriers patched out, if the engine
+    /// loads both variants.
+    unfenced: Option<Arc<CompiledModule>>,
+    unfenced_frame_info_registration: Arc<Mutex<Option<Option<Arc<GlobalFrameInfoRegistration>>>>>,
 }
 
 impl Module {
@@ -161,11 +171,17 @@ impl Module {
     /// See [`Module::new`] for other details.
     pub fn new_with_name(engine: &Engine, bytes: impl AsRef<[u8]>, name: &str) -> Result<Module> {
         let mut module = Module::new(engine, bytes.as_ref())?;
-        Arc::get_mut(&mut module.compiled)
-            .unwrap()
-            .module_mut()
-            .expect("mutable module")
-            .name = Some(name.to_string());
+        for compiled in Some(&mut module.compiled)
+            .into_iter()
+            .chain(module.unprotected.as_mut())
+            .chain(module.unfenced.as_mut())
+        {
+            Arc::get_mut(compiled)
+                .unwrap()
+                .module_mut()
+                .expect("mutable module")
+                .name = Some(name.to_string());
+        }
         Ok(module)
     }
 
@@ -301,17 +317,51 @@ impl Module {
     }
 
     unsafe fn compile(engine: &Engine, binary: &[u8]) -> Result<Self> {
-        let compiled = CompiledModule::new(engine.compiler(), binary, &*engine.config().profiler)?;
+        let profiler = &*engine.config().profiler;
+        let (compiled, unfenced) = if engine.config().blade_unfenced_variant {
+            let (compiled, unfenced) =
+                CompiledModule::new_with_unfenced(engine.compiler(), binary, profiler)?;
+            (compiled, Some(Arc::new(unfenced)))
+        } else {
+            (
+                CompiledModule::new(engine.compiler(), binary, profiler)?,
+                None,
+            )
+        };
+        let unprotected = match engine.unprotected_compiler() {
+            Some(compiler) => Some(Arc::new(CompiledModule::new(compiler, binary, profiler)?)),
+            None => None,
+        };
 
         Ok(Module {
             engine: engine.clone(),
             compiled: Arc::new(compiled),
             frame_info_registration: Arc::new(Mutex::new(None)),
+            unprotected,
+            unprotected_frame_info_registration: Arc::new(Mutex::new(None)),
+            unfenced,
+            unfenced_frame_info_registration: Arc::new(Mutex::new(None)),
         })
     }
 
-    pub(crate) fn compiled_module(&self) -> &CompiledModule {
-        &self.compiled
+    /// Get the code of the `variant` of this module, or fail if it wasn't
+    /// compiled.
+    pub(crate) fn compiled_variant(&self, variant: BladeVariant) -> Result<&CompiledModule> {
+        match variant {
+            BladeVariant::Protected => Ok(&self.compiled),
+            BladeVariant::Unprotected => match &self.unprotected {
+                Some(compiled) => Ok(compiled),
+                None => bail!(
+                    "module has no unprotected variant, see `Config::blade_unprotected_variant`"
+                ),
+            },
+            BladeVariant::Unfenced => match &self.unfenced {
+                Some(compiled) => Ok(compiled),
+                None => {
+                    bail!("module has no unfenced variant, see `Config::blade_unfenced_variant`")
+                }
+            },
+        }
     }
 
     /// Returns identifier/name that this [`Module`] has. This name
@@ -517,20 +567,83 @@ impl Module {
         Some(EntityType::new(entity_index, module).extern_type())
     }
 
+    /// Returns a report of the Spectre mitigations that Blade inserted while
+    /// compiling this [`Module`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut config = Config::new();
+    /// unsafe {
+    ///     config.cranelift_other_flag("blade", "lfence")?;
+    /// }
+    /// let engine = Engine::new(&config);
+    /// let wat = r#"
+    ///     (module
+    ///         (memory 1)
+    ///         (func (param i32) (result i32)
+    ///             (i32.load (i32.load (local.get 0))))
+    ///     )
+    /// "#;
+    /// let module = Module::new(&engine, wat)?;
+    /// let report = module.blade_report();
+    /// assert_eq!(report.functions().len(), 1);
+    /// println!("{}", report);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn blade_report(&self) -> BladeReport {
+        BladeReport::new(&self.compiled)
+    }
+
+    /// Returns whether this module was compiled in the `variant`, so that it
+    /// can be instantiated in stores selecting it.
+    ///
+    /// Every module has the [`BladeVariant::Protected`] variant. The
+    /// [`BladeVariant::Unprotected`] one is only compiled when
+    /// [`Config::blade_unprotected_variant`] is enabled, and the
+    /// [`BladeVariant::Unfenced`] one is only loaded when
+    /// [`Config::blade_unfenced_variant`] is.
+    ///
+    /// [`Config::blade_unprotected_variant`]: crate::Config::blade_unprotected_variant
+    /// [`Config::blade_unfenced_variant`]: crate::Config::blade_unfenced_variant
+    pub fn has_blade_variant(&self, variant: BladeVariant) -> bool {
+        match variant {
+            BladeVariant::Protected => true,
+            BladeVariant::Unprotected => self.unprotected.is_some(),
+            BladeVariant::Unfenced => self.unfenced.is_some(),
+        }
+    }
+
     /// Returns the [`Engine`] that this [`Module`] was compiled by.
     pub fn engine(&self) -> &Engine {
         &self.engine
     }
 
-    /// Register this module's stack frame information into the global scope.
+    /// Register the stack frame information of the `variant` of this module
+    /// into the global scope.
     ///
     /// This is required to ensure that any traps can be properly symbolicated.
-    pub(crate) fn register_frame_info(&self) -> Option<Arc<GlobalFrameInfoRegistration>> {
-        let mut info = self.frame_info_registration.lock().unwrap();
+    pub(crate) fn register_frame_info(
+        &self,
+        variant: BladeVariant,
+    ) -> Option<Arc<GlobalFrameInfoRegistration>> {
+        let (compiled, registration) = match (variant, &self.unprotected, &self.unfenced) {
+            (BladeVariant::Unprotected, Some(unprotected), _) => {
+                (unprotected, &self.unprotected_frame_info_registration)
+            }
+            (BladeVariant::Unfenced, _, Some(unfenced)) => {
+                (unfenced, &self.unfenced_frame_info_registration)
+            }
+            _ => (&self.compiled, &self.frame_info_registration),
+        };
+        let mut info = registration.lock().unwrap();
         if let Some(info) = &*info {
             return info.clone();
         }
-        let ret = super::frame_info::register(&self.compiled).map(Arc::new);
+        let ret = super::frame_info::register(compiled).map(Arc::new);
         *info = Some(ret.clone());
         return ret;
     }
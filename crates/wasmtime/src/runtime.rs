@@ -1,10 +1,10 @@
 use crate::externals::MemoryCreator;
 use crate::r#ref::ExternRef;
 use crate::trampoline::{MemoryCreatorProxy, StoreInstanceHandle};
-use crate::Module;
+use crate::BladeVariant;
 use anyhow::{bail, Result};
 use std::any::Any;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::cmp;
 use std::collections::HashMap;
 use std::convert::TryFrom;
@@ -14,9 +14,10 @@ use std::path::Path;
 use std::rc::{Rc, Weak};
 use std::sync::Arc;
 use wasmparser::{OperatorValidatorConfig, ValidatingParserConfig};
+use wasmtime_environ::blade::check_blade_settings;
 use wasmtime_environ::settings::{self, Configurable, SetError};
-use wasmtime_environ::{ir, isa, isa::TargetIsa, wasm, CacheConfig, Tunables};
-use wasmtime_jit::{native, CompilationStrategy, Compiler};
+use wasmtime_environ::{ir, isa, isa::TargetIsa, wasm, BladeProfile, CacheConfig, Tunables};
+use wasmtime_jit::{native, CompilationStrategy, CompiledModule, Compiler};
 use wasmtime_profiling::{JitDumpAgent, NullProfilerAgent, ProfilingAgent, VTuneAgent};
 use wasmtime_runtime::{
     debug_builtins, InstanceHandle, RuntimeMemoryCreator, SignalHandler, SignatureRegistry,
@@ -44,6 +45,9 @@ pub struct Config {
     pub(crate) profiler: Arc<dyn ProfilingAgent>,
     pub(crate) memory_creator: Option<MemoryCreatorProxy>,
     pub(crate) max_wasm_stack: usize,
+    pub(crate) blade_unprotected_variant: bool,
+    pub(crate) blade_unfenced_variant: bool,
+    pub(crate) blade_strict_contracts: bool,
 }
 
 impl Config {
@@ -101,6 +105,9 @@ impl Config {
             profiler: Arc::new(NullProfilerAgent),
             memory_creator: None,
             max_wasm_stack: 1 << 20,
+            blade_unprotected_variant: false,
+            blade_unfenced_variant: false,
+            blade_strict_contracts: false,
         }
     }
 
@@ -125,6 +132,120 @@ impl Config {
         self
     }
 
+    /// Restricts the Blade Spectre mitigation pass to the functions whose
+    /// name or index matches the regular expression `pattern`.
+    ///
+    /// Function names come from the module's name section, and indices are
+    /// written in decimal. The pattern may match anywhere in either, so anchor
+    /// it to match whole names. This has no effect unless Blade is enabled
+    /// with the `blade` Cranelift flag, see [`Config::cranelift_other_flag`].
+    ///
+    /// By default Blade runs on every function.
+    ///
+    /// # Errors
+    ///
+    /// This method fails if `pattern` is not a valid regular expression.
+    pub fn blade_only(&mut self, pattern: &str) -> Result<&mut Self> {
+        self.tunables.blade_filter.only(pattern)?;
+        Ok(self)
+    }
+
+    /// Leaves the functions whose name or index matches the regular expression
+    /// `pattern` out of the Blade Spectre mitigation pass.
+    ///
+    /// Functions are matched as with [`Config::blade_only`], and this takes
+    /// precedence over it.
+    ///
+    /// # Errors
+    ///
+    /// This method fails if `pattern` is not a valid regular expression.
+    pub fn blade_exclude(&mut self, pattern: &str) -> Result<&mut Self> {
+        self.tunables.blade_filter.exclude(pattern)?;
+        Ok(self)
+    }
+
+    /// Restricts the Blade Spectre mitigation pass to the hot functions of an
+    /// execution profile, those which `profile` counts at least `threshold`
+    /// times.
+    ///
+    /// The profile has one function per line: its name or index, as matched
+    /// by [`Config::blade_only`], followed by whitespace and a count, such as
+    /// the number of times it was called in a previous run. Empty lines and
+    /// lines starting with `#` are ignored. Functions the profile doesn't
+    /// mention count zero times. This combines with [`Config::blade_only`] and
+    /// [`Config::blade_exclude`], e.g. to only harden the hot functions which
+    /// handle untrusted data.
+    ///
+    /// # Errors
+    ///
+    /// This method fails if `profile` is not a valid profile.
+    pub fn blade_profile(&mut self, profile: &str, threshold: u64) -> Result<&mut Self> {
+        let profile = BladeProfile::parse(profile)?;
+        self.tunables.blade_filter.hot(profile, threshold);
+        Ok(self)
+    }
+
+    /// Configures whether every module is also compiled without the Blade
+    /// Spectre mitigations, so that each [`Store`] can choose which variant
+    /// of the code its instances run.
+    ///
+    /// This makes it easy to measure the overhead of Blade on a real workload,
+    /// or to roll it out gradually, without compiling the modules with two
+    /// engines. The unprotected variant is compiled with the same settings,
+    /// except for the `blade` Cranelift flag which is set to `none`. See
+    /// [`Store::set_blade_variant`] for selecting the variant.
+    ///
+    /// This doubles the compilation time and code memory of every module.
+    ///
+    /// By default this option is `false`.
+    pub fn blade_unprotected_variant(&mut self, enable: bool) -> &mut Self {
+        self.blade_unprotected_variant = enable;
+        self
+    }
+
+    /// Configures whether every module is also loaded with the speculation
+    /// barriers that Blade placed patched out, so that each [`Store`] running
+    /// on hardware which the host knows to be immune, e.g. because its cores
+    /// don't speculate or its microcode makes the barriers unnecessary, can
+    /// run at full speed.
+    ///
+    /// Unlike [`Config::blade_unprotected_variant`], modules are only
+    /// compiled once: the unfenced variant is a copy of the compiled code
+    /// with the barriers, found with the offsets recorded in the Blade
+    /// metadata of each function, replaced by no-ops of the same size. Loads
+    /// hardened with speculative load hardening keep their masks. See
+    /// [`Store::set_blade_variant`] for selecting the variant.
+    ///
+    /// Only the old x86 backend records the offsets of the barriers, so
+    /// compiling a module fails with other backends when this is enabled.
+    ///
+    /// This doubles the code memory of every module.
+    ///
+    /// By default this option is `false`.
+    pub fn blade_unfenced_variant(&mut self, enable: bool) -> &mut Self {
+        self.blade_unfenced_variant = enable;
+        self
+    }
+
+    /// Configures whether instantiating a module fails when some of its calls
+    /// break the Blade contract between callers and callees, rather than only
+    /// logging a warning.
+    ///
+    /// The contract is that either callers don't pass transient arguments, or
+    /// callees protect themselves from them. Instantiation checks the calls
+    /// between the functions of the module, and the calls to the functions it
+    /// imports from other modules, which may have been compiled with other
+    /// settings. See [`BladeReport::contract_violations`] for the calls which
+    /// break it.
+    ///
+    /// By default this option is `false`.
+    ///
+    /// [`BladeReport::contract_violations`]: crate::BladeReport::contract_violations
+    pub fn blade_strict_contracts(&mut self, enable: bool) -> &mut Self {
+        self.blade_strict_contracts = enable;
+        self
+    }
+
     /// Configures the maximum amount of native stack space available to
     /// executing WebAssembly code.
     ///
@@ -377,20 +498,46 @@ impl Config {
     /// # Errors
     ///
     /// This method can fail if the flag's name does not exist, or the value is not appropriate for
-    /// the flag type.
+    /// the flag type. It also fails if the flag selects Blade mitigations which the target can't
+    /// emit, see [`Config::blade_strategies`].
     pub unsafe fn cranelift_other_flag(&mut self, name: &str, value: &str) -> Result<&mut Self> {
-        if let Err(err) = self.flags.set(name, value) {
+        let mut flags = self.flags.clone();
+        let mut isa_flags = self.isa_flags.clone();
+        if let Err(err) = flags.set(name, value) {
             match err {
                 SetError::BadName(_) => {
                     // Try the target-specific flags.
-                    self.isa_flags.set(name, value)?;
+                    isa_flags.set(name, value)?;
                 }
                 _ => bail!(err),
             }
         }
+        if name.starts_with("blade") {
+            // Reject Blade settings the target can't compile now, rather than on the first
+            // module.
+            let isa = isa_flags
+                .clone()
+                .finish(settings::Flags::new(flags.clone()));
+            check_blade_settings(&*isa)?;
+        }
+        self.flags = flags;
+        self.isa_flags = isa_flags;
         Ok(self)
     }
 
+    /// Returns the values of the `blade` Cranelift flag which the target can
+    /// compile, such as `"lfence"` or `"slh"`, so that users can be offered
+    /// only the Blade strategies which work. Setting any other value with
+    /// [`Config::cranelift_other_flag`] fails.
+    pub fn blade_strategies(&self) -> Vec<String> {
+        self.target_isa()
+            .blade_capabilities()
+            .strategies()
+            .into_iter()
+            .map(|blade| blade.to_string())
+            .collect()
+    }
+
     /// Loads cache configuration specified at `path`.
     ///
     /// This method will read the file specified by `path` on the filesystem and
@@ -624,6 +771,21 @@ impl Config {
             self.tunables.clone(),
         )
     }
+
+    fn build_unprotected_compiler(&self) -> Option<Compiler> {
+        if !self.blade_unprotected_variant {
+            return None;
+        }
+        let mut flags = self.flags.clone();
+        flags.set("blade", "none").expect("should be valid flag");
+        let isa = self.isa_flags.clone().finish(settings::Flags::new(flags));
+        Some(Compiler::new(
+            isa,
+            self.strategy,
+            self.cache_config.clone(),
+            self.tunables.clone(),
+        ))
+    }
 }
 
 fn round_up_to_pages(val: u64) -> u64 {
@@ -645,6 +807,10 @@ impl fmt::Debug for Config {
         let features = &self.validating_config.operator_config;
         f.debug_struct("Config")
             .field("debug_info", &self.tunables.debug_info)
+            .field("blade_filter", &self.tunables.blade_filter)
+            .field("blade_unprotected_variant", &self.blade_unprotected_variant)
+            .field("blade_unfenced_variant", &self.blade_unfenced_variant)
+            .field("blade_strict_contracts", &self.blade_strict_contracts)
             .field("strategy", &self.strategy)
             .field("wasm_threads", &features.enable_threads)
             .field("wasm_reference_types", &features.enable_reference_types)
@@ -748,6 +914,7 @@ pub struct Engine {
 struct EngineInner {
     config: Config,
     compiler: Compiler,
+    unprotected_compiler: Option<Compiler>,
 }
 
 impl Engine {
@@ -759,6 +926,7 @@ impl Engine {
             inner: Arc::new(EngineInner {
                 config: config.clone(),
                 compiler: config.build_compiler(),
+                unprotected_compiler: config.build_unprotected_compiler(),
             }),
         }
     }
@@ -772,6 +940,12 @@ impl Engine {
         &self.inner.compiler
     }
 
+    /// Returns the compiler of the unprotected variant of modules, if they
+    /// have one.
+    pub(crate) fn unprotected_compiler(&self) -> Option<&Compiler> {
+        self.inner.unprotected_compiler.as_ref()
+    }
+
     /// Returns whether the engine `a` and `b` refer to the same configuration.
     pub fn same(a: &Engine, b: &Engine) -> bool {
         Arc::ptr_eq(&a.inner, &b.inner)
@@ -818,6 +992,7 @@ pub(crate) struct StoreInner {
     host_info: RefCell<HashMap<HostInfoKey, Rc<RefCell<dyn Any>>>>,
     externref_activations_table: Rc<VMExternRefActivationsTable>,
     stack_map_registry: Rc<StackMapRegistry>,
+    blade_variant: Cell<BladeVariant>,
 }
 
 struct HostInfoKey(VMExternRef);
@@ -860,6 +1035,7 @@ impl Store {
                 host_info: RefCell::new(HashMap::new()),
                 externref_activations_table: Rc::new(VMExternRefActivationsTable::new()),
                 stack_map_registry: Rc::new(StackMapRegistry::default()),
+                blade_variant: Cell::new(BladeVariant::default()),
             }),
         }
     }
@@ -873,6 +1049,24 @@ impl Store {
         &self.inner.engine
     }
 
+    /// Selects which variant of their code the instances created in this
+    /// store from now on run.
+    ///
+    /// Instantiating a module in the [`BladeVariant::Unprotected`] or
+    /// [`BladeVariant::Unfenced`] variant fails unless its engine builds that
+    /// variant, see [`Config::blade_unprotected_variant`] and
+    /// [`Config::blade_unfenced_variant`]. Instances created before keep
+    /// running the variant they were created with.
+    pub fn set_blade_variant(&self, variant: BladeVariant) {
+        self.inner.blade_variant.set(variant);
+    }
+
+    /// Returns the variant of their code that new instances of this store
+    /// run, see [`Store::set_blade_variant`].
+    pub fn blade_variant(&self) -> BladeVariant {
+        self.inner.blade_variant.get()
+    }
+
     /// Returns an optional reference to a ['RuntimeMemoryCreator']
     pub(crate) fn memory_creator(&self) -> Option<&dyn RuntimeMemoryCreator> {
         self.engine()
@@ -932,8 +1126,7 @@ impl Store {
         }
     }
 
-    pub(crate) fn register_stack_maps(&self, module: &Module) {
-        let module = &module.compiled_module();
+    pub(crate) fn register_stack_maps(&self, module: &CompiledModule) {
         self.stack_map_registry().register_stack_maps(
             module
                 .finished_functions()
@@ -1197,7 +1390,7 @@ fn _assert_send_sync() {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Module;
+    use crate::{BladeReport, Instance, Module};
     use tempfile::TempDir;
 
     #[test]
@@ -1263,4 +1456,378 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn blade_strategies() -> Result<()> {
+        let strategies = Config::new().blade_strategies();
+        assert!(strategies.iter().any(|s| s == "none"));
+        assert!(strategies.iter().any(|s| s == "analyze"));
+        for blade in &[
+            "none",
+            "lfence",
+            "lfence_per_block",
+            "slh",
+            "slh_with_fence_fallback",
+            "analyze",
+        ] {
+            let mut cfg = Config::new();
+            let set = unsafe { cfg.cranelift_other_flag("blade", blade) };
+            assert_eq!(set.is_ok(), strategies.iter().any(|s| s == blade));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn blade_filter_selects_functions() -> Result<()> {
+        let wat = r#"
+            (module
+                (memory 1)
+                (func $parse (param i32) (result i32)
+                    (i32.load (i32.load (local.get 0))))
+                (func $kernel (param i32) (result i32)
+                    (i32.load (i32.load (local.get 0))))
+            )
+        "#;
+        let fenced = |cfg: &mut Config| -> Result<Vec<bool>> {
+            unsafe {
+                cfg.cranelift_other_flag("blade", "lfence")?;
+            }
+            let module = Module::new(&Engine::new(cfg), wat)?;
+            Ok(module
+                .blade_report()
+                .functions()
+                .iter()
+                .map(|f| f.fences() > 0)
+                .collect())
+        };
+
+        assert_eq!(fenced(&mut Config::new())?, [true, true]);
+        assert_eq!(fenced(Config::new().blade_only("^parse$")?)?, [true, false]);
+        assert_eq!(fenced(Config::new().blade_only("^1$")?)?, [false, true]);
+        assert_eq!(
+            fenced(Config::new().blade_exclude("kernel")?)?,
+            [true, false]
+        );
+        assert_eq!(
+            fenced(Config::new().blade_only(".")?.blade_exclude("^0$")?)?,
+            [false, true]
+        );
+        assert!(Config::new().blade_only("(").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn blade_contract_violations() -> Result<()> {
+        let wat = r#"
+            (module
+                (memory 1)
+                (func $parse (export "parse") (param i32) (result i32)
+                    (i32.load (local.get 0)))
+                (func $kernel (param i32) (result i32)
+                    (call $parse (i32.load (local.get 0))))
+            )
+        "#;
+        let importer_wat = r#"
+            (module
+                (import "" "parse" (func $parse (param i32) (result i32)))
+                (memory 1)
+                (func $kernel (param i32) (result i32)
+                    (call $parse (i32.load (local.get 0))))
+            )
+        "#;
+        let mut cfg = Config::new();
+        unsafe {
+            cfg.cranelift_other_flag("blade", "lfence")?;
+        }
+        let module = Module::new(&Engine::new(&cfg), wat)?;
+        assert!(module.blade_report().contract_violations().is_empty());
+
+        // The kernel isn't hardened, so it passes transient arguments to the
+        // parser, which assumes they are stable.
+        cfg.blade_exclude("kernel")?;
+        let engine = Engine::new(&cfg);
+        let module = Module::new(&engine, wat)?;
+        let report = module.blade_report();
+        let violations = report.contract_violations();
+        assert_eq!(violations.len(), 1);
+        assert_eq!((violations[0].caller(), violations[0].callee()), (1, 0));
+        assert!(!violations[0].is_indirect());
+        let store = Store::new(&engine);
+        let parse = Instance::new(&store, &module, &[])?
+            .get_func("parse")
+            .unwrap();
+
+        // Calls to the functions of other modules are only checked once they
+        // are imported.
+        let importer = Module::new(&engine, importer_wat)?;
+        assert!(importer.blade_report().contract_violations().is_empty());
+        Instance::new(&store, &importer, &[parse.into()])?;
+
+        cfg.blade_strict_contracts(true);
+        let engine = Engine::new(&cfg);
+        let store = Store::new(&engine);
+        let module = Module::new(&engine, wat)?;
+        assert!(Instance::new(&store, &module, &[]).is_err());
+        let parser = Module::new(
+            &engine,
+            r#"(module (memory 1) (func (export "parse") (param i32) (result i32)
+                (i32.load (local.get 0))))"#,
+        )?;
+        let parse = Instance::new(&store, &parser, &[])?
+            .get_func("parse")
+            .unwrap();
+        let importer = Module::new(&engine, importer_wat)?;
+        assert!(Instance::new(&store, &importer, &[parse.into()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn blade_profile_selects_hot_functions() -> Result<()> {
+        let wat = r#"
+            (module
+                (memory 1)
+                (func $parse (param i32) (result i32)
+                    (i32.load (i32.load (local.get 0))))
+                (func $kernel (param i32) (result i32)
+                    (i32.load (i32.load (local.get 0))))
+                (func $init (param i32) (result i32)
+                    (i32.load (i32.load (local.get 0))))
+            )
+        "#;
+        let fenced = |cfg: &mut Config| -> Result<Vec<bool>> {
+            unsafe {
+                cfg.cranelift_other_flag("blade", "lfence")?;
+            }
+            let module = Module::new(&Engine::new(cfg), wat)?;
+            Ok(module
+                .blade_report()
+                .functions()
+                .iter()
+                .map(|f| f.fences() > 0)
+                .collect())
+        };
+
+        let profile = "# calls\nparse 5000\n1 40\n";
+        assert_eq!(
+            fenced(Config::new().blade_profile(profile, 1)?)?,
+            [true, true, false]
+        );
+        assert_eq!(
+            fenced(Config::new().blade_profile(profile, 100)?)?,
+            [true, false, false]
+        );
+        assert_eq!(
+            fenced(
+                Config::new()
+                    .blade_profile(profile, 1)?
+                    .blade_only("kernel")?
+            )?,
+            [false, true, false]
+        );
+        assert!(Config::new().blade_profile("parse lots", 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn blade_report_csv() -> Result<()> {
+        let wat = r#"
+            (module
+                (memory 1)
+                (func $parse (param i32) (result i32)
+                    (i32.load (i32.load (local.get 0))))
+                (func (param i32) (result i32)
+                    (local.get 0))
+            )
+        "#;
+        let mut cfg = Config::new();
+        unsafe {
+            cfg.cranelift_other_flag("blade", "lfence")?;
+        }
+        let module = Module::new(&Engine::new(&cfg), wat)?;
+        let csv = module.blade_report().to_csv();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some(
+                "func_index,function,values,graph_edges,cut_size,fences,slh_loads,fell_back,\
+                 time_us,graph_nodes,sources,sinks,max_flow,solver_time_us,slh_insts,\
+                 code_size_growth"
+            )
+        );
+        let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+        assert_eq!(rows.len(), 2);
+        // Every edge of the cut of `parse` is fenced.
+        assert_eq!(rows[0][..2], ["0", "parse"]);
+        assert_eq!(rows[0][4..8], ["2", "2", "0", "false"]);
+        // Nothing flows into a sink, so the graph is empty.
+        assert_eq!(rows[1][..2], ["1", ""]);
+        assert_eq!(rows[1][3..8], ["0", "0", "0", "0", "false"]);
+        assert_eq!(rows[0][14], "0");
+        assert_eq!((rows[1][9], rows[1][12]), ("0", "0"));
+
+        // The solver metrics of the report match the cut.
+        let report = module.blade_report();
+        let (parse, empty) = (&report.functions()[0], &report.functions()[1]);
+        assert!(parse.sources() > 0 && parse.sinks() > 0);
+        assert!(parse.graph_nodes() >= parse.sinks());
+        assert_eq!(parse.max_flow(), 2);
+        assert!(parse.solver_time() <= parse.time());
+        assert_eq!((empty.graph_nodes(), empty.max_flow()), (0, 0));
+
+        // Only fences grow the code, by three bytes each, when the backend
+        // knows the size of its code.
+        assert_eq!(parse.slh_insts(), 0);
+        if let Some(growth) = parse.code_size_growth() {
+            assert_eq!(growth as usize, 3 * parse.fences());
+            assert_eq!(empty.code_size_growth(), Some(0));
+        }
+
+        // So does the estimate of their overhead.
+        assert!(parse.estimated_slowdown() > 1.0);
+        assert_eq!(empty.estimated_slowdown(), 1.0);
+        assert!(report.estimated_slowdown() > 1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn blade_residual_risks() -> Result<()> {
+        let wat = r#"
+            (module
+                (memory 1)
+                (func $parse (param i32)
+                    (drop (i32.load (i32.load (local.get 0)))))
+            )
+        "#;
+        let report = |blade: &str| -> Result<BladeReport> {
+            let mut cfg = Config::new();
+            unsafe {
+                cfg.cranelift_other_flag("blade", blade)?;
+                cfg.cranelift_other_flag("blade_slh_fake_bounds", "true")?;
+            }
+            Ok(Module::new(&Engine::new(&cfg), wat)?.blade_report())
+        };
+        let header = "func_index,function,kind,wasm_offset,unprotected";
+
+        // Fences protect everything.
+        let fenced = report("lfence")?;
+        assert!(fenced.is_fully_protected());
+        assert_eq!(fenced.residual_risk_csv(), format!("{}\n", header));
+
+        // Hardening against fake bounds protects nothing.
+        let hardened = report("slh")?;
+        assert!(!hardened.is_fully_protected());
+        let csv = hardened.residual_risk_csv();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(header));
+        let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+        assert!(!rows.is_empty());
+        for row in rows {
+            assert_eq!(row[..3], ["0", "parse", "fake_bounds"]);
+            assert!(row[3].parse::<usize>().is_ok());
+            assert_eq!(row[4], "true");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn blade_variant_per_store() -> Result<()> {
+        let wat = r#"
+            (module
+                (memory 1)
+                (func (export "parse") (param i32) (result i32)
+                    (i32.load (i32.load (local.get 0))))
+            )
+        "#;
+        let mut cfg = Config::new();
+        unsafe {
+            cfg.cranelift_other_flag("blade", "lfence")?;
+        }
+        let module = Module::new(&Engine::new(&cfg), wat)?;
+        assert!(!module.has_blade_variant(BladeVariant::Unprotected));
+        let store = Store::new(module.engine());
+        store.set_blade_variant(BladeVariant::Unprotected);
+        assert!(Instance::new(&store, &module, &[]).is_err());
+
+        cfg.blade_unprotected_variant(true);
+        let module = Module::new(&Engine::new(&cfg), wat)?;
+        assert!(module.has_blade_variant(BladeVariant::Unprotected));
+        // The report describes the protected variant.
+        assert!(module.blade_report().functions()[0].fences() > 0);
+
+        let code = |variant| -> Result<usize> {
+            let compiled = module.compiled_variant(variant)?;
+            let body = compiled.finished_functions().values().next().unwrap();
+            Ok(unsafe { (**body).as_ptr() as usize })
+        };
+        let variants = [BladeVariant::Protected, BladeVariant::Unprotected];
+        for (i, &variant) in variants.iter().enumerate() {
+            let store = Store::new(module.engine());
+            assert_eq!(store.blade_variant(), BladeVariant::Protected);
+            store.set_blade_variant(variant);
+            let instance = Instance::new(&store, &module, &[])?;
+            let parse = instance.get_func("parse").unwrap().get1::<i32, i32>()?;
+            assert_eq!(parse(0)?, 0);
+            // Only the code of the selected variant runs in the store.
+            assert!(store.is_in_jit_code(code(variant)?));
+            assert!(!store.is_in_jit_code(code(variants[1 - i])?));
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn blade_unfenced_variant() -> Result<()> {
+        let wat = r#"
+            (module
+                (memory 1)
+                (func (export "parse") (param i32) (result i32)
+                    (i32.load (i32.load (local.get 0))))
+            )
+        "#;
+        let mut cfg = Config::new();
+        unsafe {
+            cfg.cranelift_other_flag("blade", "lfence")?;
+        }
+        cfg.blade_unfenced_variant(true);
+        let module = Module::new(&Engine::new(&cfg), wat)?;
+        assert!(module.has_blade_variant(BladeVariant::Unfenced));
+        assert!(!module.has_blade_variant(BladeVariant::Unprotected));
+
+        // The variants only differ by their barriers.
+        let body = |variant| -> Result<Vec<u8>> {
+            let compiled = module.compiled_variant(variant)?;
+            let body = compiled.finished_functions().values().next().unwrap();
+            let body = unsafe { &**body };
+            let bytes = body.as_ptr().cast::<u8>();
+            Ok(unsafe { std::slice::from_raw_parts(bytes, body.len()) }.to_vec())
+        };
+        let fenced = body(BladeVariant::Protected)?;
+        let unfenced = body(BladeVariant::Unfenced)?;
+        let stats = module
+            .compiled_variant(BladeVariant::Unfenced)?
+            .blade_stats();
+        let offsets = &stats.values().next().unwrap().barrier_offsets;
+        assert!(!offsets.is_empty());
+        assert_eq!(fenced.len(), unfenced.len());
+        for (i, (a, b)) in fenced.iter().zip(&unfenced).enumerate() {
+            match offsets
+                .iter()
+                .find(|&&offset| (offset as usize..offset as usize + 3).contains(&i))
+            {
+                Some(&offset) => {
+                    let at = i - offset as usize;
+                    assert_eq!(*a, [0x0f, 0xae, 0xe8][at]);
+                    assert_eq!(*b, [0x0f, 0x1f, 0x00][at]);
+                }
+                None => assert_eq!(a, b),
+            }
+        }
+
+        let store = Store::new(module.engine());
+        store.set_blade_variant(BladeVariant::Unfenced);
+        let instance = Instance::new(&store, &module, &[])?;
+        let parse = instance.get_func("parse").unwrap().get1::<i32, i32>()?;
+        assert_eq!(parse(0)?, 0);
+        Ok(())
+    }
 }
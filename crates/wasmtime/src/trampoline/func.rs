@@ -11,7 +11,7 @@ use std::mem;
 use std::panic::{self, AssertUnwindSafe};
 use wasmtime_environ::entity::PrimaryMap;
 use wasmtime_environ::isa::TargetIsa;
-use wasmtime_environ::{ir, settings, CompiledFunction, EntityIndex, Module};
+use wasmtime_environ::{ir, settings, CompiledFunction, EntityIndex, FunctionCalls, Module};
 use wasmtime_jit::trampoline::ir::{
     ExternalName, Function, InstBuilder, MemFlags, StackSlotData, StackSlotKind,
 };
@@ -197,6 +197,8 @@ fn make_trampoline(
                 body: code_buf,
                 jt_offsets: context.func.jt_offsets,
                 unwind_info,
+                blade_stats: context.blade_stats,
+                calls: FunctionCalls::default(),
             },
             reloc_sink.relocs().iter(),
         )
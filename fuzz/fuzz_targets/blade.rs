@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wasmtime_fuzzing::generators::blade::BladeFunction;
+
+fuzz_target!(|func: BladeFunction| {
+    wasmtime_fuzzing::oracles::blade(&func);
+});
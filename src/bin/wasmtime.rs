@@ -6,7 +6,7 @@
 use anyhow::Result;
 use structopt::{clap::AppSettings, clap::ErrorKind, StructOpt};
 use wasmtime_cli::commands::{
-    ConfigCommand, RunCommand, WasmToObjCommand, WastCommand, WASM2OBJ_AFTER_HELP,
+    BladeInfoCommand, ConfigCommand, RunCommand, WasmToObjCommand, WastCommand, WASM2OBJ_AFTER_HELP,
 };
 
 /// Wasmtime WebAssembly Runtime
@@ -36,6 +36,9 @@ use wasmtime_cli::commands::{
 )]
 enum WasmtimeApp {
     // !!! IMPORTANT: if subcommands are added or removed, update `parse_module` in `src/commands/run.rs`. !!!
+    /// Shows the Blade settings and Spectre mitigations recorded in an object file
+    #[structopt(name = "blade-info")]
+    BladeInfo(BladeInfoCommand),
     /// Controls Wasmtime configuration settings
     Config(ConfigCommand),
     /// Runs a WebAssembly module
@@ -51,6 +54,7 @@ impl WasmtimeApp {
     /// Executes the command.
     pub fn execute(&self) -> Result<()> {
         match self {
+            Self::BladeInfo(c) => c.execute(),
             Self::Config(c) => c.execute(),
             Self::Run(c) => c.execute(),
             Self::WasmToObj(c) => c.execute(),
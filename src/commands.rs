@@ -1,8 +1,9 @@
 //! The module for the Wasmtime CLI commands.
 
+mod blade_info;
 mod config;
 mod run;
 mod wasm2obj;
 mod wast;
 
-pub use self::{config::*, run::*, wasm2obj::*, wast::*};
+pub use self::{blade_info::*, config::*, run::*, wasm2obj::*, wast::*};
@@ -0,0 +1,91 @@
+//! The module that implements the `wasmtime blade-info` command.
+
+use anyhow::{bail, Context as _, Result};
+use std::fs;
+use std::path::PathBuf;
+use structopt::{clap::AppSettings, StructOpt};
+use wasmtime_obj::read_blade_metadata;
+
+/// Shows the Blade settings and Spectre mitigations recorded in an object file
+#[derive(StructOpt)]
+#[structopt(
+    name = "blade-info",
+    version = env!("CARGO_PKG_VERSION"),
+    setting = AppSettings::ColoredHelp,
+)]
+pub struct BladeInfoCommand {
+    /// The path of the object file produced by `wasm2obj`
+    #[structopt(index = 1, value_name = "OBJECT_PATH", parse(from_os_str))]
+    object: PathBuf,
+
+    /// Fail unless the Blade setting NAME was VALUE, e.g. `blade=lfence`
+    #[structopt(long, value_name = "NAME=VALUE", number_of_values = 1)]
+    expect: Vec<String>,
+
+    /// Fail if Blade may have left transient values unprotected in any
+    /// function
+    #[structopt(long)]
+    require_protected: bool,
+}
+
+impl BladeInfoCommand {
+    /// Executes the command.
+    pub fn execute(&self) -> Result<()> {
+        let data = fs::read(&self.object)
+            .with_context(|| format!("failed to read {}", self.object.display()))?;
+        let metadata = match read_blade_metadata(&data)? {
+            Some(metadata) => metadata,
+            None => bail!("{} has no Blade metadata", self.object.display()),
+        };
+
+        for (name, value) in &metadata.settings {
+            println!("{} = {}", name, value);
+        }
+        println!();
+        println!("function  fences  slh_loads  fell_back  diagnostics  unprotected");
+        for f in &metadata.functions {
+            println!(
+                "{:>8}  {:>6}  {:>9}  {:>9}  {:>11}  {:>11}",
+                f.func_index, f.fences, f.slh_loads, f.fell_back, f.diagnostics, f.unprotected
+            );
+        }
+        println!(
+            "total     {:>6}  {:>9}",
+            metadata.total_fences(),
+            metadata.total_slh_loads()
+        );
+
+        for expected in &self.expect {
+            let mut split = expected.splitn(2, '=');
+            let (name, value) = match (split.next(), split.next()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => bail!(
+                    "expected setting `{}` must be of the form NAME=VALUE",
+                    expected
+                ),
+            };
+            match metadata.setting(name) {
+                Some(actual) if actual == value => {}
+                Some(actual) => bail!(
+                    "{} was compiled with {}={}, expected {}",
+                    self.object.display(),
+                    name,
+                    actual,
+                    value
+                ),
+                None => bail!(
+                    "{} doesn't record the setting {}",
+                    self.object.display(),
+                    name
+                ),
+            }
+        }
+        if self.require_protected && !metadata.is_fully_protected() {
+            bail!(
+                "Blade may leave transient values unprotected in {}",
+                self.object.display()
+            );
+        }
+        Ok(())
+    }
+}
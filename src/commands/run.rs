@@ -6,8 +6,8 @@ use std::thread;
 use std::time::Duration;
 use std::{
     ffi::{OsStr, OsString},
-    fs::File,
-    path::{Component, PathBuf},
+    fs::{self, File},
+    path::{Component, Path, PathBuf},
     process,
 };
 use structopt::{clap::AppSettings, StructOpt};
@@ -18,9 +18,8 @@ use wasmtime_wasi::Wasi;
 fn parse_module(s: &OsStr) -> Result<PathBuf, OsString> {
     // Do not accept wasmtime subcommand names as the module name
     match s.to_str() {
-        Some("help") | Some("config") | Some("run") | Some("wasm2obj") | Some("wast") => {
-            Err("module name cannot be the same as a subcommand".into())
-        }
+        Some("help") | Some("blade-info") | Some("config") | Some("run") | Some("wasm2obj")
+        | Some("wast") => Err("module name cannot be the same as a subcommand".into()),
         _ => Ok(s.into()),
     }
 }
@@ -108,6 +107,20 @@ pub struct RunCommand {
     )]
     wasm_timeout: Option<Duration>,
 
+    /// Print a report of the Spectre mitigations Blade inserted in each module
+    #[structopt(long)]
+    blade_report: bool,
+
+    /// Write the Blade statistics of every function of the main module to a
+    /// CSV file
+    #[structopt(long, value_name = "PATH", parse(from_os_str))]
+    blade_csv: Option<PathBuf>,
+
+    /// Write every place where Blade had to approximate in the main module to
+    /// a CSV file, and fail if any of them may leave it unprotected
+    #[structopt(long, value_name = "PATH", parse(from_os_str))]
+    blade_risks: Option<PathBuf>,
+
     // NOTE: this must come last for trailing varargs
     /// The arguments to pass to the module
     #[structopt(value_name = "ARGS")]
@@ -142,6 +155,7 @@ impl RunCommand {
         for (name, path) in self.preloads.iter() {
             // Read the wasm module binary either as `*.wat` or a raw binary
             let module = Module::from_file(&engine, path)?;
+            self.maybe_print_blade_report(path, &module);
 
             // Add the module's functions to the linker.
             linker.module(name, &module).context(format!(
@@ -236,6 +250,12 @@ impl RunCommand {
         result
     }
 
+    fn maybe_print_blade_report(&self, path: &Path, module: &Module) {
+        if self.blade_report {
+            eprint!("{}: {}", path.display(), module.blade_report());
+        }
+    }
+
     fn load_main_module(&self, linker: &mut Linker) -> Result<()> {
         if let Some(timeout) = self.wasm_timeout {
             let handle = linker.store().interrupt_handle()?;
@@ -248,6 +268,23 @@ impl RunCommand {
         // Read the wasm module binary either as `*.wat` or a raw binary.
         // Use "" as a default module name.
         let module = Module::from_file(linker.store().engine(), &self.module)?;
+        self.maybe_print_blade_report(&self.module, &module);
+        if let Some(path) = &self.blade_csv {
+            fs::write(path, module.blade_report().to_csv())
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+        if let Some(path) = &self.blade_risks {
+            let report = module.blade_report();
+            fs::write(path, report.residual_risk_csv())
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            if !report.is_fully_protected() {
+                bail!(
+                    "Blade left {:?} unprotected; see {}",
+                    self.module,
+                    path.display()
+                );
+            }
+        }
         linker
             .module("", &module)
             .context(format!("failed to instantiate {:?}", self.module))?;
@@ -1,6 +1,6 @@
 //! The module that implements the `wasmtime wasm2obj` command.
 
-use crate::obj::compile_to_obj;
+use crate::obj::compile_to_obj_with_blade_trace;
 use crate::{init_file_per_thread_logger, pick_compilation_strategy, CommonOptions};
 use anyhow::{anyhow, Context as _, Result};
 use std::{
@@ -44,6 +44,17 @@ pub struct WasmToObjCommand {
     /// The target triple; default is the host triple
     #[structopt(long, value_name = "TARGET", parse(try_from_str = parse_target))]
     target: Option<Triple>,
+
+    /// Also write a trace of the Blade Spectre mitigations, with the code
+    /// offsets of the loads, sinks, and mitigations of every function, for
+    /// external speculative-execution checkers
+    #[structopt(long, value_name = "TRACE_PATH", parse(from_os_str))]
+    blade_trace: Option<PathBuf>,
+
+    /// Print a rough static estimate of the slowdown caused by the Blade
+    /// Spectre mitigations of the module
+    #[structopt(long)]
+    blade_estimate: bool,
 }
 
 impl WasmToObjCommand {
@@ -68,14 +79,21 @@ impl WasmToObjCommand {
         let strategy = pick_compilation_strategy(self.common.cranelift, self.common.lightbeam)?;
 
         let data = wat::parse_file(&self.module).context("failed to parse module")?;
+        let cranelift_flags: Vec<_> = self
+            .common
+            .cranelift_flags
+            .iter()
+            .map(|flag| (flag.name.clone(), flag.value.clone()))
+            .collect();
 
-        let obj = compile_to_obj(
+        let (obj, trace, estimate) = compile_to_obj_with_blade_trace(
             &data,
             self.target.as_ref(),
             strategy,
             self.common.enable_simd,
             self.common.opt_level(),
             self.common.debug_info,
+            &cranelift_flags,
             &cache_config,
         )?;
 
@@ -84,6 +102,23 @@ impl WasmToObjCommand {
         file.write_all(&obj.write()?)
             .context("failed to write object file")?;
 
+        if let Some(path) = &self.blade_trace {
+            std::fs::write(path, trace)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+
+        if self.blade_estimate {
+            println!(
+                "{}: estimated Blade slowdown {:.2}x ({} weighted fences, {} weighted SLH loads, \
+                 {} weighted instructions)",
+                self.module.display(),
+                estimate.slowdown(),
+                estimate.fences,
+                estimate.slh_loads,
+                estimate.insts
+            );
+        }
+
         Ok(())
     }
 }
@@ -27,12 +27,13 @@
 pub mod commands;
 mod obj;
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
+use std::fs;
 use std::path::PathBuf;
 use structopt::StructOpt;
 use wasmtime::{Config, ProfilingStrategy, Strategy};
 
-pub use obj::compile_to_obj;
+pub use obj::{compile_to_obj, compile_to_obj_with_blade_trace};
 
 fn pick_compilation_strategy(cranelift: bool, lightbeam: bool) -> Result<Strategy> {
     Ok(match (lightbeam, cranelift) {
@@ -157,6 +158,26 @@ struct CommonOptions {
     #[structopt(long, parse(try_from_str = parse_cranelift_flag))]
     cranelift_flags: Vec<CraneliftFlag>,
 
+    /// Only run the Blade Spectre mitigation pass on the functions whose name
+    /// or index matches this regular expression
+    #[structopt(long, value_name = "REGEX")]
+    blade_only: Option<String>,
+
+    /// Don't run the Blade Spectre mitigation pass on the functions whose
+    /// name or index matches this regular expression
+    #[structopt(long, value_name = "REGEX")]
+    blade_exclude: Option<String>,
+
+    /// Only run the Blade Spectre mitigation pass on the functions which this
+    /// execution profile counts at least `--blade-hot-threshold` times. Each
+    /// line holds a function name or index followed by its count
+    #[structopt(long, value_name = "PATH", parse(from_os_str))]
+    blade_profile: Option<PathBuf>,
+
+    /// The count from which a function of the `--blade-profile` is hot
+    #[structopt(long, value_name = "COUNT", default_value = "1")]
+    blade_hot_threshold: u64,
+
     /// Maximum size in bytes of wasm memory before it becomes dynamically
     /// relocatable instead of up-front-reserved.
     #[structopt(long)]
@@ -199,6 +220,17 @@ impl CommonOptions {
                 config.cranelift_other_flag(name, value)?;
             }
         }
+        if let Some(pattern) = &self.blade_only {
+            config.blade_only(pattern)?;
+        }
+        if let Some(pattern) = &self.blade_exclude {
+            config.blade_exclude(pattern)?;
+        }
+        if let Some(path) = &self.blade_profile {
+            let profile = fs::read_to_string(path)
+                .with_context(|| format!("failed to read the Blade profile {}", path.display()))?;
+            config.blade_profile(&profile, self.blade_hot_threshold)?;
+        }
         if !self.disable_cache {
             match &self.config {
                 Some(path) => {
@@ -6,12 +6,12 @@ use wasmtime_debug::{emit_dwarf, read_debuginfo, write_debugsections};
 #[cfg(feature = "lightbeam")]
 use wasmtime_environ::Lightbeam;
 use wasmtime_environ::{
-    entity::EntityRef, settings, settings::Configurable, wasm::DefinedMemoryIndex,
-    wasm::MemoryIndex, CacheConfig, Compiler, Cranelift, ModuleEnvironment, ModuleMemoryOffset,
-    ModuleVmctxInfo, Tunables, VMOffsets,
+    blade::BladeCostEstimate, entity::EntityRef, settings, settings::Configurable,
+    wasm::DefinedMemoryIndex, wasm::MemoryIndex, CacheConfig, Compiler, Cranelift,
+    ModuleEnvironment, ModuleMemoryOffset, ModuleVmctxInfo, Tunables, VMOffsets,
 };
 use wasmtime_jit::native;
-use wasmtime_obj::emit_module;
+use wasmtime_obj::{blade_metadata, blade_trace, emit_blade_metadata, emit_module};
 
 fn to_obj_format(
     triple: &Triple,
@@ -50,6 +50,10 @@ fn to_obj_format(
 }
 
 /// Creates object file from binary wasm data.
+///
+/// `cranelift_flags` are the names and values of other Cranelift settings, such as the `blade`
+/// family of settings. The object file records these, along with the Spectre mitigations
+/// placed in every function, in its Blade metadata section.
 pub fn compile_to_obj(
     wasm: &[u8],
     target: Option<&Triple>,
@@ -57,8 +61,37 @@ pub fn compile_to_obj(
     enable_simd: bool,
     opt_level: wasmtime::OptLevel,
     debug_info: bool,
+    cranelift_flags: &[(String, String)],
     cache_config: &CacheConfig,
 ) -> Result<Object> {
+    compile_to_obj_with_blade_trace(
+        wasm,
+        target,
+        strategy,
+        enable_simd,
+        opt_level,
+        debug_info,
+        cranelift_flags,
+        cache_config,
+    )
+    .map(|(obj, _, _)| obj)
+}
+
+/// Creates object file from binary wasm data, like `compile_to_obj`, along with a trace of the
+/// Blade decisions in every function for external speculative-execution checkers, and a static
+/// estimate of the run-time cost of the Blade mitigations of the whole module.
+///
+/// See `wasmtime_obj::blade_trace` for the format of the trace.
+pub fn compile_to_obj_with_blade_trace(
+    wasm: &[u8],
+    target: Option<&Triple>,
+    strategy: Strategy,
+    enable_simd: bool,
+    opt_level: wasmtime::OptLevel,
+    debug_info: bool,
+    cranelift_flags: &[(String, String)],
+    cache_config: &CacheConfig,
+) -> Result<(Object, String, BladeCostEstimate)> {
     let isa_builder = match target {
         Some(target) => native::lookup(target.clone())?,
         None => native::builder(),
@@ -84,6 +117,12 @@ pub fn compile_to_obj(
         other => bail!("unknown optimization level {:?}", other),
     }
 
+    for (name, value) in cranelift_flags {
+        flag_builder
+            .set(name, value)
+            .with_context(|| format!("failed to set Cranelift flag `{}={}`", name, value))?;
+    }
+
     let isa = isa_builder.finish(settings::Flags::new(flag_builder));
 
     let (obj_format, obj_arch, obj_endian) = to_obj_format(isa.triple())?;
@@ -153,6 +192,12 @@ pub fn compile_to_obj(
     )
     .map_err(|e| anyhow!(e))
     .context("failed to emit module")?;
+    emit_blade_metadata(
+        &mut obj,
+        &blade_metadata(&translation.module, &compilation, isa.flags()),
+    );
+    let trace = blade_trace(&translation.module, &compilation);
+    let estimate = compilation.blade_estimate();
 
     if debug_info {
         let debug_data = read_debuginfo(wasm).context("failed to emit DWARF")?;
@@ -167,5 +212,5 @@ pub fn compile_to_obj(
         .context("failed to emit debug sections")?;
         write_debugsections(&mut obj, sections).context("failed to emit debug sections")?;
     }
-    Ok(obj)
+    Ok((obj, trace, estimate))
 }
@@ -0,0 +1,129 @@
+//! The classic Spectre v1 gadget shapes, compiled with each Blade strategy.
+//!
+//! Loads of runtime data from the VM context are excluded from the sources, so
+//! that the only mitigations left are those protecting the gadgets themselves.
+
+use anyhow::Result;
+use wasmtime::*;
+
+const GADGETS: &str = r#"
+    (module
+        (memory 1)
+
+        ;; A bounds-checked index loads a secret, which then indexes a probe
+        ;; array.
+        (func $array_index (param $i i32) (result i32)
+            (if (result i32) (i32.lt_u (local.get $i) (i32.const 64))
+                (then
+                    (i32.load8_u offset=4096
+                        (i32.shl (i32.load8_u (local.get $i)) (i32.const 9))))
+                (else (i32.const 0))))
+
+        ;; The index is checked after a first load, and loaded again before
+        ;; use.
+        (func $double_fetch (param $p i32) (result i32)
+            (if (result i32) (i32.lt_u (i32.load (local.get $p)) (i32.const 64))
+                (then (i32.load8_u (i32.load (local.get $p))))
+                (else (i32.const 0))))
+
+        ;; Indexes loaded on two paths merge before use.
+        (func $phi_merged_index (param $c i32) (param $p i32) (result i32)
+            (i32.load8_u
+                (if (result i32) (local.get $c)
+                    (then (i32.load (local.get $p)))
+                    (else (i32.load offset=4 (local.get $p))))))
+
+        ;; The value loaded in one iteration is the address of the load in the
+        ;; next one.
+        (func $loop_carried (param $p i32) (param $n i32) (result i32)
+            (loop $l
+                (local.set $p (i32.load (local.get $p)))
+                (br_if $l
+                    (local.tee $n (i32.sub (local.get $n) (i32.const 1)))))
+            (local.get $p))
+
+        ;; A loaded value which never reaches a sink.
+        (func $no_leak (param $p i32) (result i32)
+            (i32.add (i32.load (local.get $p)) (i32.const 1)))
+    )
+"#;
+
+/// Compile the gadgets with the `blade` strategy, and get the kinds of the
+/// mitigations placed in every function, by function name.
+fn mitigations(blade: &str) -> Result<Vec<(String, Vec<BladeMitigationKind>)>> {
+    let mut config = Config::new();
+    unsafe {
+        config.cranelift_other_flag("blade", blade)?;
+        config.cranelift_other_flag("blade_exclude_trusted_loads", "true")?;
+    }
+    let module = Module::new(&Engine::new(&config), GADGETS)?;
+    let report = module.blade_report();
+    assert!(report.is_fully_protected());
+    Ok(report
+        .functions()
+        .iter()
+        .map(|f| {
+            (
+                f.func_name().unwrap_or_default().to_string(),
+                f.mitigations().iter().map(|m| m.kind()).collect(),
+            )
+        })
+        .collect())
+}
+
+fn kinds<'a>(
+    mitigations: &'a [(String, Vec<BladeMitigationKind>)],
+    name: &str,
+) -> &'a [BladeMitigationKind] {
+    &mitigations
+        .iter()
+        .find(|(func, _)| func == name)
+        .unwrap_or_else(|| panic!("no function named {}", name))
+        .1
+}
+
+#[test]
+fn lfence_gadgets() -> Result<()> {
+    use BladeMitigationKind::*;
+    let mitigations = mitigations("lfence")?;
+    // A barrier right after each load of a leaked value, except after the
+    // merge, where one barrier protects both loads.
+    assert_eq!(kinds(&mitigations, "array_index"), [FenceAfter]);
+    assert_eq!(
+        kinds(&mitigations, "double_fetch"),
+        [FenceAfter, FenceAfter]
+    );
+    assert_eq!(kinds(&mitigations, "phi_merged_index"), [FenceBefore]);
+    assert_eq!(kinds(&mitigations, "loop_carried"), [FenceAfter]);
+    assert!(kinds(&mitigations, "no_leak").is_empty());
+    Ok(())
+}
+
+#[test]
+fn slh_gadgets() -> Result<()> {
+    use BladeMitigationKind::*;
+    let mitigations = mitigations("slh")?;
+    // Every load whose value flows through the cut is hardened, so the merge
+    // hardens both of the loads flowing into it.
+    assert_eq!(kinds(&mitigations, "array_index"), [Slh]);
+    assert_eq!(kinds(&mitigations, "double_fetch"), [Slh, Slh]);
+    assert_eq!(kinds(&mitigations, "phi_merged_index"), [Slh, Slh]);
+    assert_eq!(kinds(&mitigations, "loop_carried"), [Slh]);
+    assert!(kinds(&mitigations, "no_leak").is_empty());
+    Ok(())
+}
+
+#[test]
+fn analyze_gadgets() -> Result<()> {
+    // The analysis reports the barriers `lfence` would insert.
+    assert_eq!(mitigations("analyze")?, mitigations("lfence")?);
+    Ok(())
+}
+
+#[test]
+fn none_leaves_gadgets_alone() -> Result<()> {
+    for (_, kinds) in mitigations("none")? {
+        assert!(kinds.is_empty());
+    }
+    Ok(())
+}
@@ -350,3 +350,24 @@ fn greeter_preload_callable_command() -> Result<()> {
     assert_eq!(stdout, "Hello _start\nHello callable greet\nHello done\n");
     Ok(())
 }
+
+// Compile a module with Blade to an object file, and check the Blade metadata
+// recorded in it.
+#[test]
+fn blade_info_reads_wasm2obj_metadata() -> Result<()> {
+    let obj = NamedTempFile::new()?;
+    let obj_path = obj.path().to_str().unwrap();
+    run_wasmtime(&[
+        "wasm2obj",
+        "tests/wasm/blade-gadget.wat",
+        obj_path,
+        "--disable-cache",
+        "--cranelift-flags",
+        "blade=lfence",
+    ])?;
+
+    let stdout = run_wasmtime(&["blade-info", obj_path, "--expect", "blade=lfence"])?;
+    assert!(stdout.contains("blade = lfence\n"));
+    assert!(run_wasmtime(&["blade-info", obj_path, "--expect", "blade=slh"]).is_err());
+    Ok(())
+}
@@ -19,6 +19,7 @@ pub fn compile_cranelift(
         false,
         wasmtime::OptLevel::None,
         true,
+        &[],
         &CacheConfig::new_cache_disabled(),
     )?;
 
@@ -0,0 +1,6 @@
+(module
+    (memory 1)
+    (func (export "gadget") (param i32) (result i32)
+        (i32.load (i32.load (local.get 0)))
+    )
+)